@@ -0,0 +1,66 @@
+//! Compares diffing two buttons reports with a `Vec<Button>`, as the polling thread used to do,
+//! with the [`ButtonSet`] it uses now.
+//!
+//! Run with `cargo bench --features testing --bench button_diff`.
+
+use std::hint::black_box;
+
+use bmdse::{Button, testing::ButtonSet};
+use criterion::{Criterion, criterion_group, criterion_main};
+
+/// The pressed buttons of consecutive buttons reports.
+const REPORTS: [&[Button]; 6] = [
+    &[Button::Cut],
+    &[],
+    &[Button::Cut, Button::In],
+    &[Button::In, Button::Out],
+    &[Button::Cam1, Button::Cam2, Button::Cam3, Button::Cam4, Button::Cam5, Button::Cam6],
+    &[Button::Cam1, Button::Cam3, Button::Cam5],
+];
+
+fn vec_diff(pressed: &mut Vec<Button>, buttons: &[Button]) {
+    let prev_pressed = std::mem::replace(pressed, buttons.to_vec());
+    for button in prev_pressed {
+        if !buttons.contains(&button) {
+            black_box((button, false));
+        }
+    }
+    for &button in buttons {
+        black_box((button, true));
+    }
+}
+
+fn button_set_diff(pressed: &mut ButtonSet, buttons: &[Button]) {
+    let new_pressed = buttons.iter().copied().collect::<ButtonSet>();
+    let prev_pressed = std::mem::replace(pressed, new_pressed);
+    for button in prev_pressed.difference(new_pressed).iter() {
+        black_box((button, false));
+    }
+    for &button in buttons {
+        black_box((button, true));
+    }
+}
+
+fn button_diff(c: &mut Criterion) {
+    let mut group = c.benchmark_group("button_diff");
+    group.bench_function("vec", |b| {
+        let mut pressed = Vec::new();
+        b.iter(|| {
+            for buttons in REPORTS {
+                vec_diff(&mut pressed, black_box(buttons));
+            }
+        })
+    });
+    group.bench_function("button_set", |b| {
+        let mut pressed = ButtonSet::default();
+        b.iter(|| {
+            for buttons in REPORTS {
+                button_set_diff(&mut pressed, black_box(buttons));
+            }
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, button_diff);
+criterion_main!(benches);
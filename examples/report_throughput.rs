@@ -0,0 +1,57 @@
+//! Measures how fast the polling thread handles reports, using a fake device.
+//!
+//! Run with `cargo run --release --features testing --example report_throughput`.
+
+use std::time::{Duration, Instant};
+
+use bmdse::{SpeedEditor, testing::FakeBackend};
+
+const ROUNDS: usize = 5;
+const REPORTS: usize = 100_000;
+
+fn main() {
+    let backend = FakeBackend::new();
+    backend.push_auth_handshake();
+
+    let _speed_editor = SpeedEditor::builder()
+        .on_wheel_change(|velocity| {
+            std::hint::black_box(velocity);
+        })
+        .on_button_change(|button, pressed| {
+            std::hint::black_box((button, pressed));
+        })
+        .connect_backend(backend.clone())
+        .unwrap();
+    backend.wait_until_idle(Duration::from_secs(1));
+
+    for round in 0..ROUNDS {
+        // Keep the reports from being read while they are queued.
+        backend.set_unresponsive(true);
+        for i in 0..REPORTS {
+            let report: &[u8] = match i % 4 {
+                // Pressing Cam1, then Cam1 and Cam2, then releasing both.
+                0 => {
+                    &[0x04, 0x33, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]
+                }
+                1 => {
+                    &[0x04, 0x33, 0x00, 0x34, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]
+                }
+                2 => {
+                    &[0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]
+                }
+                _ => &[0x03, 0x00, 0x05, 0x00, 0x00, 0x00, 0x00],
+            };
+            backend.push_input_report(report);
+        }
+
+        let start = Instant::now();
+        backend.set_unresponsive(false);
+        assert!(backend.wait_until_idle(Duration::from_secs(60)));
+        let elapsed = start.elapsed();
+
+        println!(
+            "round {round}: {REPORTS} reports in {elapsed:?} ({:?} per report)",
+            elapsed / REPORTS as u32
+        );
+    }
+}
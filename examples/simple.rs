@@ -1,7 +1,7 @@
 use bmdse::{ButtonLed, SpeedEditor, WheelLed};
 
 fn main() {
-    let mut speed_editor = SpeedEditor::new()
+    let speed_editor = SpeedEditor::new()
         .unwrap()
         .on_wheel_change(|velocity| {
             eprintln!("wheel velocity: {velocity}");
@@ -18,5 +18,5 @@ fn main() {
 
     // Because the SpeedEditor spawns a new thread handling input,
     // we have to keep the main thread running.
-    loop {}
+    speed_editor.run().unwrap();
 }
@@ -0,0 +1,12 @@
+#![no_main]
+
+use bmdse::Model;
+use bmdse::protocol::Report;
+use libfuzzer_sys::fuzz_target;
+
+// Parsing arbitrary bytes should never panic, but return a report or an error.
+fuzz_target!(|bytes: &[u8]| {
+    for model in [Model::SpeedEditor, Model::EditorKeyboard] {
+        let _ = Report::parse(bytes, model);
+    }
+});
@@ -0,0 +1,61 @@
+//! Property tests for parsing, run with `cargo test` in this directory.
+//!
+//! They complement the fuzz targets by running on every test run, without a fuzzing toolchain.
+
+use bmdse::protocol::{
+    BATTERY_REPORT_ID, BUTTONS_REPORT_ID, BUTTONS_REPORT_LEN, Report, WHEEL_REPORT_ID,
+};
+use bmdse::{Button, Model};
+use proptest::prelude::*;
+
+/// Encodes a report the way the device sends it, with the buttons in the first slots.
+fn encode(report: &Report) -> Vec<u8> {
+    match report {
+        Report::Wheel { mode, value } => {
+            let mut bytes = vec![WHEEL_REPORT_ID, *mode as u8];
+            bytes.extend_from_slice(&value.to_le_bytes());
+            bytes.push(0x00);
+            bytes
+        }
+        Report::Buttons(buttons) => {
+            let mut bytes = vec![0x00; BUTTONS_REPORT_LEN];
+            bytes[0] = BUTTONS_REPORT_ID;
+            for (slot, &button) in bytes[1..].chunks_exact_mut(2).zip(buttons) {
+                slot.copy_from_slice(&(button as u16).to_le_bytes());
+            }
+            bytes
+        }
+        Report::EditorKeyboardButtons { buttons, other } => {
+            let codes = buttons.iter().map(|&button| button as u16);
+            let codes = codes.chain(other.iter().map(|button| button.code()));
+            let mut bytes = vec![0x00; BUTTONS_REPORT_LEN];
+            bytes[0] = BUTTONS_REPORT_ID;
+            for (slot, code) in bytes[1..].chunks_exact_mut(2).zip(codes) {
+                slot.copy_from_slice(&code.to_le_bytes());
+            }
+            bytes
+        }
+        Report::Battery { charging, level } => vec![BATTERY_REPORT_ID, *charging as u8, *level],
+        Report::Unknown { id, data } => [&[*id], data.as_slice()].concat(),
+        _ => unreachable!("unexpected report {report:?}"),
+    }
+}
+
+proptest! {
+    #[test]
+    fn parse_never_panics_and_round_trips(bytes in prop::collection::vec(any::<u8>(), 0..=64)) {
+        for model in [Model::SpeedEditor, Model::EditorKeyboard] {
+            if let Ok(report) = Report::parse(&bytes, model) {
+                let encoded = encode(&report);
+                prop_assert_eq!(Report::parse(&encoded, model).unwrap(), report);
+            }
+        }
+    }
+
+    #[test]
+    fn button_round_trips(value in any::<u16>()) {
+        if let Ok(button) = Button::try_from(value) {
+            prop_assert_eq!(button as u16, value);
+        }
+    }
+}
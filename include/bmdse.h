@@ -0,0 +1,233 @@
+/* The C API of bmdse, see `src/ffi.rs`. Do not edit, regenerate it with cbindgen. */
+
+#ifndef BMDSE_H
+#define BMDSE_H
+
+#include <stdbool.h>
+#include <stdint.h>
+
+/**
+ * The result of a function of the C API.
+ */
+typedef enum BmdseStatus {
+  /**
+   * The function succeeded.
+   */
+  BMDSE_STATUS_OK = 0,
+  /**
+   * See [`ErrorKind::NotFound`].
+   */
+  BMDSE_STATUS_NOT_FOUND,
+  /**
+   * See [`ErrorKind::Permission`].
+   */
+  BMDSE_STATUS_PERMISSION,
+  /**
+   * See [`ErrorKind::Disconnected`].
+   */
+  BMDSE_STATUS_DISCONNECTED,
+  /**
+   * See [`ErrorKind::Protocol`].
+   */
+  BMDSE_STATUS_PROTOCOL,
+  /**
+   * See [`ErrorKind::Auth`].
+   */
+  BMDSE_STATUS_AUTH,
+  /**
+   * See [`ErrorKind::Io`].
+   */
+  BMDSE_STATUS_IO,
+  /**
+   * See [`ErrorKind::Other`].
+   */
+  BMDSE_STATUS_OTHER,
+  /**
+   * An argument was null or out of range.
+   */
+  BMDSE_STATUS_INVALID_ARGUMENT,
+} BmdseStatus;
+
+/**
+ * What kind of [`BmdseEvent`] happened.
+ */
+typedef enum BmdseEventKind {
+  /**
+   * The jog wheel changed, see `velocity`.
+   */
+  BMDSE_EVENT_KIND_WHEEL_CHANGE,
+  /**
+   * A button was pressed or released, see `button` and `pressed`.
+   */
+  BMDSE_EVENT_KIND_BUTTON_CHANGE,
+  /**
+   * Battery information was received, see `charging` and `percentage`.
+   */
+  BMDSE_EVENT_KIND_BATTERY_INFO,
+  /**
+   * The device started or stopped charging, see `charging`.
+   */
+  BMDSE_EVENT_KIND_CHARGING_CHANGE,
+  /**
+   * The device was connected and authenticated.
+   */
+  BMDSE_EVENT_KIND_CONNECTED,
+  /**
+   * The device stopped responding or the polling thread stopped.
+   */
+  BMDSE_EVENT_KIND_DISCONNECTED,
+} BmdseEventKind;
+
+/**
+ * A Speed Editor that is opened with `bmdse_open`.
+ */
+typedef struct BmdseSpeedEditor BmdseSpeedEditor;
+
+/**
+ * The callbacks for `bmdse_set_callbacks`, which are called with its `user_data`.
+ *
+ * A null callback is not called.
+ */
+typedef struct BmdseCallbacks {
+  /**
+   * See [`SpeedEditor::on_wheel_change`].
+   */
+  void (*on_wheel_change)(int32_t velocity, void *user_data);
+  /**
+   * See [`SpeedEditor::on_button_change`], with the value of the [`Button`][crate::Button].
+   */
+  void (*on_button_change)(uint16_t button, bool pressed, void *user_data);
+  /**
+   * See [`SpeedEditor::on_battery_info`].
+   */
+  void (*on_battery_info)(bool charging, uint8_t percentage, void *user_data);
+  /**
+   * See [`SpeedEditor::on_connect`].
+   */
+  void (*on_connect)(void *user_data);
+  /**
+   * See [`SpeedEditor::on_disconnect`].
+   */
+  void (*on_disconnect)(void *user_data);
+} BmdseCallbacks;
+
+/**
+ * An event that happened on the Speed Editor, see [`Event`].
+ *
+ * Only the fields of its kind are set, the others are zero.
+ */
+typedef struct BmdseEvent {
+  /**
+   * What kind of event happened.
+   */
+  enum BmdseEventKind kind;
+  /**
+   * The velocity of the jog wheel.
+   */
+  int32_t velocity;
+  /**
+   * The value of the [`Button`][crate::Button] that changed.
+   */
+  uint16_t button;
+  /**
+   * `true` if the button is pressed, `false` if it was released.
+   */
+  bool pressed;
+  /**
+   * `true` if the device is charging.
+   */
+  bool charging;
+  /**
+   * The battery percentage (`0..=100`).
+   */
+  uint8_t percentage;
+} BmdseEvent;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Opens the first Speed Editor that is found, see [`SpeedEditor::new`],
+ * and stores it in `out`.
+ *
+ * Close it with `bmdse_close`.
+ *
+ * # Safety
+ *
+ * `out` must be null or valid for writes.
+ */
+enum BmdseStatus bmdse_open(struct BmdseSpeedEditor **out);
+
+/**
+ * Closes a Speed Editor that was opened with `bmdse_open`.
+ *
+ * This stops its polling thread, so it must not be called from a callback.
+ *
+ * # Safety
+ *
+ * `speed_editor` must be null or returned by `bmdse_open`, and not be closed yet.
+ * It must not be used anymore afterwards.
+ */
+void bmdse_close(struct BmdseSpeedEditor *speed_editor);
+
+/**
+ * Sets the button LED to the value of a [`ButtonLed`] (e.g. `0` for off).
+ *
+ * # Safety
+ *
+ * `speed_editor` must be null or returned by `bmdse_open`, and not be closed yet.
+ */
+enum BmdseStatus bmdse_set_button_led(const struct BmdseSpeedEditor *speed_editor, uint32_t led);
+
+/**
+ * Sets the wheel LED to the value of a [`WheelLed`] (e.g. `0` for off).
+ *
+ * # Safety
+ *
+ * `speed_editor` must be null or returned by `bmdse_open`, and not be closed yet.
+ */
+enum BmdseStatus bmdse_set_wheel_led(const struct BmdseSpeedEditor *speed_editor, uint32_t led);
+
+/**
+ * Replaces the callbacks, which are called on the polling thread with `user_data`.
+ *
+ * A null `callbacks` removes all callbacks. See the [module documentation][self]
+ * for what a callback may do.
+ *
+ * # Safety
+ *
+ * `speed_editor` must be null or returned by `bmdse_open`, and not be closed yet.
+ * `callbacks` must be null or valid for reads. The callbacks must be safe to call
+ * with `user_data` from another thread until they are replaced or the device is closed.
+ */
+enum BmdseStatus bmdse_set_callbacks(const struct BmdseSpeedEditor *speed_editor,
+                                     const struct BmdseCallbacks *callbacks,
+                                     void *user_data);
+
+/**
+ * Takes the next event, if there is one, and stores it in `out`.
+ *
+ * Returns `true` if an event was stored, without waiting for one.
+ * Events are collected from the first call on, so call it regularly after that.
+ *
+ * # Safety
+ *
+ * `speed_editor` must be null or returned by `bmdse_open`, and not be closed yet.
+ * `out` must be null or valid for writes.
+ */
+bool bmdse_poll_event(const struct BmdseSpeedEditor *speed_editor, struct BmdseEvent *out);
+
+/**
+ * Returns a description of the last error of a function on this thread,
+ * or null if there was none.
+ *
+ * The string stays valid until the next error on this thread.
+ */
+const char *bmdse_last_error_message(void);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* BMDSE_H */
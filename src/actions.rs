@@ -0,0 +1,652 @@
+//! Interpreting the buttons and the jog wheel as editing actions.
+//!
+//! The keys of the Speed Editor have well-known meanings in an editor like DaVinci Resolve.
+//! An [`ActionInterpreter`] turns the [`Event`]s into the [`EditAction`]s they stand for,
+//! e.g. [`Button::In`] into [`EditAction::SetInPoint`], for applications that care about
+//! what the editor wants to do rather than which key was pressed.
+//! The events themselves are not changed, so the raw buttons are still available.
+//!
+//! # State
+//!
+//! Some keys change how other keys and the jog wheel are interpreted.
+//! The interpreter keeps track of them:
+//!
+//! | State | Changed by | Effect |
+//! |-------|------------|--------|
+//! | [`Mode`] | `SOURCE` and `TIMELINE` | Trim tools only work in [`Mode::Timeline`] |
+//! | [`TrimTool`] | The trim keys toggle their tool, `ESC` or `SOURCE` turn it off | The jog wheel trims |
+//! | [`WheelFunction`] | `JOG`, `SHTL` and `SCRL` | What the jog wheel does without a trim tool |
+//! | Transition duration | Holding `TRANS DUR` | The jog wheel changes the transition duration |
+//!
+//! Pressing a trim key in [`Mode::Source`] switches to [`Mode::Timeline`] first.
+//! When the jog wheel turns, the first of these that applies decides the action:
+//! holding `TRANS DUR`, an active trim tool, and the wheel function.
+//!
+//! Every change of the state is also an action, like [`EditAction::ModeChanged`],
+//! so the application can show it. Actions are only triggered when a key is pressed,
+//! not when it is released.
+//!
+//! # Example
+//!
+//! ```
+//! use bmdse::actions::{ActionInterpreter, EditAction, Mode, Transition, TrimTool};
+//! use bmdse::{Button, Event};
+//!
+//! let press = |button| Event::ButtonChange { button, pressed: true };
+//! let mut interpreter = ActionInterpreter::new();
+//!
+//! assert_eq!(interpreter.interpret(&press(Button::In)), [EditAction::SetInPoint]);
+//! assert_eq!(
+//!     interpreter.interpret(&press(Button::Dissolve)),
+//!     [EditAction::Cut { transition: Transition::Dissolve }],
+//! );
+//! assert_eq!(interpreter.interpret(&press(Button::Cam3)), [EditAction::SelectCamera(3)]);
+//!
+//! // A trim tool in source mode switches to the timeline, and then the wheel trims.
+//! interpreter.interpret(&press(Button::Source));
+//! assert_eq!(
+//!     interpreter.interpret(&press(Button::Roll)),
+//!     [EditAction::ModeChanged(Mode::Timeline), EditAction::TrimToolChanged(Some(TrimTool::Roll))],
+//! );
+//! assert_eq!(
+//!     interpreter.interpret(&Event::WheelChange { velocity: -2 }),
+//!     [EditAction::Trim { tool: TrimTool::Roll, velocity: -2 }],
+//! );
+//!
+//! // Escape turns the trim tool off, after which the wheel jogs again.
+//! assert_eq!(interpreter.interpret(&press(Button::Escape)), [EditAction::TrimToolChanged(None)]);
+//! assert_eq!(interpreter.interpret(&Event::WheelChange { velocity: 5 }), [EditAction::Jog(5)]);
+//! ```
+//!
+//! To receive the actions on another thread, attach an [`ActionSink`]:
+//!
+//! ```no_run
+//! use std::sync::mpsc;
+//!
+//! use bmdse::SpeedEditor;
+//! use bmdse::actions::ActionInterpreter;
+//!
+//! let speed_editor = SpeedEditor::new().unwrap();
+//! let (sender, receiver) = mpsc::channel();
+//! speed_editor.attach_sink(Box::new(ActionInterpreter::new().into_sink(sender)));
+//!
+//! for action in receiver {
+//!     eprintln!("{action:?}");
+//! }
+//! ```
+
+use std::sync::{Mutex, mpsc};
+
+use crate::{Button, Event, EventSink, SinkClosed, sync::MutexExt};
+
+/// Which viewer the editor works in, selected with the `SOURCE` and `TIMELINE` keys.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Mode {
+    /// The source viewer, to pick the part of a clip that is edited into the timeline.
+    Source,
+    /// The timeline.
+    #[default]
+    Timeline,
+}
+
+/// A tool that trims the edit under the playhead with the jog wheel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TrimTool {
+    /// Moves the start of the clip after the edit (`TRIM IN`).
+    TrimIn,
+    /// Moves the end of the clip before the edit (`TRIM OUT`).
+    TrimOut,
+    /// Moves the edit itself (`ROLL`).
+    Roll,
+    /// Slips the content of the clip within its source (`SLIP SRC`).
+    SlipSource,
+    /// Slips the clip within the timeline (`SLIP DEST`).
+    SlipDestination,
+}
+
+/// What the jog wheel does when no trim tool is active.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum WheelFunction {
+    /// Moves the playhead frame by frame.
+    #[default]
+    Jog,
+    /// Plays at a speed that follows the wheel.
+    Shuttle,
+    /// Moves the playhead quickly through the clip or timeline.
+    Scroll,
+}
+
+/// A transition that is added at an edit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Transition {
+    /// A straight cut, without a transition.
+    Cut,
+    /// A cross dissolve.
+    Dissolve,
+    /// A smooth cut, which hides a jump cut.
+    SmoothCut,
+}
+
+/// An edit that puts the selected part of the source clip into the timeline.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum EditKind {
+    /// Inserts the clip at the closest edit (`SMART INSRT`).
+    SmartInsert,
+    /// Appends the clip at the end of the timeline (`APPND`).
+    Append,
+    /// Replaces the clip under the playhead, rippling the timeline (`RIPL O/WR`).
+    RippleOverwrite,
+    /// Places a zoomed in copy of the clip on top (`CLOSE UP`).
+    CloseUp,
+    /// Places the clip on the track above (`PLACE ON TOP`).
+    PlaceOnTop,
+    /// Overwrites the timeline, synced by timecode (`SRC O/WR`).
+    SourceOverwrite,
+}
+
+/// What a key press or jog wheel change on the Speed Editor means, see [`ActionInterpreter`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum EditAction {
+    /// The [`Mode`] changed.
+    ModeChanged(Mode),
+    /// The [`TrimTool`] changed, [`None`] if it was turned off.
+    TrimToolChanged(Option<TrimTool>),
+    /// The [`WheelFunction`] changed.
+    WheelFunctionChanged(WheelFunction),
+
+    /// Sets the in point at the playhead.
+    SetInPoint,
+    /// Sets the out point at the playhead.
+    SetOutPoint,
+    /// Edits the source clip into the timeline.
+    Edit(EditKind),
+    /// Adds the transition at the edit under the playhead.
+    Cut {
+        /// The transition that is added.
+        transition: Transition,
+    },
+    /// Adds the default transition at the edit under the playhead.
+    AddTransition,
+    /// Selects the camera angle (`1..=9`) of a multicam clip.
+    SelectCamera(u8),
+    /// Toggles live overwrite of camera angles while playing.
+    LiveOverwrite,
+    /// Only edits the video of the next edit.
+    VideoOnly,
+    /// Only edits the audio of the next edit.
+    AudioOnly,
+    /// Splits the clip at the playhead.
+    Split,
+    /// Deletes the selection and closes the gap.
+    RippleDelete,
+    /// Toggles snapping.
+    ToggleSnap,
+    /// Shows the audio levels.
+    AudioLevel,
+    /// Toggles the full screen viewer.
+    FullView,
+    /// Shows the clips that are in sync with the clip under the playhead.
+    SyncBin,
+    /// Starts or stops playback.
+    PlayStop,
+    /// Cancels the current operation, when no trim tool is active.
+    Escape,
+
+    /// The jog wheel turned with [`WheelFunction::Jog`].
+    Jog(i32),
+    /// The jog wheel turned with [`WheelFunction::Shuttle`].
+    Shuttle(i32),
+    /// The jog wheel turned with [`WheelFunction::Scroll`].
+    Scroll(i32),
+    /// The jog wheel turned with a trim tool active.
+    Trim {
+        /// The active trim tool.
+        tool: TrimTool,
+        /// The velocity of the wheel.
+        velocity: i32,
+    },
+    /// The jog wheel turned while holding `TRANS DUR`.
+    AdjustTransitionDuration(i32),
+}
+
+/// Turns [`Event`]s into [`EditAction`]s, keeping track of the keys that change their meaning.
+///
+/// See the [module documentation][self] for the state it keeps.
+#[derive(Debug, Clone, Default)]
+pub struct ActionInterpreter {
+    mode: Mode,
+    trim_tool: Option<TrimTool>,
+    wheel_function: WheelFunction,
+    transition_duration_held: bool,
+}
+
+impl ActionInterpreter {
+    /// Creates an [`ActionInterpreter`] in [`Mode::Timeline`],
+    /// without a trim tool and with [`WheelFunction::Jog`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the current [`Mode`].
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    /// Returns the active [`TrimTool`], if there is one.
+    pub fn trim_tool(&self) -> Option<TrimTool> {
+        self.trim_tool
+    }
+
+    /// Returns the current [`WheelFunction`].
+    pub fn wheel_function(&self) -> WheelFunction {
+        self.wheel_function
+    }
+
+    /// Returns the actions the event stands for, in the order they happen.
+    ///
+    /// Most events stand for no action or one action, but a key that changes the state
+    /// can also change other state, like a trim key in [`Mode::Source`].
+    ///
+    /// ```
+    /// use bmdse::actions::{ActionInterpreter, EditAction, Mode, TrimTool, WheelFunction};
+    /// use bmdse::{Button, Event};
+    ///
+    /// let press = |button| Event::ButtonChange { button, pressed: true };
+    /// let release = |button| Event::ButtonChange { button, pressed: false };
+    /// let wheel = |velocity| Event::WheelChange { velocity };
+    /// let mut interpreter = ActionInterpreter::new();
+    ///
+    /// // Releasing a key does nothing.
+    /// assert_eq!(interpreter.interpret(&release(Button::In)), []);
+    ///
+    /// // The wheel function decides what the wheel does.
+    /// assert_eq!(
+    ///     interpreter.interpret(&press(Button::Shuttle)),
+    ///     [EditAction::WheelFunctionChanged(WheelFunction::Shuttle)],
+    /// );
+    /// assert_eq!(interpreter.interpret(&wheel(1)), [EditAction::Shuttle(1)]);
+    ///
+    /// // A trim key toggles its tool, and another trim key replaces it.
+    /// interpreter.interpret(&press(Button::TrimIn));
+    /// assert_eq!(
+    ///     interpreter.interpret(&press(Button::TrimOut)),
+    ///     [EditAction::TrimToolChanged(Some(TrimTool::TrimOut))],
+    /// );
+    /// assert_eq!(
+    ///     interpreter.interpret(&press(Button::TrimOut)),
+    ///     [EditAction::TrimToolChanged(None)],
+    /// );
+    ///
+    /// // Holding the transition duration key takes precedence over a trim tool.
+    /// interpreter.interpret(&press(Button::SlipSource));
+    /// interpreter.interpret(&press(Button::TransitionDuration));
+    /// assert_eq!(interpreter.interpret(&wheel(-1)), [EditAction::AdjustTransitionDuration(-1)]);
+    /// interpreter.interpret(&release(Button::TransitionDuration));
+    /// assert_eq!(
+    ///     interpreter.interpret(&wheel(-1)),
+    ///     [EditAction::Trim { tool: TrimTool::SlipSource, velocity: -1 }],
+    /// );
+    ///
+    /// // The source viewer has no trim tools, and escape without a trim tool is passed on.
+    /// assert_eq!(
+    ///     interpreter.interpret(&press(Button::Source)),
+    ///     [EditAction::ModeChanged(Mode::Source), EditAction::TrimToolChanged(None)],
+    /// );
+    /// assert_eq!(interpreter.interpret(&press(Button::Escape)), [EditAction::Escape]);
+    /// assert_eq!(interpreter.interpret(&press(Button::Source)), []);
+    /// ```
+    pub fn interpret(&mut self, event: &Event) -> Vec<EditAction> {
+        let mut actions = Vec::new();
+        match *event {
+            Event::ButtonChange { button, pressed: true } => self.press(button, &mut actions),
+            Event::ButtonChange { button: Button::TransitionDuration, pressed: false } => {
+                self.transition_duration_held = false;
+            }
+            Event::WheelChange { velocity } => actions.push(self.turn(velocity)),
+            // A key that is held while disconnecting is never released.
+            Event::Disconnected => self.transition_duration_held = false,
+            _ => {}
+        }
+        actions
+    }
+
+    fn press(&mut self, button: Button, actions: &mut Vec<EditAction>) {
+        let action = match button {
+            Button::Source => {
+                self.set_mode(Mode::Source, actions);
+                self.set_trim_tool(None, actions);
+                return;
+            }
+            Button::Timeline => return self.set_mode(Mode::Timeline, actions),
+            Button::TrimIn => return self.toggle_trim_tool(TrimTool::TrimIn, actions),
+            Button::TrimOut => return self.toggle_trim_tool(TrimTool::TrimOut, actions),
+            Button::Roll => return self.toggle_trim_tool(TrimTool::Roll, actions),
+            Button::SlipSource => return self.toggle_trim_tool(TrimTool::SlipSource, actions),
+            Button::SlipDestination => {
+                return self.toggle_trim_tool(TrimTool::SlipDestination, actions);
+            }
+            Button::Escape if self.trim_tool.is_some() => {
+                return self.set_trim_tool(None, actions);
+            }
+            Button::Jog => return self.set_wheel_function(WheelFunction::Jog, actions),
+            Button::Shuttle => return self.set_wheel_function(WheelFunction::Shuttle, actions),
+            Button::Scroll => return self.set_wheel_function(WheelFunction::Scroll, actions),
+            Button::TransitionDuration => {
+                self.transition_duration_held = true;
+                return;
+            }
+
+            Button::In => EditAction::SetInPoint,
+            Button::Out => EditAction::SetOutPoint,
+            Button::SmartInsert => EditAction::Edit(EditKind::SmartInsert),
+            Button::Append => EditAction::Edit(EditKind::Append),
+            Button::RippleOverwrite => EditAction::Edit(EditKind::RippleOverwrite),
+            Button::CloseUp => EditAction::Edit(EditKind::CloseUp),
+            Button::PlaceOnTop => EditAction::Edit(EditKind::PlaceOnTop),
+            Button::SourceOverwrite => EditAction::Edit(EditKind::SourceOverwrite),
+            Button::Cut => EditAction::Cut { transition: Transition::Cut },
+            Button::Dissolve => EditAction::Cut { transition: Transition::Dissolve },
+            Button::SmoothCut => EditAction::Cut { transition: Transition::SmoothCut },
+            Button::Transition => EditAction::AddTransition,
+            Button::Cam1 => EditAction::SelectCamera(1),
+            Button::Cam2 => EditAction::SelectCamera(2),
+            Button::Cam3 => EditAction::SelectCamera(3),
+            Button::Cam4 => EditAction::SelectCamera(4),
+            Button::Cam5 => EditAction::SelectCamera(5),
+            Button::Cam6 => EditAction::SelectCamera(6),
+            Button::Cam7 => EditAction::SelectCamera(7),
+            Button::Cam8 => EditAction::SelectCamera(8),
+            Button::Cam9 => EditAction::SelectCamera(9),
+            Button::LiveOverwrite => EditAction::LiveOverwrite,
+            Button::VideoOnly => EditAction::VideoOnly,
+            Button::AudioOnly => EditAction::AudioOnly,
+            Button::Split => EditAction::Split,
+            Button::RippleDelete => EditAction::RippleDelete,
+            Button::Snap => EditAction::ToggleSnap,
+            Button::AudioLevel => EditAction::AudioLevel,
+            Button::FullView => EditAction::FullView,
+            Button::SyncBin => EditAction::SyncBin,
+            Button::StopPlay => EditAction::PlayStop,
+            Button::Escape => EditAction::Escape,
+        };
+        actions.push(action);
+    }
+
+    fn turn(&self, velocity: i32) -> EditAction {
+        if self.transition_duration_held {
+            return EditAction::AdjustTransitionDuration(velocity);
+        }
+        if let Some(tool) = self.trim_tool {
+            return EditAction::Trim { tool, velocity };
+        }
+        match self.wheel_function {
+            WheelFunction::Jog => EditAction::Jog(velocity),
+            WheelFunction::Shuttle => EditAction::Shuttle(velocity),
+            WheelFunction::Scroll => EditAction::Scroll(velocity),
+        }
+    }
+
+    fn set_mode(&mut self, mode: Mode, actions: &mut Vec<EditAction>) {
+        if self.mode != mode {
+            self.mode = mode;
+            actions.push(EditAction::ModeChanged(mode));
+        }
+    }
+
+    fn set_trim_tool(&mut self, trim_tool: Option<TrimTool>, actions: &mut Vec<EditAction>) {
+        if self.trim_tool != trim_tool {
+            self.trim_tool = trim_tool;
+            actions.push(EditAction::TrimToolChanged(trim_tool));
+        }
+    }
+
+    /// Turns the trim tool off if it is active, and on otherwise, in [`Mode::Timeline`].
+    fn toggle_trim_tool(&mut self, trim_tool: TrimTool, actions: &mut Vec<EditAction>) {
+        if self.trim_tool == Some(trim_tool) {
+            return self.set_trim_tool(None, actions);
+        }
+        self.set_mode(Mode::Timeline, actions);
+        self.set_trim_tool(Some(trim_tool), actions);
+    }
+
+    fn set_wheel_function(&mut self, wheel_function: WheelFunction, actions: &mut Vec<EditAction>) {
+        if self.wheel_function != wheel_function {
+            self.wheel_function = wheel_function;
+            actions.push(EditAction::WheelFunctionChanged(wheel_function));
+        }
+    }
+
+    /// Turns the interpreter into an [`EventSink`] that sends the actions to `sender`.
+    pub fn into_sink(self, sender: mpsc::Sender<EditAction>) -> ActionSink {
+        ActionSink { interpreter: Mutex::new(self), sender }
+    }
+}
+
+/// An [`EventSink`] that interprets the events and sends the actions to a channel,
+/// see [`ActionInterpreter::into_sink`].
+///
+/// It is detached once the receiver of the channel is dropped.
+pub struct ActionSink {
+    interpreter: Mutex<ActionInterpreter>,
+    sender: mpsc::Sender<EditAction>,
+}
+
+impl EventSink for ActionSink {
+    fn send(&self, event: Event) -> Result<(), SinkClosed> {
+        for action in self.interpreter.lock_unpoisoned().interpret(&event) {
+            self.sender.send(action).map_err(|_| SinkClosed)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn press(button: Button) -> Event {
+        Event::ButtonChange { button, pressed: true }
+    }
+
+    fn release(button: Button) -> Event {
+        Event::ButtonChange { button, pressed: false }
+    }
+
+    fn wheel(velocity: i32) -> Event {
+        Event::WheelChange { velocity }
+    }
+
+    /// Interprets the events one by one, checking the actions of each.
+    fn assert_transitions(interpreter: &mut ActionInterpreter, steps: &[(Event, &[EditAction])]) {
+        for (event, actions) in steps {
+            assert_eq!(interpreter.interpret(event), *actions, "{event:?}");
+        }
+    }
+
+    #[test]
+    fn mode_changes_once() {
+        let mut interpreter = ActionInterpreter::new();
+        assert_transitions(
+            &mut interpreter,
+            &[
+                (press(Button::Timeline), &[]),
+                (press(Button::Source), &[EditAction::ModeChanged(Mode::Source)]),
+                (press(Button::Source), &[]),
+                (release(Button::Source), &[]),
+                (press(Button::Timeline), &[EditAction::ModeChanged(Mode::Timeline)]),
+            ],
+        );
+        assert_eq!(interpreter.mode(), Mode::Timeline);
+    }
+
+    #[test]
+    fn trim_keys_toggle_their_tool() {
+        let tools = [
+            (Button::TrimIn, TrimTool::TrimIn),
+            (Button::TrimOut, TrimTool::TrimOut),
+            (Button::Roll, TrimTool::Roll),
+            (Button::SlipSource, TrimTool::SlipSource),
+            (Button::SlipDestination, TrimTool::SlipDestination),
+        ];
+        let mut interpreter = ActionInterpreter::new();
+        for (button, tool) in tools {
+            assert_transitions(
+                &mut interpreter,
+                &[
+                    (press(button), &[EditAction::TrimToolChanged(Some(tool))]),
+                    (release(button), &[]),
+                    (wheel(3), &[EditAction::Trim { tool, velocity: 3 }]),
+                    (press(button), &[EditAction::TrimToolChanged(None)]),
+                    (wheel(3), &[EditAction::Jog(3)]),
+                ],
+            );
+        }
+
+        // Another trim key replaces the active tool.
+        assert_transitions(
+            &mut interpreter,
+            &[
+                (press(Button::Roll), &[EditAction::TrimToolChanged(Some(TrimTool::Roll))]),
+                (press(Button::TrimIn), &[EditAction::TrimToolChanged(Some(TrimTool::TrimIn))]),
+            ],
+        );
+        assert_eq!(interpreter.trim_tool(), Some(TrimTool::TrimIn));
+    }
+
+    #[test]
+    fn trim_tools_only_work_in_timeline() {
+        let mut interpreter = ActionInterpreter::new();
+        assert_transitions(
+            &mut interpreter,
+            &[
+                (press(Button::Roll), &[EditAction::TrimToolChanged(Some(TrimTool::Roll))]),
+                (
+                    press(Button::Source),
+                    &[EditAction::ModeChanged(Mode::Source), EditAction::TrimToolChanged(None)],
+                ),
+                (wheel(1), &[EditAction::Jog(1)]),
+                (
+                    press(Button::SlipDestination),
+                    &[
+                        EditAction::ModeChanged(Mode::Timeline),
+                        EditAction::TrimToolChanged(Some(TrimTool::SlipDestination)),
+                    ],
+                ),
+                // Switching to the timeline keeps the tool.
+                (press(Button::Timeline), &[]),
+            ],
+        );
+        assert_eq!(interpreter.mode(), Mode::Timeline);
+        assert_eq!(interpreter.trim_tool(), Some(TrimTool::SlipDestination));
+    }
+
+    #[test]
+    fn escape_turns_off_trim_tool_first() {
+        let mut interpreter = ActionInterpreter::new();
+        assert_transitions(
+            &mut interpreter,
+            &[
+                (press(Button::Escape), &[EditAction::Escape]),
+                (press(Button::TrimOut), &[EditAction::TrimToolChanged(Some(TrimTool::TrimOut))]),
+                (press(Button::Escape), &[EditAction::TrimToolChanged(None)]),
+                (press(Button::Escape), &[EditAction::Escape]),
+            ],
+        );
+    }
+
+    #[test]
+    fn wheel_function_changes_once() {
+        let mut interpreter = ActionInterpreter::new();
+        assert_transitions(
+            &mut interpreter,
+            &[
+                (press(Button::Jog), &[]),
+                (wheel(-4), &[EditAction::Jog(-4)]),
+                (
+                    press(Button::Shuttle),
+                    &[EditAction::WheelFunctionChanged(WheelFunction::Shuttle)],
+                ),
+                (press(Button::Shuttle), &[]),
+                (wheel(-4), &[EditAction::Shuttle(-4)]),
+                (press(Button::Scroll), &[EditAction::WheelFunctionChanged(WheelFunction::Scroll)]),
+                (wheel(0), &[EditAction::Scroll(0)]),
+                (press(Button::Jog), &[EditAction::WheelFunctionChanged(WheelFunction::Jog)]),
+            ],
+        );
+        assert_eq!(interpreter.wheel_function(), WheelFunction::Jog);
+    }
+
+    #[test]
+    fn wheel_follows_precedence() {
+        let mut interpreter = ActionInterpreter::new();
+        interpreter.interpret(&press(Button::Scroll));
+        interpreter.interpret(&press(Button::Roll));
+        assert_transitions(
+            &mut interpreter,
+            &[
+                (press(Button::TransitionDuration), &[]),
+                (wheel(2), &[EditAction::AdjustTransitionDuration(2)]),
+                // Releasing another key keeps it held.
+                (release(Button::Roll), &[]),
+                (wheel(2), &[EditAction::AdjustTransitionDuration(2)]),
+                (release(Button::TransitionDuration), &[]),
+                (wheel(2), &[EditAction::Trim { tool: TrimTool::Roll, velocity: 2 }]),
+                (press(Button::Escape), &[EditAction::TrimToolChanged(None)]),
+                (wheel(2), &[EditAction::Scroll(2)]),
+            ],
+        );
+    }
+
+    #[test]
+    fn disconnect_releases_transition_duration() {
+        let mut interpreter = ActionInterpreter::new();
+        assert_transitions(
+            &mut interpreter,
+            &[
+                (press(Button::TransitionDuration), &[]),
+                (Event::Disconnected, &[]),
+                (wheel(1), &[EditAction::Jog(1)]),
+            ],
+        );
+    }
+
+    #[test]
+    fn other_events_do_nothing() {
+        let mut interpreter = ActionInterpreter::new();
+        interpreter.interpret(&press(Button::Roll));
+        for event in [
+            release(Button::Cut),
+            release(Button::Escape),
+            Event::BatteryInfo { charging: true, percentage: 50 },
+            Event::ChargingChange { charging: false },
+            Event::UnknownReport { id: 0x09, data: vec![] },
+        ] {
+            assert_eq!(interpreter.interpret(&event), [], "{event:?}");
+        }
+        assert_eq!(interpreter.trim_tool(), Some(TrimTool::Roll));
+    }
+
+    #[test]
+    fn sink_is_closed_with_its_receiver() {
+        let (sender, receiver) = mpsc::channel();
+        let sink = ActionInterpreter::new().into_sink(sender);
+
+        sink.send(press(Button::Source)).unwrap();
+        sink.send(press(Button::Cam7)).unwrap();
+        assert_eq!(
+            receiver.try_iter().collect::<Vec<_>>(),
+            [EditAction::ModeChanged(Mode::Source), EditAction::SelectCamera(7)]
+        );
+
+        drop(receiver);
+        // An event without actions sends nothing.
+        assert_eq!(sink.send(release(Button::Cam7)), Ok(()));
+        assert_eq!(sink.send(press(Button::Cam7)), Err(SinkClosed));
+    }
+}
@@ -0,0 +1,347 @@
+// Thanks to https://github.com/smunaut/blackmagic-misc for reverse
+// engineering the authentication!
+
+use std::hash::{BuildHasher, Hasher, RandomState};
+
+use crate::{AuthFailure, AuthStep, protocol::AUTH_REPORT_ID};
+
+/// The authentication handshake with a Speed Editor, without any I/O.
+///
+/// The device stops sending reports after a while unless it is authenticated,
+/// which happens over feature report 6 in the steps of [`AuthStep`]:
+/// send the reports from [`AuthSession::outgoing_report`] and pass the feature reports
+/// read from the device to [`AuthSession::handle_reply`],
+/// as told by [`AuthSession::expected_next_step`].
+///
+/// [`SpeedEditor`][crate::SpeedEditor] does this automatically. This is meant for other
+/// projects talking to Blackmagic Design keyboards.
+///
+/// # Example
+///
+/// ```no_run
+/// use bmdse::protocol::AuthSession;
+/// # fn send_feature_report(_: &[u8]) {}
+/// # fn get_feature_report() -> Vec<u8> { Vec::new() }
+///
+/// let mut session = AuthSession::new();
+/// while session.expected_next_step().is_some() {
+///     if let Some(report) = session.outgoing_report() {
+///         send_feature_report(&report);
+///     } else if let Some(timeout) = session.handle_reply(&get_feature_report()).unwrap() {
+///         println!("authenticated for {timeout} seconds");
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuthSession {
+    next_step: Option<AuthStep>,
+    challenge: u64,
+    /// The challenge we send to the device, if its response should be verified.
+    host_challenge: Option<u64>,
+}
+
+impl AuthSession {
+    /// Creates a session that starts by resetting the authentication state of the device.
+    pub fn new() -> Self {
+        Self { next_step: Some(AuthStep::Reset), challenge: 0, host_challenge: None }
+    }
+
+    /// Creates a session that sends `host_challenge` to the device,
+    /// and verifies the response of the device to it.
+    ///
+    /// A device that does not know the algorithm (e.g. a counterfeit one) fails the handshake
+    /// with [`AuthFailure::ResponseMismatch`]. The challenge should be unpredictable,
+    /// like the ones from [`AuthSession::random_host_challenge`].
+    pub fn with_host_challenge(host_challenge: u64) -> Self {
+        Self { host_challenge: Some(host_challenge), ..Self::new() }
+    }
+
+    /// Returns a random host challenge for [`AuthSession::with_host_challenge`].
+    pub fn random_host_challenge() -> u64 {
+        // The keys of `RandomState` are random, which is good enough for a challenge
+        // and avoids depending on a random number generator.
+        RandomState::new().build_hasher().finish()
+    }
+
+    /// Returns the next step of the handshake, or [`None`] if it is done.
+    pub fn expected_next_step(&self) -> Option<AuthStep> {
+        self.next_step
+    }
+
+    /// Returns the feature report to send for the next step, and moves on to the step after it.
+    ///
+    /// Returns [`None`] if the next step is to read a reply, or if the handshake is done.
+    pub fn outgoing_report(&mut self) -> Option<[u8; 10]> {
+        let (kind, payload, next_step) = match self.next_step? {
+            // Reset the auth state machine
+            AuthStep::Reset => (0x00, 0, AuthStep::ReadChallenge),
+            // Send our challenge (to authenticate keyboard)
+            // Unless it is verified, just send 0x0000000000000000
+            AuthStep::SendChallenge => {
+                (0x01, self.host_challenge.unwrap_or(0), AuthStep::ReadResponse)
+            }
+            // Compute and send our response
+            AuthStep::SendResponse => {
+                (0x03, Self::response_for(self.challenge), AuthStep::ReadStatus)
+            }
+            _ => return None,
+        };
+        self.next_step = Some(next_step);
+
+        let mut report = [0x00; 10];
+        report[0] = AUTH_REPORT_ID;
+        report[1] = kind;
+        report[2..].copy_from_slice(&payload.to_le_bytes());
+        Some(report)
+    }
+
+    /// Handles a feature report read from the device for the next step,
+    /// and moves on to the step after it.
+    ///
+    /// Returns the number of seconds after which the device has to be authenticated again
+    /// once the handshake is done, or [`None`] before that.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Authentication`][crate::Error::Authentication] if the reply is not
+    /// what the step expects, which ends the handshake.
+    /// Returns an error if the next step is not to read a reply.
+    pub fn handle_reply(&mut self, bytes: &[u8]) -> Result<Option<u16>, crate::Error> {
+        let Some(step) = self.next_step else {
+            return Err(crate::Error::driver("the authentication is already done"));
+        };
+        let kind = match step {
+            AuthStep::ReadChallenge => 0x00,
+            AuthStep::ReadResponse => 0x02,
+            AuthStep::ReadStatus => 0x04,
+            _ => return Err(crate::Error::driver("the next authentication step is not a read")),
+        };
+
+        if bytes.len() < 10 || bytes[0] != AUTH_REPORT_ID || bytes[1] != kind {
+            self.next_step = None;
+            return Err(crate::Error::Authentication {
+                step,
+                detail: AuthFailure::UnexpectedReply { bytes: bytes.to_vec() },
+            });
+        }
+        let payload =
+            [bytes[2], bytes[3], bytes[4], bytes[5], bytes[6], bytes[7], bytes[8], bytes[9]];
+
+        match step {
+            // Read the keyboard challenge (for keyboard to authenticate app)
+            AuthStep::ReadChallenge => {
+                self.challenge = u64::from_le_bytes(payload);
+                self.next_step = Some(AuthStep::SendChallenge);
+                Ok(None)
+            }
+            // Read the keyboard response
+            // Unless it is verified, ignore the result
+            AuthStep::ReadResponse => {
+                if let Some(host_challenge) = self.host_challenge {
+                    let expected = Self::response_for(host_challenge);
+                    let received = u64::from_le_bytes(payload);
+                    if received != expected {
+                        self.next_step = None;
+                        return Err(crate::Error::Authentication {
+                            step,
+                            detail: AuthFailure::ResponseMismatch { expected, received },
+                        });
+                    }
+                }
+                self.next_step = Some(AuthStep::SendResponse);
+                Ok(None)
+            }
+            // I "think" what gets returned here is the timeout after which auth
+            // needs to be done again (returns 600 for me which is plausible)
+            _ => {
+                self.next_step = None;
+                Ok(Some(u16::from_le_bytes([payload[0], payload[1]])))
+            }
+        }
+    }
+
+    /// Returns the response to the challenge of the device.
+    pub fn response_for(challenge: u64) -> u64 {
+        bmd_kbd_auth(challenge)
+    }
+}
+
+impl Default for AuthSession {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn bmd_kbd_auth(challenge: u64) -> u64 {
+    const AUTH_EVEN_TBL: [u64; 8] = [
+        0x3ae1206f97c10bc8,
+        0x2a9ab32bebf244c6,
+        0x20a6f8b8df9adf0a,
+        0xaf80ece52cfc1719,
+        0xec2ee2f7414fd151,
+        0xb055adfd73344a15,
+        0xa63d2e3059001187,
+        0x751bf623f42e0dde,
+    ];
+
+    const AUTH_ODD_TBL: [u64; 8] = [
+        0x3e22b34f502e7fde,
+        0x24656b981875ab1c,
+        0xa17f3456df7bf8c3,
+        0x6df72e1941aef698,
+        0x72226f011e66ab94,
+        0x3831a3c606296b42,
+        0xfd7ff81881332c89,
+        0x61a3f6474ff236c6,
+    ];
+
+    const MASK: u64 = 0xa79a63f585d37bf0;
+
+    let ror8n = |mut v: u64, n: usize| -> u64 {
+        for _ in 0..n {
+            v = v.rotate_right(8);
+        }
+        v
+    };
+
+    let n = (challenge & 7) as usize;
+    let mut v = ror8n(challenge, n);
+
+    let k = if (v & 1) == ((0x78 >> n) & 1) {
+        AUTH_EVEN_TBL[n]
+    } else {
+        v = v ^ v.rotate_right(8);
+        AUTH_ODD_TBL[n]
+    };
+
+    v ^ (v.rotate_right(8) & MASK) ^ k
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Challenges and responses computed with `bmd_kbd_auth` from smunaut/blackmagic-misc,
+    /// covering every rotation and both tables.
+    const KNOWN_ANSWERS: [(u64, u64); 13] = [
+        (0x0000000000000000, 0x3ae1206f97c10bc8),
+        (0x0000000000000001, 0x2b9ab32bebf244c6),
+        (0x0000000000000002, 0x20a4fab8df9adf0a),
+        (0x0000000000000003, 0x6df72d1b40aef698),
+        (0x0000000000000004, 0x72226f051e66ab94),
+        (0x0000000000000005, 0x3831a3c6032d6a42),
+        (0x0000000000000006, 0xfd7ff81881352889),
+        (0x0000000000000007, 0x751bf623f42e0ade),
+        (0x0123456789abcdef, 0xe5c7b689e9967608),
+        (0xfedcba9876543210, 0xc4a7da4761c769e8),
+        (0xffffffffffffffff, 0x61a3f6474ff236c6),
+        (0x8000000000000000, 0xba61206f97c10bc8),
+        (0xdeadbeefcafef00d, 0x6a04b6fcff2b4b21),
+    ];
+
+    fn reply(kind: u8, payload: u64) -> [u8; 10] {
+        let mut report = [AUTH_REPORT_ID, kind, 0, 0, 0, 0, 0, 0, 0, 0];
+        report[2..].copy_from_slice(&payload.to_le_bytes());
+        report
+    }
+
+    #[test]
+    fn response_matches_reference() {
+        for (challenge, response) in KNOWN_ANSWERS {
+            assert_eq!(AuthSession::response_for(challenge), response, "challenge {challenge:#x}");
+        }
+    }
+
+    #[test]
+    fn handshake_steps() {
+        let (challenge, response) = KNOWN_ANSWERS[8];
+        let mut session = AuthSession::new();
+
+        assert_eq!(session.expected_next_step(), Some(AuthStep::Reset));
+        assert_eq!(session.outgoing_report(), Some(reply(0x00, 0)));
+
+        assert_eq!(session.expected_next_step(), Some(AuthStep::ReadChallenge));
+        assert_eq!(session.outgoing_report(), None);
+        assert_eq!(session.handle_reply(&reply(0x00, challenge)).unwrap(), None);
+
+        assert_eq!(session.expected_next_step(), Some(AuthStep::SendChallenge));
+        assert_eq!(session.outgoing_report(), Some(reply(0x01, 0)));
+
+        assert_eq!(session.expected_next_step(), Some(AuthStep::ReadResponse));
+        // Without a host challenge, the response of the device is ignored.
+        assert_eq!(session.handle_reply(&reply(0x02, 0x1234)).unwrap(), None);
+
+        assert_eq!(session.expected_next_step(), Some(AuthStep::SendResponse));
+        assert_eq!(session.outgoing_report(), Some(reply(0x03, response)));
+
+        assert_eq!(session.expected_next_step(), Some(AuthStep::ReadStatus));
+        assert_eq!(session.handle_reply(&reply(0x04, 600)).unwrap(), Some(600));
+
+        assert_eq!(session.expected_next_step(), None);
+        assert_eq!(session.outgoing_report(), None);
+        assert!(session.handle_reply(&reply(0x04, 600)).is_err());
+    }
+
+    #[test]
+    fn host_challenge_is_verified() {
+        let (host_challenge, response) = KNOWN_ANSWERS[12];
+
+        let mut session = AuthSession::with_host_challenge(host_challenge);
+        session.outgoing_report();
+        session.handle_reply(&reply(0x00, 0)).unwrap();
+        assert_eq!(session.outgoing_report(), Some(reply(0x01, host_challenge)));
+        assert_eq!(session.handle_reply(&reply(0x02, response)).unwrap(), None);
+        assert_eq!(session.expected_next_step(), Some(AuthStep::SendResponse));
+
+        let mut session = AuthSession::with_host_challenge(host_challenge);
+        session.outgoing_report();
+        session.handle_reply(&reply(0x00, 0)).unwrap();
+        session.outgoing_report();
+        let error = session.handle_reply(&reply(0x02, !response)).unwrap_err();
+        assert!(matches!(
+            error,
+            crate::Error::Authentication {
+                step: AuthStep::ReadResponse,
+                detail: AuthFailure::ResponseMismatch { expected, received },
+            } if expected == response && received == !response
+        ));
+        assert_eq!(session.expected_next_step(), None);
+    }
+
+    #[test]
+    fn random_host_challenges_differ() {
+        let challenges: std::collections::HashSet<_> =
+            (0..16).map(|_| AuthSession::random_host_challenge()).collect();
+        assert_eq!(challenges.len(), 16);
+    }
+
+    #[test]
+    fn unexpected_reply_ends_handshake() {
+        let mut session = AuthSession::new();
+        session.outgoing_report();
+        // The reply of another step.
+        let error = session.handle_reply(&reply(0x02, 0)).unwrap_err();
+        assert!(matches!(
+            error,
+            crate::Error::Authentication {
+                step: AuthStep::ReadChallenge,
+                detail: AuthFailure::UnexpectedReply { .. },
+            }
+        ));
+        assert_eq!(session.expected_next_step(), None);
+
+        // A reply that is too short.
+        let mut session = AuthSession::new();
+        session.outgoing_report();
+        assert!(session.handle_reply(&reply(0x00, 0)[..9]).is_err());
+        assert_eq!(session.expected_next_step(), None);
+    }
+
+    #[test]
+    fn reply_before_sending_is_rejected() {
+        let mut session = AuthSession::new();
+        assert!(session.handle_reply(&reply(0x00, 0)).is_err());
+        // The handshake is not ended by calling it at the wrong time.
+        assert_eq!(session.expected_next_step(), Some(AuthStep::Reset));
+    }
+}
@@ -0,0 +1,67 @@
+use hidapi::{HidDevice, HidError};
+
+use crate::DeviceInfo;
+
+/// The operations on a HID device that are needed to talk to a Speed Editor.
+///
+/// This is implemented for [`HidDevice`], which is what [`SpeedEditor`][crate::SpeedEditor]
+/// uses normally. Other implementations make it possible to run the driver without hardware,
+/// see [`SpeedEditorBuilder::connect_backend`][crate::SpeedEditorBuilder::connect_backend].
+///
+/// The methods mirror those of [`HidDevice`].
+pub trait HidBackend: Send {
+    /// Returns information about the device.
+    fn device_info(&self) -> Result<DeviceInfo, HidError>;
+
+    /// Writes an output report, with the report ID as the first byte.
+    fn write(&self, data: &[u8]) -> Result<usize, HidError>;
+
+    /// Reads an input report into `buf`, waiting at most `timeout` milliseconds.
+    ///
+    /// Returns the length of the report, or `0` if no report arrived before the timeout.
+    fn read_timeout(&self, buf: &mut [u8], timeout: i32) -> Result<usize, HidError>;
+
+    /// Sends a feature report, with the report ID as the first byte.
+    fn send_feature_report(&self, data: &[u8]) -> Result<(), HidError>;
+
+    /// Gets the feature report with the report ID in the first byte of `buf`.
+    fn get_feature_report(&self, buf: &mut [u8]) -> Result<usize, HidError>;
+
+    /// Gets the input report with the report ID in the first byte of `buf`.
+    fn get_input_report(&self, buf: &mut [u8]) -> Result<usize, HidError>;
+}
+
+impl HidBackend for HidDevice {
+    fn device_info(&self) -> Result<DeviceInfo, HidError> {
+        self.get_device_info().map(|info| DeviceInfo::from(&info))
+    }
+
+    fn write(&self, data: &[u8]) -> Result<usize, HidError> {
+        HidDevice::write(self, data)
+    }
+
+    fn read_timeout(&self, buf: &mut [u8], timeout: i32) -> Result<usize, HidError> {
+        HidDevice::read_timeout(self, buf, timeout)
+    }
+
+    fn send_feature_report(&self, data: &[u8]) -> Result<(), HidError> {
+        HidDevice::send_feature_report(self, data)
+    }
+
+    fn get_feature_report(&self, buf: &mut [u8]) -> Result<usize, HidError> {
+        HidDevice::get_feature_report(self, buf)
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    fn get_input_report(&self, buf: &mut [u8]) -> Result<usize, HidError> {
+        HidDevice::get_input_report(self, buf)
+    }
+
+    // Not every Windows backend of hidapi can get input reports, see `driver::request_battery`.
+    #[cfg(target_os = "windows")]
+    fn get_input_report(&self, _buf: &mut [u8]) -> Result<usize, HidError> {
+        Err(HidError::HidApiError {
+            message: "getting input reports is not supported on Windows".to_string(),
+        })
+    }
+}
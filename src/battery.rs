@@ -0,0 +1,98 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+/// An estimate of how fast the battery discharges.
+///
+/// See [`SpeedEditor::battery_estimate`][crate::SpeedEditor::battery_estimate].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BatteryEstimate {
+    /// How many percentage points the battery loses per hour.
+    pub rate_per_hour: f32,
+    /// How long until the battery is empty at the current rate,
+    /// or [`None`] if the battery is not discharging.
+    pub time_remaining: Option<Duration>,
+}
+
+/// Estimates the discharge rate from the battery levels of the last [`Self::WINDOW`].
+#[derive(Debug, Default)]
+pub(crate) struct BatteryEstimator {
+    samples: VecDeque<(Instant, u8)>,
+    /// A level that was excluded for jumping too far.
+    /// If the next level agrees with it, the jump was real and the estimator starts over.
+    rejected: Option<u8>,
+}
+
+impl BatteryEstimator {
+    /// How far back samples are used.
+    const WINDOW: Duration = Duration::from_secs(30 * 60);
+    /// How much time the samples have to span before the estimate is meaningful.
+    const MIN_SPAN: Duration = Duration::from_secs(10 * 60);
+    /// How many samples are needed before the estimate is meaningful.
+    const MIN_SAMPLES: usize = 3;
+    /// Jumps between two levels bigger than this (in percentage points) are considered bogus.
+    const MAX_JUMP: u8 = 10;
+
+    pub(crate) fn record(&mut self, charging: bool, level: u8, at: Instant) {
+        // Charging periods say nothing about discharging.
+        if charging {
+            self.clear();
+            return;
+        }
+
+        if let Some(&(_, last_level)) = self.samples.back()
+            && last_level.abs_diff(level) > Self::MAX_JUMP
+        {
+            match self.rejected.take() {
+                Some(rejected) if rejected.abs_diff(level) <= Self::MAX_JUMP => self.clear(),
+                _ => {
+                    self.rejected = Some(level);
+                    return;
+                }
+            }
+        }
+
+        self.rejected = None;
+        self.samples.push_back((at, level));
+        while self.samples.front().is_some_and(|&(time, _)| at.duration_since(time) > Self::WINDOW)
+        {
+            self.samples.pop_front();
+        }
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.samples.clear();
+        self.rejected = None;
+    }
+
+    pub(crate) fn estimate(&self) -> Option<BatteryEstimate> {
+        let &(first_time, _) = self.samples.front()?;
+        let &(last_time, last_level) = self.samples.back()?;
+        if self.samples.len() < Self::MIN_SAMPLES
+            || last_time.duration_since(first_time) < Self::MIN_SPAN
+        {
+            return None;
+        }
+
+        // The slope of a least-squares fit of the level over time, in points per hour.
+        let points: Vec<(f32, f32)> = self
+            .samples
+            .iter()
+            .map(|&(time, level)| {
+                (time.duration_since(first_time).as_secs_f32() / 3600.0, level as f32)
+            })
+            .collect();
+        let n = points.len() as f32;
+        let mean_x = points.iter().map(|(x, _)| x).sum::<f32>() / n;
+        let mean_y = points.iter().map(|(_, y)| y).sum::<f32>() / n;
+        let covariance: f32 = points.iter().map(|(x, y)| (x - mean_x) * (y - mean_y)).sum();
+        let variance: f32 = points.iter().map(|(x, _)| (x - mean_x).powi(2)).sum();
+        let rate_per_hour = -covariance / variance;
+
+        let time_remaining = (rate_per_hour > 0.0)
+            .then(|| Duration::from_secs_f32(last_level as f32 / rate_per_hour * 3600.0));
+
+        Some(BatteryEstimate { rate_per_hour, time_remaining })
+    }
+}
@@ -0,0 +1,466 @@
+//! A command line tool to monitor and exercise a Speed Editor.
+//!
+//! Run `bmdse help` for its usage.
+
+use std::{
+    io,
+    process::ExitCode,
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use bmdse::{
+    Button, ButtonLed, DeviceInfo, Event, SpeedEditor, WheelLed,
+    jsonl::JsonLinesWriter,
+    mapping::{Action, Mapping, Trigger},
+};
+use serde_json::{Value, json};
+
+const USAGE: &str = "\
+Monitor and exercise a Speed Editor.
+
+Usage: bmdse [--json] [--serial <serial>] <command>
+
+Commands:
+  list                      List the attached devices
+  monitor                   Print every event with the seconds since starting,
+                            or as JSON Lines with a Unix timestamp with --json
+  battery                   Print the battery level
+  leds [<led>...]           Light a button LED and/or a wheel LED (e.g. `cam1 jog`),
+                            until interrupted. Without LEDs, all LEDs are turned off
+      --blink <ms>          Blink the LEDs on and off every <ms> milliseconds
+  test                      Check that every button and the jog wheel work
+  map <file>                Print the actions that a TOML mapping file triggers
+  help                      Print this help
+
+Options:
+  --json                    Print JSON (one object per line) instead of text
+  --serial <serial>         Open the device with this serial number
+";
+
+/// How long `battery` waits for a reading.
+const BATTERY_TIMEOUT: Duration = Duration::from_secs(5);
+
+struct Options {
+    json: bool,
+    serial: Option<String>,
+    command: String,
+    arguments: Vec<String>,
+}
+
+/// Parses the arguments, without the name of the program.
+fn parse_options(args: impl IntoIterator<Item = String>) -> Result<Options, String> {
+    let mut options =
+        Options { json: false, serial: None, command: String::new(), arguments: Vec::new() };
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--json" => options.json = true,
+            "--serial" => {
+                options.serial = Some(args.next().ok_or("--serial needs a serial number")?);
+            }
+            "-h" | "--help" => options.command = "help".to_string(),
+            _ if options.command.is_empty() && !arg.starts_with('-') => options.command = arg,
+            _ if !options.command.is_empty() => options.arguments.push(arg),
+            _ => return Err(format!("unknown option `{arg}`")),
+        }
+    }
+    if options.command.is_empty() {
+        return Err("no command given".to_string());
+    }
+    Ok(options)
+}
+
+fn main() -> ExitCode {
+    let options = match parse_options(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("error: {message}\n\n{USAGE}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let result = match options.command.as_str() {
+        "list" => list(&options),
+        "monitor" => monitor(&options),
+        "battery" => battery(&options),
+        "leds" => leds(&options),
+        "test" => test(&options),
+        "map" => map(&options),
+        "help" => {
+            print!("{USAGE}");
+            Ok(())
+        }
+        command => Err(format!("unknown command `{command}`, see `bmdse help`")),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(message) => {
+            if options.json {
+                println!("{}", json!({ "error": message }));
+            } else {
+                eprintln!("error: {message}");
+            }
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn open(options: &Options) -> Result<SpeedEditor, String> {
+    let speed_editor = match &options.serial {
+        Some(serial) => SpeedEditor::open_serial(serial),
+        None => SpeedEditor::new(),
+    };
+    speed_editor.map_err(|error| error.to_string())
+}
+
+fn device_json(device: &DeviceInfo) -> Value {
+    json!({
+        "path": device.path,
+        "serial_number": device.serial_number,
+        "product": device.product,
+        "model": format!("{:?}", device.model),
+        "transport": format!("{:?}", device.transport),
+        "release_number": device.release_number,
+    })
+}
+
+fn list(options: &Options) -> Result<(), String> {
+    let devices = bmdse::list_devices().map_err(|error| error.to_string())?;
+    if options.json {
+        println!("{}", Value::Array(devices.iter().map(device_json).collect()));
+        return Ok(());
+    }
+    if devices.is_empty() {
+        println!("No devices found.");
+    }
+    for device in devices {
+        println!(
+            "{:?} (serial {}, {:?}) at {}",
+            device.model,
+            device.serial_number.as_deref().unwrap_or("unknown"),
+            device.transport,
+            device.path,
+        );
+    }
+    Ok(())
+}
+
+/// Returns the event as a line of text.
+fn event_text(event: &Event) -> String {
+    match event {
+        Event::WheelChange { velocity } => format!("wheel {velocity}"),
+        Event::ButtonChange { button, pressed: true } => format!("button {button:?} pressed"),
+        Event::ButtonChange { button, pressed: false } => format!("button {button:?} released"),
+        Event::EditorKeyboardButtonChange { button, pressed: true } => {
+            format!("key {:#06x} pressed", button.code())
+        }
+        Event::EditorKeyboardButtonChange { button, pressed: false } => {
+            format!("key {:#06x} released", button.code())
+        }
+        Event::BatteryInfo { charging, percentage } => {
+            format!("battery {percentage}%{}", if *charging { " (charging)" } else { "" })
+        }
+        Event::ChargingChange { charging: true } => "charging started".to_string(),
+        Event::ChargingChange { charging: false } => "charging stopped".to_string(),
+        Event::UnknownReport { id, data } => format!("unknown report {id:#04x} {data:02x?}"),
+        Event::Connected(device) => format!(
+            "connected to {:?} (serial {})",
+            device.model,
+            device.serial_number.as_deref().unwrap_or("unknown")
+        ),
+        Event::Disconnected => "disconnected".to_string(),
+    }
+}
+
+fn monitor(options: &Options) -> Result<(), String> {
+    let speed_editor = open(options)?;
+    if options.json {
+        // Written in the schema of the `jsonl` module, so it can be piped into other tools.
+        let writer = JsonLinesWriter::new(io::stdout()).map_err(|error| error.to_string())?;
+        speed_editor.attach_sink(Box::new(writer));
+        return speed_editor.run().map_err(|error| error.to_string());
+    }
+
+    let (sender, receiver) = mpsc::channel();
+    speed_editor.attach_sink(Box::new(sender));
+
+    let start = Instant::now();
+    // The channel closes when the polling thread stops.
+    for event in receiver {
+        let time = start.elapsed().as_secs_f64();
+        println!("[{time:10.3}] {}", event_text(&event));
+    }
+    speed_editor.shutdown().map_err(|error| error.to_string())
+}
+
+fn battery(options: &Options) -> Result<(), String> {
+    let speed_editor = open(options)?;
+    let (sender, receiver) = mpsc::channel();
+    speed_editor.attach_sink(Box::new(sender));
+    speed_editor.request_battery_update().map_err(|error| error.to_string())?;
+
+    let deadline = Instant::now() + BATTERY_TIMEOUT;
+    let (charging, level) = loop {
+        if let Some(info) = speed_editor.battery_info() {
+            break (info.charging, info.level);
+        }
+        let timeout = deadline.saturating_duration_since(Instant::now());
+        match receiver.recv_timeout(timeout) {
+            Ok(Event::BatteryInfo { charging, percentage }) => break (charging, percentage),
+            Ok(_) => {}
+            Err(_) => return Err("no battery reading received (it might have no battery)".into()),
+        }
+    };
+
+    if options.json {
+        println!("{}", json!({ "level": level, "charging": charging }));
+    } else {
+        println!("{level}%{}", if charging { " (charging)" } else { "" });
+    }
+    Ok(())
+}
+
+/// Parses the name of a [`ButtonLed`] or [`WheelLed`], ignoring case.
+fn parse_led(name: &str) -> Result<(Option<ButtonLed>, Option<WheelLed>), String> {
+    let matches = |led: String| led.eq_ignore_ascii_case(name);
+    if let Some(led) = (0..18)
+        .filter_map(|bit| ButtonLed::try_from(1 << bit).ok())
+        .find(|led| matches(format!("{led:?}")))
+    {
+        return Ok((Some(led), None));
+    }
+    if let Some(led) = (0..3)
+        .filter_map(|bit| WheelLed::try_from(1 << bit).ok())
+        .find(|led| matches(format!("{led:?}")))
+    {
+        return Ok((None, Some(led)));
+    }
+    Err(format!("unknown LED `{name}`"))
+}
+
+/// Parses the arguments of `leds` into the LEDs to light, and the interval to blink them at.
+fn parse_leds(arguments: &[String]) -> Result<(ButtonLed, WheelLed, Option<Duration>), String> {
+    let mut button_led = ButtonLed::Off;
+    let mut wheel_led = WheelLed::Off;
+    let mut blink = None;
+    let mut arguments = arguments.iter();
+    while let Some(argument) = arguments.next() {
+        if argument == "--blink" {
+            let interval = arguments.next().ok_or("--blink needs an interval")?;
+            let interval =
+                interval.parse().map_err(|_| format!("invalid interval `{interval}`"))?;
+            blink = Some(Duration::from_millis(interval));
+            continue;
+        }
+        match parse_led(argument)? {
+            (Some(led), _) if button_led == ButtonLed::Off => button_led = led,
+            (_, Some(led)) if wheel_led == WheelLed::Off => wheel_led = led,
+            _ => return Err("only one button LED and one wheel LED can be lit at once".into()),
+        }
+    }
+    Ok((button_led, wheel_led, blink))
+}
+
+fn leds(options: &Options) -> Result<(), String> {
+    let (button_led, wheel_led, blink) = parse_leds(&options.arguments)?;
+
+    let speed_editor = open(options)?;
+    speed_editor.set_button_led(button_led);
+    speed_editor.set_wheel_led(wheel_led);
+    let Some(interval) = blink else {
+        return speed_editor.run().map_err(|error| error.to_string());
+    };
+
+    let mut on = true;
+    while !speed_editor.is_stopped() {
+        thread::sleep(interval);
+        on = !on;
+        speed_editor.set_button_led(if on { button_led } else { ButtonLed::Off });
+        speed_editor.set_wheel_led(if on { wheel_led } else { WheelLed::Off });
+    }
+    speed_editor.shutdown().map_err(|error| error.to_string())
+}
+
+fn test(options: &Options) -> Result<(), String> {
+    let speed_editor = open(options)?;
+    let (sender, receiver) = mpsc::channel();
+    speed_editor.attach_sink(Box::new(sender));
+
+    // The button values are all below 0x40.
+    let mut buttons: Vec<Button> =
+        (0..0x40).filter_map(|value| Button::try_from(value).ok()).collect();
+    let mut wheel_left = [("clockwise", true), ("counterclockwise", false)].to_vec();
+    let report = |what: String, left: usize| {
+        if options.json {
+            println!("{}", json!({ "ok": what, "left": left }));
+        } else {
+            println!("ok: {what} ({left} left)");
+        }
+    };
+
+    if !options.json {
+        println!("Press every button and turn the jog wheel both ways. Left to check:");
+        let names: Vec<String> = buttons.iter().map(|button| format!("{button:?}")).collect();
+        println!("  {}, and the jog wheel", names.join(", "));
+    }
+    while !buttons.is_empty() || !wheel_left.is_empty() {
+        let event = receiver.recv().map_err(|_| "the device stopped before the test finished")?;
+        match event {
+            Event::ButtonChange { button, pressed: true } if buttons.contains(&button) => {
+                buttons.retain(|left| *left != button);
+                report(format!("{button:?}"), buttons.len() + wheel_left.len());
+            }
+            Event::WheelChange { velocity } if velocity != 0 => {
+                let Some(index) = wheel_left.iter().position(|(_, up)| *up == (velocity > 0))
+                else {
+                    continue;
+                };
+                let (direction, _) = wheel_left.remove(index);
+                report(format!("jog wheel {direction}"), buttons.len() + wheel_left.len());
+            }
+            _ => {}
+        }
+    }
+    if !options.json {
+        println!("All buttons and the jog wheel work.");
+    }
+    Ok(())
+}
+
+fn action_json(action: &Action) -> Value {
+    let (trigger, button, value) = match action.trigger {
+        Trigger::Press(button) => ("press", Some(format!("{button:?}")), None),
+        Trigger::Release(button) => ("release", Some(format!("{button:?}")), None),
+        Trigger::LongPress(button) => ("long_press", Some(format!("{button:?}")), None),
+        Trigger::Wheel(value) => ("wheel", None, Some(value)),
+    };
+    json!({
+        "action": action.name,
+        "trigger": trigger,
+        "button": button,
+        "value": value,
+        "layer": action.layer,
+    })
+}
+
+fn map(options: &Options) -> Result<(), String> {
+    let [path] = options.arguments.as_slice() else {
+        return Err("map needs the path of a mapping file".into());
+    };
+    let mapping = Mapping::load(path).map_err(|error| error.to_string())?;
+    let speed_editor = open(options)?;
+    let (_engine, receiver) =
+        mapping.start_channel(&speed_editor).map_err(|error| error.to_string())?;
+
+    let start = Instant::now();
+    for action in receiver {
+        let time = start.elapsed().as_secs_f64();
+        if options.json {
+            let mut value = action_json(&action);
+            value["time"] = json!(time);
+            println!("{value}");
+        } else {
+            let layer = action.layer.map(|layer| format!(" in layer {layer}")).unwrap_or_default();
+            println!("[{time:10.3}] {} ({:?}){layer}", action.name, action.trigger);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Options, String> {
+        parse_options(args.iter().map(|arg| arg.to_string()))
+    }
+
+    fn arguments(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn options_before_and_after_command() {
+        let options =
+            parse(&["--json", "--serial", "ABC123", "leds", "cam1", "--blink", "100"]).unwrap();
+        assert!(options.json);
+        assert_eq!(options.serial.as_deref(), Some("ABC123"));
+        assert_eq!(options.command, "leds");
+        assert_eq!(options.arguments, ["cam1", "--blink", "100"]);
+
+        let options = parse(&["monitor", "--json"]).unwrap();
+        assert!(options.json);
+        assert_eq!(options.command, "monitor");
+        assert!(options.arguments.is_empty());
+    }
+
+    #[test]
+    fn help_flags() {
+        assert_eq!(parse(&["-h"]).unwrap().command, "help");
+        assert_eq!(parse(&["--help"]).unwrap().command, "help");
+        assert_eq!(parse(&["help"]).unwrap().command, "help");
+    }
+
+    #[test]
+    fn invalid_options() {
+        assert_eq!(parse(&[]).err().unwrap(), "no command given");
+        assert_eq!(parse(&["--json"]).err().unwrap(), "no command given");
+        assert_eq!(parse(&["--serial"]).err().unwrap(), "--serial needs a serial number");
+        assert_eq!(parse(&["--verbose", "list"]).err().unwrap(), "unknown option `--verbose`");
+    }
+
+    #[test]
+    fn led_names_ignore_case() {
+        assert_eq!(parse_led("cam1"), Ok((Some(ButtonLed::Cam1), None)));
+        assert_eq!(parse_led("SmoothCut"), Ok((Some(ButtonLed::SmoothCut), None)));
+        assert_eq!(parse_led("AUDIOONLY"), Ok((Some(ButtonLed::AudioOnly), None)));
+        assert_eq!(parse_led("jog"), Ok((None, Some(WheelLed::Jog))));
+        assert_eq!(parse_led("Scroll"), Ok((None, Some(WheelLed::Scroll))));
+        assert_eq!(parse_led("off"), Err("unknown LED `off`".to_string()));
+        assert_eq!(parse_led("cam10"), Err("unknown LED `cam10`".to_string()));
+    }
+
+    #[test]
+    fn leds_arguments() {
+        assert_eq!(parse_leds(&[]), Ok((ButtonLed::Off, WheelLed::Off, None)));
+        assert_eq!(
+            parse_leds(&arguments(&["jog", "--blink", "250", "cut"])),
+            Ok((ButtonLed::Cut, WheelLed::Jog, Some(Duration::from_millis(250))))
+        );
+        assert_eq!(
+            parse_leds(&arguments(&["cut", "cam1"])),
+            Err("only one button LED and one wheel LED can be lit at once".to_string())
+        );
+        assert_eq!(
+            parse_leds(&arguments(&["--blink"])),
+            Err("--blink needs an interval".to_string())
+        );
+        assert_eq!(
+            parse_leds(&arguments(&["--blink", "fast"])),
+            Err("invalid interval `fast`".to_string())
+        );
+    }
+
+    #[test]
+    fn event_lines() {
+        let lines = [
+            (Event::WheelChange { velocity: -3 }, "wheel -3"),
+            (Event::ButtonChange { button: Button::Cam1, pressed: true }, "button Cam1 pressed"),
+            (Event::ButtonChange { button: Button::Cut, pressed: false }, "button Cut released"),
+            (Event::BatteryInfo { charging: false, percentage: 80 }, "battery 80%"),
+            (Event::BatteryInfo { charging: true, percentage: 42 }, "battery 42% (charging)"),
+            (Event::ChargingChange { charging: true }, "charging started"),
+            (Event::ChargingChange { charging: false }, "charging stopped"),
+            (
+                Event::UnknownReport { id: 0x09, data: vec![0x01, 0xff] },
+                "unknown report 0x09 [01, ff]",
+            ),
+            (Event::Disconnected, "disconnected"),
+        ];
+        for (event, line) in lines {
+            assert_eq!(event_text(&event), line);
+        }
+    }
+}
@@ -0,0 +1,638 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use hidapi::{HidApi, HidDevice};
+
+use crate::{
+    AuthRetryPolicy, BatteryLevelPolicy, Button, ButtonLed, CancellationToken, DeviceInfo,
+    EventSink, HidBackend, Inner, PollerError, ReconnectPolicy, RestartPolicy, ShutdownPolicy,
+    SpeedEditor, SpeedEditorState, SpeedEditorSync, StandbyPolicy, StateDiff, ThreadPriority,
+    WheelLed, WheelMode,
+    driver::{self, DeviceSelector, HidApiSource},
+    poller::PollOptions,
+    session::ResumedAuth,
+    thread::ThreadOptions,
+    wait::{self, OpenRetry},
+};
+
+/// A builder to configure a [`SpeedEditor`] before connecting to it.
+///
+/// Callbacks registered here are in place before the polling thread starts,
+/// so no events (like the first [`on_connect`][SpeedEditorBuilder::on_connect]) can be missed.
+///
+/// # Example
+///
+/// ```no_run
+/// use bmdse::{ButtonLed, SpeedEditor};
+///
+/// // Returns immediately, even if no Speed Editor is plugged in yet.
+/// let speed_editor = SpeedEditor::builder()
+///     .button_led(ButtonLed::Cam1)
+///     .on_connect(|device_info| {
+///         eprintln!("connected: {device_info:?}");
+///     })
+///     .on_button_change(|button, pressed| {
+///         eprintln!("button {button:?} {}", if pressed { "pressed" } else { "released" });
+///     })
+///     .build()
+///     .unwrap();
+/// ```
+#[derive(Default)]
+pub struct SpeedEditorBuilder {
+    inner: Inner,
+    thread: ThreadOptions,
+    poll: PollOptions,
+    open_retry: OpenRetry,
+}
+
+impl SpeedEditorBuilder {
+    /// Provide a callback to handle a change of the jog wheel,
+    /// with its parameter being the wheel's velocity.
+    pub fn on_wheel_change<F: Fn(i32) + Send + 'static>(mut self, f: F) -> Self {
+        self.inner.dispatch.update(move |callbacks| callbacks.on_wheel_change = Some(Box::new(f)));
+        self
+    }
+
+    /// Provide a callback to handle a press or release of a button,
+    /// with its first parameter being the button,
+    /// and its second parameter telling if it's pressed (`true`) or released (`false`).
+    pub fn on_button_change<F: Fn(Button, bool) + Send + 'static>(mut self, f: F) -> Self {
+        self.inner.dispatch.update(move |callbacks| callbacks.on_button_change = Some(Box::new(f)));
+        self
+    }
+
+    /// Provide a callback to handle battery info,
+    /// with it's first parameter telling if it's charging, and the second parameter being
+    /// the battery percentage (`0..=100`).
+    pub fn on_battery_info<F: Fn(bool, u8) + Send + 'static>(mut self, f: F) -> Self {
+        self.inner.dispatch.update(move |callbacks| callbacks.on_battery_info = Some(Box::new(f)));
+        self
+    }
+
+    /// Provide a callback to handle the device starting or stopping charging.
+    ///
+    /// See [`SpeedEditor::on_charging_change`].
+    pub fn on_charging_change<F: Fn(bool) + Send + 'static>(mut self, f: F) -> Self {
+        self.inner
+            .dispatch
+            .update(move |callbacks| callbacks.on_charging_change = Some(Box::new(f)));
+        self
+    }
+
+    /// Provide a callback to inspect every report exactly as it was read from the device.
+    ///
+    /// See [`SpeedEditor::on_raw_report`].
+    pub fn on_raw_report<F: Fn(&[u8]) + Send + 'static>(mut self, f: F) -> Self {
+        self.inner.dispatch.update(move |callbacks| callbacks.on_raw_report = Some(Box::new(f)));
+        self
+    }
+
+    /// Provide a callback to handle the device being connected,
+    /// with its parameter being information about the device.
+    pub fn on_connect<F: Fn(DeviceInfo) + Send + 'static>(mut self, f: F) -> Self {
+        self.inner.dispatch.update(move |callbacks| callbacks.on_connect = Some(Box::new(f)));
+        self
+    }
+
+    /// Provide a callback to handle the device being disconnected.
+    pub fn on_disconnect<F: Fn() + Send + 'static>(mut self, f: F) -> Self {
+        self.inner.dispatch.update(move |callbacks| callbacks.on_disconnect = Some(Box::new(f)));
+        self
+    }
+
+    /// Provide a callback that is called once when nobody touched the device for `threshold`.
+    ///
+    /// See [`SpeedEditor::on_idle`].
+    pub fn on_idle<F: Fn() + Send + 'static>(mut self, threshold: Duration, f: F) -> Self {
+        self.inner.dispatch.update(move |callbacks| callbacks.on_idle = Some(Box::new(f)));
+        self.inner.idle.set_threshold(Some(threshold));
+        self
+    }
+
+    /// Provide a callback that is called on the first input after the device became idle.
+    ///
+    /// See [`SpeedEditor::on_active`].
+    pub fn on_active<F: Fn() + Send + 'static>(mut self, f: F) -> Self {
+        self.inner.dispatch.update(move |callbacks| callbacks.on_active = Some(Box::new(f)));
+        self
+    }
+
+    /// Provide a callback that is called with everything that changed per pass of the polling thread.
+    ///
+    /// See [`SpeedEditor::on_state_change`].
+    pub fn on_state_change<F: Fn(&StateDiff) + Send + 'static>(mut self, f: F) -> Self {
+        self.inner.dispatch.update(move |callbacks| callbacks.on_state_change = Some(Box::new(f)));
+        self
+    }
+
+    /// Provide a callback to handle errors that occur in the polling thread.
+    ///
+    /// See [`SpeedEditor::on_error`].
+    pub fn on_error<F: Fn(&PollerError) + Send + 'static>(mut self, f: F) -> Self {
+        self.inner.dispatch.update(move |callbacks| callbacks.on_error = Some(Box::new(f)));
+        self
+    }
+
+    /// Attach an [`EventSink`] that will receive all [`Event`][crate::Event]s.
+    ///
+    /// See [`SpeedEditor::attach_sink`].
+    pub fn sink(mut self, sink: Box<dyn EventSink>) -> Self {
+        self.inner.dispatch.update(move |callbacks| callbacks.sinks.push(sink));
+        self
+    }
+
+    /// Request the battery state from the device every `interval`.
+    ///
+    /// See [`SpeedEditor::set_battery_poll_interval`].
+    pub fn battery_poll_interval(mut self, interval: Option<Duration>) -> Self {
+        self.inner.battery_poll_interval = interval;
+        self
+    }
+
+    /// Check whether buttons that are held for longer than `timeout` are still pressed.
+    ///
+    /// See [`SpeedEditor::set_stuck_button_timeout`].
+    pub fn stuck_button_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.inner.stuck_button_timeout = timeout;
+        self
+    }
+
+    /// Keep a wireless connection active by writing to the device every `interval`.
+    ///
+    /// See [`SpeedEditor::set_keepalive`].
+    pub fn keepalive(mut self, interval: Option<Duration>) -> Self {
+        self.inner.keepalive = interval;
+        self
+    }
+
+    /// Set the button LED that is enabled once the device is connected.
+    pub fn button_led(mut self, led: ButtonLed) -> Self {
+        self.inner.button_led = led;
+        self
+    }
+
+    /// Set the wheel LED that is enabled once the device is connected.
+    pub fn wheel_led(mut self, led: WheelLed) -> Self {
+        self.inner.wheel_led = led;
+        self
+    }
+
+    /// Set the wheel mode once the device is connected, see [`SpeedEditor::set_wheel_mode`].
+    pub fn wheel_mode(mut self, mode: WheelMode) -> Self {
+        self.inner.wheel_mode = mode;
+        self
+    }
+
+    /// Only open the Speed Editor with the given serial number.
+    ///
+    /// This is also used when reconnecting, so the [`SpeedEditor`] stays bound to the same device.
+    /// See [`DeviceInfo::serial_number`] and [`list_devices`][crate::list_devices].
+    pub fn serial_number(mut self, serial_number: impl Into<String>) -> Self {
+        self.inner.open_options.selector = DeviceSelector::SerialNumber(serial_number.into());
+        self
+    }
+
+    /// Only open the Speed Editor at the given platform-specific path.
+    ///
+    /// This is useful when serial numbers are not available (e.g. over Bluetooth),
+    /// or to bind to a specific physical port.
+    /// See [`DeviceInfo::path`] and [`list_devices`][crate::list_devices].
+    pub fn path(mut self, path: impl Into<String>) -> Self {
+        self.inner.open_options.selector = DeviceSelector::Path(path.into());
+        self
+    }
+
+    /// Look for devices with the given USB vendor and product ID,
+    /// instead of the IDs of the supported [`Model`][crate::Model]s.
+    ///
+    /// This is unsupported hardware territory, meant for devices that speak the exact same
+    /// protocol as the Speed Editor (e.g. pre-production units, future revisions or OEM variants).
+    /// Authentication, report parsing and LEDs work exactly like for a Speed Editor,
+    /// with no guarantee that the device understands them.
+    /// See [`VENDOR_ID`][crate::VENDOR_ID] and
+    /// [`SPEED_EDITOR_PRODUCT_ID`][crate::SPEED_EDITOR_PRODUCT_ID] for the built-in IDs.
+    pub fn device_ids(mut self, vendor_id: u16, product_id: u16) -> Self {
+        self.inner.open_options.ids = Some((vendor_id, product_id));
+        self
+    }
+
+    /// Use an [`HidApi`] owned by your application, instead of the one shared by this crate.
+    ///
+    /// This is useful if your application also talks to other HID devices.
+    /// The lock is only held while opening the device (or while looking for it).
+    pub fn hid_api(mut self, api: Arc<Mutex<HidApi>>) -> Self {
+        self.inner.hid_api = HidApiSource::External(api);
+        self
+    }
+
+    /// Set how the device should be (re)connected.
+    ///
+    /// See [`SpeedEditor::set_reconnect_policy`].
+    pub fn reconnect_policy(mut self, policy: ReconnectPolicy) -> Self {
+        self.inner.reconnect_policy = policy;
+        self
+    }
+
+    /// Set how often the periodic re-authentication should be retried before giving up.
+    pub fn auth_retry_policy(mut self, policy: AuthRetryPolicy) -> Self {
+        self.inner.auth_retry_policy = policy;
+        self
+    }
+
+    /// Set how long before its authentication expires the device is authenticated again.
+    ///
+    /// A larger margin leaves more time to retry when the connection is unreliable
+    /// (e.g. over Bluetooth). The margin is at most half of the timeout returned by the device.
+    /// Defaults to 5 seconds.
+    pub fn auth_margin(mut self, margin: Duration) -> Self {
+        self.inner.auth_margin = Some(margin);
+        self
+    }
+
+    /// Authenticate the device again after `timeout` (minus the
+    /// [margin][SpeedEditorBuilder::auth_margin]), if the device returns a longer timeout.
+    ///
+    /// The device returns how long its authentication lasts (usually 600 seconds),
+    /// which is used by default. A shorter timeout is only needed if a device does not
+    /// keep its promise. The timeout of the device is used if it is shorter.
+    pub fn auth_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.inner.auth_timeout = timeout;
+        self
+    }
+
+    /// Set what to do with battery reports with a level above 100%.
+    pub fn battery_level_policy(mut self, policy: BatteryLevelPolicy) -> Self {
+        self.inner.battery_level_policy = policy;
+        self
+    }
+
+    /// Set whether the poller should be restarted when it stops because of an error or a panic.
+    pub fn restart_policy(mut self, policy: RestartPolicy) -> Self {
+        self.inner.restart_policy = policy;
+        self
+    }
+
+    /// Set what should happen to the device when the polling thread stops.
+    pub fn shutdown_policy(mut self, policy: ShutdownPolicy) -> Self {
+        self.inner.shutdown_policy = policy;
+        self
+    }
+
+    /// Set what should happen to the LEDs when nobody touched the device for a while.
+    ///
+    /// See [`SpeedEditor::standby_policy`].
+    pub fn standby_policy(mut self, policy: StandbyPolicy) -> Self {
+        self.inner.set_standby_policy(policy);
+        self
+    }
+
+    /// Set the name of the polling thread.
+    ///
+    /// Defaults to `bmd_speed_editor_poller`.
+    pub fn thread_name(mut self, name: impl Into<String>) -> Self {
+        self.thread.name = name.into();
+        self
+    }
+
+    /// Set the scheduling priority of the polling thread.
+    ///
+    /// This is best-effort: raising the priority usually requires extra privileges,
+    /// and if it cannot be set the thread keeps running with its default priority.
+    /// The failure is reported through [`on_error`][SpeedEditorBuilder::on_error].
+    pub fn thread_priority(mut self, priority: ThreadPriority) -> Self {
+        self.thread.priority = priority;
+        self
+    }
+
+    /// Pin the polling thread to the given CPU cores.
+    ///
+    /// This is best-effort, and only supported on Linux.
+    pub fn thread_affinity(mut self, cores: impl IntoIterator<Item = usize>) -> Self {
+        self.thread.affinity = Some(cores.into_iter().collect());
+        self
+    }
+
+    /// Set how long a single read from the device waits for a report,
+    /// while the device is in use.
+    ///
+    /// LED changes are written to the device between reads, and a shutdown or pause is noticed
+    /// between reads, so a shorter timeout makes these more responsive.
+    /// A longer timeout wakes up the polling thread less often, which uses less CPU.
+    /// Reports are always handled as soon as they arrive, regardless of the timeout.
+    /// See also [`SpeedEditorBuilder::idle_poll_timeout`].
+    ///
+    /// Defaults to 16 milliseconds. It must be between 1 millisecond and 1 second,
+    /// otherwise creating the [`SpeedEditor`] returns
+    /// [`Error::InvalidConfiguration`][crate::Error::InvalidConfiguration].
+    pub fn poll_timeout(mut self, timeout: Duration) -> Self {
+        self.poll.timeout = timeout;
+        self
+    }
+
+    /// Set how long a single read from the device waits for a report,
+    /// while nothing happened for a second.
+    ///
+    /// When no reports arrived and no LEDs were written for a second, the polling thread waits
+    /// in longer reads, so it hardly uses any CPU while the device is not used.
+    /// The first report or LED change switches back to the [poll timeout][Self::poll_timeout].
+    /// Scheduled work, like authenticating again, cuts a read short.
+    /// LED changes, a pause or a shutdown can take up to this long to be noticed while idle.
+    ///
+    /// Defaults to 250 milliseconds, and is never shorter than the poll timeout.
+    /// It must be between 1 millisecond and 1 second, otherwise creating the [`SpeedEditor`]
+    /// returns [`Error::InvalidConfiguration`][crate::Error::InvalidConfiguration].
+    pub fn idle_poll_timeout(mut self, timeout: Duration) -> Self {
+        self.poll.idle_timeout = timeout;
+        self
+    }
+
+    /// Sleep for the given duration between every read from the device.
+    ///
+    /// This saves power (e.g. on a laptop running on battery), at the cost of latency:
+    /// reports that arrive while sleeping are only handled after waking up.
+    ///
+    /// Defaults to [`None`]. It must be at most 1 second,
+    /// otherwise creating the [`SpeedEditor`] returns
+    /// [`Error::InvalidConfiguration`][crate::Error::InvalidConfiguration].
+    pub fn poll_sleep(mut self, sleep: Option<Duration>) -> Self {
+        self.poll.sleep = sleep;
+        self
+    }
+
+    /// Set whether the device is opened exclusively.
+    ///
+    /// Opening it non-exclusively lets multiple applications (e.g. DaVinci Resolve and a
+    /// monitoring tool) use the device at the same time, and all of them receive every report.
+    /// Only one of them should write the LEDs though,
+    /// so consider enabling [observer mode][SpeedEditorBuilder::observer] as well.
+    ///
+    /// Only macOS opens devices exclusively by default. The other platforms always share them,
+    /// so passing `true` there makes creating the [`SpeedEditor`] return
+    /// [`Error::Unsupported`][crate::Error::Unsupported].
+    /// On macOS this setting applies to the whole process, for all devices opened after it.
+    pub fn exclusive(mut self, exclusive: bool) -> Self {
+        self.inner.open_options.exclusive = Some(exclusive);
+        self
+    }
+
+    /// Never write the LEDs, to only observe the device while another application controls it.
+    ///
+    /// The LED state can still be changed, but it is not written to the device.
+    /// This also skips [`ShutdownPolicy::ClearLeds`]. Defaults to `false`.
+    pub fn observer(mut self, observer: bool) -> Self {
+        self.inner.observer = observer;
+        self
+    }
+
+    /// Verify that the device is genuine while authenticating it.
+    ///
+    /// Normally only the device checks us. With this, we send it a random challenge as well,
+    /// and fail with [`AuthFailure::ResponseMismatch`][crate::AuthFailure::ResponseMismatch]
+    /// if its response is wrong. Defaults to `false`, as this is not needed to use the device.
+    ///
+    /// See [`AuthSession::with_host_challenge`][crate::protocol::AuthSession::with_host_challenge]
+    /// to choose the challenge yourself.
+    pub fn verify_device(mut self, verify_device: bool) -> Self {
+        self.inner.verify_device = verify_device;
+        self
+    }
+
+    /// Retry opening the device when it fails, e.g. because it was just plugged in
+    /// and the operating system is still setting it up.
+    ///
+    /// After the first attempt, opening is retried up to `retries` times, waiting `delay` before
+    /// the first retry and doubling it for every following one (up to 5 seconds).
+    /// Errors that retrying cannot fix, like [`Error::AlreadyOpen`][crate::Error::AlreadyOpen],
+    /// are returned right away. When all attempts failed, the last error is returned.
+    /// Use [`SpeedEditorBuilder::on_open_error`] to see the error of every attempt.
+    ///
+    /// This applies to [`connect`][Self::connect] and [`connect_sync`][Self::connect_sync].
+    /// The latter also retries when authenticating the device fails,
+    /// while the polling thread of a [`SpeedEditor`] retries that according to
+    /// the [`ReconnectPolicy`]. Defaults to no retries.
+    pub fn open_retries(mut self, retries: u32, delay: Duration) -> Self {
+        self.open_retry.retries = retries;
+        self.open_retry.delay = delay;
+        self
+    }
+
+    /// Set how long opening the device may take, including all [retries][Self::open_retries].
+    ///
+    /// No retry is started that would end after the timeout. Defaults to [`None`].
+    pub fn open_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.open_retry.timeout = timeout;
+        self
+    }
+
+    /// Provide a callback that is called when an attempt to open the device failed,
+    /// with its first parameter being the number of the attempt (starting at 1),
+    /// and its second parameter the error.
+    ///
+    /// See [`SpeedEditorBuilder::open_retries`].
+    pub fn on_open_error<F: Fn(u32, &crate::Error) + Send + 'static>(mut self, f: F) -> Self {
+        self.open_retry.on_error = Some(Box::new(f));
+        self
+    }
+
+    /// Creates the [`SpeedEditor`] without waiting for the device.
+    ///
+    /// The polling thread opens the device in the background according to the [`ReconnectPolicy`],
+    /// and calls [`on_connect`][SpeedEditorBuilder::on_connect] once it is connected.
+    /// With [`ReconnectPolicy::Never`], only a single attempt is made.
+    ///
+    /// # Errors
+    ///
+    /// This function only errors if the configuration is invalid,
+    /// or if the polling thread could not be spawned.
+    pub fn build(self) -> Result<SpeedEditor, crate::Error> {
+        self.validate()?;
+        SpeedEditor::spawn(self.inner, self.thread, self.poll, None)
+    }
+
+    /// Opens the device and creates the [`SpeedEditor`].
+    ///
+    /// # Errors
+    ///
+    /// This function might error when getting the HID device
+    /// (cannot be found, HID API cannot be initialized, etc.).
+    /// If no matching device is connected,
+    /// [`Error::HidDeviceNotFound`][crate::Error::HidDeviceNotFound] is returned.
+    /// If it is connected but could not be opened,
+    /// [`Error::CannotOpenHidDevice`][crate::Error::CannotOpenHidDevice] is returned
+    /// (or [`Error::PermissionDenied`][crate::Error::PermissionDenied] on Linux).
+    /// If it is already opened by another [`SpeedEditor`] in this process,
+    /// [`Error::AlreadyOpen`][crate::Error::AlreadyOpen] is returned.
+    /// The configuration is validated before the device is opened.
+    /// Opening is retried according to [`SpeedEditorBuilder::open_retries`].
+    pub fn connect(self) -> Result<SpeedEditor, crate::Error> {
+        self.validate()?;
+        let hid_device = self
+            .open_retry
+            .run(|| driver::get_hid_device(&self.inner.hid_api, &self.inner.open_options))?;
+        SpeedEditor::spawn(self.inner, self.thread, self.poll, Some(Box::new(hid_device)))
+    }
+
+    /// Opens the device and creates a [`SpeedEditorSync`], which is polled by the calling thread
+    /// instead of a polling thread.
+    ///
+    /// The options of the polling thread, like the [`ReconnectPolicy`] and the thread priority,
+    /// are ignored.
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as [`SpeedEditorBuilder::connect`],
+    /// and an error if authenticating the device failed (after all retries).
+    pub fn connect_sync(self) -> Result<SpeedEditorSync, crate::Error> {
+        self.validate()?;
+        let (source, options) = (self.inner.hid_api.clone(), self.inner.open_options.clone());
+        SpeedEditorSync::start_with_retry(self.inner, self.poll, &self.open_retry, || {
+            Ok(Box::new(driver::get_hid_device(&source, &options)?))
+        })
+    }
+
+    /// Creates a [`SpeedEditorSync`] for the given [`HidBackend`], instead of opening a HID device.
+    ///
+    /// See [`SpeedEditorBuilder::connect_backend`].
+    ///
+    /// # Errors
+    ///
+    /// This function only errors if the configuration is invalid,
+    /// or if authenticating the device failed.
+    pub fn connect_backend_sync(
+        self,
+        backend: impl HidBackend + 'static,
+    ) -> Result<SpeedEditorSync, crate::Error> {
+        self.validate()?;
+        SpeedEditorSync::start(self.inner, self.poll, Box::new(backend))
+    }
+
+    /// Creates the [`SpeedEditor`] for a [`HidDevice`] that was already opened,
+    /// e.g. with a [`HidApi`] that is also used for other devices.
+    ///
+    /// The device is authenticated and polled like one opened by [`SpeedEditorBuilder::connect`].
+    /// If it is reconnected, the device at the same path is opened again.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::UnsupportedDevice`][crate::Error::UnsupportedDevice] if the device
+    /// is not a Speed Editor (or does not have the [`device_ids`][Self::device_ids]),
+    /// [`Error::AlreadyOpen`][crate::Error::AlreadyOpen] if another [`SpeedEditor`]
+    /// in this process uses it, or an error if its device info could not be read.
+    pub fn connect_hid_device(mut self, device: HidDevice) -> Result<SpeedEditor, crate::Error> {
+        self.validate()?;
+        let (hid_device, path) = driver::adopt_hid_device(device, self.inner.open_options.ids)?;
+        self.inner.open_options.selector = DeviceSelector::Path(path);
+        SpeedEditor::spawn(self.inner, self.thread, self.poll, Some(Box::new(hid_device)))
+    }
+
+    /// Creates a [`SpeedEditorSync`] for a [`HidDevice`] that was already opened.
+    ///
+    /// See [`SpeedEditorBuilder::connect_hid_device`].
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as [`SpeedEditorBuilder::connect_hid_device`],
+    /// and an error if authenticating the device failed.
+    pub fn connect_hid_device_sync(
+        mut self,
+        device: HidDevice,
+    ) -> Result<SpeedEditorSync, crate::Error> {
+        self.validate()?;
+        let (hid_device, path) = driver::adopt_hid_device(device, self.inner.open_options.ids)?;
+        self.inner.open_options.selector = DeviceSelector::Path(path);
+        SpeedEditorSync::start(self.inner, self.poll, Box::new(hid_device))
+    }
+
+    /// Creates the [`SpeedEditor`] for a device handed back by [`SpeedEditor::into_parts`],
+    /// see [`SpeedEditor::from_parts`].
+    ///
+    /// The LEDs and the wheel mode of the `state` replace the ones set on this builder.
+    /// If it is reconnected, the device at the same path is opened again.
+    ///
+    /// # Errors
+    ///
+    /// This function only errors if the configuration is invalid, if the device info could not
+    /// be read, or if the polling thread could not be spawned.
+    pub fn connect_parts(
+        mut self,
+        device: Box<dyn HidBackend>,
+        state: SpeedEditorState,
+    ) -> Result<SpeedEditor, crate::Error> {
+        self.validate()?;
+        let device_info = device
+            .device_info()
+            .map_err(|error| crate::Error::hid("failed to get device info", error))?;
+        self.inner.open_options.selector = DeviceSelector::Path(device_info.path);
+        self.inner.button_led = state.button_led;
+        self.inner.wheel_led = state.wheel_led;
+        self.inner.wheel_mode = state.wheel_mode;
+        self.inner.resumed_auth =
+            Some(ResumedAuth { next_auth: state.next_auth, expiry: state.auth_expiry });
+        SpeedEditor::spawn(self.inner, self.thread, self.poll, Some(device))
+    }
+
+    /// Blocks until a Speed Editor is plugged in, then connects to it and creates the [`SpeedEditor`].
+    ///
+    /// If `timeout` is [`None`], this waits forever.
+    /// Waiting can be stopped early from another thread by cancelling the [`CancellationToken`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Timeout`][crate::Error::Timeout] if no device was found within the timeout,
+    /// [`Error::Cancelled`][crate::Error::Cancelled] if waiting was cancelled,
+    /// or an error if the HID API could not be initialized.
+    pub fn wait_for_device(
+        self,
+        timeout: Option<Duration>,
+        cancel: Option<&CancellationToken>,
+    ) -> Result<SpeedEditor, crate::Error> {
+        self.validate()?;
+        let hid_device = wait::wait_for_hid_device(
+            &self.inner.hid_api,
+            &self.inner.open_options,
+            timeout,
+            cancel,
+        )?;
+        SpeedEditor::spawn(self.inner, self.thread, self.poll, Some(Box::new(hid_device)))
+    }
+
+    /// Creates the [`SpeedEditor`] for the given [`HidBackend`], instead of opening a HID device.
+    ///
+    /// This is meant to run the driver without hardware, e.g. with a
+    /// [`FakeBackend`][crate::testing::FakeBackend] (with the `testing` feature).
+    /// The backend cannot be opened again, so the [`ReconnectPolicy`][crate::ReconnectPolicy]
+    /// and [`RestartPolicy`][crate::RestartPolicy] are ignored: the poller stops when it fails.
+    ///
+    /// # Errors
+    ///
+    /// This function only errors if the configuration is invalid,
+    /// or if the polling thread could not be spawned.
+    pub fn connect_backend(
+        mut self,
+        backend: impl HidBackend + 'static,
+    ) -> Result<SpeedEditor, crate::Error> {
+        self.validate()?;
+        self.inner.reconnect_policy = ReconnectPolicy::Never;
+        self.inner.restart_policy = RestartPolicy::Never;
+        SpeedEditor::spawn(self.inner, self.thread, self.poll, Some(Box::new(backend)))
+    }
+
+    fn validate(&self) -> Result<(), crate::Error> {
+        self.poll.validate()?;
+        if let Some(timeout) = self.inner.auth_timeout
+            && self.inner.auth_margin.is_some_and(|margin| margin >= timeout)
+        {
+            return Err(crate::Error::InvalidConfiguration {
+                message: "auth margin must be shorter than the auth timeout",
+            });
+        }
+        if self.inner.auth_timeout.is_some_and(|timeout| timeout.is_zero()) {
+            return Err(crate::Error::InvalidConfiguration {
+                message: "auth timeout must not be zero",
+            });
+        }
+        driver::check_open_exclusive(self.inner.open_options.exclusive)
+    }
+}
@@ -0,0 +1,499 @@
+use std::{
+    io::{self, BufWriter, Read, Write},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        mpsc,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use hidapi::HidError;
+
+use crate::{DeviceInfo, HidBackend, SpeedEditor, poller::Shared, sync::MutexExt};
+
+/// The first bytes of a capture.
+const MAGIC: &[u8; 8] = b"BMDSECAP";
+/// The version of the capture format.
+const VERSION: u8 = 1;
+
+/// The kind of traffic a [`CaptureRecord`] contains.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum CaptureKind {
+    /// An input report read from the device.
+    Input = 0,
+    /// An output report written to the device.
+    Output = 1,
+    /// A feature report sent to the device.
+    SetFeature = 2,
+    /// A feature report received from the device.
+    GetFeature = 3,
+}
+
+impl TryFrom<u8> for CaptureKind {
+    type Error = io::Error;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(CaptureKind::Input),
+            1 => Ok(CaptureKind::Output),
+            2 => Ok(CaptureKind::SetFeature),
+            3 => Ok(CaptureKind::GetFeature),
+            _ => Err(invalid_data("unknown kind of capture record")),
+        }
+    }
+}
+
+/// A single report of a capture.
+///
+/// See [`SpeedEditor::capture_traffic`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CaptureRecord {
+    /// The time since the capture started.
+    pub timestamp: Duration,
+    /// Which way the report went.
+    pub kind: CaptureKind,
+    /// The report, starting with its report ID.
+    pub data: Vec<u8>,
+}
+
+impl CaptureRecord {
+    fn write_to(&self, w: &mut impl Write) -> io::Result<()> {
+        // Reports are at most 64 bytes, so this never truncates.
+        let len = self.data.len().min(u16::MAX as usize);
+        w.write_all(&(self.timestamp.as_micros() as u64).to_le_bytes())?;
+        w.write_all(&[self.kind as u8])?;
+        w.write_all(&(len as u16).to_le_bytes())?;
+        w.write_all(&self.data[..len])
+    }
+}
+
+/// Reads the records of a capture made by [`SpeedEditor::capture_traffic`].
+///
+/// The input reports can be replayed by passing them to `SpeedEditor::inject_report`,
+/// which is available with the `testing` feature.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::fs::File;
+///
+/// use bmdse::protocol::{CaptureKind, CaptureReader, Report};
+///
+/// let reader = CaptureReader::new(File::open("speed_editor.cap").unwrap()).unwrap();
+/// for record in reader {
+///     let record = record.unwrap();
+///     if record.kind == CaptureKind::Input {
+///         println!("{:?}: {:?}", record.timestamp, Report::try_from(&record.data[..]));
+///     }
+/// }
+/// ```
+pub struct CaptureReader<R> {
+    reader: R,
+    start_time: SystemTime,
+}
+
+impl<R: Read> CaptureReader<R> {
+    /// Creates a [`CaptureReader`], reading the header of the capture.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if reading fails, or with [`io::ErrorKind::InvalidData`]
+    /// if it is not a capture in a supported version.
+    pub fn new(mut reader: R) -> io::Result<Self> {
+        let mut header = [0x00; 17];
+        reader.read_exact(&mut header)?;
+        if &header[..8] != MAGIC {
+            return Err(invalid_data("not a capture"));
+        }
+        if header[8] != VERSION {
+            return Err(invalid_data("unsupported capture version"));
+        }
+        let start_micros = u64::from_le_bytes(header[9..17].try_into().unwrap());
+        Ok(Self { reader, start_time: UNIX_EPOCH + Duration::from_micros(start_micros) })
+    }
+
+    /// Returns when the capture started.
+    pub fn start_time(&self) -> SystemTime {
+        self.start_time
+    }
+
+    fn read_record(&mut self) -> io::Result<Option<CaptureRecord>> {
+        let mut header = [0x00; 11];
+        // A capture can end after any record, so only a partial header is an error.
+        let mut filled = 0;
+        while filled < header.len() {
+            match self.reader.read(&mut header[filled..]) {
+                Ok(0) if filled == 0 => return Ok(None),
+                Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+                Ok(len) => filled += len,
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => {}
+                Err(error) => return Err(error),
+            }
+        }
+
+        let timestamp = Duration::from_micros(u64::from_le_bytes(header[..8].try_into().unwrap()));
+        let kind = CaptureKind::try_from(header[8])?;
+        let mut data = vec![0x00; u16::from_le_bytes([header[9], header[10]]) as usize];
+        self.reader.read_exact(&mut data)?;
+        Ok(Some(CaptureRecord { timestamp, kind, data }))
+    }
+}
+
+impl<R: Read> Iterator for CaptureReader<R> {
+    type Item = io::Result<CaptureRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_record().transpose()
+    }
+}
+
+fn invalid_data(message: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// The capture that is currently running, shared with the polling thread.
+#[derive(Default)]
+pub(crate) struct Capture {
+    /// Whether a capture is running, so the polling thread does not have to lock otherwise.
+    active: AtomicBool,
+    sink: Mutex<Option<CaptureSink>>,
+    next_id: AtomicU64,
+}
+
+struct CaptureSink {
+    id: u64,
+    start: Instant,
+    records: mpsc::Sender<CaptureRecord>,
+}
+
+impl Capture {
+    fn record(&self, kind: CaptureKind, data: &[u8]) {
+        if !self.active.load(Ordering::Relaxed) {
+            return;
+        }
+        if let Some(sink) = &*self.sink.lock_unpoisoned() {
+            // If the writer stopped because of an error, the record is dropped.
+            let _ = sink.records.send(CaptureRecord {
+                timestamp: sink.start.elapsed(),
+                kind,
+                data: data.to_vec(),
+            });
+        }
+    }
+
+    /// Stops the capture with the given ID, unless another one replaced it.
+    fn stop(&self, id: u64) {
+        let mut sink = self.sink.lock_unpoisoned();
+        if sink.as_ref().is_some_and(|sink| sink.id == id) {
+            *sink = None;
+            self.active.store(false, Ordering::Relaxed);
+        }
+    }
+}
+
+/// A [`HidBackend`] that records all traffic to the running [`Capture`].
+pub(crate) struct CapturingBackend {
+    pub(crate) device: Box<dyn HidBackend>,
+    pub(crate) capture: Arc<Capture>,
+}
+
+impl HidBackend for CapturingBackend {
+    fn device_info(&self) -> Result<DeviceInfo, HidError> {
+        self.device.device_info()
+    }
+
+    fn write(&self, data: &[u8]) -> Result<usize, HidError> {
+        let len = self.device.write(data)?;
+        self.capture.record(CaptureKind::Output, data);
+        Ok(len)
+    }
+
+    fn read_timeout(&self, buf: &mut [u8], timeout: i32) -> Result<usize, HidError> {
+        let len = self.device.read_timeout(buf, timeout)?;
+        if len > 0 {
+            self.capture.record(CaptureKind::Input, &buf[..len]);
+        }
+        Ok(len)
+    }
+
+    fn send_feature_report(&self, data: &[u8]) -> Result<(), HidError> {
+        self.device.send_feature_report(data)?;
+        self.capture.record(CaptureKind::SetFeature, data);
+        Ok(())
+    }
+
+    fn get_feature_report(&self, buf: &mut [u8]) -> Result<usize, HidError> {
+        let len = self.device.get_feature_report(buf)?;
+        self.capture.record(CaptureKind::GetFeature, &buf[..len]);
+        Ok(len)
+    }
+
+    fn get_input_report(&self, buf: &mut [u8]) -> Result<usize, HidError> {
+        let len = self.device.get_input_report(buf)?;
+        self.capture.record(CaptureKind::Input, &buf[..len]);
+        Ok(len)
+    }
+}
+
+/// Keeps capturing the traffic of a [`SpeedEditor`] until it is dropped.
+///
+/// See [`SpeedEditor::capture_traffic`].
+pub struct CaptureGuard {
+    shared: Arc<Shared>,
+    id: u64,
+    writer: Option<JoinHandle<io::Result<()>>>,
+}
+
+impl CaptureGuard {
+    /// Stops the capture, and waits until all records are written.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error writing the capture,
+    /// after which the rest of the records were dropped.
+    pub fn finish(mut self) -> io::Result<()> {
+        self.stop()
+    }
+
+    fn stop(&mut self) -> io::Result<()> {
+        self.shared.capture.stop(self.id);
+        // Stopping drops the sender, which ends the writer once it wrote all records.
+        match self.writer.take().map(JoinHandle::join) {
+            Some(Ok(result)) => result,
+            Some(Err(_)) => Err(io::Error::other("capture writer panicked")),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for CaptureGuard {
+    fn drop(&mut self) {
+        let _ = self.stop();
+    }
+}
+
+impl SpeedEditor {
+    /// Captures every report read from and written to the device, for debugging.
+    ///
+    /// The capture is written to `w` by a separate thread, so it does not slow down polling,
+    /// until the returned [`CaptureGuard`] is dropped. Starting another capture stops this one.
+    /// Reports are only captured while the device is connected,
+    /// and failed reads and writes are not captured.
+    /// Use [`CaptureReader`][crate::protocol::CaptureReader] to read it back.
+    ///
+    /// # Format
+    ///
+    /// All numbers are little endian. The capture starts with a header of 17 bytes:
+    ///
+    /// | Bytes | Content                                                   |
+    /// |-------|-----------------------------------------------------------|
+    /// | 0..8  | `BMDSECAP`                                                |
+    /// | 8     | The version of the format, currently 1                    |
+    /// | 9..17 | When the capture started, in microseconds since 1970 (u64) |
+    ///
+    /// Followed by a record for every report:
+    ///
+    /// | Bytes     | Content                                                      |
+    /// |-----------|--------------------------------------------------------------|
+    /// | 0..8      | The time since the capture started, in microseconds (u64)    |
+    /// | 8         | The [`CaptureKind`][crate::protocol::CaptureKind] (u8)      |
+    /// | 9..11     | The length of the report (u16)                               |
+    /// | 11..      | The report, starting with its report ID                      |
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use std::fs::File;
+    ///
+    /// use bmdse::SpeedEditor;
+    ///
+    /// let speed_editor = SpeedEditor::new().unwrap();
+    /// let capture = speed_editor.capture_traffic(File::create("speed_editor.cap").unwrap()).unwrap();
+    ///
+    /// std::thread::sleep(std::time::Duration::from_secs(10));
+    /// capture.finish().unwrap();
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// This function only errors if the writing thread could not be spawned.
+    pub fn capture_traffic<W: Write + Send + 'static>(
+        &self,
+        w: W,
+    ) -> Result<CaptureGuard, crate::Error> {
+        let shared = &self.poller.shared;
+        let (records, receiver) = mpsc::channel::<CaptureRecord>();
+        let (start_time, start) = (SystemTime::now(), Instant::now());
+
+        let writer = thread::Builder::new().name("bmd_speed_editor_capture".to_string()).spawn(
+            move || {
+                let mut w = BufWriter::new(w);
+                let start_micros =
+                    start_time.duration_since(UNIX_EPOCH).unwrap_or_default().as_micros() as u64;
+                w.write_all(MAGIC)?;
+                w.write_all(&[VERSION])?;
+                w.write_all(&start_micros.to_le_bytes())?;
+                for record in receiver {
+                    record.write_to(&mut w)?;
+                }
+                w.flush()
+            },
+        )?;
+
+        let id = shared.capture.next_id.fetch_add(1, Ordering::Relaxed);
+        *shared.capture.sink.lock_unpoisoned() = Some(CaptureSink { id, start, records });
+        shared.capture.active.store(true, Ordering::Relaxed);
+
+        Ok(CaptureGuard { shared: Arc::clone(shared), id, writer: Some(writer) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{TIMEOUT, WHEEL_REPORT};
+    use crate::testing::FakeBackend;
+
+    /// A [`Write`] whose contents can be read while it is owned by the capture.
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock_unpoisoned().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn header(version: u8, start_micros: u64) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(version);
+        bytes.extend_from_slice(&start_micros.to_le_bytes());
+        bytes
+    }
+
+    fn records() -> Vec<CaptureRecord> {
+        vec![
+            CaptureRecord {
+                timestamp: Duration::from_micros(1),
+                kind: CaptureKind::SetFeature,
+                data: vec![0x06, 0x00, 0, 0, 0, 0, 0, 0, 0, 0],
+            },
+            CaptureRecord {
+                timestamp: Duration::from_millis(20),
+                kind: CaptureKind::Input,
+                data: WHEEL_REPORT.to_vec(),
+            },
+            CaptureRecord {
+                timestamp: Duration::from_secs(3600),
+                kind: CaptureKind::Output,
+                data: vec![0x04, 0x01],
+            },
+            CaptureRecord {
+                timestamp: Duration::from_secs(3600),
+                kind: CaptureKind::GetFeature,
+                data: Vec::new(),
+            },
+        ]
+    }
+
+    #[test]
+    fn records_round_trip() {
+        let mut bytes = header(VERSION, 1_700_000_000_000_000);
+        for record in records() {
+            record.write_to(&mut bytes).unwrap();
+        }
+
+        let reader = CaptureReader::new(&bytes[..]).unwrap();
+        assert_eq!(reader.start_time(), UNIX_EPOCH + Duration::from_secs(1_700_000_000));
+        let read: Vec<_> = reader.collect::<io::Result<_>>().unwrap();
+        assert_eq!(read, records());
+    }
+
+    #[test]
+    fn reader_rejects_other_files() {
+        let error = |bytes: &[u8]| CaptureReader::new(bytes).err().unwrap().kind();
+
+        assert_eq!(error(b"BMDSECAQ\x01\0\0\0\0\0\0\0\0"), io::ErrorKind::InvalidData);
+        assert_eq!(error(&header(VERSION + 1, 0)), io::ErrorKind::InvalidData);
+        assert_eq!(error(&header(VERSION, 0)[..16]), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn reader_rejects_broken_records() {
+        let mut record = Vec::new();
+        records()[1].write_to(&mut record).unwrap();
+
+        let read = |record: &[u8]| {
+            let bytes = [header(VERSION, 0), record.to_vec()].concat();
+            CaptureReader::new(&bytes[..]).unwrap().next().unwrap().unwrap_err().kind()
+        };
+        // Cut off in the header of the record, and in its report.
+        assert_eq!(read(&record[..5]), io::ErrorKind::UnexpectedEof);
+        assert_eq!(read(&record[..record.len() - 1]), io::ErrorKind::UnexpectedEof);
+
+        record[8] = 0x04;
+        assert_eq!(read(&record), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn traffic_is_captured_and_replayed() {
+        let backend = FakeBackend::new();
+        backend.push_auth_handshake();
+        // Answers the battery report that is requested after connecting.
+        backend.push_input_report(&[0x07, 0x00, 0x32]);
+        let speed_editor = SpeedEditor::builder().connect_backend(backend.clone()).unwrap();
+        assert!(backend.wait_until_idle(TIMEOUT));
+
+        let buffer = SharedBuffer::default();
+        let before = SystemTime::now();
+        let capture = speed_editor.capture_traffic(buffer.clone()).unwrap();
+        backend.push_input_report(&WHEEL_REPORT);
+        assert!(backend.wait_until_idle(TIMEOUT));
+        speed_editor.set_wheel_led(crate::WheelLed::Jog);
+        let start = Instant::now();
+        while backend.written().last() != Some(&vec![0x04, 0x01]) {
+            assert!(start.elapsed() < TIMEOUT, "the wheel LED was not written");
+            thread::sleep(Duration::from_millis(1));
+        }
+        capture.finish().unwrap();
+
+        // Nothing is captured after finishing.
+        backend.push_input_report(&WHEEL_REPORT);
+        assert!(backend.wait_until_idle(TIMEOUT));
+
+        let bytes = buffer.0.lock_unpoisoned().clone();
+        let reader = CaptureReader::new(&bytes[..]).unwrap();
+        assert!(reader.start_time() >= before - Duration::from_millis(1));
+        let records: Vec<_> = reader.collect::<io::Result<_>>().unwrap();
+        let traffic: Vec<_> =
+            records.iter().map(|record| (record.kind, record.data.clone())).collect();
+        assert_eq!(
+            traffic,
+            [(CaptureKind::Input, WHEEL_REPORT.to_vec()), (CaptureKind::Output, vec![0x04, 0x01])]
+        );
+        assert!(records[0].timestamp <= records[1].timestamp);
+
+        // The captured input reports can be replayed to another device.
+        let other = FakeBackend::new();
+        other.push_auth_handshake();
+        other.push_input_report(&[0x07, 0x00, 0x32]);
+        let (sender, receiver) = mpsc::channel();
+        let replayed = SpeedEditor::builder()
+            .on_wheel_change(move |velocity| sender.send(velocity).unwrap())
+            .connect_backend(other.clone())
+            .unwrap();
+        assert!(other.wait_until_idle(TIMEOUT));
+        for record in records.iter().filter(|record| record.kind == CaptureKind::Input) {
+            replayed.inject_report(&record.data).unwrap();
+        }
+        assert_eq!(receiver.recv_timeout(TIMEOUT), Ok(5));
+    }
+}
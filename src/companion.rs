@@ -0,0 +1,913 @@
+//! Using the Speed Editor as a surface of [Bitfocus Companion](https://bitfocus.io/companion).
+//!
+//! A [`CompanionSatellite`] connects to Companion with its Satellite API (TCP port 16622)
+//! and adds the Speed Editor as a surface. The buttons can then trigger any action in
+//! Companion, and the color Companion gives a button lights up its LED.
+//! When the connection is lost, e.g. because Companion restarts, it reconnects.
+//!
+//! ```no_run
+//! use bmdse::SpeedEditor;
+//! use bmdse::companion::{CompanionSatellite, SatelliteOptions};
+//!
+//! let speed_editor = SpeedEditor::new().unwrap();
+//! let addr = ([127, 0, 0, 1], CompanionSatellite::DEFAULT_PORT).into();
+//! let _satellite = CompanionSatellite::connect(&speed_editor, addr, SatelliteOptions::new());
+//!
+//! std::thread::park();
+//! ```
+//!
+//! # Layout
+//!
+//! The surface has rows of 8 keys. The key of a button is its index in [`LAYOUT`], and the
+//! key after the last button ([`WHEEL_KEY`]) is the jog wheel, which rotates to the right
+//! when it turns clockwise. Enable rotary actions on that key in Companion to use it.
+//!
+//! Only buttons with a LED show feedback, and like on the device itself, only one button LED
+//! and one wheel LED can be lit at once. A key that gets a color (other than black) lights up
+//! its LED, replacing the LED that was lit before.
+//!
+//! # Protocol
+//!
+//! The messages of the Satellite API are lines of text, with `KEY=value` parameters.
+//! [`ServerMessage`] parses the messages from Companion, and [`ClientMessage`] formats the
+//! messages to it. This is an exchange that adds a surface and presses its first key:
+//!
+//! ```
+//! use bmdse::companion::{ClientMessage, ServerMessage};
+//!
+//! assert_eq!(
+//!     ServerMessage::parse("BEGIN CompanionVersion=3.4.0 ApiVersion=1.7.0"),
+//!     ServerMessage::Begin { api_version: Some("1.7.0".to_string()) },
+//! );
+//!
+//! let add_device = ClientMessage::AddDevice {
+//!     device_id: "bmdse-1234".to_string(),
+//!     product_name: "DaVinci Resolve Speed Editor".to_string(),
+//!     keys_total: 48,
+//!     keys_per_row: 8,
+//! };
+//! assert_eq!(
+//!     add_device.to_string(),
+//!     "ADD-DEVICE DEVICEID=bmdse-1234 PRODUCT_NAME=\"DaVinci Resolve Speed Editor\" \
+//!      KEYS_TOTAL=48 KEYS_PER_ROW=8 BITMAPS=false COLORS=hex TEXT=false",
+//! );
+//!
+//! assert_eq!(
+//!     ServerMessage::parse("ADD-DEVICE OK DEVICEID=bmdse-1234"),
+//!     ServerMessage::AddDeviceOk { device_id: "bmdse-1234".to_string() },
+//! );
+//! assert_eq!(
+//!     ServerMessage::parse("KEY-STATE DEVICEID=bmdse-1234 KEY=14 TYPE=BUTTON COLOR=#ff8000 PRESSED=false"),
+//!     ServerMessage::KeyState {
+//!         device_id: "bmdse-1234".to_string(),
+//!         key: 14,
+//!         color: Some([0xff, 0x80, 0x00]),
+//!     },
+//! );
+//!
+//! let press = ClientMessage::KeyPress { device_id: "bmdse-1234".to_string(), key: 0, pressed: true };
+//! assert_eq!(press.to_string(), "KEY-PRESS DEVICEID=bmdse-1234 KEY=0 PRESSED=true");
+//!
+//! let rotate =
+//!     ClientMessage::KeyRotate { device_id: "bmdse-1234".to_string(), key: 43, clockwise: false };
+//! assert_eq!(rotate.to_string(), "KEY-ROTATE DEVICEID=bmdse-1234 KEY=43 DIRECTION=0");
+//!
+//! assert_eq!(ServerMessage::parse("PING 1234"), ServerMessage::Ping("1234".to_string()));
+//! assert_eq!(ClientMessage::Pong("1234".to_string()).to_string(), "PONG 1234");
+//! assert_eq!(
+//!     ServerMessage::parse("ADD-DEVICE ERROR DEVICEID=bmdse-1234 MESSAGE=\"Device \\\"bmdse-1234\\\" already added\""),
+//!     ServerMessage::AddDeviceError {
+//!         device_id: "bmdse-1234".to_string(),
+//!         message: "Device \"bmdse-1234\" already added".to_string(),
+//!     },
+//! );
+//! ```
+
+use std::{
+    fmt,
+    io::{self, Read, Write},
+    net::{SocketAddr, TcpStream},
+    sync::{
+        Arc, Condvar, Mutex, MutexGuard, Weak,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    Button, ButtonLed, Event, EventSink, SinkClosed, SpeedEditor, WheelLed, driver::Led,
+    sync::MutexExt,
+};
+
+/// The buttons in the order of their keys on the Companion surface, see the [layout](self#layout).
+pub const LAYOUT: [Button; 43] = [
+    Button::SmartInsert,
+    Button::Append,
+    Button::RippleOverwrite,
+    Button::CloseUp,
+    Button::PlaceOnTop,
+    Button::SourceOverwrite,
+    Button::In,
+    Button::Out,
+    Button::TrimIn,
+    Button::TrimOut,
+    Button::Roll,
+    Button::SlipSource,
+    Button::SlipDestination,
+    Button::TransitionDuration,
+    Button::Cut,
+    Button::Dissolve,
+    Button::SmoothCut,
+    Button::Escape,
+    Button::SyncBin,
+    Button::AudioLevel,
+    Button::FullView,
+    Button::Transition,
+    Button::Split,
+    Button::Snap,
+    Button::RippleDelete,
+    Button::Cam1,
+    Button::Cam2,
+    Button::Cam3,
+    Button::Cam4,
+    Button::Cam5,
+    Button::Cam6,
+    Button::Cam7,
+    Button::Cam8,
+    Button::Cam9,
+    Button::LiveOverwrite,
+    Button::VideoOnly,
+    Button::AudioOnly,
+    Button::StopPlay,
+    Button::Source,
+    Button::Timeline,
+    Button::Shuttle,
+    Button::Jog,
+    Button::Scroll,
+];
+
+/// The key of the jog wheel on the Companion surface, see the [layout](self#layout).
+pub const WHEEL_KEY: u32 = LAYOUT.len() as u32;
+
+const KEYS_PER_ROW: u32 = 8;
+/// The keys of the buttons and the wheel, rounded up to full rows.
+const KEYS_TOTAL: u32 = (WHEEL_KEY + 1).div_ceil(KEYS_PER_ROW) * KEYS_PER_ROW;
+
+/// How often the connection thread checks for messages from Companion.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+const PING_INTERVAL: Duration = Duration::from_secs(2);
+/// After how long without any message from Companion the connection is considered lost.
+const RECEIVE_TIMEOUT: Duration = Duration::from_secs(10);
+const MIN_RECONNECT_DELAY: Duration = Duration::from_secs(1);
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(10);
+/// The most bytes that can wait to be sent before the connection is considered stalled.
+const MAX_UNSENT: usize = 64 * 1024;
+
+/// A message from Companion to a satellite.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ServerMessage {
+    /// Companion is ready for the satellite to add its surfaces.
+    Begin {
+        /// The version of the Satellite API, if Companion sent it.
+        api_version: Option<String>,
+    },
+    /// The surface was added.
+    AddDeviceOk {
+        /// The ID of the surface.
+        device_id: String,
+    },
+    /// The surface could not be added.
+    AddDeviceError {
+        /// The ID of the surface.
+        device_id: String,
+        /// Why the surface could not be added.
+        message: String,
+    },
+    /// The state of a key changed.
+    KeyState {
+        /// The ID of the surface.
+        device_id: String,
+        /// The index of the key.
+        key: u32,
+        /// The background color of the key, if Companion sent it.
+        color: Option<[u8; 3]>,
+    },
+    /// All keys were cleared.
+    KeysClear {
+        /// The ID of the surface.
+        device_id: String,
+    },
+    /// Companion checks that the satellite is still there.
+    Ping(String),
+    /// The reply to a [`ClientMessage::Ping`].
+    Pong(String),
+    /// A message that is not used by this crate, like `BRIGHTNESS`.
+    Other(String),
+}
+
+impl ServerMessage {
+    /// Parses a line from Companion, without its line ending.
+    pub fn parse(line: &str) -> Self {
+        let (command, rest) = line.split_once(' ').unwrap_or((line, ""));
+        let parameters = Parameters::parse(rest);
+        let device_id = || parameters.get("DEVICEID").unwrap_or_default().to_string();
+        match command {
+            "BEGIN" => {
+                Self::Begin { api_version: parameters.get("ApiVersion").map(str::to_string) }
+            }
+            "ADD-DEVICE" if rest.starts_with("OK") => Self::AddDeviceOk { device_id: device_id() },
+            "ADD-DEVICE" if rest.starts_with("ERROR") => Self::AddDeviceError {
+                device_id: device_id(),
+                message: parameters.get("MESSAGE").unwrap_or_default().to_string(),
+            },
+            "KEY-STATE" => match parameters.get("KEY").and_then(|key| key.parse().ok()) {
+                Some(key) => Self::KeyState {
+                    device_id: device_id(),
+                    key,
+                    color: parameters.get("COLOR").and_then(parse_color),
+                },
+                None => Self::Other(line.to_string()),
+            },
+            "KEYS-CLEAR" => Self::KeysClear { device_id: device_id() },
+            "PING" => Self::Ping(rest.to_string()),
+            "PONG" => Self::Pong(rest.to_string()),
+            _ => Self::Other(line.to_string()),
+        }
+    }
+}
+
+/// Parses a color as `#rrggbb`, or as `rgb(r,g,b)`.
+fn parse_color(color: &str) -> Option<[u8; 3]> {
+    if let Some(hex) = color.strip_prefix('#') {
+        let value = u32::from_str_radix(hex, 16).ok().filter(|_| hex.len() == 6)?;
+        let [_, red, green, blue] = value.to_be_bytes();
+        return Some([red, green, blue]);
+    }
+    let components = color.strip_prefix("rgb(")?.strip_suffix(')')?;
+    let mut components = components.split(',').map(|component| component.trim().parse().ok());
+    let color = [components.next()??, components.next()??, components.next()??];
+    components.next().is_none().then_some(color)
+}
+
+/// The `KEY=value` parameters of a message, where values with spaces are quoted.
+struct Parameters(Vec<(String, String)>);
+
+impl Parameters {
+    fn parse(text: &str) -> Self {
+        let mut parameters = Vec::new();
+        let mut chars = text.chars().peekable();
+        loop {
+            while chars.next_if_eq(&' ').is_some() {}
+            if chars.peek().is_none() {
+                break;
+            }
+            let mut key = String::new();
+            while let Some(c) = chars.next_if(|&c| c != '=' && c != ' ') {
+                key.push(c);
+            }
+
+            // A word without a value, like the `OK` of `ADD-DEVICE OK`.
+            let mut value = String::new();
+            if chars.next_if_eq(&'=').is_none() {
+                parameters.push((key, value));
+                continue;
+            }
+            if chars.next_if_eq(&'"').is_some() {
+                while let Some(c) = chars.next() {
+                    match c {
+                        '\\' => value.extend(chars.next()),
+                        '"' => break,
+                        c => value.push(c),
+                    }
+                }
+            } else {
+                value.extend(chars.by_ref().take_while(|&c| c != ' '));
+            }
+            parameters.push((key, value));
+        }
+        Self(parameters)
+    }
+
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.iter().find(|(k, _)| k == key).map(|(_, value)| value.as_str())
+    }
+}
+
+/// A message from a satellite to Companion.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ClientMessage {
+    /// Adds a surface with only buttons, that shows colors.
+    AddDevice {
+        /// The ID of the surface, which has to be unique.
+        device_id: String,
+        /// The name of the surface in Companion.
+        product_name: String,
+        /// The number of keys.
+        keys_total: u32,
+        /// The number of keys per row.
+        keys_per_row: u32,
+    },
+    /// Removes a surface.
+    RemoveDevice {
+        /// The ID of the surface.
+        device_id: String,
+    },
+    /// A key was pressed or released.
+    KeyPress {
+        /// The ID of the surface.
+        device_id: String,
+        /// The index of the key.
+        key: u32,
+        /// `true` if the key is pressed, `false` if it was released.
+        pressed: bool,
+    },
+    /// A key was rotated one step.
+    KeyRotate {
+        /// The ID of the surface.
+        device_id: String,
+        /// The index of the key.
+        key: u32,
+        /// `true` if it was rotated to the right.
+        clockwise: bool,
+    },
+    /// Checks that Companion is still there.
+    Ping(String),
+    /// The reply to a [`ServerMessage::Ping`].
+    Pong(String),
+}
+
+impl fmt::Display for ClientMessage {
+    /// Formats the message as a line, without its line ending.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::AddDevice { device_id, product_name, keys_total, keys_per_row } => write!(
+                f,
+                "ADD-DEVICE DEVICEID={device_id} PRODUCT_NAME={} KEYS_TOTAL={keys_total} \
+                 KEYS_PER_ROW={keys_per_row} BITMAPS=false COLORS=hex TEXT=false",
+                quote(product_name)
+            ),
+            Self::RemoveDevice { device_id } => write!(f, "REMOVE-DEVICE DEVICEID={device_id}"),
+            Self::KeyPress { device_id, key, pressed } => {
+                write!(f, "KEY-PRESS DEVICEID={device_id} KEY={key} PRESSED={pressed}")
+            }
+            Self::KeyRotate { device_id, key, clockwise } => {
+                let direction = *clockwise as u8;
+                write!(f, "KEY-ROTATE DEVICEID={device_id} KEY={key} DIRECTION={direction}")
+            }
+            Self::Ping(payload) => write!(f, "PING {payload}"),
+            Self::Pong(payload) => write!(f, "PONG {payload}"),
+        }
+    }
+}
+
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// How a [`CompanionSatellite`] presents the Speed Editor to Companion.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub struct SatelliteOptions {
+    /// The ID of the surface, which has to be unique in Companion.
+    ///
+    /// [`None`] uses the serial number of the device,
+    /// so the surface keeps its configuration when the device is reconnected.
+    pub device_id: Option<String>,
+    /// How much the jog wheel has to turn for one rotation step of its key.
+    /// The changes in between are added up. Zero is treated as one.
+    pub wheel_step: u32,
+}
+
+impl SatelliteOptions {
+    /// Creates the default [`SatelliteOptions`].
+    pub fn new() -> Self {
+        Self { device_id: None, wheel_step: 1 }
+    }
+
+    /// Set the ID of the surface.
+    pub fn device_id(mut self, device_id: impl Into<String>) -> Self {
+        self.device_id = Some(device_id.into());
+        self
+    }
+
+    /// Set how much the jog wheel has to turn for one rotation step of its key.
+    pub fn wheel_step(mut self, wheel_step: u32) -> Self {
+        self.wheel_step = wheel_step;
+        self
+    }
+}
+
+#[derive(Default)]
+struct Pending {
+    /// The ID of the surface once Companion added it, until the connection is lost.
+    device_id: Option<String>,
+    messages: Vec<ClientMessage>,
+    /// The wheel change that was not sent yet, because it is less than a step.
+    wheel: i64,
+    stop: bool,
+}
+
+struct SatelliteShared {
+    options: SatelliteOptions,
+    pending: Mutex<Pending>,
+    changed: Condvar,
+    connected: AtomicBool,
+}
+
+impl SatelliteShared {
+    /// Waits until a message is queued, a stop is requested, or the timeout passes.
+    fn wait(&self, timeout: Duration) -> MutexGuard<'_, Pending> {
+        let pending = self.pending.lock_unpoisoned();
+        if !pending.messages.is_empty() || pending.stop {
+            return pending;
+        }
+        self.changed
+            .wait_timeout(pending, timeout)
+            .map(|(pending, _)| pending)
+            .unwrap_or_else(|e| e.into_inner().0)
+    }
+}
+
+/// Connects a [`SpeedEditor`] to Companion as a surface, until it is dropped.
+///
+/// See the [module documentation][self]. The connection is made and kept from a separate
+/// thread, which reconnects with an increasing delay (up to 10 seconds) when Companion
+/// cannot be reached, and when Companion does not answer for 10 seconds.
+/// The surface is removed from Companion when this is dropped.
+pub struct CompanionSatellite {
+    shared: Arc<SatelliteShared>,
+    connection: Option<JoinHandle<()>>,
+}
+
+impl CompanionSatellite {
+    /// The port of the Satellite API of Companion.
+    pub const DEFAULT_PORT: u16 = 16622;
+
+    /// Starts connecting the [`SpeedEditor`] to Companion at `addr`.
+    ///
+    /// Pressing and releasing buttons, and turning the jog wheel, is forwarded while connected.
+    /// Changes while not connected are dropped. The bridge keeps the [`SpeedEditor`] open
+    /// for as long as it exists.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the connection thread could not be spawned.
+    /// Failing to connect is not an error, it is retried until it succeeds.
+    pub fn connect(
+        speed_editor: &SpeedEditor,
+        addr: SocketAddr,
+        options: SatelliteOptions,
+    ) -> Result<Self, crate::Error> {
+        let shared = Arc::new(SatelliteShared {
+            options,
+            pending: Mutex::default(),
+            changed: Condvar::new(),
+            connected: AtomicBool::new(false),
+        });
+
+        let connection_shared = Arc::clone(&shared);
+        let connection_speed_editor = speed_editor.clone();
+        let connection = thread::Builder::new()
+            .name("bmd_speed_editor_companion".to_string())
+            .spawn(move || keep_connected(&connection_shared, &connection_speed_editor, addr))?;
+
+        speed_editor.attach_sink(Box::new(SatelliteSink { shared: Arc::downgrade(&shared) }));
+        Ok(Self { shared, connection: Some(connection) })
+    }
+
+    /// Returns `true` if Companion added the surface, and the connection is not lost since.
+    pub fn is_connected(&self) -> bool {
+        self.shared.connected.load(Ordering::Acquire)
+    }
+}
+
+impl Drop for CompanionSatellite {
+    fn drop(&mut self) {
+        self.shared.pending.lock_unpoisoned().stop = true;
+        self.shared.changed.notify_all();
+        if let Some(connection) = self.connection.take() {
+            let _ = connection.join();
+        }
+    }
+}
+
+/// Queues the changes of the buttons and the wheel, while the [`CompanionSatellite`] exists.
+struct SatelliteSink {
+    shared: Weak<SatelliteShared>,
+}
+
+impl EventSink for SatelliteSink {
+    fn send(&self, event: Event) -> Result<(), SinkClosed> {
+        let shared = self.shared.upgrade().ok_or(SinkClosed)?;
+        let mut pending = shared.pending.lock_unpoisoned();
+        let pending = &mut *pending;
+        let Some(device_id) = &pending.device_id else { return Ok(()) };
+
+        match event {
+            Event::ButtonChange { button, pressed } => {
+                let Some(key) = LAYOUT.iter().position(|&b| b == button) else { return Ok(()) };
+                let device_id = device_id.clone();
+                pending.messages.push(ClientMessage::KeyPress {
+                    device_id,
+                    key: key as u32,
+                    pressed,
+                });
+            }
+            Event::WheelChange { velocity } => {
+                let step = shared.options.wheel_step.max(1) as i64;
+                pending.wheel += velocity as i64;
+                let steps = pending.wheel / step;
+                pending.wheel -= steps * step;
+                for _ in 0..steps.unsigned_abs() {
+                    pending.messages.push(ClientMessage::KeyRotate {
+                        device_id: device_id.clone(),
+                        key: WHEEL_KEY,
+                        clockwise: steps > 0,
+                    });
+                }
+            }
+            _ => return Ok(()),
+        }
+        shared.changed.notify_all();
+        Ok(())
+    }
+}
+
+/// Connects to Companion until the [`CompanionSatellite`] is dropped,
+/// reconnecting when the connection is lost.
+fn keep_connected(shared: &SatelliteShared, speed_editor: &SpeedEditor, addr: SocketAddr) {
+    let mut delay = MIN_RECONNECT_DELAY;
+    loop {
+        if let Ok(stream) = TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT) {
+            let mut connection = Connection::new(shared, speed_editor, stream);
+            let result = connection.run();
+
+            shared.connected.store(false, Ordering::Release);
+            let mut pending = shared.pending.lock_unpoisoned();
+            pending.device_id = None;
+            pending.messages.clear();
+            pending.wheel = 0;
+            drop(pending);
+
+            // A connection that was made is retried right away, e.g. after Companion restarted.
+            match result {
+                Ok(()) => return,
+                Err(_) if connection.added => delay = MIN_RECONNECT_DELAY,
+                Err(_) => {}
+            }
+        }
+
+        let pending = shared.pending.lock_unpoisoned();
+        let deadline = Instant::now() + delay;
+        let pending = shared
+            .changed
+            .wait_timeout_while(pending, delay, |pending| {
+                !pending.stop && Instant::now() < deadline
+            })
+            .map(|(pending, _)| pending)
+            .unwrap_or_else(|e| e.into_inner().0);
+        if pending.stop {
+            return;
+        }
+        drop(pending);
+        delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+    }
+}
+
+/// A connection to Companion.
+struct Connection<'a> {
+    shared: &'a SatelliteShared,
+    speed_editor: &'a SpeedEditor,
+    stream: TcpStream,
+    device_id: String,
+    /// Whether Companion added the surface.
+    added: bool,
+    /// The bytes of a line that was not received completely yet.
+    received: Vec<u8>,
+    unsent: Vec<u8>,
+    last_received: Instant,
+    last_ping: Instant,
+}
+
+impl<'a> Connection<'a> {
+    fn new(shared: &'a SatelliteShared, speed_editor: &'a SpeedEditor, stream: TcpStream) -> Self {
+        let device_id = shared.options.device_id.clone().unwrap_or_else(|| {
+            let serial_number = speed_editor.device_info().and_then(|info| info.serial_number);
+            format!("bmdse-{}", serial_number.as_deref().unwrap_or("speed-editor"))
+        });
+        let now = Instant::now();
+        Self {
+            shared,
+            speed_editor,
+            stream,
+            device_id,
+            added: false,
+            received: Vec::new(),
+            unsent: Vec::new(),
+            last_received: now,
+            last_ping: now,
+        }
+    }
+
+    /// Serves the connection until it is lost, or until a stop is requested.
+    fn run(&mut self) -> io::Result<()> {
+        self.stream.set_nonblocking(true)?;
+        self.stream.set_nodelay(true)?;
+
+        loop {
+            let messages = {
+                let mut pending = self.shared.wait(POLL_INTERVAL);
+                if pending.stop {
+                    drop(pending);
+                    // Companion would show the surface as disconnected until it is removed.
+                    self.send(&ClientMessage::RemoveDevice { device_id: self.device_id.clone() });
+                    let _ = self.write_unsent();
+                    return Ok(());
+                }
+                std::mem::take(&mut pending.messages)
+            };
+            for message in &messages {
+                self.send(message);
+            }
+
+            self.read_lines()?;
+
+            let now = Instant::now();
+            if now.duration_since(self.last_received) >= RECEIVE_TIMEOUT {
+                return Err(io::Error::new(io::ErrorKind::TimedOut, "companion: no response"));
+            }
+            if now.duration_since(self.last_ping) >= PING_INTERVAL {
+                self.last_ping = now;
+                self.send(&ClientMessage::Ping("bmdse".to_string()));
+            }
+
+            self.write_unsent()?;
+            if self.unsent.len() > MAX_UNSENT {
+                return Err(io::Error::new(io::ErrorKind::TimedOut, "companion: not receiving"));
+            }
+        }
+    }
+
+    fn send(&mut self, message: &ClientMessage) {
+        self.unsent.extend_from_slice(message.to_string().as_bytes());
+        self.unsent.push(b'\n');
+    }
+
+    /// Writes as much of the unsent bytes as the socket takes without blocking.
+    fn write_unsent(&mut self) -> io::Result<()> {
+        while !self.unsent.is_empty() {
+            match self.stream.write(&self.unsent) {
+                Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+                Ok(written) => drop(self.unsent.drain(..written)),
+                Err(error) if error.kind() == io::ErrorKind::WouldBlock => break,
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => {}
+                Err(error) => return Err(error),
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads what Companion sent without blocking, and handles the complete lines.
+    fn read_lines(&mut self) -> io::Result<()> {
+        let mut buffer = [0; 4096];
+        loop {
+            match self.stream.read(&mut buffer) {
+                Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+                Ok(len) => {
+                    self.received.extend_from_slice(&buffer[..len]);
+                    self.last_received = Instant::now();
+                }
+                Err(error) if error.kind() == io::ErrorKind::WouldBlock => break,
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => {}
+                Err(error) => return Err(error),
+            }
+        }
+
+        while let Some(end) = self.received.iter().position(|&byte| byte == b'\n') {
+            let line = self.received.drain(..=end).collect::<Vec<_>>();
+            let line = String::from_utf8_lossy(&line);
+            self.handle(ServerMessage::parse(line.trim_end_matches(['\r', '\n'])))?;
+        }
+        Ok(())
+    }
+
+    fn handle(&mut self, message: ServerMessage) -> io::Result<()> {
+        match message {
+            ServerMessage::Begin { .. } => self.send(&ClientMessage::AddDevice {
+                device_id: self.device_id.clone(),
+                product_name: "DaVinci Resolve Speed Editor".to_string(),
+                keys_total: KEYS_TOTAL,
+                keys_per_row: KEYS_PER_ROW,
+            }),
+            ServerMessage::AddDeviceOk { device_id } if device_id == self.device_id => {
+                self.added = true;
+                self.shared.pending.lock_unpoisoned().device_id = Some(device_id);
+                self.shared.connected.store(true, Ordering::Release);
+            }
+            ServerMessage::AddDeviceError { device_id, message } if device_id == self.device_id => {
+                return Err(io::Error::other(format!("companion: {message}")));
+            }
+            ServerMessage::KeyState { device_id, key, color } if device_id == self.device_id => {
+                let lit = color.is_some_and(|color| color != [0, 0, 0]);
+                let led = LAYOUT.get(key as usize).and_then(Button::to_led);
+                match led {
+                    Some(Led::Button(led)) if lit => self.speed_editor.set_button_led(led),
+                    Some(Led::Button(led)) if self.speed_editor.button_led() == led => {
+                        self.speed_editor.set_button_led(ButtonLed::Off);
+                    }
+                    Some(Led::Wheel(led)) if lit => self.speed_editor.set_wheel_led(led),
+                    Some(Led::Wheel(led)) if self.speed_editor.wheel_led() == led => {
+                        self.speed_editor.set_wheel_led(WheelLed::Off);
+                    }
+                    _ => {}
+                }
+            }
+            ServerMessage::KeysClear { device_id } if device_id == self.device_id => {
+                self.speed_editor.set_button_led(ButtonLed::Off);
+                self.speed_editor.set_wheel_led(WheelLed::Off);
+            }
+            ServerMessage::Ping(payload) => self.send(&ClientMessage::Pong(payload)),
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader},
+        net::TcpListener,
+    };
+
+    use super::*;
+    use crate::test_util::{TIMEOUT, connect, wait_for};
+
+    #[test]
+    fn parse_quoted_values() {
+        assert_eq!(
+            ServerMessage::parse(r#"KEY-STATE DEVICEID="my surface" KEY=3 COLOR="rgb(255, 0, 0)""#),
+            ServerMessage::KeyState {
+                device_id: "my surface".to_string(),
+                key: 3,
+                color: Some([255, 0, 0]),
+            },
+        );
+        assert_eq!(
+            ServerMessage::parse(r#"ADD-DEVICE ERROR  DEVICEID=a MESSAGE="say \"hi\" \\o/""#),
+            ServerMessage::AddDeviceError {
+                device_id: "a".to_string(),
+                message: r#"say "hi" \o/"#.to_string(),
+            },
+        );
+        // A quote that is not closed runs until the end of the line.
+        assert_eq!(
+            ServerMessage::parse(r#"ADD-DEVICE ERROR DEVICEID=a MESSAGE="cut off"#),
+            ServerMessage::AddDeviceError {
+                device_id: "a".to_string(),
+                message: "cut off".to_string(),
+            },
+        );
+        assert_eq!(
+            ServerMessage::parse("KEYS-CLEAR DEVICEID="),
+            ServerMessage::KeysClear { device_id: String::new() },
+        );
+    }
+
+    #[test]
+    fn parse_unknown_commands() {
+        for line in [
+            "",
+            "BRIGHTNESS DEVICEID=a VALUE=100",
+            "ping 1234",
+            "ADD-DEVICE",
+            "ADD-DEVICE MAYBE DEVICEID=a",
+            "KEY-STATE DEVICEID=a KEY=first",
+            "KEY-STATE DEVICEID=a",
+        ] {
+            assert_eq!(ServerMessage::parse(line), ServerMessage::Other(line.to_string()));
+        }
+    }
+
+    #[test]
+    fn parse_colors() {
+        let cases = [
+            ("#ff8000", Some([0xff, 0x80, 0x00])),
+            ("#FF8000", Some([0xff, 0x80, 0x00])),
+            ("rgb(1,2,3)", Some([1, 2, 3])),
+            ("rgb( 1, 2, 3 )", Some([1, 2, 3])),
+            ("#ff80", None),
+            ("#ff80000", None),
+            ("#gg8000", None),
+            ("rgb(1,2)", None),
+            ("rgb(1,2,3,4)", None),
+            ("rgb(256,0,0)", None),
+            ("red", None),
+        ];
+        for (color, parsed) in cases {
+            assert_eq!(parse_color(color), parsed, "{color:?}");
+        }
+    }
+
+    #[test]
+    fn format_quoted_values() {
+        let add_device = ClientMessage::AddDevice {
+            device_id: "a".to_string(),
+            product_name: r#"Speed "Editor" \ 2"#.to_string(),
+            keys_total: 48,
+            keys_per_row: 8,
+        };
+        let line = add_device.to_string();
+        let (command, rest) = line.split_once(' ').unwrap();
+        assert_eq!(command, "ADD-DEVICE");
+        assert_eq!(Parameters::parse(rest).get("PRODUCT_NAME"), Some(r#"Speed "Editor" \ 2"#));
+
+        let remove_device = ClientMessage::RemoveDevice { device_id: "a".to_string() };
+        assert_eq!(remove_device.to_string(), "REMOVE-DEVICE DEVICEID=a");
+        let rotate = ClientMessage::KeyRotate {
+            device_id: "a".to_string(),
+            key: WHEEL_KEY,
+            clockwise: true,
+        };
+        assert_eq!(rotate.to_string(), "KEY-ROTATE DEVICEID=a KEY=43 DIRECTION=1");
+    }
+
+    /// The side of Companion in an exchange with a satellite.
+    struct Companion {
+        reader: BufReader<TcpStream>,
+        writer: TcpStream,
+    }
+
+    impl Companion {
+        fn accept(listener: &TcpListener) -> Self {
+            let (stream, _) = listener.accept().unwrap();
+            stream.set_read_timeout(Some(TIMEOUT)).unwrap();
+            Self { reader: BufReader::new(stream.try_clone().unwrap()), writer: stream }
+        }
+
+        /// Sends the parts with a pause in between, so they arrive separately.
+        fn send(&mut self, parts: &[&str]) {
+            for part in parts {
+                self.writer.write_all(part.as_bytes()).unwrap();
+                self.writer.flush().unwrap();
+                thread::sleep(POLL_INTERVAL * 3);
+            }
+        }
+
+        /// Receives the next line, skipping the pings of the satellite.
+        fn receive(&mut self) -> String {
+            loop {
+                let mut line = String::new();
+                self.reader.read_line(&mut line).unwrap();
+                let line = line.strip_suffix('\n').unwrap().to_string();
+                if !line.starts_with("PING ") {
+                    return line;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn exchange_with_partial_lines() {
+        let (speed_editor, backend) = connect();
+        let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let satellite = CompanionSatellite::connect(
+            &speed_editor,
+            listener.local_addr().unwrap(),
+            SatelliteOptions::new().device_id("bmdse-test"),
+        )
+        .unwrap();
+        let mut companion = Companion::accept(&listener);
+
+        companion.send(&["BEGIN CompanionVersion=3.4.0 ", "ApiVersion=1.7.0\r", "\n"]);
+        assert_eq!(
+            companion.receive(),
+            "ADD-DEVICE DEVICEID=bmdse-test PRODUCT_NAME=\"DaVinci Resolve Speed Editor\" \
+             KEYS_TOTAL=48 KEYS_PER_ROW=8 BITMAPS=false COLORS=hex TEXT=false",
+        );
+
+        // Several lines in one part, and a line split over parts.
+        companion.send(&[
+            "ADD-DEVICE OK DEVICEID=bmdse-test\r\nKEY-STATE DEVICEID=bmdse-test KEY=14 CO",
+            "LOR=#ff0000 PRESSED=false\n",
+        ]);
+        assert!(wait_for(|| satellite.is_connected()));
+        assert!(wait_for(|| speed_editor.button_led() == ButtonLed::Cut));
+
+        // Unknown commands are ignored.
+        companion.send(&["BRIGHTNESS DEVICEID=bmdse-test VALUE=100\nPING 1234\r\n"]);
+        assert_eq!(companion.receive(), "PONG 1234");
+
+        backend.push_input_report(&[0x03, 0x00, 0xff, 0xff, 0xff, 0xff, 0x00]);
+        assert_eq!(companion.receive(), "KEY-ROTATE DEVICEID=bmdse-test KEY=43 DIRECTION=0");
+        backend.push_input_report(&[0x04, 0x0f, 0x00, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(companion.receive(), "KEY-PRESS DEVICEID=bmdse-test KEY=14 PRESSED=true");
+
+        drop(satellite);
+        assert_eq!(companion.receive(), "REMOVE-DEVICE DEVICEID=bmdse-test");
+    }
+}
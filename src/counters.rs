@@ -0,0 +1,100 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::driver::Report;
+
+/// A snapshot of counters of what happened in the polling thread, meant for telemetry.
+///
+/// All counters only ever go up for the lifetime of the [`SpeedEditor`][crate::SpeedEditor],
+/// also across reconnects, so rates can be computed from them.
+/// See [`SpeedEditor::counters`][crate::SpeedEditor::counters].
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+#[non_exhaustive]
+pub struct Counters {
+    /// The number of wheel reports that were parsed.
+    pub wheel_reports: u64,
+    /// The number of button reports that were parsed.
+    pub button_reports: u64,
+    /// The number of battery reports that were parsed.
+    pub battery_reports: u64,
+    /// The number of reports with an unknown ID.
+    pub unknown_reports: u64,
+    /// The number of reports that could not be parsed.
+    pub parse_errors: u64,
+    /// The number of failed reads.
+    pub read_errors: u64,
+    /// The number of times the authentication was renewed while connected.
+    pub auth_renewals: u64,
+    /// The number of times the device was opened again after the first connection.
+    pub reconnects: u64,
+    /// The number of failed LED writes.
+    pub led_write_errors: u64,
+    /// The number of times the device returned an authentication timeout of 0,
+    /// or one longer than an hour, which was clamped to an hour.
+    pub invalid_auth_timeouts: u64,
+}
+
+/// Counters behind [`Counters`], updated by the polling thread without taking any locks.
+#[derive(Default)]
+pub(crate) struct AtomicCounters {
+    wheel_reports: AtomicU64,
+    button_reports: AtomicU64,
+    battery_reports: AtomicU64,
+    unknown_reports: AtomicU64,
+    parse_errors: AtomicU64,
+    read_errors: AtomicU64,
+    auth_renewals: AtomicU64,
+    connects: AtomicU64,
+    led_write_errors: AtomicU64,
+    invalid_auth_timeouts: AtomicU64,
+}
+
+impl AtomicCounters {
+    pub(crate) fn record_report(&self, report: &Report) {
+        let counter = match report {
+            Report::Wheel { .. } => &self.wheel_reports,
+            Report::Buttons(_) | Report::EditorKeyboardButtons { .. } => &self.button_reports,
+            Report::Battery { .. } => &self.battery_reports,
+            Report::Unknown { .. } => &self.unknown_reports,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_parse_error(&self) {
+        self.parse_errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_read_error(&self) {
+        self.read_errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_auth_renewal(&self) {
+        self.auth_renewals.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_connect(&self) {
+        self.connects.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_led_write_error(&self) {
+        self.led_write_errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_invalid_auth_timeout(&self) {
+        self.invalid_auth_timeouts.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn snapshot(&self) -> Counters {
+        Counters {
+            wheel_reports: self.wheel_reports.load(Ordering::Relaxed),
+            button_reports: self.button_reports.load(Ordering::Relaxed),
+            battery_reports: self.battery_reports.load(Ordering::Relaxed),
+            unknown_reports: self.unknown_reports.load(Ordering::Relaxed),
+            parse_errors: self.parse_errors.load(Ordering::Relaxed),
+            read_errors: self.read_errors.load(Ordering::Relaxed),
+            auth_renewals: self.auth_renewals.load(Ordering::Relaxed),
+            reconnects: self.connects.load(Ordering::Relaxed).saturating_sub(1),
+            led_write_errors: self.led_write_errors.load(Ordering::Relaxed),
+            invalid_auth_timeouts: self.invalid_auth_timeouts.load(Ordering::Relaxed),
+        }
+    }
+}
@@ -0,0 +1,144 @@
+use std::fmt;
+
+use crate::Model;
+
+/// Identifying information about a connected Speed Editor.
+///
+/// See [`list_devices`][crate::list_devices].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub struct DeviceInfo {
+    /// The platform-specific path of the HID device.
+    pub path: String,
+    /// The serial number of the device, if it reports one.
+    pub serial_number: Option<String>,
+    /// The manufacturer string of the device.
+    pub manufacturer: Option<String>,
+    /// The product string of the device.
+    pub product: Option<String>,
+    /// The release number of the device in binary-coded decimal (`bcdDevice`).
+    pub release_number: u16,
+    /// The USB interface number of the device, or `-1` if it is unknown.
+    pub interface_number: i32,
+    /// The usage page of the top-level HID collection, or `0` if it is unknown.
+    pub usage_page: u16,
+    /// The usage of the top-level HID collection within its usage page,
+    /// or `0` if it is unknown.
+    pub usage: u16,
+    /// How the device is connected to the host.
+    pub transport: Transport,
+    /// The USB product ID of the device.
+    pub product_id: u16,
+    /// The model of the device.
+    pub model: Model,
+}
+
+impl DeviceInfo {
+    /// Returns the firmware version of the device, decoded from its
+    /// [release number][DeviceInfo::release_number].
+    pub fn firmware_version(&self) -> FirmwareVersion {
+        FirmwareVersion::from_bcd(self.release_number)
+    }
+
+    /// Returns `true` if this is a vendor-defined HID collection,
+    /// which is the one carrying the reports of the Speed Editor.
+    ///
+    /// Some platforms (e.g. Windows) list every top-level collection of the device separately.
+    pub(crate) fn is_vendor_defined(&self) -> bool {
+        // The HID specification reserves usage pages 0xFF00 to 0xFFFF for vendors.
+        self.usage_page >= 0xff00
+    }
+}
+
+impl From<&hidapi::DeviceInfo> for DeviceInfo {
+    fn from(info: &hidapi::DeviceInfo) -> Self {
+        Self {
+            path: info.path().to_string_lossy().into_owned(),
+            serial_number: info.serial_number().map(ToString::to_string),
+            manufacturer: info.manufacturer_string().map(ToString::to_string),
+            product: info.product_string().map(ToString::to_string),
+            release_number: info.release_number(),
+            interface_number: info.interface_number(),
+            usage_page: info.usage_page(),
+            usage: info.usage(),
+            transport: Transport::from(info.bus_type()),
+            product_id: info.product_id(),
+            // Devices with an unknown product ID are assumed to be a Speed Editor.
+            model: Model::from_product_id(info.product_id()).unwrap_or(Model::SpeedEditor),
+        }
+    }
+}
+
+/// The firmware version of a Speed Editor, as reported in its USB descriptor.
+///
+/// The Speed Editor has no known report to query the version from the device itself,
+/// so this is decoded from the binary-coded decimal release number (`bcdDevice`),
+/// where `0x0123` is version `1.2.3`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct FirmwareVersion {
+    /// The major version.
+    pub major: u8,
+    /// The minor version.
+    pub minor: u8,
+    /// The patch version.
+    pub patch: u8,
+}
+
+impl FirmwareVersion {
+    fn from_bcd(bcd: u16) -> Self {
+        let [high, low] = bcd.to_be_bytes();
+        Self { major: (high >> 4) * 10 + (high & 0x0f), minor: low >> 4, patch: low & 0x0f }
+    }
+}
+
+impl fmt::Display for FirmwareVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+/// How a Speed Editor is connected to the host.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Transport {
+    /// Connected with a USB cable.
+    Usb,
+    /// Connected over Bluetooth.
+    Bluetooth,
+    /// The platform did not report how the device is connected.
+    #[default]
+    Unknown,
+}
+
+impl From<hidapi::BusType> for Transport {
+    fn from(bus_type: hidapi::BusType) -> Self {
+        match bus_type {
+            hidapi::BusType::Usb => Transport::Usb,
+            hidapi::BusType::Bluetooth => Transport::Bluetooth,
+            _ => Transport::Unknown,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{HidBackend, testing::FakeBackend};
+
+    #[test]
+    fn vendor_defined_usage_pages() {
+        let device_info = FakeBackend::new().device_info().unwrap();
+        for (usage_page, vendor_defined) in [
+            (0x0000, false),
+            (0x0001, false),
+            (0x000c, false),
+            (0xfeff, false),
+            (0xff00, true),
+            (0xffff, true),
+        ] {
+            let device_info = DeviceInfo { usage_page, ..device_info.clone() };
+            assert_eq!(device_info.is_vendor_defined(), vendor_defined, "{usage_page:#06x}");
+        }
+    }
+}
@@ -0,0 +1,42 @@
+//! Diagnostic messages about what the polling thread does.
+//!
+//! Every message is written once, at the place it is about, with the macro of its kind.
+//! They pass it to the `log` facade with the `log` feature, and compile to nothing without it.
+//! A different logging backend is added here, next to `log`, so all backends get the same
+//! messages at the same levels. The arguments are only evaluated when a backend is enabled.
+
+/// Passes the message to every enabled logging backend at the given level.
+macro_rules! emit {
+    ($level:ident, $($arg:tt)+) => {{
+        #[cfg(feature = "log")]
+        ::log::$level!($($arg)+);
+        // Still type checks the message, so it does not break once a backend is enabled.
+        #[cfg(not(feature = "log"))]
+        if false {
+            let _ = format_args!($($arg)+);
+        }
+    }};
+}
+
+/// Something that happens for every report, like receiving one, at the debug level.
+macro_rules! report {
+    ($($arg:tt)+) => { $crate::diagnostics::emit!(debug, $($arg)+) };
+}
+
+/// A change in the connection with the device, like connecting or authenticating,
+/// at the info level.
+macro_rules! connection {
+    ($($arg:tt)+) => { $crate::diagnostics::emit!(info, $($arg)+) };
+}
+
+/// An error the poller recovers from, at the warn level.
+macro_rules! recoverable {
+    ($($arg:tt)+) => { $crate::diagnostics::emit!(warn, $($arg)+) };
+}
+
+/// An error that stops the poller, at the error level.
+macro_rules! fatal {
+    ($($arg:tt)+) => { $crate::diagnostics::emit!(error, $($arg)+) };
+}
+
+pub(crate) use {connection, emit, fatal, recoverable, report};
@@ -0,0 +1,239 @@
+use crate::{Button, ButtonLed, Event, WheelLed};
+
+/// What changed on a [`SpeedEditor`][crate::SpeedEditor] since the last notification.
+///
+/// See [`SpeedEditor::on_state_change`][crate::SpeedEditor::on_state_change].
+/// A button that is both in [`StateDiff::pressed`] and [`StateDiff::released`]
+/// changed more than once, use [`SpeedEditor::is_button_pressed`][crate::SpeedEditor::is_button_pressed]
+/// to get its current state.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub struct StateDiff {
+    /// The buttons that were pressed, in order.
+    pub pressed: Vec<Button>,
+    /// The buttons that were released, in order.
+    pub released: Vec<Button>,
+    /// The sum of the velocities of all wheel changes.
+    pub wheel_delta: i64,
+    /// The new button LED, if it changed.
+    pub button_led: Option<ButtonLed>,
+    /// The new wheel LED, if it changed.
+    pub wheel_led: Option<WheelLed>,
+    /// The new battery percentage (`0..=100`), if it changed.
+    pub battery_level: Option<u8>,
+    /// Whether the battery is charging now, if it changed.
+    pub charging: Option<bool>,
+    /// Whether the device is connected now, if it changed.
+    pub connected: Option<bool>,
+}
+
+impl StateDiff {
+    /// Returns `true` if nothing changed.
+    pub fn is_empty(&self) -> bool {
+        *self == StateDiff::default()
+    }
+}
+
+/// Collects the changes between two notifications of the state change callback.
+#[derive(Default)]
+pub(crate) struct StateDiffTracker {
+    pending: StateDiff,
+    /// The values in the last notification, to leave out what did not change.
+    button_led: ButtonLed,
+    wheel_led: WheelLed,
+    battery_level: Option<u8>,
+    charging: Option<bool>,
+    connected: Option<bool>,
+}
+
+impl StateDiffTracker {
+    pub(crate) fn record(&mut self, event: &Event) {
+        let pending = &mut self.pending;
+        match *event {
+            Event::WheelChange { velocity } => pending.wheel_delta += velocity as i64,
+            Event::ButtonChange { button, pressed: true } => pending.pressed.push(button),
+            Event::ButtonChange { button, pressed: false } => pending.released.push(button),
+            Event::BatteryInfo { charging, percentage } => {
+                pending.battery_level =
+                    (self.battery_level != Some(percentage)).then_some(percentage);
+                pending.charging = (self.charging != Some(charging)).then_some(charging);
+            }
+            Event::EditorKeyboardButtonChange { .. }
+            | Event::ChargingChange { .. }
+            | Event::UnknownReport { .. } => {}
+            Event::Connected(_) => {
+                pending.connected = (self.connected != Some(true)).then_some(true)
+            }
+            Event::Disconnected => {
+                pending.connected = (self.connected != Some(false)).then_some(false);
+            }
+        }
+    }
+
+    /// Returns the changes since the last call, if anything changed.
+    pub(crate) fn take(&mut self, button_led: ButtonLed, wheel_led: WheelLed) -> Option<StateDiff> {
+        self.pending.button_led = (self.button_led != button_led).then_some(button_led);
+        self.pending.wheel_led = (self.wheel_led != wheel_led).then_some(wheel_led);
+        if self.pending.is_empty() {
+            return None;
+        }
+
+        let diff = std::mem::take(&mut self.pending);
+        self.button_led = button_led;
+        self.wheel_led = wheel_led;
+        self.battery_level = diff.battery_level.or(self.battery_level);
+        self.charging = diff.charging.or(self.charging);
+        self.connected = diff.connected.or(self.connected);
+        Some(diff)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use super::*;
+    use crate::test_util::TIMEOUT;
+    use crate::{HidBackend, SpeedEditor, testing::FakeBackend};
+
+    fn record(events: &[Event]) -> StateDiffTracker {
+        let mut tracker = StateDiffTracker::default();
+        for event in events {
+            tracker.record(event);
+        }
+        tracker
+    }
+
+    fn take(tracker: &mut StateDiffTracker) -> Option<StateDiff> {
+        tracker.take(ButtonLed::Off, WheelLed::Off)
+    }
+
+    fn button(button: Button, pressed: bool) -> Event {
+        Event::ButtonChange { button, pressed }
+    }
+
+    #[test]
+    fn nothing_changed() {
+        assert_eq!(take(&mut record(&[])), None);
+        // Charging changes are part of the battery information, unknown reports are no state.
+        let mut tracker = record(&[
+            Event::ChargingChange { charging: true },
+            Event::UnknownReport { id: 0x09, data: vec![0x01] },
+        ]);
+        assert_eq!(take(&mut tracker), None);
+    }
+
+    #[test]
+    fn wheel_changes_are_summed() {
+        let mut tracker = record(&[
+            Event::WheelChange { velocity: 5 },
+            Event::WheelChange { velocity: -2 },
+            Event::WheelChange { velocity: i32::MAX },
+        ]);
+        let diff = take(&mut tracker).unwrap();
+        assert_eq!(diff.wheel_delta, 3 + i32::MAX as i64);
+        assert_eq!(take(&mut tracker), None);
+
+        // Turning back and forth adds up to nothing.
+        let mut tracker =
+            record(&[Event::WheelChange { velocity: 5 }, Event::WheelChange { velocity: -5 }]);
+        assert_eq!(take(&mut tracker), None);
+    }
+
+    #[test]
+    fn buttons_keep_their_order() {
+        let mut tracker = record(&[
+            button(Button::Cut, true),
+            button(Button::In, true),
+            button(Button::Cut, false),
+            button(Button::Cut, true),
+        ]);
+        let diff = take(&mut tracker).unwrap();
+        assert_eq!(diff.pressed, [Button::Cut, Button::In, Button::Cut]);
+        assert_eq!(diff.released, [Button::Cut]);
+        assert_eq!(diff.wheel_delta, 0);
+        assert_eq!(take(&mut tracker), None);
+    }
+
+    #[test]
+    fn battery_changes_are_left_out_when_unchanged() {
+        let mut tracker = record(&[Event::BatteryInfo { charging: false, percentage: 50 }]);
+        let diff = take(&mut tracker).unwrap();
+        assert_eq!((diff.battery_level, diff.charging), (Some(50), Some(false)));
+
+        tracker.record(&Event::BatteryInfo { charging: false, percentage: 50 });
+        assert_eq!(take(&mut tracker), None);
+
+        tracker.record(&Event::BatteryInfo { charging: true, percentage: 50 });
+        let diff = take(&mut tracker).unwrap();
+        assert_eq!((diff.battery_level, diff.charging), (None, Some(true)));
+
+        // Only the last battery information of a pass counts.
+        tracker.record(&Event::BatteryInfo { charging: true, percentage: 40 });
+        tracker.record(&Event::BatteryInfo { charging: true, percentage: 50 });
+        assert_eq!(take(&mut tracker), None);
+    }
+
+    #[test]
+    fn led_changes_are_reported_once() {
+        let mut tracker = record(&[]);
+        let diff = tracker.take(ButtonLed::Cut, WheelLed::Off).unwrap();
+        assert_eq!((diff.button_led, diff.wheel_led), (Some(ButtonLed::Cut), None));
+        assert_eq!(tracker.take(ButtonLed::Cut, WheelLed::Off), None);
+
+        let diff = tracker.take(ButtonLed::Cut, WheelLed::Jog).unwrap();
+        assert_eq!((diff.button_led, diff.wheel_led), (None, Some(WheelLed::Jog)));
+        let diff = tracker.take(ButtonLed::Off, WheelLed::Off).unwrap();
+        assert_eq!((diff.button_led, diff.wheel_led), (Some(ButtonLed::Off), Some(WheelLed::Off)));
+    }
+
+    #[test]
+    fn connection_changes() {
+        let device_info = FakeBackend::new().device_info().unwrap();
+        let mut tracker = record(&[Event::Connected(device_info.clone())]);
+        assert_eq!(take(&mut tracker).unwrap().connected, Some(true));
+
+        tracker.record(&Event::Connected(device_info.clone()));
+        assert_eq!(take(&mut tracker), None);
+
+        tracker.record(&Event::Disconnected);
+        tracker.record(&Event::Connected(device_info));
+        assert_eq!(take(&mut tracker), None);
+
+        tracker.record(&Event::Disconnected);
+        assert_eq!(take(&mut tracker).unwrap().connected, Some(false));
+    }
+
+    #[test]
+    fn reports_of_one_pass_are_one_diff() {
+        let backend = FakeBackend::new();
+        backend.push_auth_handshake();
+        let (sender, receiver) = mpsc::channel();
+        let speed_editor = SpeedEditor::builder()
+            .on_state_change(move |diff| sender.send(diff.clone()).unwrap())
+            .connect_backend(backend.clone())
+            .unwrap();
+        assert_eq!(receiver.recv_timeout(TIMEOUT).unwrap().connected, Some(true));
+        // The battery report is requested after connecting, which could take the one below.
+        assert!(backend.wait_until_idle(TIMEOUT));
+
+        backend.set_unresponsive(true);
+        backend.push_input_report(&[0x04, 0x0f, 0x00, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        backend.push_input_report(&[0x03, 0x00, 0x05, 0x00, 0x00, 0x00, 0x00]);
+        backend.push_input_report(&[0x03, 0x00, 0xfe, 0xff, 0xff, 0xff, 0x00]);
+        backend.push_input_report(&[0x04, 0x07, 0x00, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        backend.push_input_report(&[0x07, 0x01, 0x50]);
+        backend.set_unresponsive(false);
+
+        let diff = receiver.recv_timeout(TIMEOUT).unwrap();
+        assert_eq!(diff.pressed, [Button::Cut, Button::In]);
+        assert_eq!(diff.released, [Button::Cut]);
+        assert_eq!(diff.wheel_delta, 3);
+        assert_eq!((diff.battery_level, diff.charging), (Some(80), Some(true)));
+        assert_eq!(diff.connected, None);
+
+        speed_editor.set_button_led(ButtonLed::Cut);
+        let diff = receiver.recv_timeout(TIMEOUT).unwrap();
+        assert_eq!(diff, StateDiff { button_led: Some(ButtonLed::Cut), ..StateDiff::default() });
+    }
+}
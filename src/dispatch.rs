@@ -0,0 +1,517 @@
+//! Calling the callbacks and sinks without holding the lock on the shared state.
+//!
+//! While [`Inner`] is locked, everything for the callbacks and sinks is queued as a [`Call`].
+//! When the [`InnerGuard`] is dropped, the lock is released first, and the queued calls are made
+//! with the callbacks taken out of [`Inner`]. So a callback can use every method of a (cloned)
+//! [`SpeedEditor`][crate::SpeedEditor], which locks [`Inner`] again.
+//!
+//! Only one thread calls the callbacks at a time, in the order the calls were queued. Calls that
+//! are queued meanwhile are made by that thread before it puts the callbacks back. Changing the
+//! callbacks from another thread waits until they are back, so after unregistering a callback it
+//! is not called anymore. Changing them from within a callback is applied once they are back.
+
+use std::{
+    collections::VecDeque,
+    ops::{Deref, DerefMut},
+    sync::{Condvar, Mutex, MutexGuard, PoisonError},
+    thread::{self, ThreadId},
+};
+
+use crate::{
+    Button, ButtonLed, DeviceInfo, Event, EventSink, Inner, PollerError, StateDiff, WheelLed,
+    diff::StateDiffTracker, sync::MutexExt,
+};
+
+type RawReportCallback = Box<dyn Fn(&[u8]) + Send>;
+type ErrorCallback = Box<dyn Fn(&PollerError) + Send>;
+type StateChangeCallback = Box<dyn Fn(&StateDiff) + Send>;
+type CallbacksChange = Box<dyn FnOnce(&mut Callbacks) + Send>;
+
+/// The callbacks and sinks of a [`SpeedEditor`][crate::SpeedEditor].
+#[derive(Default)]
+pub(crate) struct Callbacks {
+    pub(crate) on_wheel_change: Option<Box<dyn Fn(i32) + Send>>,
+    pub(crate) on_button_change: Option<Box<dyn Fn(Button, bool) + Send>>,
+    pub(crate) on_battery_info: Option<Box<dyn Fn(bool, u8) + Send>>,
+    pub(crate) on_charging_change: Option<Box<dyn Fn(bool) + Send>>,
+    pub(crate) on_raw_report: Option<RawReportCallback>,
+    pub(crate) on_connect: Option<Box<dyn Fn(DeviceInfo) + Send>>,
+    pub(crate) on_disconnect: Option<Box<dyn Fn() + Send>>,
+    pub(crate) on_error: Option<ErrorCallback>,
+    pub(crate) on_state_change: Option<StateChangeCallback>,
+    /// The changes since the last call to `on_state_change`.
+    state_diff: StateDiffTracker,
+    pub(crate) on_idle: Option<Box<dyn Fn() + Send>>,
+    pub(crate) on_active: Option<Box<dyn Fn() + Send>>,
+    pub(crate) sinks: Vec<Box<dyn EventSink>>,
+}
+
+impl Callbacks {
+    /// Returns `true` if a callback or sink receives the event.
+    ///
+    /// The state change callback only receives the events that are part of a [`StateDiff`].
+    fn receives(&self, event: &Event) -> bool {
+        let state_change = self.on_state_change.is_some();
+        let has_callback = match event {
+            Event::WheelChange { .. } => self.on_wheel_change.is_some() || state_change,
+            Event::ButtonChange { .. } => self.on_button_change.is_some() || state_change,
+            Event::BatteryInfo { .. } => self.on_battery_info.is_some() || state_change,
+            Event::ChargingChange { .. } => self.on_charging_change.is_some(),
+            Event::EditorKeyboardButtonChange { .. } | Event::UnknownReport { .. } => false,
+            Event::Connected(_) => self.on_connect.is_some() || state_change,
+            Event::Disconnected => self.on_disconnect.is_some() || state_change,
+        };
+        has_callback || !self.sinks.is_empty()
+    }
+
+    /// Passes the event to its callback and to all attached sinks,
+    /// detaching the sinks that are closed.
+    ///
+    /// Returns the event if nothing receives it.
+    fn emit(&mut self, event: Event) -> Option<Event> {
+        if self.on_state_change.is_some() {
+            self.state_diff.record(&event);
+        }
+        if !self.receives(&event) {
+            return Some(event);
+        }
+
+        match event {
+            Event::WheelChange { velocity } => {
+                if let Some(on_wheel_change) = &self.on_wheel_change {
+                    on_wheel_change(velocity);
+                }
+            }
+            Event::ButtonChange { button, pressed } => {
+                if let Some(on_button_change) = &self.on_button_change {
+                    on_button_change(button, pressed);
+                }
+            }
+            Event::BatteryInfo { charging, percentage } => {
+                if let Some(on_battery_info) = &self.on_battery_info {
+                    on_battery_info(charging, percentage);
+                }
+            }
+            Event::ChargingChange { charging } => {
+                if let Some(on_charging_change) = &self.on_charging_change {
+                    on_charging_change(charging);
+                }
+            }
+            Event::EditorKeyboardButtonChange { .. } | Event::UnknownReport { .. } => {}
+            Event::Connected(ref device_info) => {
+                if let Some(on_connect) = &self.on_connect {
+                    on_connect(device_info.clone());
+                }
+            }
+            Event::Disconnected => {
+                if let Some(on_disconnect) = &self.on_disconnect {
+                    on_disconnect();
+                }
+            }
+        }
+
+        self.sinks.retain(|sink| sink.send(event.clone()).is_ok());
+        None
+    }
+
+    /// Makes the call, returning its event if nothing receives it.
+    fn call(&mut self, call: Call) -> Option<Event> {
+        match call {
+            Call::Event(event) => return self.emit(event),
+            Call::RawReport(report_bytes) => {
+                if let Some(on_raw_report) = &self.on_raw_report {
+                    on_raw_report(&report_bytes);
+                }
+            }
+            Call::Error(error) => {
+                if let Some(on_error) = &self.on_error {
+                    on_error(&error);
+                }
+            }
+            Call::StateChange { button_led, wheel_led } => {
+                if let Some(on_state_change) = &self.on_state_change
+                    && let Some(diff) = self.state_diff.take(button_led, wheel_led)
+                {
+                    on_state_change(&diff);
+                }
+            }
+            Call::Idle => {
+                if let Some(on_idle) = &self.on_idle {
+                    on_idle();
+                }
+            }
+            Call::Active => {
+                if let Some(on_active) = &self.on_active {
+                    on_active();
+                }
+            }
+        }
+        None
+    }
+}
+
+/// A call of the callbacks, queued while [`Inner`] is locked.
+pub(crate) enum Call {
+    /// Passes the event to its callback and to the sinks.
+    Event(Event),
+    RawReport(Vec<u8>),
+    Error(PollerError),
+    /// Passes what changed since the last one to the state change callback,
+    /// with the LEDs as they were when it was queued.
+    StateChange {
+        button_led: ButtonLed,
+        wheel_led: WheelLed,
+    },
+    Idle,
+    Active,
+}
+
+/// The callbacks, and what is waiting for them. Part of [`Inner`].
+pub(crate) struct Dispatch {
+    /// The callbacks, or [`None`] while they are being called.
+    callbacks: Option<Callbacks>,
+    /// The thread calling the callbacks.
+    calling_thread: Option<ThreadId>,
+    calls: VecDeque<Call>,
+    /// Changes made from within a callback, in order.
+    changes: Vec<CallbacksChange>,
+}
+
+impl Default for Dispatch {
+    fn default() -> Self {
+        Self {
+            callbacks: Some(Callbacks::default()),
+            calling_thread: None,
+            calls: VecDeque::new(),
+            changes: Vec::new(),
+        }
+    }
+}
+
+impl Dispatch {
+    /// Changes the callbacks, or queues the change while they are being called.
+    ///
+    /// Outside of [`SpeedEditorBuilder`][crate::SpeedEditorBuilder], this is done through
+    /// [`InnerGuard::update_callbacks`], which waits for other threads calling them.
+    pub(crate) fn update(&mut self, change: impl FnOnce(&mut Callbacks) + Send + 'static) {
+        match &mut self.callbacks {
+            Some(callbacks) => change(callbacks),
+            None => self.changes.push(Box::new(change)),
+        }
+    }
+
+    /// Queues the call, unless `wanted` tells that the callbacks ignore it.
+    ///
+    /// While the callbacks are being called it is always queued, as they might change meanwhile.
+    pub(crate) fn queue(
+        &mut self,
+        wanted: impl FnOnce(&Callbacks) -> bool,
+        call: impl FnOnce() -> Call,
+    ) {
+        if self.callbacks.as_ref().is_none_or(wanted) {
+            self.calls.push_back(call());
+        }
+    }
+
+    /// Queues the event, or returns it if nothing receives it.
+    pub(crate) fn emit(&mut self, event: Event) -> Option<Event> {
+        if self.callbacks.as_ref().is_some_and(|callbacks| !callbacks.receives(&event)) {
+            return Some(event);
+        }
+        self.calls.push_back(Call::Event(event));
+        None
+    }
+}
+
+impl Inner {
+    /// Puts the callbacks back after calling them, applying the changes made meanwhile,
+    /// and buffers the events that nothing received.
+    fn restore_callbacks(&mut self, mut callbacks: Callbacks, unreceived: Vec<Event>) {
+        let changed = !self.dispatch.changes.is_empty();
+        for change in self.dispatch.changes.drain(..) {
+            change(&mut callbacks);
+        }
+        self.dispatch.callbacks = Some(callbacks);
+        self.dispatch.calling_thread = None;
+
+        for event in unreceived {
+            self.event_buffer.push(event);
+        }
+        // A callback registered from within a callback receives the events buffered meanwhile.
+        if changed {
+            self.flush_buffered_events();
+        }
+    }
+}
+
+/// The shared state of a [`SpeedEditor`][crate::SpeedEditor], see the [module documentation][self].
+pub(crate) struct InnerLock {
+    inner: Mutex<Inner>,
+    /// Notified when the callbacks are put back.
+    callbacks_returned: Condvar,
+}
+
+impl InnerLock {
+    pub(crate) fn new(inner: Inner) -> Self {
+        Self { inner: Mutex::new(inner), callbacks_returned: Condvar::new() }
+    }
+
+    /// Locks the state, recovering it if it was poisoned.
+    ///
+    /// The calls queued while it is locked are made after unlocking, when the guard is dropped.
+    pub(crate) fn lock_unpoisoned(&self) -> InnerGuard<'_> {
+        InnerGuard { lock: self, guard: Some(self.inner.lock_unpoisoned()) }
+    }
+
+    pub(crate) fn clear_poison(&self) {
+        self.inner.clear_poison();
+    }
+}
+
+/// A lock on [`Inner`] that makes the queued calls when it is dropped.
+pub(crate) struct InnerGuard<'a> {
+    lock: &'a InnerLock,
+    /// Only taken when dropped.
+    guard: Option<MutexGuard<'a, Inner>>,
+}
+
+impl InnerGuard<'_> {
+    /// Changes the callbacks.
+    ///
+    /// If another thread is calling them, this waits until it is done, so a callback that is
+    /// replaced or removed is not called anymore afterwards. Within a callback, the change is
+    /// applied once the callback returns.
+    pub(crate) fn update_callbacks(
+        &mut self,
+        change: impl FnOnce(&mut Callbacks) + Send + 'static,
+    ) {
+        let mut guard = self.guard.take().expect("guard is held until dropped");
+        while guard.dispatch.callbacks.is_none()
+            && guard.dispatch.calling_thread != Some(thread::current().id())
+        {
+            guard =
+                self.lock.callbacks_returned.wait(guard).unwrap_or_else(PoisonError::into_inner);
+        }
+        guard.dispatch.update(change);
+        self.guard = Some(guard);
+    }
+}
+
+impl Deref for InnerGuard<'_> {
+    type Target = Inner;
+
+    fn deref(&self) -> &Inner {
+        self.guard.as_ref().expect("guard is held until dropped")
+    }
+}
+
+impl DerefMut for InnerGuard<'_> {
+    fn deref_mut(&mut self) -> &mut Inner {
+        self.guard.as_mut().expect("guard is held until dropped")
+    }
+}
+
+impl Drop for InnerGuard<'_> {
+    fn drop(&mut self) {
+        let Some(mut guard) = self.guard.take() else { return };
+        // A callback panicking during unwinding aborts,
+        // so the calls are left for the next time the lock is released.
+        if thread::panicking() {
+            return;
+        }
+
+        while !guard.dispatch.calls.is_empty() {
+            // The thread calling the callbacks makes these calls too.
+            let Some(callbacks) = guard.dispatch.callbacks.take() else { return };
+            guard.dispatch.calling_thread = Some(thread::current().id());
+            let calls = std::mem::take(&mut guard.dispatch.calls);
+            drop(guard);
+
+            let mut running =
+                Running { lock: self.lock, callbacks: Some(callbacks), unreceived: Vec::new() };
+            for call in calls {
+                running.call(call);
+            }
+
+            guard = self.lock.inner.lock_unpoisoned();
+            running.finish(&mut guard);
+        }
+    }
+}
+
+/// The callbacks while they are taken out of [`Inner`] to call them.
+///
+/// They are put back when this is dropped, so a panicking callback does not take them along.
+struct Running<'a> {
+    lock: &'a InnerLock,
+    callbacks: Option<Callbacks>,
+    unreceived: Vec<Event>,
+}
+
+impl Running<'_> {
+    fn call(&mut self, call: Call) {
+        let Some(callbacks) = &mut self.callbacks else { return };
+        if let Some(event) = callbacks.call(call) {
+            self.unreceived.push(event);
+        }
+    }
+
+    fn finish(&mut self, inner: &mut Inner) {
+        if let Some(callbacks) = self.callbacks.take() {
+            inner.restore_callbacks(callbacks, std::mem::take(&mut self.unreceived));
+            self.lock.callbacks_returned.notify_all();
+        }
+    }
+}
+
+impl Drop for Running<'_> {
+    fn drop(&mut self) {
+        if self.callbacks.is_some() {
+            // The calls queued meanwhile are left for the next time the lock is released.
+            let mut inner = self.lock.inner.lock_unpoisoned();
+            self.finish(&mut inner);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::mpsc, thread};
+
+    use crate::test_util::{CUT_PRESSED, TIMEOUT, WHEEL_REPORT, connect};
+    use crate::{Button, ButtonLed, Event, SpeedEditor, WheelLed, testing::FakeBackend};
+
+    const BATTERY_REPORT: [u8; 3] = [0x07, 0x01, 80];
+    const UNKNOWN_REPORT: [u8; 3] = [0x09, 0x01, 0x02];
+
+    #[test]
+    fn callback_can_use_a_clone() {
+        let (speed_editor, backend) = connect();
+        speed_editor.set_wheel_led(WheelLed::Jog);
+
+        let (sender, receiver) = mpsc::channel();
+        let clone = speed_editor.clone();
+        speed_editor.set_on_wheel_change(move |_| {
+            sender.send(clone.wheel_led()).unwrap();
+            clone.set_button_led(ButtonLed::Cam1);
+        });
+        backend.push_input_report(&WHEEL_REPORT);
+
+        assert_eq!(receiver.recv_timeout(TIMEOUT), Ok(WheelLed::Jog));
+        assert!(backend.wait_until_idle(TIMEOUT));
+        assert_eq!(speed_editor.button_led(), ButtonLed::Cam1);
+    }
+
+    #[test]
+    fn callback_can_register_a_callback() {
+        let (speed_editor, backend) = connect();
+
+        let (sender, receiver) = mpsc::channel();
+        let clone = speed_editor.clone();
+        speed_editor.set_on_wheel_change(move |velocity| {
+            let sender = sender.clone();
+            clone.set_on_wheel_change(move |next| sender.send((velocity, next)).unwrap());
+        });
+        backend.push_input_report(&WHEEL_REPORT);
+        assert!(backend.wait_until_idle(TIMEOUT));
+        backend.push_input_report(&WHEEL_REPORT);
+
+        assert_eq!(receiver.recv_timeout(TIMEOUT), Ok((5, 5)));
+    }
+
+    #[test]
+    fn leds_can_be_set_from_many_threads() {
+        const THREADS: usize = 8;
+        const ITERATIONS: usize = 200;
+
+        let (speed_editor, backend) = connect();
+        let clone = speed_editor.clone();
+        speed_editor.set_on_wheel_change(move |_| clone.set_wheel_led(WheelLed::Shuttle));
+
+        let threads: Vec<_> = (0..THREADS)
+            .map(|index| {
+                let speed_editor = speed_editor.clone();
+                let backend = backend.clone();
+                thread::spawn(move || {
+                    for _ in 0..ITERATIONS {
+                        let led = if index % 2 == 0 { ButtonLed::Cam1 } else { ButtonLed::Cam2 };
+                        speed_editor.set_button_led(led);
+                        speed_editor.set_wheel_led(WheelLed::Jog);
+                        assert!(matches!(
+                            speed_editor.button_led(),
+                            ButtonLed::Cam1 | ButtonLed::Cam2
+                        ));
+                        backend.push_input_report(&WHEEL_REPORT);
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        assert!(backend.wait_until_idle(TIMEOUT));
+        speed_editor.set_button_led(ButtonLed::Cam3);
+        assert_eq!(speed_editor.button_led(), ButtonLed::Cam3);
+        assert_eq!(speed_editor.wheel_led(), WheelLed::Shuttle);
+    }
+
+    #[test]
+    fn press_during_construction_is_delivered() {
+        let backend = FakeBackend::new();
+        backend.push_auth_handshake();
+        backend.push_input_report(&CUT_PRESSED);
+        let speed_editor = SpeedEditor::builder().connect_backend(backend.clone()).unwrap();
+        assert!(backend.wait_until_idle(TIMEOUT));
+
+        let (sender, receiver) = mpsc::channel();
+        speed_editor
+            .set_on_button_change(move |button, pressed| sender.send((button, pressed)).unwrap());
+        assert_eq!(receiver.try_recv(), Ok((Button::Cut, true)));
+    }
+
+    #[test]
+    fn state_change_callback_leaves_other_events_buffered() {
+        let (speed_editor, backend) = connect();
+        let (diff_sender, diffs) = mpsc::channel();
+        speed_editor.set_on_state_change(move |diff| diff_sender.send(diff.clone()).unwrap());
+        backend.push_input_report(&BATTERY_REPORT);
+        assert!(backend.wait_until_idle(TIMEOUT));
+        assert!(diffs.try_iter().any(|diff| diff.battery_level == Some(80)));
+
+        let (sender, receiver) = mpsc::channel();
+        speed_editor.set_on_charging_change(move |charging| sender.send(charging).unwrap());
+        assert_eq!(receiver.try_recv(), Ok(true));
+    }
+
+    #[test]
+    fn unknown_reports_are_not_buffered() {
+        let (speed_editor, backend) = connect();
+        backend.push_input_report(&UNKNOWN_REPORT);
+        backend.push_input_report(&WHEEL_REPORT);
+        assert!(backend.wait_until_idle(TIMEOUT));
+
+        let (sender, receiver) = mpsc::channel();
+        speed_editor.attach_sink(Box::new(sender));
+        let events: Vec<_> = receiver.try_iter().collect();
+        assert!(events.contains(&Event::WheelChange { velocity: 5 }));
+        assert!(!events.iter().any(|event| matches!(event, Event::UnknownReport { .. })));
+    }
+
+    #[test]
+    fn received_events_are_not_flushed_again() {
+        let (speed_editor, backend) = connect();
+        let (wheel_sender, wheel) = mpsc::channel();
+        speed_editor.set_on_wheel_change(move |velocity| wheel_sender.send(velocity).unwrap());
+        backend.push_input_report(&WHEEL_REPORT);
+        assert_eq!(wheel.recv_timeout(TIMEOUT), Ok(5));
+        assert!(backend.wait_until_idle(TIMEOUT));
+
+        let (sender, receiver) = mpsc::channel();
+        speed_editor.attach_sink(Box::new(sender));
+        assert!(!receiver.try_iter().any(|event| matches!(event, Event::WheelChange { .. })));
+
+        backend.push_input_report(&WHEEL_REPORT);
+        assert_eq!(receiver.recv_timeout(TIMEOUT), Ok(Event::WheelChange { velocity: 5 }));
+    }
+}
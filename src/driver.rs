@@ -1,78 +1,296 @@
 // Thanks to https://github.com/smunaut/blackmagic-misc for reverse
 // engineering the difficult parts like authentication!
 
+use std::{
+    ffi::{CStr, CString},
+    sync::{Arc, Mutex},
+};
+
 use hidapi::{HidApi, HidDevice};
 
-const VENDOR_ID: u16 = 0x1EDB; // Blackmagic Design
-const PRODUCT_ID: u16 = 0xDA0E; // Speed Editor
+use crate::protocol::{
+    AUTH_REPORT_ID, BATTERY_REPORT_ID, BATTERY_REPORT_LEN, BUTTONS_REPORT_ID, BUTTONS_REPORT_LEN,
+    WHEEL_REPORT_ID, WHEEL_REPORT_LEN,
+};
+use crate::registry::{self, OpenDevice, Registration};
+use crate::sync::MutexExt;
+use crate::{HidBackend, auth::AuthSession};
+
+/// The USB vendor ID of Blackmagic Design.
+pub const VENDOR_ID: u16 = 0x1EDB;
+/// The USB product ID of the DaVinci Resolve Speed Editor.
+pub const SPEED_EDITOR_PRODUCT_ID: u16 = 0xDA0E;
+/// The USB product ID of the DaVinci Resolve Editor Keyboard.
+///
+/// Unlike the ID of the Speed Editor, this one has not been verified against a device (yet).
+pub const EDITOR_KEYBOARD_PRODUCT_ID: u16 = 0xDA0B;
+
+/// A supported Blackmagic Design panel.
+///
+/// All models use the same authentication and report layout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub enum Model {
+    /// The DaVinci Resolve Speed Editor.
+    SpeedEditor,
+    /// The DaVinci Resolve Editor Keyboard.
+    ///
+    /// The keys it shares with the Speed Editor are reported as the same [`Button`]s.
+    /// Keys that only exist on the Editor Keyboard are reported as [`EditorKeyboardButton`]s,
+    /// see [`Report::EditorKeyboardButtons`].
+    /// [`ButtonLed`]s are written using [`Model::button_led_bits`].
+    EditorKeyboard,
+}
+
+impl Model {
+    /// All supported models, in the order they are tried when opening any device.
+    const ALL: [Model; 2] = [Model::SpeedEditor, Model::EditorKeyboard];
+
+    /// Returns the USB product ID of this model.
+    pub fn product_id(self) -> u16 {
+        match self {
+            Model::SpeedEditor => SPEED_EDITOR_PRODUCT_ID,
+            Model::EditorKeyboard => EDITOR_KEYBOARD_PRODUCT_ID,
+        }
+    }
+
+    /// Returns the model with the given USB product ID, if it is supported.
+    pub fn from_product_id(product_id: u16) -> Option<Model> {
+        Model::ALL.into_iter().find(|model| model.product_id() == product_id)
+    }
+
+    /// Returns the bits of the button LED report that turn on the [`ButtonLed`] on this model.
+    ///
+    /// The layout of the Editor Keyboard is assumed to be the one of the Speed Editor,
+    /// which has not been verified against a device (yet).
+    ///
+    /// ```
+    /// use bmdse::{ButtonLed, Model};
+    ///
+    /// assert_eq!(Model::SpeedEditor.button_led_bits(ButtonLed::Cam1), 1 << 14);
+    /// ```
+    pub fn button_led_bits(self, led: ButtonLed) -> u32 {
+        match self {
+            Model::SpeedEditor => led as u32,
+            Model::EditorKeyboard => match led {
+                ButtonLed::Off => 0,
+                ButtonLed::CloseUp => 1 << 0,
+                ButtonLed::Cut => 1 << 1,
+                ButtonLed::Dissolve => 1 << 2,
+                ButtonLed::SmoothCut => 1 << 3,
+                ButtonLed::Transition => 1 << 4,
+                ButtonLed::Snap => 1 << 5,
+                ButtonLed::Cam7 => 1 << 6,
+                ButtonLed::Cam8 => 1 << 7,
+                ButtonLed::Cam9 => 1 << 8,
+                ButtonLed::LiveOverwrite => 1 << 9,
+                ButtonLed::Cam4 => 1 << 10,
+                ButtonLed::Cam5 => 1 << 11,
+                ButtonLed::Cam6 => 1 << 12,
+                ButtonLed::VideoOnly => 1 << 13,
+                ButtonLed::Cam1 => 1 << 14,
+                ButtonLed::Cam2 => 1 << 15,
+                ButtonLed::Cam3 => 1 << 16,
+                ButtonLed::AudioOnly => 1 << 17,
+            },
+        }
+    }
+}
 
+/// A report read from the device.
 #[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
 pub enum Report {
-    Wheel { mode: WheelMode, value: i32 },
+    /// The wheel moved.
+    Wheel {
+        /// The mode the wheel is in.
+        mode: WheelMode,
+        /// The velocity or position of the wheel, depending on the mode.
+        value: i32,
+    },
+    /// The buttons that are currently pressed.
     Buttons(Vec<Button>),
-    Battery { charging: bool, level: u8 },
+    /// The buttons that are currently pressed on an [Editor Keyboard][Model::EditorKeyboard].
+    EditorKeyboardButtons {
+        /// The pressed keys that the Speed Editor has too.
+        buttons: Vec<Button>,
+        /// The pressed keys that only the Editor Keyboard has.
+        other: Vec<EditorKeyboardButton>,
+    },
+    /// The state of the battery.
+    Battery {
+        /// Whether the battery is charging.
+        charging: bool,
+        /// The battery level, as a percentage.
+        level: u8,
+    },
+    /// A report with an ID that is not known (yet), e.g. sent by newer firmware.
+    Unknown {
+        /// The ID of the report.
+        id: u8,
+        /// The bytes of the report after the ID.
+        data: Vec<u8>,
+    },
 }
 
-impl TryFrom<&[u8]> for Report {
-    type Error = crate::Error;
-
-    fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
-        if bytes.is_empty() {
-            return Err(Self::Error::Driver { message: "" });
-        }
+impl Report {
+    /// Parses a report read from a device of the given model.
+    ///
+    /// Zeros after the end of the report are ignored, as some backends of hidapi
+    /// return the whole buffer padded with zeros. Reports that are too short,
+    /// or have other bytes after their end, are rejected.
+    pub fn parse(bytes: &[u8], model: Model) -> Result<Self, crate::Error> {
+        let invalid = |message| crate::Error::invalid_report(message, bytes);
 
-        let report_id = bytes[0];
+        let Some(&report_id) = bytes.first() else {
+            return Err(invalid("empty report"));
+        };
 
         let report = match report_id {
-            0x03 => {
-                if bytes.len() != 7 {
-                    return Err(crate::Error::Driver {
-                        message: "invalid length for wheel report",
-                    });
+            WHEEL_REPORT_ID => {
+                if bytes.len() < WHEEL_REPORT_LEN {
+                    return Err(invalid("wheel report is too short"));
                 }
                 Report::Wheel {
-                    mode: WheelMode::try_from(bytes[1])?,
+                    mode: WheelMode::try_from(bytes[1])
+                        .map_err(|_| invalid("received invalid wheel mode"))?,
                     value: i32::from_le_bytes([bytes[2], bytes[3], bytes[4], bytes[5]]),
                 }
             }
-            0x04 => {
-                if bytes.len() != 13 {
-                    return Err(crate::Error::Driver {
-                        message: "invalid length for button report",
-                    });
+            BUTTONS_REPORT_ID => {
+                if bytes.len() < BUTTONS_REPORT_LEN {
+                    return Err(invalid("button report is too short"));
                 }
 
                 let mut buttons = Vec::new();
-                for chunk in bytes[1..13].chunks(2) {
+                let mut other = Vec::new();
+                for chunk in bytes[1..BUTTONS_REPORT_LEN].chunks_exact(2) {
                     let val = u16::from_le_bytes([chunk[0], chunk[1]]);
-                    if val != 0x00 {
-                        buttons.push(Button::try_from(val)?);
+                    if val == 0x00 {
+                        continue;
+                    }
+                    match Button::try_from(val) {
+                        Ok(button) => buttons.push(button),
+                        Err(_) if model == Model::EditorKeyboard => {
+                            other.push(EditorKeyboardButton(val));
+                        }
+                        Err(_) => return Err(invalid("invalid button value received")),
                     }
                 }
 
-                Report::Buttons(buttons)
+                match model {
+                    Model::SpeedEditor => Report::Buttons(buttons),
+                    Model::EditorKeyboard => Report::EditorKeyboardButtons { buttons, other },
+                }
             }
-            0x07 => {
-                if bytes.len() != 3 {
-                    return Err(crate::Error::Driver {
-                        message: "invalid length for battery report",
-                    });
+            BATTERY_REPORT_ID => {
+                if bytes.len() < BATTERY_REPORT_LEN {
+                    return Err(invalid("battery report is too short"));
                 }
 
                 Report::Battery { charging: bytes[1] == 0x01, level: bytes[2] }
             }
-            _ => {
-                return Err(crate::Error::Driver { message: "unknown report" });
-            }
+            id => Report::Unknown { id, data: bytes[1..].to_vec() },
         };
 
+        let len = match report {
+            Report::Wheel { .. } => WHEEL_REPORT_LEN,
+            Report::Buttons(_) | Report::EditorKeyboardButtons { .. } => BUTTONS_REPORT_LEN,
+            Report::Battery { .. } => BATTERY_REPORT_LEN,
+            Report::Unknown { .. } => bytes.len(),
+        };
+        if bytes[len..].iter().any(|&byte| byte != 0) {
+            return Err(invalid("report has bytes after its end that are not padding"));
+        }
+
         Ok(report)
     }
 }
 
+impl TryFrom<&[u8]> for Report {
+    type Error = crate::Error;
+
+    /// Parses a report read from a Speed Editor.
+    ///
+    /// Use [`Report::parse`] for other models.
+    fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+        Report::parse(bytes, Model::SpeedEditor)
+    }
+}
+
+/// Which kinds of [`Report`]s are handled, see [`SpeedEditor::set_report_mask`].
+///
+/// Masks are combined with `|`. Reports with an unknown ID are always handled.
+///
+/// [`SpeedEditor::set_report_mask`]: crate::SpeedEditor::set_report_mask
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ReportMask(u8);
+
+impl ReportMask {
+    /// No reports are handled, except the unknown ones.
+    pub const NONE: ReportMask = ReportMask(0);
+    /// Wheel reports, which emit [`Event::WheelChange`][crate::Event::WheelChange].
+    pub const WHEEL: ReportMask = ReportMask(1 << 0);
+    /// Buttons reports, which emit [`Event::ButtonChange`][crate::Event::ButtonChange]
+    /// and [`Event::EditorKeyboardButtonChange`][crate::Event::EditorKeyboardButtonChange].
+    pub const BUTTONS: ReportMask = ReportMask(1 << 1);
+    /// Battery reports, which emit [`Event::BatteryInfo`][crate::Event::BatteryInfo]
+    /// and [`Event::ChargingChange`][crate::Event::ChargingChange].
+    pub const BATTERY: ReportMask = ReportMask(1 << 2);
+    /// All reports are handled, which is the default.
+    pub const ALL: ReportMask = ReportMask(Self::WHEEL.0 | Self::BUTTONS.0 | Self::BATTERY.0);
+
+    /// Returns `true` if all reports in `other` are also in this mask.
+    pub const fn contains(self, other: ReportMask) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Returns the mask that contains the report with the given ID.
+    ///
+    /// Unknown reports are always handled, so they are in every mask.
+    pub(crate) fn for_report_id(report_id: u8) -> ReportMask {
+        match report_id {
+            WHEEL_REPORT_ID => ReportMask::WHEEL,
+            BUTTONS_REPORT_ID => ReportMask::BUTTONS,
+            BATTERY_REPORT_ID => ReportMask::BATTERY,
+            _ => ReportMask::NONE,
+        }
+    }
+
+    pub(crate) const fn bits(self) -> u8 {
+        self.0
+    }
+
+    pub(crate) const fn from_bits(bits: u8) -> ReportMask {
+        ReportMask(bits & Self::ALL.0)
+    }
+}
+
+impl Default for ReportMask {
+    fn default() -> Self {
+        ReportMask::ALL
+    }
+}
+
+impl std::ops::BitOr for ReportMask {
+    type Output = ReportMask;
+
+    fn bitor(self, rhs: ReportMask) -> ReportMask {
+        ReportMask(self.0 | rhs.0)
+    }
+}
+
+impl std::ops::BitOrAssign for ReportMask {
+    fn bitor_assign(&mut self, rhs: ReportMask) {
+        self.0 |= rhs.0;
+    }
+}
+
 /// Any physical button on the Speed Editor.
 #[repr(u16)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[allow(missing_docs)]
 pub enum Button {
     SmartInsert = 0x0001,
@@ -158,6 +376,60 @@ impl Button {
     }
 }
 
+/// A set of [`Button`]s, without allocating.
+///
+/// The value of every button is below 64, so it is a bit in a `u64`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ButtonSet(u64);
+
+impl ButtonSet {
+    /// Adds the button to this set.
+    pub fn insert(&mut self, button: Button) {
+        self.0 |= 1 << button as u16;
+    }
+
+    /// Returns `true` if the button is in this set.
+    pub fn contains(&self, button: Button) -> bool {
+        self.0 & (1 << button as u16) != 0
+    }
+
+    /// Returns `true` if no button is in this set.
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    /// Returns the buttons in this set that are not in `other`.
+    pub fn difference(self, other: ButtonSet) -> ButtonSet {
+        ButtonSet(self.0 & !other.0)
+    }
+
+    /// Returns the buttons in this set, ordered by their value.
+    pub fn iter(self) -> impl Iterator<Item = Button> {
+        let mut bits = self.0;
+        std::iter::from_fn(move || {
+            while bits != 0 {
+                let bit = bits.trailing_zeros() as u16;
+                bits &= bits - 1;
+                // Only valid buttons are ever inserted.
+                if let Ok(button) = Button::try_from(bit) {
+                    return Some(button);
+                }
+            }
+            None
+        })
+    }
+}
+
+impl FromIterator<Button> for ButtonSet {
+    fn from_iter<I: IntoIterator<Item = Button>>(buttons: I) -> Self {
+        let mut set = ButtonSet::default();
+        for button in buttons {
+            set.insert(button);
+        }
+        set
+    }
+}
+
 impl TryFrom<u16> for Button {
     type Error = crate::Error;
 
@@ -206,16 +478,41 @@ impl TryFrom<u16> for Button {
             0x001c => Ok(Button::Shuttle),
             0x001d => Ok(Button::Jog),
             0x001e => Ok(Button::Scroll),
-            _ => Err(crate::Error::Driver { message: "invalid button value received" }),
+            _ => Err(crate::Error::driver("invalid button value received")),
         }
     }
 }
 
+/// A key of the [Editor Keyboard][Model::EditorKeyboard] that the Speed Editor does not have.
+///
+/// These keys are not named (yet), so they are identified by the code the keyboard reports.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EditorKeyboardButton(u16);
+
+impl EditorKeyboardButton {
+    /// Returns the key with the code reported by the keyboard.
+    pub const fn from_code(code: u16) -> EditorKeyboardButton {
+        EditorKeyboardButton(code)
+    }
+
+    /// Returns the code reported by the keyboard for this key.
+    pub const fn code(self) -> u16 {
+        self.0
+    }
+}
+
+/// How the wheel reports its movement.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(u8)]
 pub enum WheelMode {
+    /// The velocity of the wheel.
+    #[default]
     Relative = 0x00,
+    /// The position of the wheel, which keeps counting in both directions.
     AbsoluteContinuous = 0x01,
+    /// The position of the wheel, with a dead zone around zero.
     AbsoluteDeadZero = 0x03,
 }
 
@@ -228,7 +525,7 @@ impl TryFrom<u8> for WheelMode {
             0x01 => Ok(WheelMode::AbsoluteContinuous),
             0x02 => Ok(WheelMode::Relative), // NOTE: 0x00 and 0x02 appear to be the same.
             0x03 => Ok(WheelMode::AbsoluteDeadZero),
-            _ => Err(crate::Error::Driver { message: "received invalid wheel mode" }),
+            _ => Err(crate::Error::driver("received invalid wheel mode")),
         }
     }
 }
@@ -240,6 +537,7 @@ impl TryFrom<u8> for WheelMode {
 ///
 /// The [`Off`][ButtonLed::Off] variant can be used to disable all button LEDs.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(u32)]
 #[allow(missing_docs)]
 pub enum ButtonLed {
@@ -273,6 +571,7 @@ pub enum ButtonLed {
 ///
 /// The [`Off`][WheelLed::Off] variant can be used to disable all wheel LEDs.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(u32)]
 #[allow(missing_docs)]
 pub enum WheelLed {
@@ -284,177 +583,728 @@ pub enum WheelLed {
     Scroll = 1 << 2,
 }
 
+impl TryFrom<u32> for ButtonLed {
+    type Error = crate::Error;
+
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(ButtonLed::Off),
+            0x00001 => Ok(ButtonLed::CloseUp),
+            0x00002 => Ok(ButtonLed::Cut),
+            0x00004 => Ok(ButtonLed::Dissolve),
+            0x00008 => Ok(ButtonLed::SmoothCut),
+            0x00010 => Ok(ButtonLed::Transition),
+            0x00020 => Ok(ButtonLed::Snap),
+            0x00040 => Ok(ButtonLed::Cam7),
+            0x00080 => Ok(ButtonLed::Cam8),
+            0x00100 => Ok(ButtonLed::Cam9),
+            0x00200 => Ok(ButtonLed::LiveOverwrite),
+            0x00400 => Ok(ButtonLed::Cam4),
+            0x00800 => Ok(ButtonLed::Cam5),
+            0x01000 => Ok(ButtonLed::Cam6),
+            0x02000 => Ok(ButtonLed::VideoOnly),
+            0x04000 => Ok(ButtonLed::Cam1),
+            0x08000 => Ok(ButtonLed::Cam2),
+            0x10000 => Ok(ButtonLed::Cam3),
+            0x20000 => Ok(ButtonLed::AudioOnly),
+            _ => Err(crate::Error::driver("invalid button LED value")),
+        }
+    }
+}
+
+impl TryFrom<u32> for WheelLed {
+    type Error = crate::Error;
+
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(WheelLed::Off),
+            0x1 => Ok(WheelLed::Jog),
+            0x2 => Ok(WheelLed::Shuttle),
+            0x4 => Ok(WheelLed::Scroll),
+            _ => Err(crate::Error::driver("invalid wheel LED value")),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Led {
     Button(ButtonLed),
     Wheel(WheelLed),
 }
 
-pub fn get_hid_device() -> Result<HidDevice, crate::Error> {
-    let api = HidApi::new().map_err(|_| crate::Error::HidApiAlreadyInitialized)?;
+/// How to find and open a Speed Editor.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct OpenOptions {
+    /// Which of the found devices to open.
+    pub selector: DeviceSelector,
+    /// Whether to open the device exclusively, or [`None`] to keep the platform default.
+    pub exclusive: Option<bool>,
+    /// The vendor and product ID to look for instead of those of the supported models.
+    pub ids: Option<(u16, u16)>,
+}
 
-    let device = api.open(VENDOR_ID, PRODUCT_ID).map_err(|_| crate::Error::CannotOpenHidDevice)?;
+/// Which Speed Editor to open.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub enum DeviceSelector {
+    /// The first Speed Editor that is found.
+    #[default]
+    Any,
+    /// The Speed Editor with the given serial number.
+    SerialNumber(String),
+    /// The Speed Editor at the given platform-specific path.
+    Path(String),
+}
 
-    Ok(device)
+impl DeviceSelector {
+    pub fn matches(&self, device_info: &crate::DeviceInfo) -> bool {
+        match self {
+            DeviceSelector::Any => true,
+            DeviceSelector::SerialNumber(serial_number) => {
+                device_info.serial_number.as_ref() == Some(serial_number)
+            }
+            DeviceSelector::Path(path) => &device_info.path == path,
+        }
+    }
 }
 
-pub fn authenticate(device: &mut HidDevice) -> Result<u16, crate::Error> {
-    let mut buf = [0x00; 10];
+/// The process-wide [`HidApi`] used when no external one is provided.
+static SHARED_HID_API: Mutex<Option<HidApi>> = Mutex::new(None);
 
-    // The authentication is performed over SET_FEATURE/GET_FEATURE on
-    // Report ID 6
+/// Where to get the [`HidApi`] from.
+#[derive(Clone, Default)]
+pub enum HidApiSource {
+    /// The process-wide [`HidApi`] of this crate, which is created the first time it is used.
+    #[default]
+    Shared,
+    /// An [`HidApi`] owned by the user.
+    External(Arc<Mutex<HidApi>>),
+}
 
-    // Reset the auth state machine
-    device
-        .send_feature_report(&[0x06, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00])
-        .map_err(|_| crate::Error::Driver { message: "failed to send auth reset" })?;
-
-    fn feature<'a>(buf: &'a mut [u8; 10], device: &HidDevice) -> Result<&'a [u8], crate::Error> {
-        // Prepare buffer and set the Report ID (0x06) before requesting it.
-        // hidapi requires buf[0] to contain the report id for GET_FEATURE.
-        *buf = [0x06, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00];
-        let len = device
-            .get_feature_report(buf)
-            .map_err(|_| crate::Error::Driver { message: "failed to get feature report" })?;
-        Ok(&buf[..len])
-    }
-
-    // Read the keyboard challenge (for keyboard to authenticate app)
-    let data = feature(&mut buf, device)
-        .map_err(|_| crate::Error::Driver { message: "failed to get keyboard challenge" })?;
-    if data.len() < 10 {
-        return Err(crate::Error::Driver { message: "authentication failed" });
-    }
-    if data[0] != 0x06 || data[1] != 0x00 {
-        return Err(crate::Error::Driver { message: "authentication failed" });
-    }
-    let challenge = u64::from_le_bytes(data[2..10].try_into().map_err(|_| {
-        crate::Error::Driver { message: "failed to parse keyboard challenge bytes" }
-    })?);
-
-    // Send our challenge (to authenticate keyboard)
-    // We don't care ... so just send 0x0000000000000000
-    device
-        .send_feature_report(&[0x06, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00])
-        .map_err(|_| crate::Error::Driver { message: "failed to send challenge feature" })?;
+impl HidApiSource {
+    /// Calls `f` with the [`HidApi`], only holding its lock for the duration of the call.
+    pub fn with<T>(
+        &self,
+        f: impl FnOnce(&mut HidApi) -> Result<T, crate::Error>,
+    ) -> Result<T, crate::Error> {
+        match self {
+            HidApiSource::Shared => {
+                let mut api = SHARED_HID_API.lock_unpoisoned();
+                if api.is_none() {
+                    *api = Some(HidApi::new().map_err(|error| {
+                        crate::Error::CannotInitializeHidApi { source: Arc::new(error) }
+                    })?);
+                }
+                f(api.as_mut().unwrap())
+            }
+            HidApiSource::External(api) => f(&mut api.lock_unpoisoned()),
+        }
+    }
+}
+
+pub fn get_hid_device(
+    source: &HidApiSource,
+    options: &OpenOptions,
+) -> Result<OpenDevice, crate::Error> {
+    source.with(|api| open_hid_device(api, options))
+}
 
-    // Read the keyboard response
-    // Again, we don't care, ignore the result
-    let data = feature(&mut buf, device)
-        .map_err(|_| crate::Error::Driver { message: "failed to get keyboard response" })?;
-    if data.len() < 10 {
-        return Err(crate::Error::Driver { message: "authentication failed" });
+pub fn open_hid_device(
+    api: &mut HidApi,
+    options: &OpenOptions,
+) -> Result<OpenDevice, crate::Error> {
+    set_open_exclusive(api, options.exclusive)?;
+
+    // Enumerating first tells a device that is not connected apart from one that cannot be opened.
+    let selector = &options.selector;
+    let device_infos = list_devices_with_ids(api, options.ids)?
+        .into_iter()
+        .filter(|device_info| selector.matches(device_info))
+        .collect();
+    let (device, registration) = open_enumerated(device_infos, |path| api.open_path(path))?;
+    Ok(OpenDevice { device, _registration: registration })
+}
+
+/// Selects the device to open among the enumerated matching devices,
+/// and opens and registers it with `open`.
+fn open_enumerated<T>(
+    mut device_infos: Vec<crate::DeviceInfo>,
+    open: impl FnOnce(&CStr) -> Result<T, hidapi::HidError>,
+) -> Result<(T, Registration), crate::Error> {
+    // Devices that are already open in this process are only picked if nothing else matches,
+    // in which case registering it fails.
+    if device_infos.iter().any(|device_info| !registry::is_open(&device_info.path)) {
+        device_infos.retain(|device_info| !registry::is_open(&device_info.path));
     }
-    if data[0] != 0x06 || data[1] != 0x02 {
-        return Err(crate::Error::Driver { message: "authentication failed" });
+
+    // Opening by ID or serial number might pick another collection (or unit) than the one
+    // that is registered, so the selected device is always opened by its path.
+    let Some(device_info) = select_device(&device_infos) else {
+        return Err(crate::Error::HidDeviceNotFound);
+    };
+    let registration = Registration::new(&device_info.path)?;
+    open(&device_path(&device_info.path)?)
+        .map(|device| (device, registration))
+        .map_err(|error| open_error(device_info.path.clone(), error))
+}
+
+/// Registers a [`HidDevice`] that was opened elsewhere, after checking that it is a Speed Editor
+/// (or has the given IDs), and returns it with its path.
+pub fn adopt_hid_device(
+    device: HidDevice,
+    ids: Option<(u16, u16)>,
+) -> Result<(OpenDevice, String), crate::Error> {
+    let device_info = device
+        .get_device_info()
+        .map_err(|error| crate::Error::hid("failed to get device info", error))?;
+    let (vendor_id, product_id) = (device_info.vendor_id(), device_info.product_id());
+    let supported = match ids {
+        Some(ids) => ids == (vendor_id, product_id),
+        None => vendor_id == VENDOR_ID && Model::from_product_id(product_id).is_some(),
+    };
+    if !supported {
+        return Err(crate::Error::UnsupportedDevice { vendor_id, product_id });
     }
 
-    // Compute and send our response
-    let response = bmd_kbd_auth(challenge);
-    let rb = response.to_le_bytes();
-    device
-        .send_feature_report(&[0x06, 0x03, rb[0], rb[1], rb[2], rb[3], rb[4], rb[5], rb[6], rb[7]])
-        .map_err(|_| crate::Error::Driver { message: "failed to send challenge response" })?;
+    let path = device_info.path().to_string_lossy().into_owned();
+    let registration = Registration::new(&path)?;
+    Ok((OpenDevice { device, _registration: registration }, path))
+}
+
+/// Returns the device to open among the matching devices, preferring a Speed Editor
+/// over other models.
+///
+/// The vendor-defined collection carries the reports, so it is picked if the platform reports
+/// usage pages. Otherwise the first collection of the device is picked.
+fn select_device(device_infos: &[crate::DeviceInfo]) -> Option<&crate::DeviceInfo> {
+    let preference = |device_info: &&crate::DeviceInfo| {
+        Model::ALL.iter().position(|&model| model == device_info.model)
+    };
+    device_infos
+        .iter()
+        .filter(|device_info| device_info.is_vendor_defined())
+        .min_by_key(preference)
+        .or_else(|| device_infos.iter().min_by_key(preference))
+}
+
+fn device_path(path: &str) -> Result<CString, crate::Error> {
+    CString::new(path).map_err(|_| crate::Error::driver("device path contains a nul byte"))
+}
 
-    // Read the status
-    let data = feature(&mut buf, device)
-        .map_err(|_| crate::Error::Driver { message: "failed to get status" })?;
-    if data.len() < 10 {
-        return Err(crate::Error::Driver { message: "authentication failed" });
+/// Turns a failure to open the device at the given path into an error,
+/// pointing out the missing udev rule if the permission was denied on Linux.
+fn open_error(path: String, error: hidapi::HidError) -> crate::Error {
+    if cfg!(target_os = "linux") && crate::error::is_permission_denied(&error) {
+        return crate::Error::PermissionDenied { path, source: Arc::new(error) };
     }
-    if data[0] != 0x06 || data[1] != 0x04 {
-        return Err(crate::Error::Driver { message: "authentication failed" });
+    crate::Error::CannotOpenHidDevice { source: Arc::new(error) }
+}
+
+/// Returns an error if the device cannot be opened with the given exclusivity on this platform.
+///
+/// [`None`] keeps the default of the platform.
+pub fn check_open_exclusive(exclusive: Option<bool>) -> Result<(), crate::Error> {
+    // Only macOS opens devices exclusively, the other platforms always share them.
+    if exclusive == Some(true) && !cfg!(target_os = "macos") {
+        return Err(crate::Error::Unsupported {
+            message: "exclusive device access is only supported on macOS",
+        });
     }
+    Ok(())
+}
 
-    // I "think" what gets returned here is the timeout after which auth
-    // needs to be done again (returns 600 for me which is plausible)
-    Ok(u16::from_le_bytes([data[2], data[3]]))
+#[cfg(target_os = "macos")]
+fn set_open_exclusive(api: &mut HidApi, exclusive: Option<bool>) -> Result<(), crate::Error> {
+    if let Some(exclusive) = exclusive {
+        api.set_open_exclusive(exclusive);
+    }
+    Ok(())
 }
 
-pub fn set_button_led(device: &mut HidDevice, led: ButtonLed) -> Result<(), crate::Error> {
-    let mut buf = [0u8; 5];
-    buf[0] = 2;
-    buf[1..5].copy_from_slice(&(led as u32).to_le_bytes());
-    device
-        .write(&buf)
-        .map_err(|_| crate::Error::Driver { message: "failed to write LED state" })?;
+#[cfg(not(target_os = "macos"))]
+fn set_open_exclusive(_api: &mut HidApi, exclusive: Option<bool>) -> Result<(), crate::Error> {
+    check_open_exclusive(exclusive)
+}
+
+pub fn list_devices(api: &mut HidApi) -> Result<Vec<crate::DeviceInfo>, crate::Error> {
+    list_devices_with_ids(api, None)
+}
+
+/// Lists the devices with the given vendor and product ID,
+/// or all supported models if no IDs are given.
+pub fn list_devices_with_ids(
+    api: &mut HidApi,
+    ids: Option<(u16, u16)>,
+) -> Result<Vec<crate::DeviceInfo>, crate::Error> {
+    // A product ID of 0 adds all devices of the vendor, which are filtered by supported model.
+    let (vendor_id, product_id) = ids.unwrap_or((VENDOR_ID, 0));
+    api.reset_devices()
+        .and_then(|_| api.add_devices(vendor_id, product_id))
+        .map_err(|error| crate::Error::hid("failed to refresh HID devices", error))?;
+    Ok(api
+        .device_list()
+        .filter(|device_info| {
+            ids.is_some() || Model::from_product_id(device_info.product_id()).is_some()
+        })
+        .map(crate::DeviceInfo::from)
+        .collect())
+}
+
+/// Refreshes the device list of the HID API,
+/// and returns `true` if a Speed Editor matching the options is in it.
+pub fn is_device_present(api: &mut HidApi, options: &OpenOptions) -> Result<bool, crate::Error> {
+    Ok(list_devices_with_ids(api, options.ids)?
+        .iter()
+        .any(|device_info| options.selector.matches(device_info)))
+}
+
+/// Authenticates the device, and returns the number of seconds until it has to be done again.
+///
+/// With `host_challenge`, the response of the device to it is verified as well.
+pub fn authenticate(
+    device: &(impl HidBackend + ?Sized),
+    host_challenge: Option<u64>,
+) -> Result<u16, crate::Error> {
+    let mut session = auth_session(host_challenge);
+    loop {
+        if let Some(auth_time) = authenticate_step(device, &mut session)? {
+            return Ok(auth_time);
+        }
+    }
+}
+
+/// Creates the session for [`authenticate_step`], verifying the device with `host_challenge`.
+pub fn auth_session(host_challenge: Option<u64>) -> AuthSession {
+    match host_challenge {
+        Some(host_challenge) => AuthSession::with_host_challenge(host_challenge),
+        None => AuthSession::new(),
+    }
+}
+
+/// Performs a single step of the authentication session, which is a single round trip.
+///
+/// Returns the number of seconds until the device has to be authenticated again
+/// once the session is done, or [`None`] before that.
+pub fn authenticate_step(
+    device: &(impl HidBackend + ?Sized),
+    session: &mut AuthSession,
+) -> Result<Option<u16>, crate::Error> {
+    // The authentication is performed over SET_FEATURE/GET_FEATURE on
+    // Report ID 6, following the steps of the `AuthSession`.
+    let Some(step) = session.expected_next_step() else {
+        return Err(crate::Error::driver("authentication session ended without a status"));
+    };
+    if let Some(report) = session.outgoing_report() {
+        device.send_feature_report(&report).map_err(|error| crate::Error::auth(step, error))?;
+        return Ok(None);
+    }
+
+    // hidapi requires buf[0] to contain the report id for GET_FEATURE.
+    let mut buf = [0x00; 10];
+    buf[0] = AUTH_REPORT_ID;
+    let len =
+        device.get_feature_report(&mut buf).map_err(|error| crate::Error::auth(step, error))?;
+    session.handle_reply(&buf[..len])
+}
+
+pub fn set_button_led(
+    device: &(impl HidBackend + ?Sized),
+    model: Model,
+    led: ButtonLed,
+) -> Result<(), crate::Error> {
+    let buf = crate::protocol::model_button_led_report(model, led);
+    device.write(&buf).map_err(|error| crate::Error::hid("failed to write LED state", error))?;
     Ok(())
 }
 
-pub fn set_wheel_led(device: &mut HidDevice, led: WheelLed) -> Result<(), crate::Error> {
-    let buf = [4u8, led as u8];
+pub fn set_wheel_led(
+    device: &(impl HidBackend + ?Sized),
+    led: WheelLed,
+) -> Result<(), crate::Error> {
+    let buf = crate::protocol::wheel_led_report(led);
     device
         .write(&buf)
-        .map_err(|_| crate::Error::Driver { message: "failed to write wheel LED state" })?;
+        .map_err(|error| crate::Error::hid("failed to write wheel LED state", error))?;
     Ok(())
 }
 
-pub fn _set_wheel_mode(device: &mut HidDevice, wheel_mode: WheelMode) {
-    let mut buf = [0u8; 7];
-    buf[0] = 3;
-    buf[1] = wheel_mode as u8;
-    buf[2..6].copy_from_slice(&0u32.to_le_bytes());
-    buf[6] = 0; // unknown
-    let _ = device.write(&buf);
-}
-
-fn bmd_kbd_auth(challenge: u64) -> u64 {
-    const AUTH_EVEN_TBL: [u64; 8] = [
-        0x3ae1206f97c10bc8,
-        0x2a9ab32bebf244c6,
-        0x20a6f8b8df9adf0a,
-        0xaf80ece52cfc1719,
-        0xec2ee2f7414fd151,
-        0xb055adfd73344a15,
-        0xa63d2e3059001187,
-        0x751bf623f42e0dde,
-    ];
-
-    const AUTH_ODD_TBL: [u64; 8] = [
-        0x3e22b34f502e7fde,
-        0x24656b981875ab1c,
-        0xa17f3456df7bf8c3,
-        0x6df72e1941aef698,
-        0x72226f011e66ab94,
-        0x3831a3c606296b42,
-        0xfd7ff81881332c89,
-        0x61a3f6474ff236c6,
-    ];
-
-    const MASK: u64 = 0xa79a63f585d37bf0;
-
-    let ror8n = |mut v: u64, n: usize| -> u64 {
-        for _ in 0..n {
-            v = v.rotate_right(8);
-        }
-        v
-    };
+pub fn set_wheel_mode(
+    device: &(impl HidBackend + ?Sized),
+    wheel_mode: WheelMode,
+) -> Result<(), crate::Error> {
+    let buf = crate::protocol::wheel_mode_report(wheel_mode);
+    device.write(&buf).map_err(|error| crate::Error::hid("failed to write wheel mode", error))?;
+    Ok(())
+}
 
-    let n = (challenge & 7) as usize;
-    let mut v = ror8n(challenge, n);
+/// Whether [`request_battery`] and [`request_buttons`] are supported on this platform.
+///
+/// The Windows backend of hidapi might not be able to get input reports.
+pub const CAN_REQUEST_INPUT_REPORTS: bool = cfg!(not(target_os = "windows"));
 
-    let k = if (v & 1) == ((0x78 >> n) & 1) {
-        AUTH_EVEN_TBL[n]
-    } else {
-        v = v ^ v.rotate_right(8);
-        AUTH_ODD_TBL[n]
-    };
+/// Asks the device for its battery report (report ID 7), instead of waiting for it to be pushed.
+#[cfg(not(target_os = "windows"))]
+pub fn request_battery<'a>(
+    device: &(impl HidBackend + ?Sized),
+    buf: &'a mut [u8; 64],
+) -> Result<&'a [u8], crate::Error> {
+    buf[0] = BATTERY_REPORT_ID;
+    let len = device
+        .get_input_report(buf)
+        .map_err(|error| crate::Error::hid("failed to request battery report", error))?;
+    Ok(&buf[..len])
+}
 
-    v ^ (v.rotate_right(8) & MASK) ^ k
+#[cfg(target_os = "windows")]
+pub fn request_battery<'a>(
+    _device: &(impl HidBackend + ?Sized),
+    _buf: &'a mut [u8; 64],
+) -> Result<&'a [u8], crate::Error> {
+    Err(crate::Error::Unsupported {
+        message: "requesting a battery update is not supported on Windows",
+    })
 }
 
-pub fn poll(device: &mut HidDevice, timeout: i32) -> Result<Report, crate::Error> {
-    let mut buf = [0x00; 64];
+/// Asks the device for its buttons report (report ID 4), instead of waiting for it to be pushed.
+#[cfg(not(target_os = "windows"))]
+pub fn request_buttons<'a>(
+    device: &(impl HidBackend + ?Sized),
+    buf: &'a mut [u8; 64],
+) -> Result<&'a [u8], crate::Error> {
+    buf[0] = BUTTONS_REPORT_ID;
     let len = device
-        .read_timeout(&mut buf, timeout)
-        .map_err(|_| crate::Error::Driver { message: "failed to read" })?;
+        .get_input_report(buf)
+        .map_err(|error| crate::Error::hid("failed to request buttons report", error))?;
+    Ok(&buf[..len])
+}
+
+#[cfg(target_os = "windows")]
+pub fn request_buttons<'a>(
+    _device: &(impl HidBackend + ?Sized),
+    _buf: &'a mut [u8; 64],
+) -> Result<&'a [u8], crate::Error> {
+    Err(crate::Error::Unsupported { message: "requesting the buttons is not supported on Windows" })
+}
+
+/// Reads a single report, returning [`None`] if no report arrived before the timeout.
+pub fn read<'a>(
+    device: &(impl HidBackend + ?Sized),
+    buf: &'a mut [u8; 64],
+    timeout: i32,
+) -> Result<Option<&'a [u8]>, crate::Error> {
+    let len = device
+        .read_timeout(buf, timeout)
+        .map_err(|error| crate::Error::hid("failed to read", error))?;
     if len == 0 {
-        return Err(crate::Error::Driver { message: "received empty report" });
+        return Ok(None);
     }
-    let report_bytes = &buf[0..len];
+    Ok(Some(&buf[0..len]))
+}
 
-    let report = Report::try_from(report_bytes)
-        .map_err(|_| crate::Error::Driver { message: "failed to parse report" })?;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{FakeBackend, Fault};
 
-    Ok(report)
+    fn device_info(path: &str, model: Model, usage_page: u16) -> crate::DeviceInfo {
+        crate::DeviceInfo {
+            path: path.to_string(),
+            serial_number: None,
+            manufacturer: None,
+            product: None,
+            release_number: 0,
+            interface_number: 0,
+            usage_page,
+            usage: 0x0001,
+            transport: crate::Transport::Usb,
+            product_id: model.product_id(),
+            model,
+        }
+    }
+
+    #[test]
+    fn parse_keeps_unknown_report() {
+        let bytes = [0x09, 0x01, 0x00, 0x02];
+        let report = Report::Unknown { id: 0x09, data: vec![0x01, 0x00, 0x02] };
+        assert_eq!(Report::parse(&bytes, Model::SpeedEditor).unwrap(), report);
+    }
+
+    #[test]
+    fn select_device_prefers_vendor_collection() {
+        let device_infos = [
+            device_info("keyboard", Model::SpeedEditor, 0x0001),
+            device_info("vendor", Model::SpeedEditor, 0xff00),
+        ];
+        assert_eq!(select_device(&device_infos).unwrap().path, "vendor");
+    }
+
+    #[test]
+    fn select_device_prefers_speed_editor() {
+        let device_infos = [
+            device_info("editor-keyboard", Model::EditorKeyboard, 0xff00),
+            device_info("speed-editor", Model::SpeedEditor, 0xff00),
+        ];
+        assert_eq!(select_device(&device_infos).unwrap().path, "speed-editor");
+    }
+
+    #[test]
+    fn select_device_among_windows_collections() {
+        // Windows lists the keyboard, consumer control and vendor collections separately,
+        // in no particular order.
+        let mut device_infos = [
+            device_info("keyboard", Model::SpeedEditor, 0x0001),
+            device_info("consumer", Model::SpeedEditor, 0x000c),
+            device_info("vendor", Model::SpeedEditor, 0xff00),
+        ];
+        for _ in 0..device_infos.len() {
+            device_infos.rotate_left(1);
+            assert_eq!(select_device(&device_infos).unwrap().path, "vendor");
+        }
+    }
+
+    #[test]
+    fn select_device_prefers_vendor_collection_over_model() {
+        // Only the vendor collection carries the reports, whatever the model.
+        let device_infos = [
+            device_info("speed-editor-keyboard", Model::SpeedEditor, 0x0001),
+            device_info("editor-keyboard-vendor", Model::EditorKeyboard, 0xff00),
+        ];
+        assert_eq!(select_device(&device_infos).unwrap().path, "editor-keyboard-vendor");
+    }
+
+    #[test]
+    fn select_device_without_usage_pages() {
+        let device_infos = [
+            device_info("editor-keyboard", Model::EditorKeyboard, 0),
+            device_info("speed-editor-1", Model::SpeedEditor, 0),
+            device_info("speed-editor-2", Model::SpeedEditor, 0),
+        ];
+        assert_eq!(select_device(&device_infos).unwrap().path, "speed-editor-1");
+        assert!(select_device(&[]).is_none());
+    }
+
+    #[test]
+    fn open_enumerated_without_devices() {
+        let result = open_enumerated(Vec::new(), |_| -> Result<(), _> { panic!("opened") });
+        assert!(matches!(result, Err(crate::Error::HidDeviceNotFound)));
+    }
+
+    #[test]
+    fn open_enumerated_opens_selected_path() {
+        let device_infos = vec![
+            device_info("fake-open-keyboard", Model::SpeedEditor, 0x0001),
+            device_info("fake-open-vendor", Model::SpeedEditor, 0xff00),
+        ];
+        let (path, registration) =
+            open_enumerated(device_infos, |path| Ok(path.to_owned())).unwrap();
+        assert_eq!(path.to_str(), Ok("fake-open-vendor"));
+        assert!(registry::is_open("fake-open-vendor"));
+        drop(registration);
+        assert!(!registry::is_open("fake-open-vendor"));
+    }
+
+    #[test]
+    fn open_enumerated_skips_open_devices() {
+        let _registration = Registration::new("fake-skip-1").unwrap();
+        let device_infos = vec![
+            device_info("fake-skip-1", Model::SpeedEditor, 0xff00),
+            device_info("fake-skip-2", Model::SpeedEditor, 0xff00),
+        ];
+        let (path, _) = open_enumerated(device_infos, |path| Ok(path.to_owned())).unwrap();
+        assert_eq!(path.to_str(), Ok("fake-skip-2"));
+
+        // The only matching device is picked even if it is open, and fails to register.
+        let device_infos = vec![device_info("fake-skip-1", Model::SpeedEditor, 0xff00)];
+        let result = open_enumerated(device_infos, |_| -> Result<(), _> { panic!("opened") });
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn open_enumerated_keeps_open_failure() {
+        let device_infos = vec![device_info("fake-busy", Model::SpeedEditor, 0xff00)];
+        let result = open_enumerated(device_infos, |_| -> Result<(), _> {
+            Err(hidapi::HidError::HidApiError { message: "Device or resource busy".to_string() })
+        });
+        let Err(crate::Error::CannotOpenHidDevice { source }) = result else {
+            panic!("unexpected result: {:?}", result.map(|_| ()));
+        };
+        assert_eq!(source.to_string(), "hidapi error: Device or resource busy");
+        // The device is not kept registered after failing to open it.
+        assert!(!registry::is_open("fake-busy"));
+    }
+
+    #[test]
+    fn authenticate_frames_feature_reports() {
+        let backend = FakeBackend::new();
+        backend.push_auth_handshake();
+        assert_eq!(authenticate(&backend, None).unwrap(), 600);
+
+        // Reset, our (empty) challenge, and the response to the challenge of 0 of the device.
+        let response = 0x3ae1206f97c10bc8_u64.to_le_bytes();
+        let mut sent_response = vec![0x06, 0x03];
+        sent_response.extend_from_slice(&response);
+        assert_eq!(
+            backend.sent_feature_reports(),
+            [
+                vec![0x06, 0x00, 0, 0, 0, 0, 0, 0, 0, 0],
+                vec![0x06, 0x01, 0, 0, 0, 0, 0, 0, 0, 0],
+                sent_response
+            ]
+        );
+        assert!(backend.written().is_empty());
+    }
+
+    #[test]
+    fn authenticate_rejects_unexpected_reply() {
+        let backend = FakeBackend::new();
+        // A status where the challenge was expected.
+        backend.push_feature_report(&[0x06, 0x04, 0x58, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]);
+        let error = authenticate(&backend, None).unwrap_err();
+        assert!(matches!(
+            error,
+            crate::Error::Authentication {
+                step: crate::AuthStep::ReadChallenge,
+                detail: crate::AuthFailure::UnexpectedReply { .. }
+            }
+        ));
+        assert_eq!(backend.sent_feature_reports().len(), 1);
+    }
+
+    #[test]
+    fn authenticate_reports_failing_step() {
+        let backend = FakeBackend::new();
+        backend.fail_auth_at(crate::AuthStep::SendResponse, Fault::Error);
+        backend.push_auth_handshake();
+        let error = authenticate(&backend, None).unwrap_err();
+        assert!(matches!(
+            error,
+            crate::Error::Authentication {
+                step: crate::AuthStep::SendResponse,
+                detail: crate::AuthFailure::Hid(_)
+            }
+        ));
+    }
+
+    /// The handshake of a device that answers `host_challenge` with `response`.
+    fn push_verified_handshake(backend: &FakeBackend, response: u64) {
+        let mut reply = [0x06, 0x02, 0, 0, 0, 0, 0, 0, 0, 0];
+        reply[2..].copy_from_slice(&response.to_le_bytes());
+        backend.push_feature_report(&[0x06, 0x00, 0, 0, 0, 0, 0, 0, 0, 0]);
+        backend.push_feature_report(&reply);
+        backend.push_feature_report(&[0x06, 0x04, 0x58, 0x02, 0, 0, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn authenticate_verifies_host_challenge() {
+        // A challenge and response computed with `bmd_kbd_auth` from smunaut/blackmagic-misc.
+        let (host_challenge, response) = (0xdeadbeefcafef00d_u64, 0x6a04b6fcff2b4b21_u64);
+
+        let backend = FakeBackend::new();
+        push_verified_handshake(&backend, response);
+        assert_eq!(authenticate(&backend, Some(host_challenge)).unwrap(), 600);
+        let mut sent_challenge = vec![0x06, 0x01];
+        sent_challenge.extend_from_slice(&host_challenge.to_le_bytes());
+        assert_eq!(backend.sent_feature_reports()[1], sent_challenge);
+
+        let backend = FakeBackend::new();
+        push_verified_handshake(&backend, !response);
+        let error = authenticate(&backend, Some(host_challenge)).unwrap_err();
+        assert!(matches!(
+            error,
+            crate::Error::Authentication {
+                step: crate::AuthStep::ReadResponse,
+                detail: crate::AuthFailure::ResponseMismatch { expected, received },
+            } if expected == response && received == !response
+        ));
+        // Our response is not sent to a device that failed the check.
+        assert_eq!(backend.sent_feature_reports().len(), 2);
+    }
+
+    #[test]
+    fn led_writes_use_output_reports() {
+        let backend = FakeBackend::new();
+        set_wheel_mode(&backend, WheelMode::Relative).unwrap();
+        set_button_led(&backend, Model::SpeedEditor, ButtonLed::Cam1).unwrap();
+        set_wheel_led(&backend, WheelLed::Jog).unwrap();
+        assert_eq!(
+            backend.written(),
+            [
+                crate::protocol::wheel_mode_report(WheelMode::Relative).to_vec(),
+                crate::protocol::button_led_report(ButtonLed::Cam1).to_vec(),
+                crate::protocol::wheel_led_report(WheelLed::Jog).to_vec(),
+            ]
+        );
+
+        backend.fail_writes(1, Fault::Error);
+        assert!(set_button_led(&backend, Model::SpeedEditor, ButtonLed::Cut).is_err());
+        assert_eq!(backend.written().len(), 3);
+    }
+
+    #[test]
+    fn open_error_points_out_permission() {
+        let denied = hidapi::HidError::HidApiError { message: "Permission denied".to_string() };
+        let error = open_error("/dev/hidraw3".to_string(), denied);
+        if cfg!(target_os = "linux") {
+            assert!(
+                matches!(error, crate::Error::PermissionDenied { ref path, .. } if path == "/dev/hidraw3")
+            );
+        } else {
+            assert!(matches!(error, crate::Error::CannotOpenHidDevice { .. }));
+        }
+
+        let busy = hidapi::HidError::HidApiError { message: "Device or resource busy".to_string() };
+        let error = open_error("/dev/hidraw3".to_string(), busy);
+        assert!(matches!(error, crate::Error::CannotOpenHidDevice { .. }));
+    }
+
+    /// A report of every kind, with its parsed form.
+    fn reports() -> [(Vec<u8>, Report); 3] {
+        [
+            (
+                vec![0x03, 0x00, 0xfb, 0xff, 0xff, 0xff, 0x00],
+                Report::Wheel { mode: WheelMode::Relative, value: -5 },
+            ),
+            (
+                vec![0x04, 0x0f, 0x00, 0x07, 0x00, 0, 0, 0, 0, 0, 0, 0, 0],
+                Report::Buttons(vec![Button::Cut, Button::In]),
+            ),
+            (vec![0x07, 0x01, 0x50], Report::Battery { charging: true, level: 80 }),
+        ]
+    }
+
+    #[test]
+    fn parse_exact_length() {
+        for (bytes, report) in reports() {
+            assert_eq!(Report::parse(&bytes, Model::SpeedEditor).unwrap(), report);
+        }
+    }
+
+    #[test]
+    fn parse_zero_padded() {
+        for (mut bytes, report) in reports() {
+            bytes.resize(64, 0x00);
+            assert_eq!(Report::parse(&bytes, Model::SpeedEditor).unwrap(), report);
+        }
+    }
+
+    #[test]
+    fn parse_rejects_truncated() {
+        for (bytes, _) in reports() {
+            for len in 0..bytes.len() {
+                assert!(Report::parse(&bytes[..len], Model::SpeedEditor).is_err(), "{len} bytes");
+            }
+        }
+    }
+
+    #[test]
+    fn parse_rejects_trailing_bytes() {
+        for (mut bytes, _) in reports() {
+            let len = bytes.len();
+            bytes.resize(64, 0x00);
+            bytes[len] = 0x01;
+            assert!(Report::parse(&bytes, Model::SpeedEditor).is_err());
+            bytes[len] = 0x00;
+            bytes[63] = 0xff;
+            assert!(Report::parse(&bytes, Model::SpeedEditor).is_err());
+        }
+    }
 }
@@ -1,33 +1,164 @@
-use std::{error, fmt, io};
+use std::{error, fmt, io, sync::Arc};
+
+use hidapi::HidError;
 
 /// Various error variants used in `bmdse`.
-#[derive(Debug)]
+///
+/// Errors that come from hidapi keep the original [`HidError`] as their
+/// [source][error::Error::source]. They are wrapped in an [`Arc`] so errors can be cloned.
+///
+/// New variants may be added in the future, so use [`Error::kind`] to decide how to handle
+/// an error in general.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
 pub enum Error {
     /// An [io::Error][std::io::Error].
-    Io(io::Error),
+    ///
+    /// It is wrapped in an [`Arc`] so errors can be cloned (e.g. for [`PollerError`]).
+    Io(Arc<io::Error>),
 
     /// An error that occured in the driver.
     Driver {
         /// Information about what went wrong.
         message: &'static str,
+        /// The hidapi error that caused this, if any.
+        source: Option<Arc<HidError>>,
     },
 
     /// The BMD Speed Editor HID device was not found.
     HidDeviceNotFound,
     /// The HID API already has been initialized.
+    #[deprecated(note = "no longer returned, see `Error::CannotInitializeHidApi`")]
     HidApiAlreadyInitialized,
+    /// The HID API could not be initialized.
+    CannotInitializeHidApi {
+        /// The hidapi error that caused this.
+        source: Arc<HidError>,
+    },
     /// Could not open the BMD Speed Editor HID device.
-    CannotOpenHidDevice,
+    CannotOpenHidDevice {
+        /// The hidapi error that caused this (e.g. missing permissions).
+        source: Arc<HidError>,
+    },
+
+    /// The BMD Speed Editor HID device could not be opened,
+    /// because the user is not allowed to access it.
+    ///
+    /// This is only returned on Linux, where a udev rule is needed to access the device.
+    PermissionDenied {
+        /// The path of the device that could not be opened.
+        path: String,
+        /// The hidapi error that caused this.
+        source: Arc<HidError>,
+    },
+
+    /// The BMD Speed Editor HID device is already opened by another
+    /// [`SpeedEditor`][crate::SpeedEditor] in this process.
+    ///
+    /// Two handles to the same device would break each other's authentication.
+    /// Share the existing [`SpeedEditor`][crate::SpeedEditor] instead, which can be cloned.
+    AlreadyOpen {
+        /// The path of the device.
+        path: String,
+        /// The name (or ID) of the thread that opened it.
+        owner: String,
+    },
+
+    /// A HID device that was opened elsewhere is not a Speed Editor,
+    /// see [`SpeedEditor::from_hid_device`][crate::SpeedEditor::from_hid_device].
+    UnsupportedDevice {
+        /// The USB vendor ID of the device.
+        vendor_id: u16,
+        /// The USB product ID of the device.
+        product_id: u16,
+    },
+
+    /// The BMD Speed Editor HID device sent a report that could not be parsed.
+    InvalidReport {
+        /// Information about what is wrong with the report.
+        message: &'static str,
+        /// The ID of the report, or [`None`] if the report was empty.
+        report_id: Option<u8>,
+        /// The length of the report, including the report ID.
+        len: usize,
+        /// The first [`Error::MAX_REPORT_PAYLOAD`] bytes of the report, including the report ID.
+        payload: Vec<u8>,
+    },
+
+    /// Authenticating the BMD Speed Editor HID device failed.
+    Authentication {
+        /// The step of the handshake that failed.
+        step: AuthStep,
+        /// What went wrong in that step.
+        detail: AuthFailure,
+    },
+
+    /// The BMD Speed Editor HID device went away (e.g. it was unplugged).
+    Disconnected {
+        /// The hidapi error that showed the device went away.
+        source: Arc<HidError>,
+    },
+
+    /// Waiting for the BMD Speed Editor HID device timed out.
+    Timeout,
+    /// Waiting for the BMD Speed Editor HID device was cancelled.
+    Cancelled,
+
+    /// The requested feature is not supported on this platform.
+    Unsupported {
+        /// Information about what is not supported.
+        message: &'static str,
+    },
+
+    /// The [`SpeedEditorBuilder`][crate::SpeedEditorBuilder] was configured with invalid values.
+    InvalidConfiguration {
+        /// Information about what is invalid.
+        message: &'static str,
+    },
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Error::Io(e) => write!(f, "I/O error: {}", e),
-            Error::Driver { message } => write!(f, "Driver error: {}", message),
+            Error::Driver { message, .. } => write!(f, "Driver error: {}", message),
             Error::HidDeviceNotFound => write!(f, "HID device not found"),
+            #[allow(deprecated)]
             Error::HidApiAlreadyInitialized => write!(f, "HID API already initialized"),
-            Error::CannotOpenHidDevice => write!(f, "cannot open HID device"),
+            Error::CannotInitializeHidApi { .. } => write!(f, "cannot initialize HID API"),
+            Error::CannotOpenHidDevice { .. } => write!(f, "cannot open HID device"),
+            Error::PermissionDenied { path, .. } => write!(
+                f,
+                "permission denied opening HID device {path}: \
+                 a udev rule is required to give access to devices with VID 1EDB \
+                 (PID DA0E for the Speed Editor, DA0B for the Editor Keyboard), e.g. \
+                 `KERNEL==\"hidraw*\", ATTRS{{idVendor}}==\"1edb\", MODE=\"0660\", \
+                 TAG+=\"uaccess\"` in `/etc/udev/rules.d/50-bmdse.rules`"
+            ),
+            Error::AlreadyOpen { path, owner } => {
+                write!(f, "HID device {path} is already open in this process (by thread {owner})")
+            }
+            Error::UnsupportedDevice { vendor_id, product_id } => {
+                write!(f, "HID device {vendor_id:04x}:{product_id:04x} is not a Speed Editor")
+            }
+            Error::InvalidReport { message, report_id: Some(report_id), len, payload } => write!(
+                f,
+                "invalid report: {} (ID {:#04X}, {} bytes: {:02X?})",
+                message, report_id, len, payload
+            ),
+            Error::InvalidReport { message, report_id: None, .. } => {
+                write!(f, "invalid report: {}", message)
+            }
+            Error::Authentication { step, detail } => {
+                write!(f, "authentication failed while {}: {}", step, detail)
+            }
+            Error::Disconnected { .. } => write!(f, "HID device disconnected"),
+            Error::Timeout => write!(f, "timed out waiting for HID device"),
+            Error::Cancelled => write!(f, "cancelled waiting for HID device"),
+            Error::Unsupported { message } => write!(f, "unsupported: {}", message),
+            Error::InvalidConfiguration { message } => {
+                write!(f, "invalid configuration: {}", message)
+            }
         }
     }
 }
@@ -35,14 +166,347 @@ impl fmt::Display for Error {
 impl error::Error for Error {
     fn source(&self) -> Option<&(dyn error::Error + 'static)> {
         match self {
-            Error::Io(e) => Some(e),
+            Error::Io(e) => Some(e.as_ref()),
+            Error::Driver { source, .. } => source.as_deref().map(|e| e as _),
+            Error::CannotInitializeHidApi { source }
+            | Error::CannotOpenHidDevice { source }
+            | Error::PermissionDenied { source, .. }
+            | Error::Disconnected { source } => Some(source.as_ref()),
+            Error::Authentication { detail: AuthFailure::Hid(source), .. } => Some(source.as_ref()),
             _ => None,
         }
     }
 }
 
+impl Error {
+    /// Returns the general kind of this error.
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Error::Io(_) | Error::Driver { source: Some(_), .. } => ErrorKind::Io,
+            Error::Driver { source: None, .. } | Error::InvalidReport { .. } => ErrorKind::Protocol,
+            Error::HidDeviceNotFound => ErrorKind::NotFound,
+            Error::PermissionDenied { .. } => ErrorKind::Permission,
+            Error::Authentication { .. } => ErrorKind::Auth,
+            Error::Disconnected { .. } => ErrorKind::Disconnected,
+            _ => ErrorKind::Other,
+        }
+    }
+
+    /// Returns `true` if trying again might succeed,
+    /// like the [`ReconnectPolicy`][crate::ReconnectPolicy] does after an error.
+    ///
+    /// This is `false` for errors that need the user or the application to change something
+    /// first, like [`Error::PermissionDenied`] or [`Error::InvalidConfiguration`].
+    pub fn is_recoverable(&self) -> bool {
+        matches!(self, Error::CannotOpenHidDevice { .. })
+            || matches!(
+                self.kind(),
+                ErrorKind::NotFound
+                    | ErrorKind::Disconnected
+                    | ErrorKind::Protocol
+                    | ErrorKind::Auth
+                    | ErrorKind::Io
+            )
+    }
+
+    /// How many bytes of a report are kept in [`Error::InvalidReport`].
+    pub const MAX_REPORT_PAYLOAD: usize = 16;
+
+    /// Creates a [`Error::Driver`] without a source.
+    pub(crate) fn driver(message: &'static str) -> Self {
+        Error::Driver { message, source: None }
+    }
+
+    /// Creates a [`Error::Driver`] caused by a hidapi error,
+    /// or a [`Error::Disconnected`] if the error shows that the device went away.
+    pub(crate) fn hid(message: &'static str, error: HidError) -> Self {
+        if is_disconnect(&error) {
+            return Error::Disconnected { source: Arc::new(error) };
+        }
+        Error::Driver { message, source: Some(Arc::new(error)) }
+    }
+
+    /// Creates a [`Error::InvalidReport`] for the given report.
+    pub(crate) fn invalid_report(message: &'static str, bytes: &[u8]) -> Self {
+        Error::InvalidReport {
+            message,
+            report_id: bytes.first().copied(),
+            len: bytes.len(),
+            payload: bytes[..bytes.len().min(Self::MAX_REPORT_PAYLOAD)].to_vec(),
+        }
+    }
+
+    /// Creates a [`Error::Authentication`] caused by a hidapi error,
+    /// or a [`Error::Disconnected`] if the error shows that the device went away.
+    pub(crate) fn auth(step: AuthStep, error: HidError) -> Self {
+        if is_disconnect(&error) {
+            return Error::Disconnected { source: Arc::new(error) };
+        }
+        Error::Authentication { step, detail: AuthFailure::Hid(Arc::new(error)) }
+    }
+}
+
+/// The general kind of an [`Error`].
+///
+/// See [`Error::kind`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// The device was not found (e.g. it is not plugged in).
+    NotFound,
+    /// The user is not allowed to access the device.
+    Permission,
+    /// The device went away.
+    Disconnected,
+    /// The device sent something unexpected.
+    Protocol,
+    /// Authenticating the device failed.
+    Auth,
+    /// Communicating with the device failed.
+    Io,
+    /// Any other error.
+    Other,
+}
+
+/// A step of the authentication handshake with the device.
+///
+/// See [`Error::Authentication`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AuthStep {
+    /// Resetting the authentication state of the device.
+    Reset,
+    /// Reading the challenge of the device.
+    ReadChallenge,
+    /// Sending our challenge to the device.
+    SendChallenge,
+    /// Reading the response of the device to our challenge.
+    ReadResponse,
+    /// Sending our response to the challenge of the device.
+    SendResponse,
+    /// Reading whether the device accepted our response.
+    ReadStatus,
+}
+
+impl fmt::Display for AuthStep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuthStep::Reset => write!(f, "resetting"),
+            AuthStep::ReadChallenge => write!(f, "reading the challenge"),
+            AuthStep::SendChallenge => write!(f, "sending the challenge"),
+            AuthStep::ReadResponse => write!(f, "reading the response"),
+            AuthStep::SendResponse => write!(f, "sending the response"),
+            AuthStep::ReadStatus => write!(f, "reading the status"),
+        }
+    }
+}
+
+/// What went wrong in a step of the authentication handshake.
+///
+/// See [`Error::Authentication`].
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub enum AuthFailure {
+    /// The device could not be reached (e.g. it never answered).
+    Hid(Arc<HidError>),
+    /// The device answered, but not with what was expected (e.g. it rejected our response).
+    UnexpectedReply {
+        /// The bytes that were received.
+        bytes: Vec<u8>,
+    },
+    /// The response of the device to our challenge is wrong (e.g. it is not a genuine device).
+    ///
+    /// This is only checked with
+    /// [`SpeedEditorBuilder::verify_device`][crate::SpeedEditorBuilder::verify_device].
+    ResponseMismatch {
+        /// The response that was expected.
+        expected: u64,
+        /// The response that was received.
+        received: u64,
+    },
+}
+
+impl fmt::Display for AuthFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuthFailure::Hid(error) => write!(f, "{}", error),
+            AuthFailure::UnexpectedReply { bytes } => write!(f, "unexpected reply {:02X?}", bytes),
+            AuthFailure::ResponseMismatch { expected, received } => {
+                write!(f, "wrong response {:016X}, expected {:016X}", received, expected)
+            }
+        }
+    }
+}
+
+/// Returns `true` if the hidapi error shows that the user is not allowed to access the device.
+pub(crate) fn is_permission_denied(error: &HidError) -> bool {
+    match error {
+        // Linux (hidraw): `strerror(EACCES)`.
+        HidError::HidApiError { message } => message.to_lowercase().contains("permission denied"),
+        HidError::IoError { error } => error.kind() == io::ErrorKind::PermissionDenied,
+        _ => false,
+    }
+}
+
+/// Returns `true` if the hidapi error shows that the device went away.
+///
+/// Most backends of hidapi only report a message, so this has to look at the text.
+fn is_disconnect(error: &HidError) -> bool {
+    /// Parts of the messages the hidapi backends use when the device went away.
+    const DISCONNECT_MESSAGES: [&str; 4] = [
+        // Linux (hidraw): `strerror(ENODEV)`, or a poll error.
+        "no such device",
+        "(device disconnected)",
+        // macOS.
+        "device is disconnected",
+        // Windows: `ERROR_DEVICE_NOT_CONNECTED`, formatted as `op: (0x0000048F) message`.
+        "(0x0000048f)",
+    ];
+
+    match error {
+        HidError::HidApiError { message } => {
+            let message = message.to_lowercase();
+            DISCONNECT_MESSAGES
+                .iter()
+                .any(|disconnect_message| message.contains(disconnect_message))
+        }
+        HidError::IoError { error } => {
+            #[cfg(unix)]
+            if error.raw_os_error() == Some(libc::ENODEV) {
+                return true;
+            }
+            matches!(error.kind(), io::ErrorKind::NotConnected | io::ErrorKind::BrokenPipe)
+        }
+        _ => false,
+    }
+}
+
 impl From<io::Error> for Error {
     fn from(err: io::Error) -> Self {
-        Error::Io(err)
+        Error::Io(Arc::new(err))
+    }
+}
+
+/// An error that occurred in the polling thread.
+///
+/// See [`SpeedEditor::on_error`][crate::SpeedEditor::on_error].
+#[derive(Debug, Clone)]
+pub struct PollerError {
+    /// The error that occurred.
+    pub error: Error,
+    /// `true` if the polling thread stopped because of this error,
+    /// `false` if it was recovered from (e.g. by retrying or reconnecting).
+    pub fatal: bool,
+}
+
+impl fmt::Display for PollerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.fatal {
+            write!(f, "fatal poller error: {}", self.error)
+        } else {
+            write!(f, "poller error: {}", self.error)
+        }
+    }
+}
+
+impl error::Error for PollerError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hidapi_error(message: &str) -> HidError {
+        HidError::HidApiError { message: message.to_string() }
+    }
+
+    fn io_error(kind: io::ErrorKind) -> HidError {
+        HidError::IoError { error: io::Error::from(kind) }
+    }
+
+    #[test]
+    fn disconnect_messages_are_classified() {
+        let cases = [
+            // Linux (hidraw).
+            ("hid_read_timeout: No such device", true),
+            ("hid_write: (device disconnected)", true),
+            // macOS.
+            ("IOHIDDeviceGetReport failed: device is disconnected", true),
+            // Windows.
+            ("WriteFile: (0x0000048F) The device is not connected.", true),
+            ("ReadFile: (0x0000048f) the device is not connected.", true),
+            // Other failures.
+            ("Permission denied", false),
+            ("hid_read_timeout: Resource temporarily unavailable", false),
+            ("WriteFile: (0x00000057) The parameter is incorrect.", false),
+            ("", false),
+        ];
+        for (message, disconnect) in cases {
+            assert_eq!(is_disconnect(&hidapi_error(message)), disconnect, "{message:?}");
+        }
+    }
+
+    #[test]
+    fn disconnect_io_errors_are_classified() {
+        let cases = [
+            (io::ErrorKind::NotConnected, true),
+            (io::ErrorKind::BrokenPipe, true),
+            (io::ErrorKind::PermissionDenied, false),
+            (io::ErrorKind::TimedOut, false),
+            (io::ErrorKind::Other, false),
+        ];
+        for (kind, disconnect) in cases {
+            assert_eq!(is_disconnect(&io_error(kind)), disconnect, "{kind:?}");
+        }
+
+        #[cfg(unix)]
+        assert!(is_disconnect(&HidError::IoError {
+            error: io::Error::from_raw_os_error(libc::ENODEV),
+        }));
+        assert!(!is_disconnect(&HidError::HidApiErrorEmpty));
+    }
+
+    #[test]
+    fn hid_errors_become_disconnected() {
+        let error = Error::hid("failed to read", hidapi_error("No such device"));
+        assert!(matches!(error, Error::Disconnected { .. }));
+        assert_eq!(error.kind(), ErrorKind::Disconnected);
+        assert!(error.is_recoverable());
+
+        let error = Error::hid("failed to read", hidapi_error("Invalid argument"));
+        assert!(matches!(error, Error::Driver { .. }));
+
+        let error = Error::auth(AuthStep::ReadStatus, io_error(io::ErrorKind::BrokenPipe));
+        assert!(matches!(error, Error::Disconnected { .. }));
+        let error = Error::auth(AuthStep::ReadStatus, hidapi_error("Broken"));
+        assert!(matches!(error, Error::Authentication { step: AuthStep::ReadStatus, .. }));
+    }
+
+    #[test]
+    fn permission_denied_is_classified() {
+        assert!(is_permission_denied(&hidapi_error("Permission denied")));
+        assert!(is_permission_denied(&hidapi_error("hid_open_path: PERMISSION DENIED")));
+        assert!(is_permission_denied(&io_error(io::ErrorKind::PermissionDenied)));
+
+        assert!(!is_permission_denied(&hidapi_error("No such device")));
+        assert!(!is_permission_denied(&io_error(io::ErrorKind::NotFound)));
+        assert!(!is_permission_denied(&HidError::HidApiErrorEmpty));
+    }
+
+    #[test]
+    fn permission_denied_names_path_and_devices() {
+        let error = Error::PermissionDenied {
+            path: "/dev/hidraw3".to_string(),
+            source: Arc::new(hidapi_error("Permission denied")),
+        };
+        let message = error.to_string();
+        assert!(message.contains("/dev/hidraw3"), "{message}");
+        assert!(message.contains("DA0E") && message.contains("DA0B"), "{message}");
+        assert!(message.contains("ATTRS{idVendor}==\"1edb\""), "{message}");
+        assert_eq!(error.kind(), ErrorKind::Permission);
+        assert!(!error.is_recoverable());
     }
 }
@@ -0,0 +1,176 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use crate::{Button, DeviceInfo, EditorKeyboardButton};
+
+/// An event that happened on the Speed Editor.
+///
+/// These are the same events that are passed to the callbacks on [`SpeedEditor`][crate::SpeedEditor],
+/// bundled into a single type so they can be forwarded to an [`EventSink`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Event {
+    /// The jog wheel changed.
+    WheelChange {
+        /// The velocity of the wheel.
+        velocity: i32,
+    },
+    /// A button was pressed or released.
+    ButtonChange {
+        /// The button that changed.
+        button: Button,
+        /// `true` if the button is pressed, `false` if it was released.
+        pressed: bool,
+    },
+    /// A key that only the [Editor Keyboard][crate::Model::EditorKeyboard] has was pressed or
+    /// released.
+    ///
+    /// This has no callback of its own.
+    EditorKeyboardButtonChange {
+        /// The key that changed.
+        button: EditorKeyboardButton,
+        /// `true` if the key is pressed, `false` if it was released.
+        pressed: bool,
+    },
+    /// Battery information was received.
+    BatteryInfo {
+        /// `true` if the device is charging.
+        charging: bool,
+        /// The battery percentage (`0..=100`).
+        percentage: u8,
+    },
+    /// The device started or stopped charging.
+    ///
+    /// This is also sent for the first battery information after connecting.
+    ChargingChange {
+        /// `true` if the device is charging.
+        charging: bool,
+    },
+    /// The device sent a report that is not known (yet), e.g. because of newer firmware.
+    ///
+    /// This has no callback of its own, see also
+    /// [`SpeedEditor::on_raw_report`][crate::SpeedEditor::on_raw_report].
+    UnknownReport {
+        /// The ID of the report.
+        id: u8,
+        /// The bytes of the report after the ID.
+        data: Vec<u8>,
+    },
+    /// The device was connected and authenticated.
+    Connected(DeviceInfo),
+    /// The device stopped responding or the polling thread stopped.
+    Disconnected,
+}
+
+/// Something that can receive [`Event`]s from a [`SpeedEditor`][crate::SpeedEditor].
+///
+/// Use this to forward events into the event loop of your application
+/// (e.g. a channel, or a GUI framework's proxy), instead of handling them in callbacks.
+///
+/// Sinks are attached using [`SpeedEditor::attach_sink`][crate::SpeedEditor::attach_sink].
+pub trait EventSink: Send {
+    /// Send an event to this sink.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`SinkClosed`] if the receiving end is gone.
+    /// The sink will then be detached and will not receive any more events.
+    fn send(&self, event: Event) -> Result<(), SinkClosed>;
+}
+
+/// The receiving end of an [`EventSink`] is closed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct SinkClosed;
+
+impl EventSink for std::sync::mpsc::Sender<Event> {
+    fn send(&self, event: Event) -> Result<(), SinkClosed> {
+        std::sync::mpsc::Sender::send(self, event).map_err(|_| SinkClosed)
+    }
+}
+
+impl EventSink for std::sync::mpsc::SyncSender<Event> {
+    fn send(&self, event: Event) -> Result<(), SinkClosed> {
+        match self.try_send(event) {
+            Ok(()) | Err(std::sync::mpsc::TrySendError::Full(_)) => Ok(()),
+            Err(std::sync::mpsc::TrySendError::Disconnected(_)) => Err(SinkClosed),
+        }
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl EventSink for tokio::sync::mpsc::UnboundedSender<Event> {
+    fn send(&self, event: Event) -> Result<(), SinkClosed> {
+        tokio::sync::mpsc::UnboundedSender::send(self, event).map_err(|_| SinkClosed)
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl EventSink for tokio::sync::mpsc::Sender<Event> {
+    fn send(&self, event: Event) -> Result<(), SinkClosed> {
+        // The poller thread should never block on a slow receiver,
+        // so events are dropped when the channel is full.
+        match self.try_send(event) {
+            Ok(()) | Err(tokio::sync::mpsc::error::TrySendError::Full(_)) => Ok(()),
+            Err(tokio::sync::mpsc::error::TrySendError::Closed(_)) => Err(SinkClosed),
+        }
+    }
+}
+
+#[cfg(feature = "winit")]
+impl<T: From<Event> + Send + 'static> EventSink for winit::event_loop::EventLoopProxy<T> {
+    fn send(&self, event: Event) -> Result<(), SinkClosed> {
+        self.send_event(T::from(event)).map_err(|_| SinkClosed)
+    }
+}
+
+/// Events that no callback or sink received right after the [`SpeedEditor`][crate::SpeedEditor]
+/// was created, kept until a callback or sink for them is registered.
+#[derive(Default)]
+pub(crate) struct EventBuffer {
+    events: VecDeque<Event>,
+    /// Until when events are buffered, or [`None`] if buffering did not start or is over.
+    until: Option<Instant>,
+}
+
+impl EventBuffer {
+    /// The most events that are kept. The oldest ones are dropped first.
+    const CAPACITY: usize = 64;
+    /// How long after creating the [`SpeedEditor`][crate::SpeedEditor] events are kept.
+    const WINDOW: Duration = Duration::from_secs(5);
+
+    pub(crate) fn start(&mut self) {
+        self.until = Some(Instant::now() + Self::WINDOW);
+    }
+
+    /// Returns `false` and drops the buffered events once the window is over,
+    /// as they would only be stale by the time they are delivered.
+    fn is_open(&mut self) -> bool {
+        if self.until.is_some_and(|until| Instant::now() >= until) {
+            self.until = None;
+            self.events = VecDeque::new();
+        }
+        self.until.is_some()
+    }
+
+    pub(crate) fn push(&mut self, event: Event) {
+        // Only a sink receives unknown reports. Keeping them for a sink that might be attached
+        // later would push out the events that the callbacks are registered for.
+        if !self.is_open() || matches!(event, Event::UnknownReport { .. }) {
+            return;
+        }
+        if self.events.len() == Self::CAPACITY {
+            self.events.pop_front();
+        }
+        self.events.push_back(event);
+    }
+
+    /// Takes all buffered events, in the order they happened.
+    pub(crate) fn take(&mut self) -> VecDeque<Event> {
+        if !self.is_open() {
+            return VecDeque::new();
+        }
+        std::mem::take(&mut self.events)
+    }
+}
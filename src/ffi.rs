@@ -0,0 +1,390 @@
+//! A C API, to use the crate from other languages.
+//!
+//! Build it as a shared library with
+//! `cargo rustc --release --features ffi --crate-type cdylib`,
+//! and include `include/bmdse.h`, which is generated from this module with
+//! `cbindgen --config cbindgen.toml --output include/bmdse.h`.
+//!
+//! # Threads
+//!
+//! All functions can be called from any thread. Callbacks are usually called on the polling
+//! thread of the device, one at a time, so a callback must return quickly. It can call the
+//! other `bmdse_*` functions for the same device (e.g. to set an LED), except `bmdse_close`.
+//! To react to events on your own thread instead, use `bmdse_poll_event`.
+//!
+//! # Errors
+//!
+//! Functions that can fail return a [`BmdseStatus`] that mirrors [`ErrorKind`],
+//! and store a description of the error for `bmdse_last_error_message`.
+
+use std::{
+    cell::RefCell,
+    ffi::{CString, c_char, c_void},
+    ptr,
+    sync::{Mutex, mpsc},
+};
+
+use crate::{Button, ButtonLed, ErrorKind, Event, SpeedEditor, WheelLed, sync::MutexExt};
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// The result of a function of the C API.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BmdseStatus {
+    /// The function succeeded.
+    Ok = 0,
+    /// See [`ErrorKind::NotFound`].
+    NotFound,
+    /// See [`ErrorKind::Permission`].
+    Permission,
+    /// See [`ErrorKind::Disconnected`].
+    Disconnected,
+    /// See [`ErrorKind::Protocol`].
+    Protocol,
+    /// See [`ErrorKind::Auth`].
+    Auth,
+    /// See [`ErrorKind::Io`].
+    Io,
+    /// See [`ErrorKind::Other`].
+    Other,
+    /// An argument was null or out of range.
+    InvalidArgument,
+}
+
+impl From<ErrorKind> for BmdseStatus {
+    fn from(kind: ErrorKind) -> Self {
+        match kind {
+            ErrorKind::NotFound => BmdseStatus::NotFound,
+            ErrorKind::Permission => BmdseStatus::Permission,
+            ErrorKind::Disconnected => BmdseStatus::Disconnected,
+            ErrorKind::Protocol => BmdseStatus::Protocol,
+            ErrorKind::Auth => BmdseStatus::Auth,
+            ErrorKind::Io => BmdseStatus::Io,
+            ErrorKind::Other => BmdseStatus::Other,
+        }
+    }
+}
+
+/// Stores the message for `bmdse_last_error_message`, and returns the status.
+fn fail(status: BmdseStatus, message: &str) -> BmdseStatus {
+    let message = CString::new(message.replace('\0', " ")).ok();
+    LAST_ERROR.with(|last_error| *last_error.borrow_mut() = message);
+    status
+}
+
+fn fail_with(error: &crate::Error) -> BmdseStatus {
+    fail(error.kind().into(), &error.to_string())
+}
+
+/// A Speed Editor that is opened with `bmdse_open`.
+pub struct BmdseSpeedEditor {
+    speed_editor: SpeedEditor,
+    /// The events for `bmdse_poll_event`, only collected after it is first called.
+    events: Mutex<Option<mpsc::Receiver<Event>>>,
+}
+
+/// What kind of [`BmdseEvent`] happened.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BmdseEventKind {
+    /// The jog wheel changed, see `velocity`.
+    WheelChange,
+    /// A button was pressed or released, see `button` and `pressed`.
+    ButtonChange,
+    /// Battery information was received, see `charging` and `percentage`.
+    BatteryInfo,
+    /// The device started or stopped charging, see `charging`.
+    ChargingChange,
+    /// The device was connected and authenticated.
+    Connected,
+    /// The device stopped responding or the polling thread stopped.
+    Disconnected,
+}
+
+/// An event that happened on the Speed Editor, see [`Event`].
+///
+/// Only the fields of its kind are set, the others are zero.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BmdseEvent {
+    /// What kind of event happened.
+    pub kind: BmdseEventKind,
+    /// The velocity of the jog wheel.
+    pub velocity: i32,
+    /// The value of the [`Button`][crate::Button] that changed.
+    pub button: u16,
+    /// `true` if the button is pressed, `false` if it was released.
+    pub pressed: bool,
+    /// `true` if the device is charging.
+    pub charging: bool,
+    /// The battery percentage (`0..=100`).
+    pub percentage: u8,
+}
+
+impl BmdseEvent {
+    fn from_event(event: Event) -> Option<Self> {
+        let empty = |kind| BmdseEvent {
+            kind,
+            velocity: 0,
+            button: 0,
+            pressed: false,
+            charging: false,
+            percentage: 0,
+        };
+        Some(match event {
+            Event::WheelChange { velocity } => {
+                BmdseEvent { velocity, ..empty(BmdseEventKind::WheelChange) }
+            }
+            Event::ButtonChange { button, pressed } => {
+                BmdseEvent { button: button as u16, pressed, ..empty(BmdseEventKind::ButtonChange) }
+            }
+            Event::BatteryInfo { charging, percentage } => {
+                BmdseEvent { charging, percentage, ..empty(BmdseEventKind::BatteryInfo) }
+            }
+            Event::ChargingChange { charging } => {
+                BmdseEvent { charging, ..empty(BmdseEventKind::ChargingChange) }
+            }
+            Event::Connected(_) => empty(BmdseEventKind::Connected),
+            Event::Disconnected => empty(BmdseEventKind::Disconnected),
+            Event::EditorKeyboardButtonChange { .. } | Event::UnknownReport { .. } => return None,
+        })
+    }
+}
+
+/// The callbacks for `bmdse_set_callbacks`, which are called with its `user_data`.
+///
+/// A null callback is not called.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct BmdseCallbacks {
+    /// See [`SpeedEditor::on_wheel_change`].
+    pub on_wheel_change: Option<unsafe extern "C" fn(velocity: i32, user_data: *mut c_void)>,
+    /// See [`SpeedEditor::on_button_change`], with the value of the [`Button`][crate::Button].
+    pub on_button_change:
+        Option<unsafe extern "C" fn(button: u16, pressed: bool, user_data: *mut c_void)>,
+    /// See [`SpeedEditor::on_battery_info`].
+    pub on_battery_info:
+        Option<unsafe extern "C" fn(charging: bool, percentage: u8, user_data: *mut c_void)>,
+    /// See [`SpeedEditor::on_connect`].
+    pub on_connect: Option<unsafe extern "C" fn(user_data: *mut c_void)>,
+    /// See [`SpeedEditor::on_disconnect`].
+    pub on_disconnect: Option<unsafe extern "C" fn(user_data: *mut c_void)>,
+}
+
+/// The user data of the callbacks, which the caller of `bmdse_set_callbacks`
+/// promised can be used from the polling thread.
+#[derive(Clone, Copy)]
+struct UserData(*mut c_void);
+
+// SAFETY: See `bmdse_set_callbacks`.
+unsafe impl Send for UserData {}
+
+impl UserData {
+    // A method, so closures capture the whole `UserData` instead of the pointer in it.
+    fn get(self) -> *mut c_void {
+        self.0
+    }
+}
+
+/// Opens the first Speed Editor that is found, see [`SpeedEditor::new`],
+/// and stores it in `out`.
+///
+/// Close it with `bmdse_close`.
+///
+/// # Safety
+///
+/// `out` must be null or valid for writes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn bmdse_open(out: *mut *mut BmdseSpeedEditor) -> BmdseStatus {
+    if out.is_null() {
+        return fail(BmdseStatus::InvalidArgument, "out is null");
+    }
+    match SpeedEditor::new() {
+        Ok(speed_editor) => {
+            let handle = BmdseSpeedEditor { speed_editor, events: Mutex::new(None) };
+            // SAFETY: `out` is not null, and the caller promised it is valid for writes.
+            unsafe { out.write(Box::into_raw(Box::new(handle))) };
+            BmdseStatus::Ok
+        }
+        Err(error) => fail_with(&error),
+    }
+}
+
+/// Closes a Speed Editor that was opened with `bmdse_open`.
+///
+/// This stops its polling thread, so it must not be called from a callback.
+///
+/// # Safety
+///
+/// `speed_editor` must be null or returned by `bmdse_open`, and not be closed yet.
+/// It must not be used anymore afterwards.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn bmdse_close(speed_editor: *mut BmdseSpeedEditor) {
+    if !speed_editor.is_null() {
+        // SAFETY: The caller promised it was returned by `bmdse_open` and is not closed yet.
+        drop(unsafe { Box::from_raw(speed_editor) });
+    }
+}
+
+/// Sets the button LED to the value of a [`ButtonLed`] (e.g. `0` for off).
+///
+/// # Safety
+///
+/// `speed_editor` must be null or returned by `bmdse_open`, and not be closed yet.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn bmdse_set_button_led(
+    speed_editor: *const BmdseSpeedEditor,
+    led: u32,
+) -> BmdseStatus {
+    // SAFETY: The caller promised it was returned by `bmdse_open` and is not closed yet.
+    let Some(handle) = (unsafe { speed_editor.as_ref() }) else {
+        return fail(BmdseStatus::InvalidArgument, "speed_editor is null");
+    };
+    match ButtonLed::try_from(led) {
+        Ok(led) => {
+            handle.speed_editor.set_button_led(led);
+            BmdseStatus::Ok
+        }
+        Err(_) => fail(BmdseStatus::InvalidArgument, "led is not a button LED"),
+    }
+}
+
+/// Sets the wheel LED to the value of a [`WheelLed`] (e.g. `0` for off).
+///
+/// # Safety
+///
+/// `speed_editor` must be null or returned by `bmdse_open`, and not be closed yet.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn bmdse_set_wheel_led(
+    speed_editor: *const BmdseSpeedEditor,
+    led: u32,
+) -> BmdseStatus {
+    // SAFETY: The caller promised it was returned by `bmdse_open` and is not closed yet.
+    let Some(handle) = (unsafe { speed_editor.as_ref() }) else {
+        return fail(BmdseStatus::InvalidArgument, "speed_editor is null");
+    };
+    match WheelLed::try_from(led) {
+        Ok(led) => {
+            handle.speed_editor.set_wheel_led(led);
+            BmdseStatus::Ok
+        }
+        Err(_) => fail(BmdseStatus::InvalidArgument, "led is not a wheel LED"),
+    }
+}
+
+/// Replaces the callbacks, which are called on the polling thread with `user_data`.
+///
+/// A null `callbacks` removes all callbacks. See the [module documentation][self]
+/// for what a callback may do.
+///
+/// # Safety
+///
+/// `speed_editor` must be null or returned by `bmdse_open`, and not be closed yet.
+/// `callbacks` must be null or valid for reads. The callbacks must be safe to call
+/// with `user_data` from another thread until they are replaced or the device is closed.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn bmdse_set_callbacks(
+    speed_editor: *const BmdseSpeedEditor,
+    callbacks: *const BmdseCallbacks,
+    user_data: *mut c_void,
+) -> BmdseStatus {
+    // SAFETY: The caller promised it was returned by `bmdse_open` and is not closed yet.
+    let Some(handle) = (unsafe { speed_editor.as_ref() }) else {
+        return fail(BmdseStatus::InvalidArgument, "speed_editor is null");
+    };
+    // SAFETY: The caller promised it is null or valid for reads.
+    let callbacks = unsafe { callbacks.as_ref() }.copied();
+    let callbacks = callbacks.unwrap_or(BmdseCallbacks {
+        on_wheel_change: None,
+        on_button_change: None,
+        on_battery_info: None,
+        on_connect: None,
+        on_disconnect: None,
+    });
+    let user_data = UserData(user_data);
+
+    // All callbacks are replaced in a single change, so an event is never passed to a mix of
+    // the old and the new callbacks.
+    let mut inner = handle.speed_editor.inner.lock_unpoisoned();
+    inner.update_callbacks(move |replaced| {
+        replaced.on_wheel_change = None;
+        replaced.on_button_change = None;
+        replaced.on_battery_info = None;
+        replaced.on_connect = None;
+        replaced.on_disconnect = None;
+        // SAFETY (for all callbacks): The caller promised they can be called with `user_data`
+        // from the polling thread, until they are replaced (here) or the device is closed.
+        if let Some(f) = callbacks.on_wheel_change {
+            replaced.on_wheel_change =
+                Some(Box::new(move |velocity| unsafe { f(velocity, user_data.get()) }));
+        }
+        if let Some(f) = callbacks.on_button_change {
+            replaced.on_button_change = Some(Box::new(move |button: Button, pressed| unsafe {
+                f(button as u16, pressed, user_data.get())
+            }));
+        }
+        if let Some(f) = callbacks.on_battery_info {
+            replaced.on_battery_info = Some(Box::new(move |charging, percentage| unsafe {
+                f(charging, percentage, user_data.get())
+            }));
+        }
+        if let Some(f) = callbacks.on_connect {
+            replaced.on_connect = Some(Box::new(move |_| unsafe { f(user_data.get()) }));
+        }
+        if let Some(f) = callbacks.on_disconnect {
+            replaced.on_disconnect = Some(Box::new(move || unsafe { f(user_data.get()) }));
+        }
+    });
+    inner.flush_buffered_events();
+    BmdseStatus::Ok
+}
+
+/// Takes the next event, if there is one, and stores it in `out`.
+///
+/// Returns `true` if an event was stored, without waiting for one.
+/// Events are collected from the first call on, so call it regularly after that.
+///
+/// # Safety
+///
+/// `speed_editor` must be null or returned by `bmdse_open`, and not be closed yet.
+/// `out` must be null or valid for writes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn bmdse_poll_event(
+    speed_editor: *const BmdseSpeedEditor,
+    out: *mut BmdseEvent,
+) -> bool {
+    // SAFETY: The caller promised it was returned by `bmdse_open` and is not closed yet.
+    let Some(handle) = (unsafe { speed_editor.as_ref() }) else { return false };
+    if out.is_null() {
+        return false;
+    }
+
+    let mut events = handle.events.lock_unpoisoned();
+    let events = events.get_or_insert_with(|| {
+        let (sender, receiver) = mpsc::channel();
+        handle.speed_editor.attach_sink(Box::new(sender));
+        receiver
+    });
+    while let Ok(event) = events.try_recv() {
+        if let Some(event) = BmdseEvent::from_event(event) {
+            // SAFETY: `out` is not null, and the caller promised it is valid for writes.
+            unsafe { out.write(event) };
+            return true;
+        }
+    }
+    false
+}
+
+/// Returns a description of the last error of a function on this thread,
+/// or null if there was none.
+///
+/// The string stays valid until the next error on this thread.
+#[unsafe(no_mangle)]
+pub extern "C" fn bmdse_last_error_message() -> *const c_char {
+    LAST_ERROR.with(|last_error| {
+        last_error.borrow().as_ref().map_or(ptr::null(), |message| message.as_ptr())
+    })
+}
@@ -0,0 +1,228 @@
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+/// A snapshot of how the polling thread is doing.
+///
+/// See [`SpeedEditor::health`][crate::SpeedEditor::health].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub struct Health {
+    /// Whether the device is currently connected.
+    pub connected: bool,
+    /// The time since the last report was read from the device,
+    /// or [`None`] if no report has been read since it connected.
+    pub since_last_report: Option<Duration>,
+    /// The time until the device is authenticated again,
+    /// or [`None`] if it is not connected.
+    ///
+    /// This is [`Duration::ZERO`] if the re-authentication is overdue.
+    pub until_reauthentication: Option<Duration>,
+    /// The time until the authentication of the device expires,
+    /// or [`None`] if it is not connected.
+    ///
+    /// This is later than [`Health::until_reauthentication`] by the
+    /// [margin][crate::SpeedEditorBuilder::auth_margin], unless authenticating again failed.
+    /// It is [`Duration::ZERO`] if the authentication expired.
+    pub until_auth_expiry: Option<Duration>,
+    /// The number of failed reads since the device connected.
+    pub read_errors: u64,
+    /// The number of times the device was opened again after the first connection
+    /// (e.g. after it was unplugged, or after resuming with the device released).
+    pub reconnects: u64,
+    /// The number of times the poller was restarted after it stopped because of an error.
+    ///
+    /// See [`RestartPolicy`][crate::RestartPolicy].
+    pub restarts: u64,
+}
+
+/// Counters behind [`Health`], updated by the polling thread without taking any locks.
+///
+/// Instants are stored as nanoseconds since `epoch`, with [`HealthCounters::NONE`] meaning unset.
+pub(crate) struct HealthCounters {
+    epoch: Instant,
+    last_report: AtomicU64,
+    next_auth: AtomicU64,
+    auth_expiry: AtomicU64,
+    read_errors: AtomicU64,
+    connects: AtomicU64,
+    restarts: AtomicU64,
+}
+
+impl HealthCounters {
+    const NONE: u64 = u64::MAX;
+
+    pub(crate) fn new() -> Self {
+        Self {
+            epoch: Instant::now(),
+            last_report: AtomicU64::new(Self::NONE),
+            next_auth: AtomicU64::new(Self::NONE),
+            auth_expiry: AtomicU64::new(Self::NONE),
+            read_errors: AtomicU64::new(0),
+            connects: AtomicU64::new(0),
+            restarts: AtomicU64::new(0),
+        }
+    }
+
+    pub(crate) fn record_connect(&self) {
+        self.connects.fetch_add(1, Ordering::Relaxed);
+        self.read_errors.store(0, Ordering::Relaxed);
+        self.last_report.store(Self::NONE, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_disconnect(&self) {
+        self.next_auth.store(Self::NONE, Ordering::Relaxed);
+        self.auth_expiry.store(Self::NONE, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_restart(&self) {
+        self.restarts.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_report(&self) {
+        self.last_report.store(self.encode(Instant::now()), Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_read_error(&self) {
+        self.read_errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn set_next_auth(&self, next_auth: Instant) {
+        self.next_auth.store(self.encode(next_auth), Ordering::Relaxed);
+    }
+
+    pub(crate) fn set_auth_expiry(&self, auth_expiry: Instant) {
+        self.auth_expiry.store(self.encode(auth_expiry), Ordering::Relaxed);
+    }
+
+    pub(crate) fn snapshot(&self, connected: bool) -> Health {
+        let now = Instant::now();
+        Health {
+            connected,
+            since_last_report: self
+                .decode(self.last_report.load(Ordering::Relaxed))
+                .map(|last_report| now.saturating_duration_since(last_report)),
+            until_reauthentication: self
+                .decode(self.next_auth.load(Ordering::Relaxed))
+                .map(|next_auth| next_auth.saturating_duration_since(now)),
+            until_auth_expiry: self
+                .decode(self.auth_expiry.load(Ordering::Relaxed))
+                .map(|auth_expiry| auth_expiry.saturating_duration_since(now)),
+            read_errors: self.read_errors.load(Ordering::Relaxed),
+            reconnects: self.connects.load(Ordering::Relaxed).saturating_sub(1),
+            restarts: self.restarts.load(Ordering::Relaxed),
+        }
+    }
+
+    fn encode(&self, instant: Instant) -> u64 {
+        // Saturates after ~584 years, well before reaching `NONE`.
+        let nanos = instant.saturating_duration_since(self.epoch).as_nanos();
+        nanos.min(Self::NONE as u128 - 1) as u64
+    }
+
+    fn decode(&self, nanos: u64) -> Option<Instant> {
+        (nanos != Self::NONE).then(|| self.epoch + Duration::from_nanos(nanos))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::mpsc, thread};
+
+    use super::*;
+    use crate::{
+        SpeedEditor,
+        test_util::TIMEOUT,
+        testing::{FakeBackend, Fault},
+    };
+
+    #[test]
+    fn counters_start_unset() {
+        let counters = HealthCounters::new();
+        assert_eq!(
+            counters.snapshot(false),
+            Health {
+                connected: false,
+                since_last_report: None,
+                until_reauthentication: None,
+                until_auth_expiry: None,
+                read_errors: 0,
+                reconnects: 0,
+                restarts: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn connect_resets_session_counters() {
+        let counters = HealthCounters::new();
+        counters.record_connect();
+        counters.record_report();
+        counters.record_read_error();
+        counters.record_restart();
+        let health = counters.snapshot(true);
+        assert!(health.since_last_report.is_some());
+        assert_eq!((health.read_errors, health.reconnects, health.restarts), (1, 0, 1));
+
+        counters.record_connect();
+        let health = counters.snapshot(true);
+        assert_eq!(health.since_last_report, None);
+        assert_eq!((health.read_errors, health.reconnects, health.restarts), (0, 1, 1));
+    }
+
+    #[test]
+    fn auth_times_count_down() {
+        let counters = HealthCounters::new();
+        let now = Instant::now();
+        counters.set_next_auth(now + Duration::from_secs(60));
+        counters.set_auth_expiry(now + Duration::from_secs(65));
+        let health = counters.snapshot(true);
+        assert!(health.until_reauthentication.unwrap() <= Duration::from_secs(60));
+        assert!(health.until_reauthentication.unwrap() > Duration::from_secs(59));
+        assert!(health.until_auth_expiry.unwrap() > Duration::from_secs(64));
+
+        // Overdue times are zero rather than negative.
+        counters.set_next_auth(now);
+        thread::sleep(Duration::from_millis(1));
+        assert_eq!(counters.snapshot(true).until_reauthentication, Some(Duration::ZERO));
+
+        counters.record_disconnect();
+        let health = counters.snapshot(false);
+        assert_eq!((health.until_reauthentication, health.until_auth_expiry), (None, None));
+    }
+
+    #[test]
+    fn poller_keeps_health() {
+        let backend = FakeBackend::new();
+        backend.push_auth_handshake();
+        let (sender, receiver) = mpsc::channel();
+        let speed_editor = SpeedEditor::builder()
+            .on_disconnect(move || sender.send(()).unwrap())
+            .connect_backend(backend.clone())
+            .unwrap();
+        assert!(backend.wait_until_idle(TIMEOUT));
+
+        let health = speed_editor.health();
+        assert!(health.connected);
+        assert_eq!(health.since_last_report, None);
+        // The handshake asks to authenticate again after 600 seconds, minus the margin.
+        assert!(health.until_reauthentication.unwrap() > Duration::from_secs(590));
+        assert!(health.until_auth_expiry.unwrap() > health.until_reauthentication.unwrap());
+
+        backend.fail_reads(2, Fault::Error);
+        backend.push_input_report(&[0x03, 0x00, 0x05, 0x00, 0x00, 0x00, 0x00]);
+        assert!(backend.wait_until_idle(TIMEOUT));
+        let health = speed_editor.health();
+        assert!(health.since_last_report.is_some());
+        assert_eq!(health.read_errors, 2);
+
+        backend.fail_reads(1, Fault::Disconnected);
+        backend.push_input_report(&[0x03, 0x00, 0x05, 0x00, 0x00, 0x00, 0x00]);
+        assert_eq!(receiver.recv_timeout(TIMEOUT), Ok(()));
+        let health = speed_editor.health();
+        assert!(!health.connected);
+        assert_eq!((health.until_reauthentication, health.until_auth_expiry), (None, None));
+        assert_eq!((health.read_errors, health.reconnects), (3, 0));
+    }
+}
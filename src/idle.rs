@@ -0,0 +1,53 @@
+use std::time::{Duration, Instant};
+
+/// Tracks when the operator last touched the device, see [`SpeedEditor::on_idle`][crate::SpeedEditor::on_idle].
+#[derive(Debug)]
+pub(crate) struct IdleTracker {
+    /// When the last button or wheel report was received,
+    /// or when the tracker was created if there was none yet.
+    last_input: Instant,
+    /// How long without input until the device is idle, if anything wants to know.
+    threshold: Option<Duration>,
+    idle: bool,
+}
+
+impl Default for IdleTracker {
+    fn default() -> Self {
+        Self { last_input: Instant::now(), threshold: None, idle: false }
+    }
+}
+
+impl IdleTracker {
+    pub(crate) fn idle_for(&self) -> Duration {
+        self.last_input.elapsed()
+    }
+
+    pub(crate) fn is_idle(&self) -> bool {
+        self.idle
+    }
+
+    pub(crate) fn set_threshold(&mut self, threshold: Option<Duration>) {
+        self.threshold = threshold;
+        self.idle = false;
+    }
+
+    /// Records input, returning `true` if it ends an idle period.
+    pub(crate) fn record_input(&mut self, at: Instant) -> bool {
+        self.last_input = at;
+        std::mem::take(&mut self.idle)
+    }
+
+    /// Returns `true` if the device just became idle,
+    /// and when to check again if it is not idle yet.
+    pub(crate) fn check(&mut self, now: Instant) -> (bool, Option<Instant>) {
+        let Some(threshold) = self.threshold.filter(|_| !self.idle) else {
+            return (false, None);
+        };
+        let idle_at = self.last_input + threshold;
+        if now < idle_at {
+            return (false, Some(idle_at));
+        }
+        self.idle = true;
+        (true, None)
+    }
+}
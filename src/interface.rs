@@ -0,0 +1,226 @@
+use crate::{
+    Button, ButtonLed, DeviceInfo, EventSink, PollerError, SpeedEditor, WheelLed, WheelMode,
+};
+
+/// What an application uses of a [`SpeedEditor`], so it can depend on this trait instead,
+/// e.g. to test it with a double (like the `MockSpeedEditor` with the `mock` feature).
+///
+/// The trait is object safe, so it can be used as `Box<dyn SpeedEditorInterface>`.
+/// That is why the callbacks are boxed here, unlike the methods of [`SpeedEditor`].
+///
+/// # Example
+///
+/// ```no_run
+/// use bmdse::{ButtonLed, SpeedEditor, SpeedEditorInterface};
+///
+/// fn setup(panel: &dyn SpeedEditorInterface) {
+///     panel.set_button_led(ButtonLed::Cam1);
+///     panel.set_on_button_change(Box::new(|button, pressed| {
+///         eprintln!("button {button:?} {}", if pressed { "pressed" } else { "released" });
+///     }));
+/// }
+///
+/// let panel: Box<dyn SpeedEditorInterface> = Box::new(SpeedEditor::new().unwrap());
+/// setup(&*panel);
+/// ```
+pub trait SpeedEditorInterface: Send + Sync {
+    /// See [`SpeedEditor::set_on_wheel_change`].
+    fn set_on_wheel_change(&self, f: Box<dyn Fn(i32) + Send>);
+
+    /// See [`SpeedEditor::set_on_button_change`].
+    fn set_on_button_change(&self, f: Box<dyn Fn(Button, bool) + Send>);
+
+    /// See [`SpeedEditor::set_on_battery_info`].
+    fn set_on_battery_info(&self, f: Box<dyn Fn(bool, u8) + Send>);
+
+    /// See [`SpeedEditor::set_on_connect`].
+    fn set_on_connect(&self, f: Box<dyn Fn(DeviceInfo) + Send>);
+
+    /// See [`SpeedEditor::set_on_disconnect`].
+    fn set_on_disconnect(&self, f: Box<dyn Fn() + Send>);
+
+    /// See [`SpeedEditor::set_on_error`].
+    fn set_on_error(&self, f: Box<dyn Fn(&PollerError) + Send>);
+
+    /// See [`SpeedEditor::attach_sink`].
+    fn attach_sink(&self, sink: Box<dyn EventSink>);
+
+    /// See [`SpeedEditor::is_button_pressed`].
+    fn is_button_pressed(&self, button: Button) -> bool;
+
+    /// See [`SpeedEditor::pressed_buttons`].
+    fn pressed_buttons(&self) -> Vec<Button>;
+
+    /// See [`SpeedEditor::set_button_led`].
+    fn set_button_led(&self, led: ButtonLed);
+
+    /// See [`SpeedEditor::button_led`].
+    fn button_led(&self) -> ButtonLed;
+
+    /// See [`SpeedEditor::set_wheel_led`].
+    fn set_wheel_led(&self, led: WheelLed);
+
+    /// See [`SpeedEditor::wheel_led`].
+    fn wheel_led(&self) -> WheelLed;
+
+    /// See [`SpeedEditor::set_wheel_mode`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the wheel mode could not be changed, e.g. by an implementation for a
+    /// panel that is not attached to this computer. A [`SpeedEditor`] keeps the mode like the
+    /// LEDs, and writes it to the device later, so it never returns an error.
+    fn set_wheel_mode(&self, mode: WheelMode) -> Result<(), crate::Error>;
+
+    /// See [`SpeedEditor::wheel_mode`].
+    fn wheel_mode(&self) -> WheelMode;
+
+    /// See [`SpeedEditor::is_connected`].
+    fn is_connected(&self) -> bool;
+
+    /// See [`SpeedEditor::reset_device`], which also writes the wheel mode again.
+    ///
+    /// # Errors
+    ///
+    /// See [`SpeedEditor::reset_device`].
+    fn reset_device(&self) -> Result<(), crate::Error>;
+}
+
+impl SpeedEditorInterface for SpeedEditor {
+    fn set_on_wheel_change(&self, f: Box<dyn Fn(i32) + Send>) {
+        SpeedEditor::set_on_wheel_change(self, f);
+    }
+
+    fn set_on_button_change(&self, f: Box<dyn Fn(Button, bool) + Send>) {
+        SpeedEditor::set_on_button_change(self, f);
+    }
+
+    fn set_on_battery_info(&self, f: Box<dyn Fn(bool, u8) + Send>) {
+        SpeedEditor::set_on_battery_info(self, f);
+    }
+
+    fn set_on_connect(&self, f: Box<dyn Fn(DeviceInfo) + Send>) {
+        SpeedEditor::set_on_connect(self, f);
+    }
+
+    fn set_on_disconnect(&self, f: Box<dyn Fn() + Send>) {
+        SpeedEditor::set_on_disconnect(self, f);
+    }
+
+    fn set_on_error(&self, f: Box<dyn Fn(&PollerError) + Send>) {
+        SpeedEditor::set_on_error(self, f);
+    }
+
+    fn attach_sink(&self, sink: Box<dyn EventSink>) {
+        SpeedEditor::attach_sink(self, sink);
+    }
+
+    fn is_button_pressed(&self, button: Button) -> bool {
+        SpeedEditor::is_button_pressed(self, button)
+    }
+
+    fn pressed_buttons(&self) -> Vec<Button> {
+        SpeedEditor::pressed_buttons(self)
+    }
+
+    fn set_button_led(&self, led: ButtonLed) {
+        SpeedEditor::set_button_led(self, led);
+    }
+
+    fn button_led(&self) -> ButtonLed {
+        SpeedEditor::button_led(self)
+    }
+
+    fn set_wheel_led(&self, led: WheelLed) {
+        SpeedEditor::set_wheel_led(self, led);
+    }
+
+    fn wheel_led(&self) -> WheelLed {
+        SpeedEditor::wheel_led(self)
+    }
+
+    fn set_wheel_mode(&self, mode: WheelMode) -> Result<(), crate::Error> {
+        SpeedEditor::set_wheel_mode(self, mode);
+        Ok(())
+    }
+
+    fn wheel_mode(&self) -> WheelMode {
+        SpeedEditor::wheel_mode(self)
+    }
+
+    fn is_connected(&self) -> bool {
+        SpeedEditor::is_connected(self)
+    }
+
+    fn reset_device(&self) -> Result<(), crate::Error> {
+        SpeedEditor::reset_device(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::mpsc, thread};
+
+    use super::*;
+    use crate::test_util::{TIMEOUT, connect};
+    use crate::{Event, protocol};
+
+    #[test]
+    fn trait_object_can_move_between_threads() {
+        let (speed_editor, _backend) = connect();
+        let interface: Box<dyn SpeedEditorInterface> = Box::new(speed_editor);
+        let interface = thread::spawn(move || {
+            interface.set_wheel_led(WheelLed::Jog);
+            interface
+        })
+        .join()
+        .unwrap();
+        assert_eq!(interface.wheel_led(), WheelLed::Jog);
+    }
+
+    #[test]
+    fn speed_editor_is_used_through_trait_object() {
+        let (speed_editor, backend) = connect();
+        let interface: Box<dyn SpeedEditorInterface> = Box::new(speed_editor.clone());
+        assert!(interface.is_connected());
+
+        let (wheel_sender, wheel_receiver) = mpsc::channel();
+        interface
+            .set_on_wheel_change(Box::new(move |velocity| wheel_sender.send(velocity).unwrap()));
+        let (button_sender, button_receiver) = mpsc::channel();
+        interface.set_on_button_change(Box::new(move |button, pressed| {
+            button_sender.send((button, pressed)).unwrap();
+        }));
+        let (sink, events) = mpsc::channel();
+        interface.attach_sink(Box::new(sink));
+
+        backend.push_input_report(&[0x03, 0x00, 0x05, 0x00, 0x00, 0x00, 0x00]);
+        assert_eq!(wheel_receiver.recv_timeout(TIMEOUT), Ok(5));
+        backend.push_input_report(&[0x04, 0x0f, 0x00, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(button_receiver.recv_timeout(TIMEOUT), Ok((Button::Cut, true)));
+        assert!(interface.is_button_pressed(Button::Cut));
+        assert_eq!(interface.pressed_buttons(), [Button::Cut]);
+        // The sink also gets the events from before it was attached, like the battery info.
+        let mut events = std::iter::from_fn(|| events.recv_timeout(TIMEOUT).ok());
+        assert!(events.any(|event| matches!(event, Event::WheelChange { velocity: 5 })));
+
+        interface.set_button_led(ButtonLed::Cam1);
+        assert_eq!(
+            (interface.button_led(), speed_editor.button_led()),
+            (ButtonLed::Cam1, ButtonLed::Cam1)
+        );
+
+        // Resetting authenticates again, and writes the wheel mode again.
+        backend.push_auth_handshake();
+        interface.reset_device().unwrap();
+        let wheel_mode = protocol::wheel_mode_report(WheelMode::Relative).to_vec();
+        assert!(backend.written().contains(&wheel_mode));
+        assert!(backend.written().contains(&protocol::button_led_report(ButtonLed::Cam1).to_vec()));
+
+        interface.set_wheel_mode(WheelMode::AbsoluteDeadZero).unwrap();
+        assert_eq!(
+            (interface.wheel_mode(), speed_editor.wheel_mode()),
+            (WheelMode::AbsoluteDeadZero, WheelMode::AbsoluteDeadZero)
+        );
+    }
+}
@@ -0,0 +1,434 @@
+//! Writing the events of the Speed Editor as JSON Lines, e.g. to pipe them into other tools.
+//!
+//! A [`JsonLinesWriter`] is an [`EventSink`] that writes one JSON object per line:
+//!
+//! ```no_run
+//! use bmdse::SpeedEditor;
+//! use bmdse::jsonl::JsonLinesWriter;
+//!
+//! let speed_editor = SpeedEditor::new().unwrap();
+//! speed_editor.attach_sink(Box::new(JsonLinesWriter::new(std::io::stdout()).unwrap()));
+//!
+//! speed_editor.run().unwrap();
+//! ```
+//!
+//! # Schema
+//!
+//! Every line has the same three fields, in this order:
+//!
+//! - `type`: what kind of line it is, see below.
+//! - `timestamp`: when the event was received, in milliseconds since the Unix epoch.
+//! - `payload`: an object with the fields of the event.
+//!
+//! | `type` | `payload` |
+//! |--------|-----------|
+//! | `wheel` | `velocity`: the change of the jog wheel |
+//! | `button` | `button`: the name of the button (e.g. `StopPlay`), `pressed`: `true` or `false` |
+//! | `editor_keyboard_button` | `code`: the code of the [`EditorKeyboardButton`][crate::EditorKeyboardButton], `pressed`: `true` or `false` |
+//! | `battery` | `level`: the battery level (`0..=100`), `charging`: `true` or `false` |
+//! | `charging` | `charging`: `true` or `false` |
+//! | `unknown_report` | `id`: the report ID, `data`: the bytes after the ID |
+//! | `connected` | `device`: `path`, `serial_number`, `product`, `model`, `transport` and `release_number` |
+//! | `disconnected` | No fields |
+//! | `dropped` | `count`: how many lines were dropped before this one, see [`JsonLinesWriter`] |
+//!
+//! New types and payload fields might be added, existing ones are not changed or removed.
+//!
+//! ```
+//! use std::io::{BufRead, BufReader};
+//!
+//! use bmdse::jsonl::JsonLinesWriter;
+//! use bmdse::{Button, Event, EventSink};
+//!
+//! let (reader, writer) = std::io::pipe().unwrap();
+//! let jsonl = JsonLinesWriter::new(writer).unwrap();
+//! jsonl.send(Event::WheelChange { velocity: -3 }).unwrap();
+//! jsonl.send(Event::ButtonChange { button: Button::StopPlay, pressed: true }).unwrap();
+//! jsonl.send(Event::BatteryInfo { charging: false, percentage: 80 }).unwrap();
+//! jsonl.send(Event::ChargingChange { charging: true }).unwrap();
+//! jsonl.send(Event::UnknownReport { id: 0x07, data: vec![1, 2] }).unwrap();
+//! jsonl.send(Event::Disconnected).unwrap();
+//! drop(jsonl);
+//!
+//! # /// Replaces the timestamp, which differs on every run.
+//! # fn snapshot(line: &str) -> String {
+//! #     let (start, rest) = line.split_once("\"timestamp\":").unwrap();
+//! #     let rest = rest.trim_start_matches(|c: char| c.is_ascii_digit());
+//! #     format!("{start}\"timestamp\":0{rest}")
+//! # }
+//! let lines: Vec<String> =
+//!     BufReader::new(reader).lines().map(|line| snapshot(&line.unwrap())).collect();
+//! assert_eq!(lines, [
+//!     r#"{"type":"wheel","timestamp":0,"payload":{"velocity":-3}}"#,
+//!     r#"{"type":"button","timestamp":0,"payload":{"button":"StopPlay","pressed":true}}"#,
+//!     r#"{"type":"battery","timestamp":0,"payload":{"charging":false,"level":80}}"#,
+//!     r#"{"type":"charging","timestamp":0,"payload":{"charging":true}}"#,
+//!     r#"{"type":"unknown_report","timestamp":0,"payload":{"data":[1,2],"id":7}}"#,
+//!     r#"{"type":"disconnected","timestamp":0,"payload":{}}"#,
+//! ]);
+//! ```
+
+use std::{
+    collections::VecDeque,
+    io::Write,
+    sync::{Arc, Condvar, Mutex},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use serde_json::{Value, json};
+
+use crate::{DeviceInfo, Event, EventSink, SinkClosed, sync::MutexExt};
+
+/// A line of the [schema](self#schema).
+#[derive(serde::Serialize)]
+struct Line {
+    r#type: &'static str,
+    timestamp: u64,
+    payload: Value,
+}
+
+impl Line {
+    fn new(event: &Event, timestamp: u64) -> Self {
+        let (r#type, payload) = match event {
+            Event::WheelChange { velocity } => ("wheel", json!({ "velocity": velocity })),
+            Event::ButtonChange { button, pressed } => {
+                ("button", json!({ "button": format!("{button:?}"), "pressed": pressed }))
+            }
+            Event::EditorKeyboardButtonChange { button, pressed } => {
+                ("editor_keyboard_button", json!({ "code": button.code(), "pressed": pressed }))
+            }
+            Event::BatteryInfo { charging, percentage } => {
+                ("battery", json!({ "level": percentage, "charging": charging }))
+            }
+            Event::ChargingChange { charging } => ("charging", json!({ "charging": charging })),
+            Event::UnknownReport { id, data } => {
+                ("unknown_report", json!({ "id": id, "data": data }))
+            }
+            Event::Connected(device) => ("connected", json!({ "device": device_json(device) })),
+            Event::Disconnected => ("disconnected", json!({})),
+        };
+        Self { r#type, timestamp, payload }
+    }
+
+    fn dropped(count: u64, timestamp: u64) -> Self {
+        Self { r#type: "dropped", timestamp, payload: json!({ "count": count }) }
+    }
+
+    fn encode(&self) -> String {
+        // Serializing a string key map cannot fail.
+        let mut line = serde_json::to_string(self).unwrap_or_default();
+        line.push('\n');
+        line
+    }
+}
+
+fn device_json(device: &DeviceInfo) -> Value {
+    json!({
+        "path": device.path,
+        "serial_number": device.serial_number,
+        "product": device.product,
+        "model": format!("{:?}", device.model),
+        "transport": format!("{:?}", device.transport),
+        "release_number": device.release_number,
+    })
+}
+
+fn unix_millis() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_millis() as u64)
+}
+
+#[derive(Default)]
+struct Pending {
+    lines: VecDeque<String>,
+    /// How many lines were dropped since the last line that was queued.
+    dropped: u64,
+    /// Set once writing failed, after which nothing is written anymore.
+    closed: bool,
+    stop: bool,
+}
+
+struct JsonlShared {
+    pending: Mutex<Pending>,
+    changed: Condvar,
+    capacity: usize,
+}
+
+/// An [`EventSink`] that writes the events as JSON Lines, following the [schema](self#schema).
+///
+/// The lines are written and flushed one by one from a separate thread,
+/// so a slow reader never holds up the events. When more lines than the capacity wait to be
+/// written, e.g. because the reader of a pipe stalls, new lines are dropped. The next line
+/// that fits is preceded by a `dropped` line with how many were dropped.
+///
+/// Once writing fails, e.g. because the reader of a pipe exited, the sink is closed.
+/// Dropping the writer writes the lines that are waiting, waiting at most a second for them.
+pub struct JsonLinesWriter {
+    shared: Arc<JsonlShared>,
+    writer: Option<JoinHandle<()>>,
+}
+
+impl JsonLinesWriter {
+    /// The number of lines that can wait to be written by default.
+    pub const DEFAULT_CAPACITY: usize = 1024;
+
+    /// Creates a [`JsonLinesWriter`] that writes to `writer`,
+    /// keeping at most [`JsonLinesWriter::DEFAULT_CAPACITY`] lines waiting.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the writing thread could not be spawned.
+    pub fn new(writer: impl Write + Send + 'static) -> Result<Self, crate::Error> {
+        Self::with_capacity(writer, Self::DEFAULT_CAPACITY)
+    }
+
+    /// Creates a [`JsonLinesWriter`] that writes to `writer`,
+    /// keeping at most `capacity` lines waiting.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the writing thread could not be spawned.
+    pub fn with_capacity(
+        writer: impl Write + Send + 'static,
+        capacity: usize,
+    ) -> Result<Self, crate::Error> {
+        let shared =
+            Arc::new(JsonlShared { pending: Mutex::default(), changed: Condvar::new(), capacity });
+
+        let writer_shared = Arc::clone(&shared);
+        let writer = thread::Builder::new()
+            .name("bmd_speed_editor_jsonl".to_string())
+            .spawn(move || write_pending(&writer_shared, writer))?;
+        Ok(Self { shared, writer: Some(writer) })
+    }
+}
+
+impl EventSink for JsonLinesWriter {
+    fn send(&self, event: Event) -> Result<(), SinkClosed> {
+        let timestamp = unix_millis();
+        let mut pending = self.shared.pending.lock_unpoisoned();
+        if pending.closed {
+            return Err(SinkClosed);
+        }
+
+        let needed = if pending.dropped > 0 { 2 } else { 1 };
+        if pending.lines.len() + needed > self.shared.capacity {
+            pending.dropped += 1;
+            return Ok(());
+        }
+        if pending.dropped > 0 {
+            let dropped = std::mem::take(&mut pending.dropped);
+            pending.lines.push_back(Line::dropped(dropped, timestamp).encode());
+        }
+        pending.lines.push_back(Line::new(&event, timestamp).encode());
+        drop(pending);
+
+        self.shared.changed.notify_all();
+        Ok(())
+    }
+}
+
+impl Drop for JsonLinesWriter {
+    fn drop(&mut self) {
+        const DRAIN_TIMEOUT: Duration = Duration::from_secs(1);
+
+        self.shared.pending.lock_unpoisoned().stop = true;
+        self.shared.changed.notify_all();
+        let Some(writer) = self.writer.take() else { return };
+
+        // A stalled reader would otherwise block the drop, which usually runs on the polling thread.
+        let start = Instant::now();
+        while !writer.is_finished() {
+            if start.elapsed() >= DRAIN_TIMEOUT {
+                return;
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+        let _ = writer.join();
+    }
+}
+
+/// Writes the pending lines until the [`JsonLinesWriter`] is dropped and they are all written.
+fn write_pending(shared: &JsonlShared, mut writer: impl Write) {
+    loop {
+        let line = {
+            let mut pending = shared.pending.lock_unpoisoned();
+            loop {
+                if let Some(line) = pending.lines.pop_front() {
+                    break line;
+                }
+                if pending.stop {
+                    return;
+                }
+                pending = shared.changed.wait(pending).unwrap_or_else(|e| e.into_inner());
+            }
+        };
+
+        if writer.write_all(line.as_bytes()).and_then(|()| writer.flush()).is_err() {
+            let mut pending = shared.pending.lock_unpoisoned();
+            pending.closed = true;
+            pending.lines.clear();
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io, sync::mpsc};
+
+    use super::*;
+    use crate::test_util::TIMEOUT;
+    use crate::{Button, EditorKeyboardButton, HidBackend, testing::FakeBackend};
+
+    #[test]
+    fn lines_match_snapshot() {
+        let device = FakeBackend::new().device_info().unwrap();
+        let cases = [
+            (
+                Line::new(&Event::WheelChange { velocity: -3 }, 1234),
+                r#"{"type":"wheel","timestamp":1234,"payload":{"velocity":-3}}"#,
+            ),
+            (
+                Line::new(&Event::ButtonChange { button: Button::Cam9, pressed: false }, 1234),
+                r#"{"type":"button","timestamp":1234,"payload":{"button":"Cam9","pressed":false}}"#,
+            ),
+            (
+                Line::new(
+                    &Event::EditorKeyboardButtonChange {
+                        button: EditorKeyboardButton::from_code(0x80),
+                        pressed: true,
+                    },
+                    1234,
+                ),
+                r#"{"type":"editor_keyboard_button","timestamp":1234,"payload":{"code":128,"pressed":true}}"#,
+            ),
+            (
+                Line::new(&Event::BatteryInfo { charging: true, percentage: 100 }, 1234),
+                r#"{"type":"battery","timestamp":1234,"payload":{"charging":true,"level":100}}"#,
+            ),
+            (
+                Line::new(&Event::ChargingChange { charging: false }, 1234),
+                r#"{"type":"charging","timestamp":1234,"payload":{"charging":false}}"#,
+            ),
+            (
+                Line::new(&Event::UnknownReport { id: 0x09, data: vec![] }, 1234),
+                r#"{"type":"unknown_report","timestamp":1234,"payload":{"data":[],"id":9}}"#,
+            ),
+            (
+                Line::new(&Event::Connected(device), 1234),
+                concat!(
+                    r#"{"type":"connected","timestamp":1234,"payload":{"device":{"#,
+                    r#""model":"SpeedEditor","path":"fake","product":"DaVinci Resolve Speed Editor","#,
+                    r#""release_number":0,"serial_number":null,"transport":"Usb"}}}"#,
+                ),
+            ),
+            (
+                Line::new(&Event::Disconnected, 1234),
+                r#"{"type":"disconnected","timestamp":1234,"payload":{}}"#,
+            ),
+            (
+                Line::dropped(5, 1234),
+                r#"{"type":"dropped","timestamp":1234,"payload":{"count":5}}"#,
+            ),
+        ];
+        for (line, snapshot) in cases {
+            assert_eq!(line.encode(), format!("{snapshot}\n"));
+        }
+    }
+
+    /// A writer that blocks in its first write until it is released.
+    struct StalledWriter {
+        output: Arc<Mutex<Vec<u8>>>,
+        stalled: Option<(mpsc::Sender<()>, mpsc::Receiver<()>)>,
+    }
+
+    impl Write for StalledWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if let Some((stalled, release)) = self.stalled.take() {
+                stalled.send(()).unwrap();
+                release.recv().unwrap();
+            }
+            self.output.lock_unpoisoned().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Returns the types of the lines, and the payload of the `dropped` lines.
+    fn types(output: &[u8]) -> Vec<String> {
+        String::from_utf8_lossy(output)
+            .lines()
+            .map(|line| {
+                let line: Value = serde_json::from_str(line).unwrap();
+                match line["type"].as_str().unwrap() {
+                    "dropped" => format!("dropped {}", line["payload"]["count"]),
+                    r#type => r#type.to_string(),
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn stalled_writer_drops_lines() {
+        let output = Arc::new(Mutex::new(Vec::new()));
+        let (stalled_sender, stalled) = mpsc::channel();
+        let (release, release_receiver) = mpsc::channel();
+        let writer = StalledWriter {
+            output: Arc::clone(&output),
+            stalled: Some((stalled_sender, release_receiver)),
+        };
+        let jsonl = JsonLinesWriter::with_capacity(writer, 2).unwrap();
+
+        jsonl.send(Event::WheelChange { velocity: 1 }).unwrap();
+        stalled.recv_timeout(TIMEOUT).unwrap();
+        // Two lines fit while the first one is being written, the rest is dropped.
+        for _ in 0..2 {
+            jsonl.send(Event::ChargingChange { charging: true }).unwrap();
+        }
+        for _ in 0..3 {
+            jsonl.send(Event::Disconnected).unwrap();
+        }
+
+        release.send(()).unwrap();
+        let start = Instant::now();
+        while types(&output.lock_unpoisoned()).len() < 3 {
+            assert!(start.elapsed() < TIMEOUT);
+            thread::sleep(Duration::from_millis(1));
+        }
+        jsonl.send(Event::WheelChange { velocity: 2 }).unwrap();
+        drop(jsonl);
+
+        assert_eq!(
+            types(&output.lock_unpoisoned()),
+            ["wheel", "charging", "charging", "dropped 3", "wheel"]
+        );
+    }
+
+    struct FailingWriter;
+
+    impl Write for FailingWriter {
+        fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+            Err(io::ErrorKind::BrokenPipe.into())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn failed_write_closes_sink() {
+        let jsonl = JsonLinesWriter::new(FailingWriter).unwrap();
+
+        let start = Instant::now();
+        while jsonl.send(Event::Disconnected).is_ok() {
+            assert!(start.elapsed() < TIMEOUT);
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(jsonl.send(Event::Disconnected), Err(SinkClosed));
+    }
+}
@@ -0,0 +1,322 @@
+//! Using the Speed Editor as a keyboard on any platform, through synthetic key presses.
+//!
+//! A [`KeystrokeBridge`] presses the [`Chord`] that a [`Keymap`] maps a button to while the
+//! button is held, using the input simulation of the operating system (with [`enigo`]).
+//! Any application then receives the keys as if they were typed on a keyboard.
+//! On Linux, the `uinput` feature does the same with a virtual keyboard,
+//! which also works without a display server.
+//!
+//! Depending on the platform, simulating input needs permission first,
+//! like the accessibility permission on macOS.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use bmdse::keystrokes::{Chord, Key, KeystrokeBridge, Keymap};
+//! use bmdse::{Button, SpeedEditor};
+//!
+//! let speed_editor = SpeedEditor::new().unwrap();
+//!
+//! let mut keymap = Keymap::davinci_resolve();
+//! keymap.set(Button::Cam1, Chord::new(Key::Unicode('1')).with(Key::Alt));
+//! let bridge = KeystrokeBridge::new(keymap).unwrap();
+//! // Holding both buttons suspends the bridge, and holding them again resumes it.
+//! bridge.set_kill_switch([Button::Source, Button::Timeline]);
+//! bridge.attach(&speed_editor);
+//!
+//! // The keys are typed until the bridge is dropped.
+//! std::thread::park();
+//! ```
+
+use std::{
+    collections::HashMap,
+    io,
+    sync::{Arc, Mutex, Weak},
+};
+
+use enigo::{Direction, Enigo, Keyboard, Settings};
+
+use crate::{Button, Event, EventSink, SinkClosed, SpeedEditor, sync::MutexExt};
+
+pub use enigo::Key;
+
+/// A key with the modifiers that are held with it, like `Ctrl+Shift+Z`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Chord {
+    /// The modifiers, pressed in order before the key and released in reverse order after it.
+    pub modifiers: Vec<Key>,
+    /// The key.
+    pub key: Key,
+}
+
+impl Chord {
+    /// Creates a [`Chord`] of a single key without modifiers.
+    pub fn new(key: Key) -> Self {
+        Self { modifiers: Vec::new(), key }
+    }
+
+    /// Adds a modifier that is held with the key.
+    pub fn with(mut self, modifier: Key) -> Self {
+        self.modifiers.push(modifier);
+        self
+    }
+
+    /// Creates a [`Chord`] of the key with `Ctrl`, or `Cmd` on macOS,
+    /// which is the modifier of most shortcuts on each platform.
+    pub fn primary(key: Key) -> Self {
+        if cfg!(target_os = "macos") {
+            Self::new(key).with(Key::Meta)
+        } else {
+            Self::new(key).with(Key::Control)
+        }
+    }
+
+    /// Creates a [`Chord`] of the key with `Shift`.
+    pub fn shift(key: Key) -> Self {
+        Self::new(key).with(Key::Shift)
+    }
+}
+
+impl From<Key> for Chord {
+    fn from(key: Key) -> Self {
+        Self::new(key)
+    }
+}
+
+/// Which [`Chord`] each button presses.
+///
+/// Start from [`Keymap::davinci_resolve`] and override the buttons you want,
+/// or start from an empty [`Keymap::new`].
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Keymap {
+    chords: Vec<(Button, Chord)>,
+}
+
+impl Keymap {
+    /// Creates a [`Keymap`] without any chords.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a [`Keymap`] with the default shortcuts of the edit page of DaVinci Resolve.
+    ///
+    /// Only the buttons with an equivalent keyboard shortcut are mapped.
+    pub fn davinci_resolve() -> Self {
+        let mut keymap = Self::new();
+        keymap.set(Button::SmartInsert, Key::F9);
+        keymap.set(Button::Append, Chord::shift(Key::F12));
+        keymap.set(Button::RippleOverwrite, Chord::shift(Key::F10));
+        keymap.set(Button::PlaceOnTop, Key::F12);
+        keymap.set(Button::SourceOverwrite, Key::F10);
+        keymap.set(Button::In, Key::Unicode('i'));
+        keymap.set(Button::Out, Key::Unicode('o'));
+        keymap.set(Button::TrimIn, Chord::shift(Key::Unicode('[')));
+        keymap.set(Button::TrimOut, Chord::shift(Key::Unicode(']')));
+        keymap.set(Button::Transition, Chord::primary(Key::Unicode('t')));
+        keymap.set(Button::Split, Chord::primary(Key::Unicode('b')));
+        keymap.set(Button::Snap, Key::Unicode('n'));
+        keymap.set(Button::RippleDelete, Chord::shift(Key::Backspace));
+        keymap.set(Button::FullView, Chord::primary(Key::Unicode('f')));
+        keymap.set(Button::Escape, Key::Escape);
+        keymap.set(Button::StopPlay, Key::Space);
+        keymap
+    }
+
+    /// Maps the button to the chord, replacing the chord it was mapped to.
+    pub fn set(&mut self, button: Button, chord: impl Into<Chord>) {
+        let chord = chord.into();
+        match self.chords.iter_mut().find(|(mapped, _)| *mapped == button) {
+            Some((_, mapped)) => *mapped = chord,
+            None => self.chords.push((button, chord)),
+        }
+    }
+
+    /// Removes the chord of the button, returning it if there was one.
+    pub fn remove(&mut self, button: Button) -> Option<Chord> {
+        let index = self.chords.iter().position(|(mapped, _)| *mapped == button)?;
+        Some(self.chords.remove(index).1)
+    }
+
+    /// Returns the chord the button is mapped to.
+    pub fn get(&self, button: Button) -> Option<&Chord> {
+        self.chords.iter().find(|(mapped, _)| *mapped == button).map(|(_, chord)| chord)
+    }
+}
+
+struct SyntheticKeyboard {
+    enigo: Enigo,
+    /// How many chords hold each key, so a modifier that is shared by two held chords
+    /// is only released with the last one.
+    held: HashMap<Key, u32>,
+}
+
+impl SyntheticKeyboard {
+    fn press(&mut self, chord: &Chord) -> Result<(), enigo::InputError> {
+        for &key in chord.modifiers.iter().chain([&chord.key]) {
+            let count = self.held.entry(key).or_default();
+            *count += 1;
+            if *count == 1 {
+                self.enigo.key(key, Direction::Press)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn release(&mut self, chord: &Chord) -> Result<(), enigo::InputError> {
+        for &key in [&chord.key].into_iter().chain(chord.modifiers.iter().rev()) {
+            let Some(count) = self.held.get_mut(&key) else { continue };
+            *count -= 1;
+            if *count == 0 {
+                self.held.remove(&key);
+                self.enigo.key(key, Direction::Release)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn release_all(&mut self) -> Result<(), enigo::InputError> {
+        for (key, _) in std::mem::take(&mut self.held) {
+            self.enigo.key(key, Direction::Release)?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for SyntheticKeyboard {
+    fn drop(&mut self) {
+        // Keys that stay pressed would repeat forever in other applications.
+        let _ = self.release_all();
+    }
+}
+
+struct BridgeState {
+    keyboard: SyntheticKeyboard,
+    keymap: Keymap,
+    kill_switch: Vec<Button>,
+    /// The buttons that are held, to recognize the kill switch.
+    pressed: Vec<Button>,
+    suspended: bool,
+}
+
+impl BridgeState {
+    fn set_suspended(&mut self, suspended: bool) {
+        if suspended {
+            let _ = self.keyboard.release_all();
+        }
+        self.suspended = suspended;
+    }
+}
+
+/// Presses the chords of the buttons of a [`SpeedEditor`], until it is dropped.
+///
+/// See the [module documentation][self].
+/// Dropping it releases all held keys.
+pub struct KeystrokeBridge {
+    state: Arc<Mutex<BridgeState>>,
+}
+
+impl KeystrokeBridge {
+    /// Creates a [`KeystrokeBridge`] that presses the [`Chord`] that `keymap` maps a button to,
+    /// pressing it when the button is pressed and releasing it when the button is released.
+    ///
+    /// Holding multiple buttons holds all their keys, and a modifier they share stays held
+    /// until the last of them is released. Nothing is pressed until it is
+    /// [attached][KeystrokeBridge::attach] to a [`SpeedEditor`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the input simulation of the operating system is not available,
+    /// e.g. because there is no display server.
+    pub fn new(keymap: Keymap) -> Result<Self, crate::Error> {
+        let enigo = Enigo::new(&Settings::default())
+            .map_err(|error| io::Error::other(format!("keystrokes: {error}")))?;
+        let keyboard = SyntheticKeyboard { enigo, held: HashMap::new() };
+        let state = BridgeState {
+            keyboard,
+            keymap,
+            kill_switch: Vec::new(),
+            pressed: Vec::new(),
+            suspended: false,
+        };
+        Ok(Self { state: Arc::new(Mutex::new(state)) })
+    }
+
+    /// Presses the chords of the buttons of the [`SpeedEditor`].
+    ///
+    /// The keys are pressed by the polling thread, and failed key presses are ignored.
+    pub fn attach(&self, speed_editor: &SpeedEditor) {
+        speed_editor.attach_sink(Box::new(BridgeSink { state: Arc::downgrade(&self.state) }));
+    }
+
+    /// Replaces the [`Keymap`], releasing the keys that are held.
+    pub fn set_keymap(&self, keymap: Keymap) {
+        let mut state = self.state.lock_unpoisoned();
+        let _ = state.keyboard.release_all();
+        state.keymap = keymap;
+    }
+
+    /// Sets the buttons that suspend the bridge when they are held at the same time,
+    /// and resume it when they are held again. An empty kill switch (the default) does nothing.
+    ///
+    /// The buttons still press their own chords, so use buttons that are not in the [`Keymap`].
+    pub fn set_kill_switch(&self, buttons: impl IntoIterator<Item = Button>) {
+        self.state.lock_unpoisoned().kill_switch = buttons.into_iter().collect();
+    }
+
+    /// Suspends the bridge, releasing the keys that are held, or resumes it.
+    ///
+    /// While it is suspended, no keys are pressed.
+    pub fn set_suspended(&self, suspended: bool) {
+        self.state.lock_unpoisoned().set_suspended(suspended);
+    }
+
+    /// Returns `true` if the bridge is suspended, see [`KeystrokeBridge::set_suspended`].
+    pub fn is_suspended(&self) -> bool {
+        self.state.lock_unpoisoned().suspended
+    }
+}
+
+/// Forwards the button events to the synthetic keyboard, while the [`KeystrokeBridge`] exists.
+struct BridgeSink {
+    state: Weak<Mutex<BridgeState>>,
+}
+
+impl EventSink for BridgeSink {
+    fn send(&self, event: Event) -> Result<(), SinkClosed> {
+        let state = self.state.upgrade().ok_or(SinkClosed)?;
+        let mut state = state.lock_unpoisoned();
+        let state = &mut *state;
+        match event {
+            Event::ButtonChange { button, pressed: true } => {
+                state.pressed.push(button);
+                if !state.kill_switch.is_empty()
+                    && state.kill_switch.contains(&button)
+                    && state.kill_switch.iter().all(|button| state.pressed.contains(button))
+                {
+                    state.set_suspended(!state.suspended);
+                    return Ok(());
+                }
+            }
+            Event::ButtonChange { button, pressed: false } => {
+                state.pressed.retain(|pressed| *pressed != button);
+            }
+            Event::Disconnected => state.pressed.clear(),
+            _ => {}
+        }
+        if state.suspended {
+            return Ok(());
+        }
+
+        // There is nobody to report a failed key press to, and the next one might work again.
+        let _ = match event {
+            Event::ButtonChange { button, pressed } => match state.keymap.get(button) {
+                Some(chord) if pressed => state.keyboard.press(chord),
+                Some(chord) => state.keyboard.release(chord),
+                None => Ok(()),
+            },
+            Event::Disconnected => state.keyboard.release_all(),
+            _ => Ok(()),
+        };
+        Ok(())
+    }
+}
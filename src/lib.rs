@@ -6,7 +6,7 @@
 //!
 //! ## Example
 //!
-//! ```rust
+//! ```no_run
 //! use std::{
 //!     sync::{Arc, RwLock},
 //!     thread,
@@ -70,20 +70,135 @@
 //! `cargo run --release --example simple` or `cargo run --release --example state`
 
 use std::{
-    sync::{Arc, Mutex},
-    thread,
-    time::Instant,
+    sync::{Arc, atomic::Ordering, mpsc},
+    time::{Duration, Instant},
 };
 
+pub mod actions;
+mod auth;
+mod backend;
+mod battery;
+mod builder;
+mod capture;
+#[cfg(feature = "companion")]
+pub mod companion;
+mod counters;
+mod device_info;
+mod diagnostics;
+mod diff;
+mod dispatch;
 mod driver;
 mod error;
+mod event;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+mod health;
+mod idle;
+mod interface;
+#[cfg(feature = "jsonl")]
+pub mod jsonl;
+#[cfg(feature = "keystrokes")]
+pub mod keystrokes;
+#[cfg(target_os = "linux")]
+pub mod linux;
+mod manager;
+mod manual;
+#[cfg(feature = "mapping")]
+pub mod mapping;
+mod metrics;
+#[cfg(feature = "midi")]
+pub mod midi;
+#[cfg(feature = "mock")]
+mod mock;
+#[cfg(feature = "mqtt")]
+pub mod mqtt;
+#[cfg(feature = "osc")]
+pub mod osc;
+mod parts;
+mod poller;
+pub mod protocol;
+#[cfg(feature = "unstable-raw")]
+mod raw;
+mod registry;
+mod session;
+mod split;
+mod state;
+mod sync;
+#[cfg(test)]
+mod test_util;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+mod thread;
+#[cfg(all(target_os = "linux", feature = "uinput"))]
+pub mod uinput;
+mod wait;
+#[cfg(feature = "websocket")]
+pub mod websocket;
 
-use hidapi::HidDevice;
+use crate::battery::BatteryEstimator;
+use crate::dispatch::{Call, Dispatch, InnerLock};
+use crate::driver::{ButtonSet, HidApiSource, OpenOptions};
+use crate::idle::IdleTracker;
+use crate::poller::{PollOptions, PollerHandle, release_all_buttons};
+use crate::session::ResumedAuth;
+use crate::sync::MutexExt;
+use crate::thread::ThreadOptions;
 
-use crate::driver::{Report, WheelMode};
+pub use crate::backend::HidBackend;
+pub use crate::battery::BatteryEstimate;
+pub use crate::builder::SpeedEditorBuilder;
+pub use crate::capture::CaptureGuard;
+pub use crate::counters::Counters;
+pub use crate::device_info::{DeviceInfo, FirmwareVersion, Transport};
+pub use crate::diff::StateDiff;
+pub use crate::driver::{
+    Button, ButtonLed, EDITOR_KEYBOARD_PRODUCT_ID, EditorKeyboardButton, Model, ReportMask,
+    SPEED_EDITOR_PRODUCT_ID, VENDOR_ID, WheelLed, WheelMode,
+};
+pub use crate::error::{AuthFailure, AuthStep, Error, ErrorKind, PollerError};
+use crate::event::EventBuffer;
+pub use crate::event::{Event, EventSink, SinkClosed};
+pub use crate::health::Health;
+pub use crate::interface::SpeedEditorInterface;
+pub use crate::manager::{DeviceId, SpeedEditorManager, SpeedEditorManagerBuilder};
+pub use crate::manual::SpeedEditorSync;
+pub use crate::metrics::{DisconnectReason, SessionMetrics};
+#[cfg(feature = "mock")]
+pub use crate::mock::MockSpeedEditor;
+pub use crate::parts::SpeedEditorState;
+pub use crate::split::{ControlHalf, EventHalf};
+pub use crate::state::DeviceState;
+pub use crate::thread::ThreadPriority;
+pub use crate::wait::CancellationToken;
+
+pub use hidapi;
 
-pub use crate::driver::{Button, ButtonLed, WheelLed};
-pub use crate::error::Error;
+/// Returns information about all connected Speed Editors.
+///
+/// This does not open any of the devices, so it can be used to decide which device to open,
+/// even if opening it would fail because of missing permissions.
+///
+/// # Errors
+///
+/// This function might error if the HID API could not be initialized,
+/// or if the list of HID devices could not be read.
+pub fn list_devices() -> Result<Vec<DeviceInfo>, crate::Error> {
+    HidApiSource::Shared.with(driver::list_devices)
+}
+
+/// Blocks until a Speed Editor is plugged in, then connects to it.
+///
+/// If `timeout` is [`None`], this waits forever.
+/// Use [`SpeedEditorBuilder::wait_for_device`] to configure the [`SpeedEditor`],
+/// or to be able to cancel waiting from another thread.
+///
+/// # Errors
+///
+/// Returns [`Error::Timeout`] if no device was found within the timeout,
+/// or an error if the HID API could not be initialized.
+pub fn wait_for_device(timeout: Option<Duration>) -> Result<SpeedEditor, crate::Error> {
+    SpeedEditor::builder().wait_for_device(timeout, None)
+}
 
 /// The main interface to talk with the Speed Editor device.
 ///
@@ -92,12 +207,28 @@ pub use crate::error::Error;
 ///
 /// On creation, it will spawn a new thread handling all the event polling, so you do not need to think about that.
 ///
+/// A [`SpeedEditor`] is a cheap handle to the shared device state, so it can be cloned
+/// and passed to other threads. All clones control the same device.
+/// Dropping the last clone stops the polling thread and releases the device.
+///
+/// The callbacks and sinks are called one at a time, without the device state being locked,
+/// so they can use a clone of the [`SpeedEditor`], e.g. to set an LED in response to a button.
+/// Replacing a callback from another thread waits for a running callback to return.
+///
+/// Events can happen before a callback for them is registered, e.g. the first battery
+/// information usually arrives right after connecting. These are not lost: for the first
+/// 5 seconds after creating the [`SpeedEditor`], up to 64 events that no callback or
+/// [sink][SpeedEditor::attach_sink] receives are kept, and passed to the first callback or sink
+/// registered for them, in order. Events that are older are dropped, and so are
+/// [unknown reports][Event::UnknownReport]. To receive every event without relying on this,
+/// register the callbacks on the [`SpeedEditorBuilder`] instead.
+///
 /// # Example
 ///
-/// ```
+/// ```no_run
 /// use bmdse::{ButtonLed, SpeedEditor, WheelLed};
 ///
-/// let mut speed_editor = SpeedEditor::new()
+/// let speed_editor = SpeedEditor::new()
 ///     .unwrap()
 ///     .on_wheel_change(|velocity| {
 ///         eprintln!("wheel velocity: {velocity}");
@@ -114,10 +245,12 @@ pub use crate::error::Error;
 ///
 /// // Because the SpeedEditor spawns a new thread handling input,
 /// // we have to keep the main thread running.
-/// loop {}
+/// speed_editor.run().unwrap();
 /// ```
+#[derive(Clone)]
 pub struct SpeedEditor {
-    inner: Arc<Mutex<Inner>>,
+    inner: Arc<InnerLock>,
+    poller: Arc<PollerHandle>,
 }
 
 impl SpeedEditor {
@@ -125,48 +258,164 @@ impl SpeedEditor {
     ///
     /// # Errors
     ///
-    /// This function might error when getting the HID device
-    /// (cannot be found, HID API already initialized, etc.).
+    /// Returns [`Error::HidDeviceNotFound`] if no Speed Editor is connected,
+    /// or [`Error::CannotOpenHidDevice`] if it is connected but could not be opened
+    /// (e.g. because another application is using it).
+    /// This function might also error for other reasons, like the HID API failing to initialize.
     ///
     /// It will spawn a new thread, that handles all event polling.
     pub fn new() -> Result<Self, crate::Error> {
-        let inner = Arc::new(Mutex::new(Inner {
-            pressed_buttons: Vec::new(),
+        Self::builder().connect()
+    }
 
-            button_led: ButtonLed::default(),
-            wheel_led: WheelLed::default(),
+    /// Creates a new [`SpeedEditor`], trying to open the device up to `attempts` times.
+    ///
+    /// This helps right after the device was plugged in, when opening it might fail
+    /// until the operating system finished setting it up.
+    /// The `delay` between attempts doubles after every retry,
+    /// see [`SpeedEditorBuilder::open_retries`].
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of the last attempt, see [`SpeedEditor::new`].
+    pub fn new_with_retry(attempts: u32, delay: Duration) -> Result<Self, crate::Error> {
+        Self::builder().open_retries(attempts.saturating_sub(1), delay).connect()
+    }
 
-            on_wheel_change: None,
-            on_button_change: None,
-            on_battery_info: None,
-        }));
+    /// Creates a new [`SpeedEditor`] for the device with the given serial number.
+    ///
+    /// Use this to tell multiple connected Speed Editors apart.
+    /// See [`list_devices`] to find the serial numbers of the connected devices.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::HidDeviceNotFound`] if no device with the serial number is connected,
+    /// or [`Error::CannotOpenHidDevice`] if it is connected but could not be opened.
+    pub fn open_serial(serial_number: &str) -> Result<Self, crate::Error> {
+        Self::builder().serial_number(serial_number).connect()
+    }
 
-        let hid_device = driver::get_hid_device()?;
-        thread::Builder::new().name("bmd_speed_editor_poller".to_string()).spawn({
-            let inner = Arc::clone(&inner);
-            move || poller(hid_device, inner)
-        })?;
+    /// Creates a new [`SpeedEditor`] for the device at the given platform-specific path.
+    ///
+    /// Use this to tell multiple connected Speed Editors apart when their serial numbers
+    /// are not available. See [`list_devices`] to find the paths of the connected devices.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::HidDeviceNotFound`] if no device exists at the path (anymore),
+    /// [`Error::PermissionDenied`] if it exists but access to it was denied (on Linux),
+    /// or [`Error::CannotOpenHidDevice`] if it could not be opened for another reason.
+    pub fn open_path(path: &str) -> Result<Self, crate::Error> {
+        Self::builder().path(path).connect()
+    }
 
-        Ok(Self { inner })
+    /// Creates a new [`SpeedEditor`] for the first device with the given USB vendor and product ID.
+    ///
+    /// This is meant for unsupported hardware that speaks the Speed Editor protocol,
+    /// see [`SpeedEditorBuilder::device_ids`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::HidDeviceNotFound`] if no such device is connected,
+    /// or [`Error::CannotOpenHidDevice`] if it is connected but could not be opened.
+    pub fn open_with_ids(vendor_id: u16, product_id: u16) -> Result<Self, crate::Error> {
+        Self::builder().device_ids(vendor_id, product_id).connect()
+    }
+
+    /// Creates a [`SpeedEditor`] for a [`HidDevice`][hidapi::HidDevice] that was already opened,
+    /// see [`SpeedEditorBuilder::connect_hid_device`].
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use bmdse::{Model, SpeedEditor, VENDOR_ID};
+    ///
+    /// let api = bmdse::hidapi::HidApi::new().unwrap();
+    /// let device = api.open(VENDOR_ID, Model::SpeedEditor.product_id()).unwrap();
+    /// let speed_editor = SpeedEditor::from_hid_device(device).unwrap();
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::UnsupportedDevice`] if the device is not a Speed Editor,
+    /// or [`Error::AlreadyOpen`] if another [`SpeedEditor`] in this process uses it.
+    pub fn from_hid_device(device: hidapi::HidDevice) -> Result<Self, crate::Error> {
+        Self::builder().connect_hid_device(device)
+    }
+
+    /// Creates a [`SpeedEditorBuilder`] to configure a [`SpeedEditor`] before connecting to it.
+    pub fn builder() -> SpeedEditorBuilder {
+        SpeedEditorBuilder::default()
+    }
+
+    fn spawn(
+        mut inner: Inner,
+        thread_options: ThreadOptions,
+        poll_options: PollOptions,
+        hid_device: Option<Box<dyn HidBackend>>,
+    ) -> Result<Self, crate::Error> {
+        inner.event_buffer.start();
+        let inner = Arc::new(InnerLock::new(inner));
+        let poller =
+            PollerHandle::spawn(hid_device, Arc::clone(&inner), thread_options, poll_options)?;
+        Ok(Self { inner, poller: Arc::new(poller) })
+    }
+
+    /// Provide a callback that is called with everything that changed (buttons, the wheel,
+    /// LEDs, battery and connection), instead of handling each change in its own callback.
+    ///
+    /// The changes are combined per pass of the polling thread, which handles all reports
+    /// that arrived since the last pass, so it is called at most once per pass.
+    /// Wheel changes are summed, and the LEDs, battery and connection are only included
+    /// if they differ from the last call. The other callbacks are still called as well.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use bmdse::SpeedEditor;
+    ///
+    /// let speed_editor = SpeedEditor::new().unwrap().on_state_change(|diff| {
+    ///     for button in &diff.pressed {
+    ///         eprintln!("pressed {button:?}");
+    ///     }
+    ///     if diff.wheel_delta != 0 {
+    ///         eprintln!("wheel moved by {}", diff.wheel_delta);
+    ///     }
+    /// });
+    /// ```
+    pub fn on_state_change<F: Fn(&StateDiff) + Send + 'static>(self, f: F) -> Self {
+        self.set_on_state_change(f);
+        self
+    }
+
+    /// Provide a callback that is called with everything that changed,
+    /// see [`SpeedEditor::on_state_change`].
+    pub fn set_on_state_change<F: Fn(&StateDiff) + Send + 'static>(&self, f: F) {
+        let mut inner = self.inner.lock_unpoisoned();
+        inner.update_callbacks(move |callbacks| callbacks.on_state_change = Some(Box::new(f)));
+        inner.flush_buffered_events();
+        inner.flush_state_diff();
     }
 
     /// Provide a callback to handle a change of the jog wheel,
     /// with its parameter being the wheel's velocity.
-    pub fn on_wheel_change<F: Fn(i32) + Send + 'static>(mut self, f: F) -> Self {
+    pub fn on_wheel_change<F: Fn(i32) + Send + 'static>(self, f: F) -> Self {
         self.set_on_wheel_change(f);
         self
     }
 
     /// Provide a callback to handle a change of the jog wheel,
     /// with it's parameter being the wheel's velocity.
-    pub fn set_on_wheel_change<F: Fn(i32) + Send + 'static>(&mut self, f: F) {
-        self.inner.lock().unwrap().on_wheel_change = Some(Box::new(f));
+    pub fn set_on_wheel_change<F: Fn(i32) + Send + 'static>(&self, f: F) {
+        let mut inner = self.inner.lock_unpoisoned();
+        inner.update_callbacks(move |callbacks| callbacks.on_wheel_change = Some(Box::new(f)));
+        inner.flush_buffered_events();
     }
 
     /// Provide a callback to handle a press or release of a button,
     /// with its first parameter being the button,
     /// and its second parameter telling if it's pressed (`true`) or released (`false`).
-    pub fn on_button_change<F: Fn(Button, bool) + Send + 'static>(mut self, f: F) -> Self {
+    pub fn on_button_change<F: Fn(Button, bool) + Send + 'static>(self, f: F) -> Self {
         self.set_on_button_change(f);
         self
     }
@@ -174,14 +423,16 @@ impl SpeedEditor {
     /// Provide a callback to handle a press or release of a button,
     /// with its first parameter being the button,
     /// and its second parameter telling if it's pressed (`true`) or released (`false`).
-    pub fn set_on_button_change<F: Fn(Button, bool) + Send + 'static>(&mut self, f: F) {
-        self.inner.lock().unwrap().on_button_change = Some(Box::new(f));
+    pub fn set_on_button_change<F: Fn(Button, bool) + Send + 'static>(&self, f: F) {
+        let mut inner = self.inner.lock_unpoisoned();
+        inner.update_callbacks(move |callbacks| callbacks.on_button_change = Some(Box::new(f)));
+        inner.flush_buffered_events();
     }
 
     /// Provide a callback to handle battery info,
     /// with it's first parameter telling if it's charging, and the second parameter being
     /// the battery percentage (`0..=100`).
-    pub fn on_battery_info<F: Fn(bool, u8) + Send + 'static>(mut self, f: F) -> Self {
+    pub fn on_battery_info<F: Fn(bool, u8) + Send + 'static>(self, f: F) -> Self {
         self.set_on_battery_info(f);
         self
     }
@@ -189,125 +440,948 @@ impl SpeedEditor {
     /// Provide a callback to handle battery info,
     /// with it's first parameter telling if it's charging, and the second parameter being
     /// the battery percentage (`0..=100`).
-    pub fn set_on_battery_info<F: Fn(bool, u8) + Send + 'static>(&mut self, f: F) {
-        self.inner.lock().unwrap().on_battery_info = Some(Box::new(f));
+    pub fn set_on_battery_info<F: Fn(bool, u8) + Send + 'static>(&self, f: F) {
+        let mut inner = self.inner.lock_unpoisoned();
+        inner.update_callbacks(move |callbacks| callbacks.on_battery_info = Some(Box::new(f)));
+        inner.flush_buffered_events();
+    }
+
+    /// Provide a callback to inspect every report exactly as it was read from the device,
+    /// with its parameter being the raw bytes (including the report ID).
+    ///
+    /// This is called before the report is parsed, so it also receives reports that `bmdse`
+    /// does not understand. Useful for debugging and exploring the protocol.
+    pub fn on_raw_report<F: Fn(&[u8]) + Send + 'static>(self, f: F) -> Self {
+        self.set_on_raw_report(f);
+        self
+    }
+
+    /// Provide a callback to inspect every report exactly as it was read from the device,
+    /// with its parameter being the raw bytes (including the report ID).
+    ///
+    /// This is called before the report is parsed, so it also receives reports that `bmdse`
+    /// does not understand. Useful for debugging and exploring the protocol.
+    pub fn set_on_raw_report<F: Fn(&[u8]) + Send + 'static>(&self, f: F) {
+        self.inner
+            .lock_unpoisoned()
+            .update_callbacks(move |callbacks| callbacks.on_raw_report = Some(Box::new(f)));
+    }
+
+    /// Provide a callback to handle the device being connected,
+    /// with its parameter being information about the device.
+    ///
+    /// This is called once the device is authenticated, and again when it starts responding
+    /// after having been disconnected.
+    pub fn on_connect<F: Fn(DeviceInfo) + Send + 'static>(self, f: F) -> Self {
+        self.set_on_connect(f);
+        self
+    }
+
+    /// Provide a callback to handle the device being connected,
+    /// with its parameter being information about the device.
+    ///
+    /// This is called once the device is authenticated, and again when it starts responding
+    /// after having been disconnected.
+    pub fn set_on_connect<F: Fn(DeviceInfo) + Send + 'static>(&self, f: F) {
+        let mut inner = self.inner.lock_unpoisoned();
+        inner.update_callbacks(move |callbacks| callbacks.on_connect = Some(Box::new(f)));
+        inner.flush_buffered_events();
+    }
+
+    /// Provide a callback to handle the device starting or stopping charging,
+    /// with its parameter telling if it's charging.
+    ///
+    /// Unlike [`on_battery_info`][SpeedEditor::on_battery_info], this is only called when
+    /// the charging state changes, and once for the first battery information after connecting.
+    pub fn on_charging_change<F: Fn(bool) + Send + 'static>(self, f: F) -> Self {
+        self.set_on_charging_change(f);
+        self
+    }
+
+    /// Provide a callback to handle the device starting or stopping charging,
+    /// with its parameter telling if it's charging.
+    ///
+    /// Unlike [`on_battery_info`][SpeedEditor::on_battery_info], this is only called when
+    /// the charging state changes, and once for the first battery information after connecting.
+    pub fn set_on_charging_change<F: Fn(bool) + Send + 'static>(&self, f: F) {
+        let mut inner = self.inner.lock_unpoisoned();
+        inner.update_callbacks(move |callbacks| callbacks.on_charging_change = Some(Box::new(f)));
+        inner.flush_buffered_events();
+    }
+
+    /// Provide a callback to handle the device being disconnected.
+    ///
+    /// This is called when reading from the device fails repeatedly,
+    /// or when the polling thread stops. See [`SpeedEditor::is_connected`].
+    pub fn on_disconnect<F: Fn() + Send + 'static>(self, f: F) -> Self {
+        self.set_on_disconnect(f);
+        self
+    }
+
+    /// Provide a callback to handle the device being disconnected.
+    ///
+    /// This is called when reading from the device fails repeatedly,
+    /// or when the polling thread stops. See [`SpeedEditor::is_connected`].
+    pub fn set_on_disconnect<F: Fn() + Send + 'static>(&self, f: F) {
+        let mut inner = self.inner.lock_unpoisoned();
+        inner.update_callbacks(move |callbacks| callbacks.on_disconnect = Some(Box::new(f)));
+        inner.flush_buffered_events();
+    }
+
+    /// Provide a callback that is called once when nobody touched the device for `threshold`.
+    ///
+    /// Only button and wheel reports count as input, battery reports do not.
+    /// The time without input is measured from the last input, or from creating the
+    /// [`SpeedEditor`] if there was none yet, and is only checked while the device is connected.
+    /// After the next input, [`on_active`][SpeedEditor::on_active] is called,
+    /// and this is called again once the device is idle again.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use std::time::Duration;
+    ///
+    /// use bmdse::SpeedEditor;
+    ///
+    /// let speed_editor = SpeedEditor::new()
+    ///     .unwrap()
+    ///     .on_idle(Duration::from_secs(60), || eprintln!("dimming the overlay"))
+    ///     .on_active(|| eprintln!("lighting up the overlay"));
+    /// ```
+    pub fn on_idle<F: Fn() + Send + 'static>(self, threshold: Duration, f: F) -> Self {
+        self.set_on_idle(threshold, f);
+        self
+    }
+
+    /// Provide a callback that is called once when nobody touched the device for `threshold`,
+    /// see [`SpeedEditor::on_idle`].
+    pub fn set_on_idle<F: Fn() + Send + 'static>(&self, threshold: Duration, f: F) {
+        let mut inner = self.inner.lock_unpoisoned();
+        inner.update_callbacks(move |callbacks| callbacks.on_idle = Some(Box::new(f)));
+        inner.idle.set_threshold(Some(threshold));
+    }
+
+    /// Provide a callback that is called on the first input after the device became idle,
+    /// see [`SpeedEditor::on_idle`].
+    ///
+    /// Without an [`on_idle`][SpeedEditor::on_idle] threshold the device never becomes idle,
+    /// so this is never called.
+    pub fn on_active<F: Fn() + Send + 'static>(self, f: F) -> Self {
+        self.set_on_active(f);
+        self
+    }
+
+    /// Provide a callback that is called on the first input after the device became idle,
+    /// see [`SpeedEditor::on_idle`].
+    pub fn set_on_active<F: Fn() + Send + 'static>(&self, f: F) {
+        self.inner
+            .lock_unpoisoned()
+            .update_callbacks(move |callbacks| callbacks.on_active = Some(Box::new(f)));
+    }
+
+    /// Returns how long ago the last button or wheel report was received,
+    /// or how long ago the [`SpeedEditor`] was created if there was none yet.
+    ///
+    /// See [`SpeedEditor::on_idle`] to be notified instead.
+    pub fn idle_for(&self) -> Duration {
+        self.inner.lock_unpoisoned().idle.idle_for()
+    }
+
+    /// Provide a callback to handle errors that occur in the polling thread,
+    /// with its parameter being the error and whether it stopped the polling thread.
+    ///
+    /// Most errors are recovered from (e.g. a report that could not be parsed,
+    /// or a failed attempt to reconnect), and are only reported for diagnostics.
+    pub fn on_error<F: Fn(&PollerError) + Send + 'static>(self, f: F) -> Self {
+        self.set_on_error(f);
+        self
+    }
+
+    /// Provide a callback to handle errors that occur in the polling thread,
+    /// with its parameter being the error and whether it stopped the polling thread.
+    ///
+    /// Most errors are recovered from (e.g. a report that could not be parsed,
+    /// or a failed attempt to reconnect), and are only reported for diagnostics.
+    pub fn set_on_error<F: Fn(&PollerError) + Send + 'static>(&self, f: F) {
+        self.inner
+            .lock_unpoisoned()
+            .update_callbacks(move |callbacks| callbacks.on_error = Some(Box::new(f)));
+    }
+
+    /// Returns the last error that occurred in the polling thread, if any.
+    pub fn last_error(&self) -> Option<PollerError> {
+        self.inner.lock_unpoisoned().last_error.clone()
+    }
+
+    /// Attach an [`EventSink`] that will receive all [`Event`]s.
+    ///
+    /// Multiple sinks can be attached. They are called after the callbacks,
+    /// and are detached automatically when they report they are closed.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use std::sync::mpsc;
+    ///
+    /// use bmdse::SpeedEditor;
+    ///
+    /// let (tx, rx) = mpsc::channel();
+    ///
+    /// let speed_editor = SpeedEditor::new().unwrap();
+    /// speed_editor.attach_sink(Box::new(tx));
+    ///
+    /// for event in rx {
+    ///     eprintln!("{event:?}");
+    /// }
+    /// ```
+    pub fn attach_sink(&self, sink: Box<dyn EventSink>) {
+        let mut inner = self.inner.lock_unpoisoned();
+        inner.update_callbacks(move |callbacks| callbacks.sinks.push(sink));
+        inner.flush_buffered_events();
     }
 
     /// Returns `true` if the provided button is currently pressed.
     pub fn is_button_pressed(&self, button: Button) -> bool {
-        self.inner.lock().unwrap().pressed_buttons.contains(&button)
+        self.inner.lock_unpoisoned().pressed_buttons.contains(button)
     }
 
     /// Returns a all currently pressed buttons.
     pub fn pressed_buttons(&self) -> Vec<Button> {
-        self.inner.lock().unwrap().pressed_buttons.to_owned()
+        self.inner.lock_unpoisoned().pressed_buttons.iter().collect()
     }
 
     /// Set the current wheel LED state.
-    pub fn set_wheel_led(&mut self, led: WheelLed) {
-        self.inner.lock().unwrap().wheel_led = led;
+    pub fn set_wheel_led(&self, led: WheelLed) {
+        self.inner.lock_unpoisoned().set_wheel_led(led);
     }
 
     /// Get the current wheel LED state.
     pub fn wheel_led(&self) -> WheelLed {
-        self.inner.lock().unwrap().wheel_led
+        self.inner.lock_unpoisoned().wheel_led
     }
 
     /// Set the current button LED state.
-    pub fn set_button_led(&mut self, led: ButtonLed) {
-        self.inner.lock().unwrap().button_led = led;
+    pub fn set_button_led(&self, led: ButtonLed) {
+        self.inner.lock_unpoisoned().set_button_led(led);
     }
 
     /// Get the current button LED state.
     pub fn button_led(&self) -> ButtonLed {
-        self.inner.lock().unwrap().button_led
+        self.inner.lock_unpoisoned().button_led
     }
-}
 
-fn poller(mut hid_device: HidDevice, inner: Arc<Mutex<Inner>>) -> Result<(), crate::Error> {
-    const MAX_POLL_MS: i32 = 16;
+    /// Set how the wheel reports its movement. Defaults to [`WheelMode::Relative`].
+    ///
+    /// Only the relative mode calls [`on_wheel_change`][SpeedEditor::on_wheel_change].
+    /// In the absolute modes the position is only kept in [`DeviceState::wheel_position`].
+    /// Like the LEDs, the mode is written by the polling thread, and again after reconnecting.
+    pub fn set_wheel_mode(&self, mode: WheelMode) {
+        self.inner.lock_unpoisoned().wheel_mode = mode;
+    }
 
-    let mut auth_time = 600;
-    let auth_instant = Instant::now();
+    /// Get the current wheel mode.
+    pub fn wheel_mode(&self) -> WheelMode {
+        self.inner.lock_unpoisoned().wheel_mode
+    }
 
-    let mut last_button_led = None;
-    let mut last_wheel_led = None;
+    /// Returns `true` if the device is connected and responding.
+    ///
+    /// This becomes `false` when reading from the device fails repeatedly,
+    /// and `true` again once it has been reconnected (see [`ReconnectPolicy`]).
+    pub fn is_connected(&self) -> bool {
+        self.poller.shared.connected.load(Ordering::Acquire)
+    }
+
+    /// Set how the device should be reconnected after it stopped responding.
+    pub fn reconnect_policy(self, policy: ReconnectPolicy) -> Self {
+        self.set_reconnect_policy(policy);
+        self
+    }
 
-    driver::authenticate(&mut hid_device)?;
+    /// Set how the device should be reconnected after it stopped responding.
+    pub fn set_reconnect_policy(&self, policy: ReconnectPolicy) {
+        self.inner.lock_unpoisoned().reconnect_policy = policy;
+    }
 
-    loop {
-        if auth_instant.elapsed().as_secs() >= (auth_time - 5) as u64 {
-            auth_time = driver::authenticate(&mut hid_device)?;
+    /// Returns information about the device, like its serial number.
+    ///
+    /// This is read once when the device connects, so calling this does not talk to the device.
+    /// It returns [`None`] if no device has been connected yet. After a disconnect,
+    /// it keeps returning the information of the most recently connected device.
+    pub fn device_info(&self) -> Option<DeviceInfo> {
+        self.inner.lock_unpoisoned().device_info.clone()
+    }
+
+    /// Returns the firmware version of the device.
+    ///
+    /// Like [`SpeedEditor::device_info`], this returns [`None`] if no device has been connected yet.
+    pub fn firmware_version(&self) -> Option<FirmwareVersion> {
+        self.device_info().map(|device_info| device_info.firmware_version())
+    }
+
+    /// Returns the most recent battery information reported by the device.
+    ///
+    /// The device only reports its battery every now and then, so this returns [`None`]
+    /// until the first report after connecting. It is cleared when the device disconnects.
+    pub fn battery_info(&self) -> Option<BatteryInfo> {
+        self.inner.lock_unpoisoned().battery_info
+    }
+
+    /// Returns an estimate of how fast the battery discharges, and how long it will last.
+    ///
+    /// This is based on the battery levels of the last 30 minutes, so it returns [`None`] until
+    /// the device reported enough of them over at least 10 minutes. It starts over while charging,
+    /// and ignores levels that jump too far to be real.
+    /// Consider [polling the battery][SpeedEditor::set_battery_poll_interval] to get more levels.
+    pub fn battery_estimate(&self) -> Option<BatteryEstimate> {
+        self.inner.lock_unpoisoned().battery_estimator.estimate()
+    }
+
+    /// Asks the device for its battery state, instead of waiting for it to push an update.
+    ///
+    /// The answer is delivered like any other battery report, through
+    /// [`on_battery_info`][SpeedEditor::on_battery_info] and [`SpeedEditor::battery_info`].
+    /// This also happens automatically right after connecting.
+    /// The request is made by the polling thread, so if it fails
+    /// the error is reported through [`on_error`][SpeedEditor::on_error].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::HidDeviceNotFound`] if the device is not connected,
+    /// or [`Error::Unsupported`] on Windows, where the battery state can only be pushed by the device.
+    pub fn request_battery_update(&self) -> Result<(), crate::Error> {
+        if !driver::CAN_REQUEST_INPUT_REPORTS {
+            return Err(crate::Error::Unsupported {
+                message: "requesting a battery update is not supported on Windows",
+            });
+        }
+        if !self.is_connected() {
+            return Err(crate::Error::HidDeviceNotFound);
+        }
+        self.poller.shared.battery_requested.store(true, Ordering::Release);
+        Ok(())
+    }
+
+    /// Brings the device back into the state this crate expects, e.g. when the LEDs froze or
+    /// another application changed the wheel mode, without recreating the [`SpeedEditor`].
+    ///
+    /// This takes these steps in order, stopping at the first one that fails:
+    ///
+    /// 1. Release all pressed buttons, emitting a release for each,
+    ///    and start the [wheel position][DeviceState::wheel_position] over at 0.
+    /// 2. Authenticate the device again.
+    /// 3. Write the [wheel mode][SpeedEditor::set_wheel_mode] again.
+    /// 4. Write the button LED and the wheel LED again.
+    ///
+    /// In [observer mode][SpeedEditorBuilder::observer] the last two steps are skipped.
+    /// The steps are taken by the polling thread between two reads,
+    /// and this blocks until they are done.
+    /// While the device is [paused][SpeedEditor::pause], this blocks until it is resumed.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of the step that failed: [`Error::Authentication`] if authenticating
+    /// failed (which the polling thread keeps retrying according to the [`AuthRetryPolicy`]),
+    /// or an [`Error::Driver`] naming the report that could not be written
+    /// (which the polling thread writes again later for the LEDs).
+    /// Returns [`Error::HidDeviceNotFound`] if the device is not connected,
+    /// and an error if called from a callback.
+    pub fn reset_device(&self) -> Result<(), crate::Error> {
+        if self.poller.is_polling_thread() {
+            return Err(crate::Error::driver("cannot reset the device from one of its callbacks"));
         }
 
+        let (reply, result) = mpsc::channel();
         {
-            let inner_guard = inner.lock().unwrap();
-            if last_button_led.is_none_or(|last_led| last_led != inner_guard.button_led) {
-                driver::set_button_led(&mut hid_device, inner_guard.button_led)?;
-                last_button_led = Some(inner_guard.button_led);
-            }
-            if last_wheel_led.is_none_or(|last_led| last_led != inner_guard.wheel_led) {
-                driver::set_wheel_led(&mut hid_device, inner_guard.wheel_led)?;
-                last_wheel_led = Some(inner_guard.wheel_led);
+            // The connection is checked while holding the lock, so a disconnect either
+            // happens before this check or cancels the request.
+            let mut reset_requests = self.poller.shared.reset_requests.lock_unpoisoned();
+            if !self.is_connected() {
+                return Err(crate::Error::HidDeviceNotFound);
             }
+            reset_requests.push(reply);
         }
+        result.recv().unwrap_or(Err(crate::Error::HidDeviceNotFound))
+    }
 
-        let report = match driver::poll(&mut hid_device, MAX_POLL_MS) {
-            Ok(report) => report,
-            Err(_) => {
-                std::thread::yield_now();
-                continue;
-            }
-        };
+    /// Only handle the kinds of reports in `mask`, e.g. only [`ReportMask::WHEEL`] for an
+    /// application that only uses the wheel.
+    ///
+    /// Other reports are discarded by the polling thread right after reading them, before they
+    /// are parsed, counted or passed to [`on_raw_report`][SpeedEditor::on_raw_report].
+    /// The state kept for them is not updated while they are masked: masking the buttons
+    /// releases all pressed buttons, and [`SpeedEditor::battery_info`] keeps returning the last
+    /// battery information that was handled. Requested reports (e.g. with
+    /// [`SpeedEditor::request_battery_update`]) are discarded too.
+    ///
+    /// Defaults to [`ReportMask::ALL`].
+    pub fn set_report_mask(&self, mask: ReportMask) {
+        self.poller.shared.report_mask.store(mask.bits(), Ordering::Release);
+        if !mask.contains(ReportMask::BUTTONS) {
+            release_all_buttons(&self.inner);
+        }
+    }
 
-        match report {
-            Report::Wheel { mode, value } => {
-                if let WheelMode::Relative = mode {
-                    let inner_guard = inner.lock().unwrap();
+    /// Returns the kinds of reports that are handled, see [`SpeedEditor::set_report_mask`].
+    pub fn report_mask(&self) -> ReportMask {
+        ReportMask::from_bits(self.poller.shared.report_mask.load(Ordering::Acquire))
+    }
 
-                    if let Some(on_wheel_change) = &inner_guard.on_wheel_change {
-                        on_wheel_change(value);
-                    }
-                }
-            }
-            Report::Buttons(buttons) => {
-                let mut inner_guard = inner.lock().unwrap();
-
-                // Save previous pressed buttons for comparison
-                let prev_pressed = inner_guard.pressed_buttons.clone();
-                inner_guard.pressed_buttons = buttons.clone();
-
-                if let Some(on_button_change) = &inner_guard.on_button_change {
-                    // For all buttons that were previously pressed but are not in the new list, call with false
-                    for button in prev_pressed.iter() {
-                        if !buttons.contains(button) {
-                            on_button_change(*button, false);
-                        }
-                    }
-
-                    // For all buttons that are currently pressed, call with true
-                    for button in &buttons {
-                        on_button_change(*button, true);
-                    }
-                }
-            }
-            Report::Battery { charging, level } => {
-                let inner_guard = inner.lock().unwrap();
+    /// Request the battery state from the device every `interval`.
+    ///
+    /// See [`SpeedEditor::set_battery_poll_interval`].
+    pub fn battery_poll_interval(self, interval: Option<Duration>) -> Self {
+        self.set_battery_poll_interval(interval);
+        self
+    }
 
-                if let Some(on_battery_info) = &inner_guard.on_battery_info {
-                    on_battery_info(charging, level);
-                }
-            }
+    /// Request the battery state from the device every `interval`,
+    /// so it stays up to date even if the device does not push any updates.
+    ///
+    /// See [`SpeedEditor::request_battery_update`], this does nothing on platforms
+    /// where that is not supported. [`None`] disables polling, which is the default.
+    pub fn set_battery_poll_interval(&self, interval: Option<Duration>) {
+        self.inner.lock_unpoisoned().battery_poll_interval = interval;
+    }
+
+    /// Check whether buttons that are held for longer than `timeout` are still pressed.
+    ///
+    /// See [`SpeedEditor::set_stuck_button_timeout`].
+    pub fn stuck_button_timeout(self, timeout: Option<Duration>) -> Self {
+        self.set_stuck_button_timeout(timeout);
+        self
+    }
+
+    /// Check whether buttons that are held for longer than `timeout` are still pressed.
+    ///
+    /// Very rarely (e.g. with Bluetooth interference) the report releasing a button is missed,
+    /// and the button stays pressed until the next buttons report. With a timeout, the state of
+    /// all buttons is requested from the device again every `timeout` while a button is held,
+    /// which releases the buttons that are not pressed anymore.
+    ///
+    /// See [`SpeedEditor::request_battery_update`], this does nothing on platforms
+    /// where requesting reports is not supported. [`None`] disables the check, which is the default.
+    pub fn set_stuck_button_timeout(&self, timeout: Option<Duration>) {
+        self.inner.lock_unpoisoned().stuck_button_timeout = timeout;
+    }
+
+    /// Keep a wireless connection active by writing to the device every `interval`.
+    ///
+    /// See [`SpeedEditor::set_keepalive`].
+    pub fn keepalive(self, interval: Option<Duration>) -> Self {
+        self.set_keepalive(interval);
+        self
+    }
+
+    /// Keep a wireless connection active by writing to the device
+    /// when nothing was written to it for `interval`.
+    ///
+    /// Over Bluetooth, the device dozes off after idling for a few minutes,
+    /// which loses the first wheel turn after it. This writes the wheel LED state again,
+    /// which does not change anything visible. It is skipped when connected over USB,
+    /// and in [observer mode][SpeedEditorBuilder::observer].
+    ///
+    /// [`None`] disables the keep-alive, which is the default, as it costs battery.
+    pub fn set_keepalive(&self, interval: Option<Duration>) {
+        self.inner.lock_unpoisoned().keepalive = interval;
+    }
+
+    /// Returns the model of the device.
+    ///
+    /// Like [`SpeedEditor::device_info`], this returns [`None`] if no device has been connected yet.
+    pub fn model(&self) -> Option<Model> {
+        self.device_info().map(|device_info| device_info.model)
+    }
+
+    /// Returns how the device is connected to the host.
+    ///
+    /// Returns [`Transport::Unknown`] if no device has been connected yet.
+    pub fn transport(&self) -> Transport {
+        self.device_info().map_or(Transport::Unknown, |device_info| device_info.transport)
+    }
+
+    /// Returns a snapshot of how the polling thread is doing,
+    /// like the time since the last report and the number of reconnects.
+    ///
+    /// This only reads counters maintained by the polling thread, so it is cheap to call often.
+    pub fn health(&self) -> Health {
+        let shared = &self.poller.shared;
+        shared.health.snapshot(shared.connected.load(Ordering::Acquire))
+    }
+
+    /// Returns a snapshot of counters of what happened in the polling thread,
+    /// like the number of parsed reports and failed reads.
+    ///
+    /// Unlike [`SpeedEditor::health`], the counters are never reset,
+    /// which makes them suitable for exporting to a metrics system.
+    pub fn counters(&self) -> Counters {
+        self.poller.shared.counters.snapshot()
+    }
+
+    /// Returns a snapshot of metrics about the connection to the device,
+    /// like how long it has been connected and why it disconnected the last time.
+    ///
+    /// Unlike [`SpeedEditor::health`], these carry on across reconnects,
+    /// until they are reset with [`SpeedEditor::reset_session_metrics`].
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use bmdse::SpeedEditor;
+    ///
+    /// let speed_editor = SpeedEditor::new().unwrap();
+    /// let metrics = speed_editor.session_metrics();
+    /// eprintln!("{} reconnects, {:?} disconnected", metrics.reconnects, metrics.disconnected_time);
+    /// ```
+    pub fn session_metrics(&self) -> SessionMetrics {
+        let shared = &self.poller.shared;
+        let counters = shared.counters.snapshot();
+        shared.session_metrics.lock_unpoisoned().snapshot(&counters, Instant::now())
+    }
+
+    /// Starts the [`SessionMetrics`] over, as if the device first connected now.
+    pub fn reset_session_metrics(&self) {
+        let shared = &self.poller.shared;
+        let connected = shared.connected.load(Ordering::Acquire);
+        let counters = shared.counters.snapshot();
+        shared.session_metrics.lock_unpoisoned().reset(connected, counters, Instant::now());
+    }
+
+    /// Pauses reading from the device, without losing the callbacks or LED state.
+    ///
+    /// All buttons that are pressed will be released (and their callbacks called),
+    /// so nothing is left pressed while paused.
+    ///
+    /// If `release_device` is `true`, the device is closed so other applications can use it,
+    /// and it will be reopened and authenticated again when [resuming][SpeedEditor::resume].
+    pub fn pause(&self, release_device: bool) {
+        let shared = &self.poller.shared;
+        shared.release_while_paused.store(release_device, Ordering::Release);
+        shared.paused.store(true, Ordering::Release);
+    }
+
+    /// Resumes reading from the device after it has been [paused][SpeedEditor::pause],
+    /// and restores the LED state and the wheel mode.
+    ///
+    /// Both are restored whether the device was released or not, in case another application
+    /// changed them in the meantime.
+    pub fn resume(&self) {
+        self.poller.shared.paused.store(false, Ordering::Release);
+    }
+
+    /// Returns `true` if the device is [paused][SpeedEditor::pause].
+    pub fn is_paused(&self) -> bool {
+        self.poller.shared.paused.load(Ordering::Acquire)
+    }
+
+    /// Set how often the periodic re-authentication should be retried before giving up.
+    pub fn auth_retry_policy(self, policy: AuthRetryPolicy) -> Self {
+        self.set_auth_retry_policy(policy);
+        self
+    }
+
+    /// Set how often the periodic re-authentication should be retried before giving up.
+    pub fn set_auth_retry_policy(&self, policy: AuthRetryPolicy) {
+        self.inner.lock_unpoisoned().auth_retry_policy = policy;
+    }
+
+    /// Set what to do with battery reports with a level above 100%.
+    pub fn battery_level_policy(self, policy: BatteryLevelPolicy) -> Self {
+        self.set_battery_level_policy(policy);
+        self
+    }
+
+    /// Set what to do with battery reports with a level above 100%.
+    pub fn set_battery_level_policy(&self, policy: BatteryLevelPolicy) {
+        self.inner.lock_unpoisoned().battery_level_policy = policy;
+    }
+
+    /// Set whether the poller should be restarted when it stops because of an error or a panic.
+    pub fn restart_policy(self, policy: RestartPolicy) -> Self {
+        self.set_restart_policy(policy);
+        self
+    }
+
+    /// Set whether the poller should be restarted when it stops because of an error or a panic.
+    pub fn set_restart_policy(&self, policy: RestartPolicy) {
+        self.inner.lock_unpoisoned().restart_policy = policy;
+    }
+
+    /// Returns `true` if the polling thread has stopped for good,
+    /// because of a shutdown or because it gave up after an error.
+    ///
+    /// See [`SpeedEditor::last_error`] for the error it gave up on.
+    pub fn is_stopped(&self) -> bool {
+        self.poller.is_finished()
+    }
+
+    /// Set what should happen to the device when the polling thread stops.
+    pub fn shutdown_policy(self, policy: ShutdownPolicy) -> Self {
+        self.set_shutdown_policy(policy);
+        self
+    }
+
+    /// Set what should happen to the device when the polling thread stops.
+    pub fn set_shutdown_policy(&self, policy: ShutdownPolicy) {
+        self.inner.lock_unpoisoned().shutdown_policy = policy;
+    }
+
+    /// Set what should happen to the LEDs when nobody touched the device for a while,
+    /// e.g. to save the battery of a wireless device overnight.
+    ///
+    /// Only button and wheel reports count as input, like for [`SpeedEditor::on_idle`].
+    /// The first input after going into standby restores the LEDs as they were,
+    /// and so does setting an LED, which also starts the period without input over.
+    /// The getters keep returning the LEDs that will be restored.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use std::time::Duration;
+    ///
+    /// use bmdse::{SpeedEditor, StandbyPolicy};
+    ///
+    /// let speed_editor = SpeedEditor::new()
+    ///     .unwrap()
+    ///     .standby_policy(StandbyPolicy::Off { after: Duration::from_secs(15 * 60) });
+    /// ```
+    pub fn standby_policy(self, policy: StandbyPolicy) -> Self {
+        self.set_standby_policy(policy);
+        self
+    }
+
+    /// Set what should happen to the LEDs when nobody touched the device for a while,
+    /// see [`SpeedEditor::standby_policy`].
+    pub fn set_standby_policy(&self, policy: StandbyPolicy) {
+        self.inner.lock_unpoisoned().set_standby_policy(policy);
+    }
+
+    /// Returns `true` if the LEDs are in standby, see [`SpeedEditor::standby_policy`].
+    pub fn is_in_standby(&self) -> bool {
+        self.inner.lock_unpoisoned().standby.is_idle()
+    }
+
+    /// Stops the polling thread, applies the [`ShutdownPolicy`] and releases the device.
+    ///
+    /// This stops the device for all clones of this [`SpeedEditor`].
+    /// Dropping the last clone does the same, but ignores any errors.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error that made the polling thread stop, if it stopped because of one
+    /// (e.g. an authentication failure or a device I/O error).
+    pub fn shutdown(self) -> Result<(), crate::Error> {
+        self.poller.shutdown()
+    }
+
+    /// Blocks the calling thread until the polling thread stops,
+    /// without using any CPU while waiting.
+    ///
+    /// The polling thread stops when [`SpeedEditor::shutdown`] is called on another clone,
+    /// or when the device disconnects with [`ReconnectPolicy::Never`].
+    /// With a [`ReconnectPolicy::Backoff`], this only returns after a shutdown.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error that made the polling thread stop, if it stopped because of one.
+    /// If another clone already called [`SpeedEditor::shutdown`], that call receives the error
+    /// instead and this returns `Ok(())`.
+    /// Calling this from one of the callbacks returns an error instead of blocking forever.
+    pub fn run(self) -> Result<(), crate::Error> {
+        self.poller.join()
+    }
+}
+
+/// How to reconnect the device after it stopped responding (e.g. when it was unplugged).
+///
+/// After reconnecting, the device is authenticated again and the LED state and the wheel mode
+/// are restored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ReconnectPolicy {
+    /// Do not reconnect. The polling thread stops (unless a [`RestartPolicy`] restarts it) and
+    /// [`SpeedEditor::shutdown`] returns the error that caused the disconnect.
+    Never,
+    /// Try to reopen the device, doubling the delay between attempts until it reaches `max_delay`.
+    Backoff {
+        /// The delay before the first attempt.
+        initial_delay: Duration,
+        /// The maximum delay between attempts.
+        max_delay: Duration,
+    },
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        ReconnectPolicy::Backoff {
+            initial_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(5),
+        }
+    }
+}
+
+/// How to retry the periodic re-authentication of the device when it fails.
+///
+/// The device has to be authenticated again every few minutes. When this fails
+/// (e.g. because of a short Bluetooth hiccup), it is retried after `delay`,
+/// while reports are still being read in the meantime. When all attempts fail, the error is
+/// reported through [`SpeedEditor::on_error`], the device is considered disconnected
+/// and the [`ReconnectPolicy`] applies.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct AuthRetryPolicy {
+    /// The number of attempts before giving up.
+    pub max_attempts: u32,
+    /// The delay between attempts.
+    pub delay: Duration,
+}
+
+impl Default for AuthRetryPolicy {
+    fn default() -> Self {
+        Self { max_attempts: 3, delay: Duration::from_secs(1) }
+    }
+}
+
+/// The most recent battery information reported by the device.
+///
+/// See [`SpeedEditor::battery_info`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BatteryInfo {
+    /// Whether the battery is charging.
+    pub charging: bool,
+    /// The battery percentage (`0..=100`).
+    pub level: u8,
+    /// When the device reported this.
+    pub received_at: Instant,
+    /// `true` if this was [requested][SpeedEditor::request_battery_update] from the device,
+    /// `false` if the device pushed it on its own.
+    pub solicited: bool,
+}
+
+/// What to do with battery reports with a level above 100%.
+///
+/// Some devices report a level of 255 right after waking up from Bluetooth sleep.
+/// Valid levels (`0..=100`) are always passed on as they are.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum BatteryLevelPolicy {
+    /// Pass the report on with a level of 100%.
+    Clamp,
+    /// Ignore the report.
+    #[default]
+    Drop,
+    /// Ignore the report, and report an error through [`SpeedEditor::on_error`].
+    ReportError,
+}
+
+/// What to do when the poller stops because of an error (e.g. with [`ReconnectPolicy::Never`])
+/// or because of a panic (e.g. in one of the callbacks).
+///
+/// Every stop is reported through [`SpeedEditor::on_error`], and is only
+/// [fatal][PollerError::fatal] once the poller is not restarted anymore.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum RestartPolicy {
+    /// Do not restart. The polling thread stops and
+    /// [`SpeedEditor::shutdown`] returns the error that stopped it.
+    #[default]
+    Never,
+    /// Restart the poller after a delay, reopening the device if needed.
+    ///
+    /// After `max_restarts` restarts, the poller gives up and stops like with
+    /// [`RestartPolicy::Never`].
+    After {
+        /// The delay before restarting.
+        delay: Duration,
+        /// The maximum number of restarts.
+        max_restarts: u32,
+    },
+}
+
+/// What to do with the LEDs when nobody touched the device for a while.
+///
+/// See [`SpeedEditor::standby_policy`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum StandbyPolicy {
+    /// Leave the LEDs as they are.
+    #[default]
+    Never,
+    /// Turn off all LEDs.
+    Off {
+        /// How long without input until the LEDs are turned off.
+        after: Duration,
+    },
+    /// Switch to a minimal set of LEDs.
+    Leds {
+        /// How long without input until the LEDs are switched.
+        after: Duration,
+        /// The button LEDs in standby.
+        button_led: ButtonLed,
+        /// The wheel LEDs in standby.
+        wheel_led: WheelLed,
+    },
+}
+
+impl StandbyPolicy {
+    /// Returns how long without input until standby, or [`None`] if there is no standby.
+    fn after(&self) -> Option<Duration> {
+        match *self {
+            StandbyPolicy::Never => None,
+            StandbyPolicy::Off { after } | StandbyPolicy::Leds { after, .. } => Some(after),
         }
     }
 }
 
+/// What to do with the device when the polling thread stops.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum ShutdownPolicy {
+    /// Leave the LEDs as they are.
+    #[default]
+    KeepLeds,
+    /// Turn off all LEDs.
+    ClearLeds,
+}
+
+#[derive(Default)]
 struct Inner {
-    pressed_buttons: Vec<Button>,
+    pressed_buttons: ButtonSet,
+    /// The pressed keys that only the Editor Keyboard has.
+    pressed_editor_keyboard_buttons: Vec<EditorKeyboardButton>,
     button_led: ButtonLed,
     wheel_led: WheelLed,
+    wheel_mode: WheelMode,
+    /// The sum of the wheel velocities in relative mode, or the last position in the absolute
+    /// modes.
+    wheel_position: i64,
+
+    dispatch: Dispatch,
+    idle: IdleTracker,
+    standby_policy: StandbyPolicy,
+    /// Tracks the input for the [`StandbyPolicy`], which is in standby while idle.
+    standby: IdleTracker,
+
+    shutdown_policy: ShutdownPolicy,
+    reconnect_policy: ReconnectPolicy,
+    auth_retry_policy: AuthRetryPolicy,
+    auth_margin: Option<Duration>,
+    auth_timeout: Option<Duration>,
+    restart_policy: RestartPolicy,
+    battery_level_policy: BatteryLevelPolicy,
+    open_options: OpenOptions,
+    hid_api: HidApiSource,
+    /// Whether LED writes are disabled.
+    observer: bool,
+    /// Whether the response of the device to a random host challenge is verified.
+    verify_device: bool,
+    /// The authentication of a device that is handed over from another [`SpeedEditor`],
+    /// which is used instead of authenticating it again.
+    resumed_auth: Option<ResumedAuth>,
+
+    device_info: Option<DeviceInfo>,
+    battery_info: Option<BatteryInfo>,
+    battery_estimator: BatteryEstimator,
+    battery_poll_interval: Option<Duration>,
+    keepalive: Option<Duration>,
+    stuck_button_timeout: Option<Duration>,
+    /// Events that arrived before a callback or sink for them was registered.
+    event_buffer: EventBuffer,
+    last_error: Option<PollerError>,
+}
+
+impl Inner {
+    /// Queues the event for its callback and the attached sinks.
+    ///
+    /// Shortly after creating the [`SpeedEditor`], an event that nothing receives is buffered,
+    /// see [`Inner::flush_buffered_events`].
+    fn emit(&mut self, event: Event) {
+        if let Some(event) = self.dispatch.emit(event) {
+            self.event_buffer.push(event);
+        }
+    }
+
+    /// Passes what changed since the last call to the state change callback, if anything did.
+    ///
+    /// This is called after every pass of the poller, so all changes of a pass are combined.
+    fn flush_state_diff(&mut self) {
+        let (button_led, wheel_led) = (self.button_led, self.wheel_led);
+        self.dispatch.queue(
+            |callbacks| callbacks.on_state_change.is_some(),
+            || Call::StateChange { button_led, wheel_led },
+        );
+    }
+
+    /// Emits the buffered events that a newly registered callback or sink receives,
+    /// in the order they happened. The others stay buffered.
+    fn flush_buffered_events(&mut self) {
+        for event in self.event_buffer.take() {
+            // Events without a receiver are buffered again by `emit`.
+            self.emit(event);
+        }
+    }
+
+    fn set_button_led(&mut self, led: ButtonLed) {
+        self.button_led = led;
+        self.end_standby();
+    }
 
-    on_wheel_change: Option<Box<dyn Fn(i32) + Send>>,
-    on_button_change: Option<Box<dyn Fn(Button, bool) + Send>>,
-    on_battery_info: Option<Box<dyn Fn(bool, u8) + Send>>,
+    fn set_wheel_led(&mut self, led: WheelLed) {
+        self.wheel_led = led;
+        self.end_standby();
+    }
+
+    fn set_standby_policy(&mut self, policy: StandbyPolicy) {
+        self.standby_policy = policy;
+        self.standby.set_threshold(policy.after());
+    }
+
+    /// Leaves standby, if the LEDs are in standby, and starts the period without input over.
+    fn end_standby(&mut self) {
+        if self.standby.is_idle() {
+            self.standby.record_input(Instant::now());
+        }
+    }
+
+    /// Goes into standby if the device just became idle,
+    /// and returns when to check again if it is not idle yet.
+    fn check_standby(&mut self) -> Option<Instant> {
+        self.standby.check(Instant::now()).1
+    }
+
+    /// Returns the LEDs that should be on the device, which differ from the LEDs that were set
+    /// while in standby.
+    fn leds(&self) -> (ButtonLed, WheelLed) {
+        match self.standby_policy {
+            _ if !self.standby.is_idle() => (self.button_led, self.wheel_led),
+            StandbyPolicy::Never => (self.button_led, self.wheel_led),
+            StandbyPolicy::Off { .. } => (ButtonLed::Off, WheelLed::Off),
+            StandbyPolicy::Leds { button_led, wheel_led, .. } => (button_led, wheel_led),
+        }
+    }
+
+    /// Records a button or wheel report, calling the active callback if it ends an idle period.
+    fn record_input(&mut self) {
+        self.standby.record_input(Instant::now());
+        if self.idle.record_input(Instant::now()) {
+            self.dispatch.queue(|callbacks| callbacks.on_active.is_some(), || Call::Active);
+        }
+    }
+
+    /// Calls the idle callback if the device just became idle,
+    /// and returns when to check again if it is not idle yet.
+    fn check_idle(&mut self) -> Option<Instant> {
+        let (became_idle, next_check) = self.idle.check(Instant::now());
+        if became_idle {
+            self.dispatch.queue(|callbacks| callbacks.on_idle.is_some(), || Call::Idle);
+        }
+        next_check
+    }
+
+    /// Remembers the error as the last error and passes it to its callback.
+    fn report_error(&mut self, error: crate::Error, fatal: bool) {
+        if fatal {
+            diagnostics::fatal!("polling stopped: {error}");
+        } else {
+            diagnostics::recoverable!("{error}");
+        }
+        let error = PollerError { error, fatal };
+        self.dispatch
+            .queue(|callbacks| callbacks.on_error.is_some(), || Call::Error(error.clone()));
+        self.last_error = Some(error);
+    }
 }
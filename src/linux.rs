@@ -0,0 +1,147 @@
+//! Helpers to diagnose and fix access to the Speed Editor on Linux.
+//!
+//! Linux only lets root open HID devices, unless a udev rule gives access to them.
+//! Opening the device without one fails with
+//! [`Error::PermissionDenied`][crate::Error::PermissionDenied].
+//!
+//! # Example
+//!
+//! ```no_run
+//! use bmdse::linux::{self, PermissionStatus};
+//!
+//! match linux::check_permissions() {
+//!     PermissionStatus::Accessible { .. } => println!("all good"),
+//!     PermissionStatus::Denied { path } => {
+//!         eprintln!("cannot open {}, install this rule as root:", path.display());
+//!         eprintln!("{}", linux::udev_rule());
+//!     }
+//!     PermissionStatus::NotConnected => eprintln!("no Speed Editor is connected"),
+//! }
+//! ```
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use crate::{Model, VENDOR_ID};
+
+/// Where [`install_udev_rule`] writes the rule.
+pub const UDEV_RULE_PATH: &str = "/etc/udev/rules.d/50-bmdse.rules";
+
+/// Whether the current user can open the Speed Editor.
+///
+/// See [`check_permissions`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum PermissionStatus {
+    /// The hidraw node of the device can be opened.
+    Accessible {
+        /// The path of the hidraw node.
+        path: PathBuf,
+    },
+    /// The hidraw node of the device cannot be opened, which needs a udev rule.
+    Denied {
+        /// The path of the hidraw node.
+        path: PathBuf,
+    },
+    /// No Speed Editor is connected.
+    NotConnected,
+}
+
+/// Checks whether the current user can open the connected Speed Editors.
+///
+/// This looks for their hidraw nodes in sysfs, without going through hidapi.
+/// If multiple devices are connected, a device that cannot be opened is reported first.
+pub fn check_permissions() -> PermissionStatus {
+    let mut status = PermissionStatus::NotConnected;
+    for path in hidraw_nodes() {
+        // Opening a hidraw node has no side effects, so this is the most reliable check.
+        match fs::OpenOptions::new().read(true).write(true).open(&path) {
+            Ok(_) => {
+                if status == PermissionStatus::NotConnected {
+                    status = PermissionStatus::Accessible { path };
+                }
+            }
+            Err(error) if error.kind() == io::ErrorKind::PermissionDenied => {
+                return PermissionStatus::Denied { path };
+            }
+            // The device went away in the meantime.
+            Err(_) => {}
+        }
+    }
+    status
+}
+
+/// Returns the udev rules that give the logged in user access to the Speed Editor
+/// (VID 1EDB / PID DA0E) and the Editor Keyboard (PID DA0B), as they are written to
+/// [`UDEV_RULE_PATH`].
+pub fn udev_rule() -> &'static str {
+    concat!(
+        "# Blackmagic Design DaVinci Resolve Speed Editor\n",
+        "KERNEL==\"hidraw*\", ATTRS{idVendor}==\"1edb\", ATTRS{idProduct}==\"da0e\", ",
+        "MODE=\"0660\", TAG+=\"uaccess\"\n",
+        "# Blackmagic Design DaVinci Resolve Editor Keyboard\n",
+        "KERNEL==\"hidraw*\", ATTRS{idVendor}==\"1edb\", ATTRS{idProduct}==\"da0b\", ",
+        "MODE=\"0660\", TAG+=\"uaccess\"\n",
+    )
+}
+
+/// Writes the [`udev_rule`] to [`UDEV_RULE_PATH`], and makes udev apply it to
+/// connected devices.
+///
+/// This has to run as root (e.g. with `sudo`).
+///
+/// # Errors
+///
+/// Returns [`Error::Io`][crate::Error::Io] with [`io::ErrorKind::PermissionDenied`]
+/// if the process is not allowed to write the rule, and an error if reloading udev failed.
+pub fn install_udev_rule() -> Result<(), crate::Error> {
+    fs::write(UDEV_RULE_PATH, udev_rule()).map_err(|error| {
+        if error.kind() == io::ErrorKind::PermissionDenied {
+            io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("writing {UDEV_RULE_PATH} requires root privileges, e.g. run with sudo"),
+            )
+        } else {
+            error
+        }
+    })?;
+
+    udevadm(&["control", "--reload-rules"])?;
+    udevadm(&["trigger", "--subsystem-match=hidraw"])?;
+    Ok(())
+}
+
+fn udevadm(args: &[&str]) -> Result<(), crate::Error> {
+    let status = Command::new("udevadm").args(args).status()?;
+    if !status.success() {
+        let message = format!("`udevadm {}` failed: {status}", args.join(" "));
+        return Err(io::Error::other(message).into());
+    }
+    Ok(())
+}
+
+/// Returns the hidraw nodes of all connected Speed Editors.
+fn hidraw_nodes() -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir("/sys/class/hidraw") else { return Vec::new() };
+    entries
+        .filter_map(Result::ok)
+        .filter(|entry| is_speed_editor(&entry.path().join("device/uevent")))
+        .map(|entry| Path::new("/dev").join(entry.file_name()))
+        .collect()
+}
+
+/// Returns `true` if the uevent file belongs to a Speed Editor.
+fn is_speed_editor(uevent: &Path) -> bool {
+    let Ok(uevent) = fs::read_to_string(uevent) else { return false };
+    // `HID_ID=<bus>:<vendor>:<product>`, in hexadecimal.
+    uevent.lines().filter_map(|line| line.strip_prefix("HID_ID=")).any(|hid_id| {
+        let mut parts = hid_id.split(':').skip(1).map(|part| u32::from_str_radix(part, 16));
+        let (Some(Ok(vendor_id)), Some(Ok(product_id))) = (parts.next(), parts.next()) else {
+            return false;
+        };
+        vendor_id == VENDOR_ID as u32
+            && u16::try_from(product_id).ok().and_then(Model::from_product_id).is_some()
+    })
+}
@@ -0,0 +1,347 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use hidapi::HidApi;
+
+use crate::{
+    DeviceInfo, Event, EventSink, PollerError, ReconnectPolicy, SinkClosed, SpeedEditor,
+    driver::{self, HidApiSource},
+    sync::MutexExt,
+};
+
+/// Identifies a Speed Editor managed by a [`SpeedEditorManager`].
+///
+/// This is the serial number of the device, or its path if it does not report one.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct DeviceId(String);
+
+impl DeviceId {
+    /// Returns the identifier as a string.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<&DeviceInfo> for DeviceId {
+    fn from(info: &DeviceInfo) -> Self {
+        Self(info.serial_number.clone().unwrap_or_else(|| info.path.clone()))
+    }
+}
+
+impl fmt::Display for DeviceId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+type EventCallback = Box<dyn Fn(&DeviceId, Event) + Send + Sync>;
+type DeviceErrorCallback = Box<dyn Fn(&DeviceId, &PollerError) + Send + Sync>;
+
+/// The callbacks of the manager, shared with the polling threads of all devices.
+///
+/// They are kept apart from the devices, so the devices don't keep themselves alive.
+#[derive(Default)]
+struct Callbacks {
+    on_event: Option<EventCallback>,
+    on_error: Option<DeviceErrorCallback>,
+}
+
+/// Forwards the events of a single device to the manager, tagged with its [`DeviceId`].
+struct TaggedSink {
+    id: DeviceId,
+    callbacks: Arc<Callbacks>,
+}
+
+impl EventSink for TaggedSink {
+    fn send(&self, event: Event) -> Result<(), SinkClosed> {
+        if let Some(on_event) = &self.callbacks.on_event {
+            on_event(&self.id, event);
+        }
+        Ok(())
+    }
+}
+
+/// A builder to configure a [`SpeedEditorManager`] before it starts looking for devices.
+#[derive(Default)]
+pub struct SpeedEditorManagerBuilder {
+    serial_numbers: Option<Vec<String>>,
+    hid_api: HidApiSource,
+    scan_interval: Option<Duration>,
+    callbacks: Callbacks,
+}
+
+impl SpeedEditorManagerBuilder {
+    /// Provide a callback to handle the events of all devices,
+    /// with its first parameter identifying the device the event came from.
+    ///
+    /// A device appearing and disappearing is reported as [`Event::Connected`]
+    /// and [`Event::Disconnected`]. The callback is called from the polling threads of the
+    /// devices, possibly at the same time, which is why it has to be [`Sync`].
+    pub fn on_event<F: Fn(&DeviceId, Event) + Send + Sync + 'static>(mut self, f: F) -> Self {
+        self.callbacks.on_event = Some(Box::new(f));
+        self
+    }
+
+    /// Provide a callback to handle errors of all devices,
+    /// with its first parameter identifying the device the error came from.
+    ///
+    /// A fatal error only stops that device. If it is still plugged in,
+    /// the manager tries to open it again on the next scan.
+    pub fn on_error<F: Fn(&DeviceId, &PollerError) + Send + Sync + 'static>(
+        mut self,
+        f: F,
+    ) -> Self {
+        self.callbacks.on_error = Some(Box::new(f));
+        self
+    }
+
+    /// Only manage the Speed Editors with one of the given serial numbers.
+    ///
+    /// By default, all connected Speed Editors are managed.
+    pub fn serial_numbers<S: Into<String>>(
+        mut self,
+        serial_numbers: impl IntoIterator<Item = S>,
+    ) -> Self {
+        self.serial_numbers = Some(serial_numbers.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Use an [`HidApi`] owned by your application, instead of the one shared by this crate.
+    ///
+    /// See [`SpeedEditorBuilder::hid_api`][crate::SpeedEditorBuilder::hid_api].
+    pub fn hid_api(mut self, api: Arc<Mutex<HidApi>>) -> Self {
+        self.hid_api = HidApiSource::External(api);
+        self
+    }
+
+    /// Set how often to look for devices that were plugged in.
+    ///
+    /// Defaults to 500 milliseconds.
+    pub fn scan_interval(mut self, interval: Duration) -> Self {
+        self.scan_interval = Some(interval);
+        self
+    }
+
+    /// Creates the [`SpeedEditorManager`], which starts looking for devices in the background.
+    ///
+    /// # Errors
+    ///
+    /// This function only errors if the scanning thread could not be spawned.
+    pub fn build(self) -> Result<SpeedEditorManager, crate::Error> {
+        const DEFAULT_SCAN_INTERVAL: Duration = Duration::from_millis(500);
+
+        let shared = Arc::new(Shared {
+            shutdown: AtomicBool::new(false),
+            devices: Mutex::new(HashMap::new()),
+            callbacks: Arc::new(self.callbacks),
+            serial_numbers: self.serial_numbers,
+            hid_api: self.hid_api,
+            scan_interval: self.scan_interval.unwrap_or(DEFAULT_SCAN_INTERVAL),
+        });
+
+        let thread =
+            thread::Builder::new().name("bmd_speed_editor_manager".to_string()).spawn({
+                let shared = Arc::clone(&shared);
+                move || scan_until_shutdown(&shared)
+            })?;
+
+        Ok(SpeedEditorManager { shared, thread: Some(thread) })
+    }
+}
+
+/// State shared with the scanning thread.
+struct Shared {
+    shutdown: AtomicBool,
+    devices: Mutex<HashMap<DeviceId, SpeedEditor>>,
+    callbacks: Arc<Callbacks>,
+    serial_numbers: Option<Vec<String>>,
+    hid_api: HidApiSource,
+    scan_interval: Duration,
+}
+
+impl Shared {
+    fn is_managed(&self, info: &DeviceInfo) -> bool {
+        match &self.serial_numbers {
+            Some(serial_numbers) => {
+                info.serial_number.as_ref().is_some_and(|serial| serial_numbers.contains(serial))
+            }
+            None => true,
+        }
+    }
+}
+
+/// Manages multiple Speed Editors at once.
+///
+/// Every device gets its own [`SpeedEditor`] with its own polling thread,
+/// so one device failing does not affect the others.
+/// Devices that are plugged in later are picked up automatically.
+///
+/// # Example
+///
+/// ```no_run
+/// use bmdse::{ButtonLed, SpeedEditorManager};
+///
+/// let manager = SpeedEditorManager::builder()
+///     .on_event(|id, event| {
+///         eprintln!("{id}: {event:?}");
+///     })
+///     .build()
+///     .unwrap();
+///
+/// for id in manager.devices() {
+///     if let Some(speed_editor) = manager.device(&id) {
+///         speed_editor.set_button_led(ButtonLed::Cam1);
+///     }
+/// }
+/// ```
+pub struct SpeedEditorManager {
+    shared: Arc<Shared>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl SpeedEditorManager {
+    /// Creates a [`SpeedEditorManager`] that manages all connected Speed Editors.
+    ///
+    /// # Errors
+    ///
+    /// This function only errors if the scanning thread could not be spawned.
+    pub fn new() -> Result<Self, crate::Error> {
+        Self::builder().build()
+    }
+
+    /// Returns a [`SpeedEditorManagerBuilder`] to configure the manager.
+    pub fn builder() -> SpeedEditorManagerBuilder {
+        SpeedEditorManagerBuilder::default()
+    }
+
+    /// Returns the identifiers of all devices that are currently managed, sorted.
+    pub fn devices(&self) -> Vec<DeviceId> {
+        let mut ids: Vec<_> = self.shared.devices.lock_unpoisoned().keys().cloned().collect();
+        ids.sort();
+        ids
+    }
+
+    /// Returns a handle to the device with the given identifier,
+    /// which can be used to control its LEDs or to ask about its state.
+    ///
+    /// Returns [`None`] if no such device is currently managed.
+    pub fn device(&self, id: &DeviceId) -> Option<SpeedEditor> {
+        self.shared.devices.lock_unpoisoned().get(id).cloned()
+    }
+
+    /// Stops looking for devices, and shuts down all managed devices.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error returned by [`SpeedEditor::shutdown`].
+    pub fn shutdown(mut self) -> Result<(), crate::Error> {
+        self.stop_scanning();
+
+        let devices = std::mem::take(&mut *self.shared.devices.lock_unpoisoned());
+        let mut result = Ok(());
+        for speed_editor in devices.into_values() {
+            let device_result = speed_editor.shutdown();
+            if result.is_ok() {
+                result = device_result;
+            }
+        }
+        result
+    }
+
+    fn stop_scanning(&mut self) {
+        self.shared.shutdown.store(true, Ordering::Release);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for SpeedEditorManager {
+    fn drop(&mut self) {
+        self.stop_scanning();
+    }
+}
+
+/// Scans for devices every scan interval, until shutdown.
+fn scan_until_shutdown(shared: &Shared) {
+    const SLICE: Duration = Duration::from_millis(10);
+
+    while !shared.shutdown.load(Ordering::Acquire) {
+        scan(shared);
+
+        let start = Instant::now();
+        while start.elapsed() < shared.scan_interval && !shared.shutdown.load(Ordering::Acquire) {
+            thread::sleep(SLICE);
+        }
+    }
+}
+
+/// Forgets devices that stopped, and opens devices that are not managed yet.
+fn scan(shared: &Shared) {
+    // Devices that stopped are removed first, so they are opened again if they are still present.
+    // They are dropped after releasing the lock, as dropping waits for their polling thread.
+    let stopped: Vec<SpeedEditor> = {
+        let mut devices = shared.devices.lock_unpoisoned();
+        let ids: Vec<DeviceId> = devices
+            .iter()
+            .filter(|(_, speed_editor)| speed_editor.poller.is_finished())
+            .map(|(id, _)| id.clone())
+            .collect();
+        ids.iter().filter_map(|id| devices.remove(id)).collect()
+    };
+    drop(stopped);
+
+    // If listing fails, it is tried again on the next scan.
+    let Ok(infos) = shared.hid_api.with(driver::list_devices) else { return };
+
+    for info in infos.iter().filter(|info| shared.is_managed(info)) {
+        let id = DeviceId::from(info);
+        if shared.devices.lock_unpoisoned().contains_key(&id) {
+            continue;
+        }
+
+        match open(shared, &id, info) {
+            Ok(speed_editor) => {
+                shared.devices.lock_unpoisoned().insert(id, speed_editor);
+            }
+            Err(error) => {
+                if let Some(on_error) = &shared.callbacks.on_error {
+                    on_error(&id, &PollerError { error, fatal: true });
+                }
+            }
+        }
+    }
+}
+
+/// Creates the [`SpeedEditor`] for a single device, forwarding its events to the manager.
+fn open(shared: &Shared, id: &DeviceId, info: &DeviceInfo) -> Result<SpeedEditor, crate::Error> {
+    // The manager takes care of reopening the device when it comes back.
+    let mut builder = SpeedEditor::builder()
+        .path(info.path.clone())
+        .reconnect_policy(ReconnectPolicy::Never)
+        .sink(Box::new(TaggedSink { id: id.clone(), callbacks: Arc::clone(&shared.callbacks) }));
+
+    if let HidApiSource::External(api) = &shared.hid_api {
+        builder = builder.hid_api(Arc::clone(api));
+    }
+
+    if shared.callbacks.on_error.is_some() {
+        let id = id.clone();
+        let callbacks = Arc::clone(&shared.callbacks);
+        builder = builder.on_error(move |error| {
+            if let Some(on_error) = &callbacks.on_error {
+                on_error(&id, error);
+            }
+        });
+    }
+
+    builder.build()
+}
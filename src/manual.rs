@@ -0,0 +1,270 @@
+use std::{
+    sync::{atomic::Ordering, mpsc},
+    time::{Duration, Instant},
+};
+
+use crate::dispatch::InnerLock;
+use crate::poller::{PollOptions, Shared, release_all_buttons, set_disconnected};
+use crate::session::Session;
+use crate::sync::MutexExt;
+use crate::wait::OpenRetry;
+use crate::{
+    BatteryInfo, Button, ButtonLed, Counters, DeviceInfo, DisconnectReason, Event, Health,
+    HidBackend, Inner, SessionMetrics, WheelLed, WheelMode,
+};
+
+/// A Speed Editor that is polled by the calling thread, instead of a polling thread.
+///
+/// This is meant for applications that need to control which thread talks to the device
+/// and when, like a real-time audio or video engine. It does the same work as the polling
+/// thread of a [`SpeedEditor`][crate::SpeedEditor], split into two calls:
+///
+/// - [`SpeedEditorSync::poll`] reads all reports from the device, and returns their events.
+/// - [`SpeedEditorSync::maintain`] writes changed LEDs, authenticates the device again when
+///   that is due, and requests reports (e.g. [battery polling][crate::SpeedEditorBuilder]).
+///   It returns how long it can wait until it has to be called again.
+///
+/// The callbacks registered on the [`SpeedEditorBuilder`][crate::SpeedEditorBuilder] are
+/// called as well, on the calling thread. Options of the polling thread (like the
+/// [`ReconnectPolicy`][crate::ReconnectPolicy]) are ignored: once the device stopped
+/// responding, every call returns an error, and a new [`SpeedEditorSync`] has to be opened.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::time::Duration;
+///
+/// use bmdse::{ButtonLed, SpeedEditorSync};
+///
+/// let mut speed_editor = SpeedEditorSync::open().unwrap();
+/// speed_editor.set_button_led(ButtonLed::Cam1);
+///
+/// loop {
+///     let until_maintenance = speed_editor.maintain().unwrap();
+///     for event in speed_editor.poll(until_maintenance.min(Duration::from_millis(16))).unwrap() {
+///         eprintln!("{event:?}");
+///     }
+/// }
+/// ```
+pub struct SpeedEditorSync {
+    inner: InnerLock,
+    shared: Shared,
+    /// The connection with the device, until it stopped responding.
+    session: Option<Session>,
+    events: mpsc::Receiver<Event>,
+}
+
+impl SpeedEditorSync {
+    /// Opens the device and authenticates it.
+    ///
+    /// Use [`SpeedEditorBuilder::connect_sync`][crate::SpeedEditorBuilder::connect_sync]
+    /// to configure it first.
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as [`SpeedEditor::new`][crate::SpeedEditor::new],
+    /// and an error if authenticating the device failed.
+    pub fn open() -> Result<Self, crate::Error> {
+        crate::SpeedEditor::builder().connect_sync()
+    }
+
+    /// Authenticates a [`HidDevice`][hidapi::HidDevice] that was already opened.
+    ///
+    /// See [`SpeedEditorBuilder::connect_hid_device_sync`][crate::SpeedEditorBuilder::connect_hid_device_sync].
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as [`SpeedEditor::from_hid_device`][crate::SpeedEditor::from_hid_device],
+    /// and an error if authenticating the device failed.
+    pub fn from_hid_device(device: hidapi::HidDevice) -> Result<Self, crate::Error> {
+        crate::SpeedEditor::builder().connect_hid_device_sync(device)
+    }
+
+    pub(crate) fn start(
+        inner: Inner,
+        poll_options: PollOptions,
+        device: Box<dyn HidBackend>,
+    ) -> Result<Self, crate::Error> {
+        // Without retries, the device is only taken once.
+        let mut device = Some(device);
+        Self::start_with_retry(inner, poll_options, &OpenRetry::default(), || {
+            device.take().ok_or(crate::Error::driver("device was already taken"))
+        })
+    }
+
+    /// Opens and authenticates the device, retrying both together.
+    pub(crate) fn start_with_retry(
+        mut inner: Inner,
+        poll_options: PollOptions,
+        retry: &OpenRetry,
+        mut open: impl FnMut() -> Result<Box<dyn HidBackend>, crate::Error>,
+    ) -> Result<Self, crate::Error> {
+        poll_options.validate()?;
+
+        // Events are returned from `poll`, besides being passed to the callbacks.
+        let (sender, events) = mpsc::channel();
+        inner.dispatch.update(move |callbacks| callbacks.sinks.push(Box::new(sender)));
+
+        let inner = InnerLock::new(inner);
+        let shared = Shared::new(poll_options);
+        let session = retry.run(|| Session::start(open()?, &inner, &shared))?;
+        Ok(Self { inner, shared, session: Some(session), events })
+    }
+
+    /// Reads and handles all reports from the device, waiting up to `timeout` for the first,
+    /// and returns the events that happened since the last call.
+    ///
+    /// A `timeout` of zero does not wait at all. After a failed read this waits for up to
+    /// 250 milliseconds, so a failing device does not keep the calling thread busy.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the device stopped responding, after which it is disconnected.
+    /// The events up to then, including [`Event::Disconnected`], are returned by the next call,
+    /// after which [`Error::HidDeviceNotFound`][crate::Error::HidDeviceNotFound] is returned.
+    pub fn poll(&mut self, timeout: Duration) -> Result<Vec<Event>, crate::Error> {
+        let Some(session) = &mut self.session else {
+            let events = self.events.try_iter().collect::<Vec<_>>();
+            return if events.is_empty() {
+                Err(crate::Error::HidDeviceNotFound)
+            } else {
+                Ok(events)
+            };
+        };
+        let result = session.read(timeout, &self.inner, &self.shared);
+        if let Err(error) = result {
+            self.disconnect(&error);
+            return Err(error);
+        }
+        Ok(self.events.try_iter().collect())
+    }
+
+    /// Writes changed LEDs, authenticates the device again when that is due,
+    /// and requests reports from the device.
+    ///
+    /// Returns how long until this has to be called again at the latest. Calling it more
+    /// often is cheap, e.g. before every [`SpeedEditorSync::poll`].
+    /// Authenticating again takes a few round trips with the device, and only one is made
+    /// per call, so this returns [`Duration::ZERO`] until it is done.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the device stopped responding, after which it is disconnected.
+    pub fn maintain(&mut self) -> Result<Duration, crate::Error> {
+        let session = self.session.as_mut().ok_or(crate::Error::HidDeviceNotFound)?;
+        match session.maintain(&self.inner, &self.shared) {
+            Ok(next_maintenance) => Ok(next_maintenance.saturating_duration_since(Instant::now())),
+            Err(error) => {
+                self.disconnect(&error);
+                Err(error)
+            }
+        }
+    }
+
+    /// Brings the device back into the state this crate expects, like
+    /// [`SpeedEditor::reset_device`][crate::SpeedEditor::reset_device] does.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of the first step that failed,
+    /// or [`Error::HidDeviceNotFound`][crate::Error::HidDeviceNotFound] if the device is not connected.
+    pub fn reset_device(&mut self) -> Result<(), crate::Error> {
+        let session = self.session.as_mut().ok_or(crate::Error::HidDeviceNotFound)?;
+        session.reset(&self.inner, &self.shared)
+    }
+
+    fn disconnect(&mut self, error: &crate::Error) {
+        self.session = None;
+        set_disconnected(&self.inner, &self.shared, DisconnectReason::from(error));
+        release_all_buttons(&self.inner);
+    }
+
+    /// Returns `true` if the device is connected and responding.
+    pub fn is_connected(&self) -> bool {
+        self.shared.connected.load(Ordering::Acquire)
+    }
+
+    /// Returns information about the device, like its serial number.
+    pub fn device_info(&self) -> Option<DeviceInfo> {
+        self.session.as_ref().map(|session| session.device_info().clone())
+    }
+
+    /// Set the button LED state, which is written by the next [`SpeedEditorSync::maintain`].
+    pub fn set_button_led(&self, led: ButtonLed) {
+        self.inner.lock_unpoisoned().set_button_led(led);
+    }
+
+    /// Get the current button LED state.
+    pub fn button_led(&self) -> ButtonLed {
+        self.inner.lock_unpoisoned().button_led
+    }
+
+    /// Set the wheel LED state, which is written by the next [`SpeedEditorSync::maintain`].
+    pub fn set_wheel_led(&self, led: WheelLed) {
+        self.inner.lock_unpoisoned().set_wheel_led(led);
+    }
+
+    /// Get the current wheel LED state.
+    pub fn wheel_led(&self) -> WheelLed {
+        self.inner.lock_unpoisoned().wheel_led
+    }
+
+    /// Set the wheel mode, which is written by the next [`SpeedEditorSync::maintain`].
+    ///
+    /// See [`SpeedEditor::set_wheel_mode`][crate::SpeedEditor::set_wheel_mode].
+    pub fn set_wheel_mode(&self, mode: WheelMode) {
+        self.inner.lock_unpoisoned().wheel_mode = mode;
+    }
+
+    /// Get the current wheel mode.
+    pub fn wheel_mode(&self) -> WheelMode {
+        self.inner.lock_unpoisoned().wheel_mode
+    }
+
+    /// Returns `true` if the provided button is currently pressed.
+    pub fn is_button_pressed(&self, button: Button) -> bool {
+        self.inner.lock_unpoisoned().pressed_buttons.contains(button)
+    }
+
+    /// Returns all currently pressed buttons.
+    pub fn pressed_buttons(&self) -> Vec<Button> {
+        self.inner.lock_unpoisoned().pressed_buttons.iter().collect()
+    }
+
+    /// Returns the most recent battery information reported by the device.
+    pub fn battery_info(&self) -> Option<BatteryInfo> {
+        self.inner.lock_unpoisoned().battery_info
+    }
+
+    /// Returns how healthy the connection with the device is.
+    pub fn health(&self) -> Health {
+        self.shared.health.snapshot(self.shared.connected.load(Ordering::Acquire))
+    }
+
+    /// Returns the number of reports and errors, e.g. for diagnostics.
+    pub fn counters(&self) -> Counters {
+        self.shared.counters.snapshot()
+    }
+
+    /// Returns metrics about the connection to the device, see [`SpeedEditor::session_metrics`][crate::SpeedEditor::session_metrics].
+    pub fn session_metrics(&self) -> SessionMetrics {
+        let counters = self.shared.counters.snapshot();
+        self.shared.session_metrics.lock_unpoisoned().snapshot(&counters, Instant::now())
+    }
+
+    /// Starts the [`SessionMetrics`] over, as if the device first connected now.
+    pub fn reset_session_metrics(&self) {
+        let connected = self.shared.connected.load(Ordering::Acquire);
+        let counters = self.shared.counters.snapshot();
+        self.shared.session_metrics.lock_unpoisoned().reset(connected, counters, Instant::now());
+    }
+}
+
+impl Drop for SpeedEditorSync {
+    fn drop(&mut self) {
+        if let Some(session) = self.session.take() {
+            // There is nobody left to report the error to.
+            let _ = session.finish(&self.inner, &self.shared);
+        }
+    }
+}
@@ -0,0 +1,636 @@
+//! Turning the buttons and the jog wheel into named actions, described by a TOML document.
+//!
+//! Instead of matching on buttons in the application, a [`Mapping`] binds them to action
+//! names, and the [`MappingEngine`] reports the [`Action`]s that happen. The mapping can
+//! then be changed without changing the application, e.g. with `bmdse map <file>`.
+//!
+//! # Format
+//!
+//! ```toml
+//! # The version of the format, which is 1.
+//! version = 1
+//! # How long a button is held before it is a long press, in milliseconds. Defaults to 500.
+//! long_press = 500
+//!
+//! # A button binds an action to being pressed, released and/or held long.
+//! # With a `long_press`, `press` only happens when the button is released before that.
+//! [buttons.Cut]
+//! press = "cut"
+//! long_press = "ripple_cut"
+//!
+//! [buttons.StopPlay]
+//! press = "play"
+//! release = "stop"
+//!
+//! # The wheel binds an action to its changes, multiplied by `scale` (defaults to 1).
+//! [wheel]
+//! action = "scrub"
+//! scale = 0.5
+//!
+//! # The LEDs that are lit while no layer is active. Without this, the LEDs are left alone.
+//! [leds]
+//! button = "Cut"
+//! wheel = "Jog"
+//!
+//! # A layer is activated by pressing its button, and deactivated by pressing it again.
+//! # Its bindings replace those outside of it, and its LEDs are lit while it is active.
+//! [layers.source]
+//! activate = "Source"
+//! leds = { wheel = "Shuttle" }
+//! wheel = { action = "shuttle_source" }
+//! buttons.In = { press = "mark_source_in" }
+//! ```
+//!
+//! The names of the buttons and LEDs are those of [`Button`], [`ButtonLed`] and [`WheelLed`].
+//!
+//! # Example
+//!
+//! ```no_run
+//! use bmdse::SpeedEditor;
+//! use bmdse::mapping::Mapping;
+//!
+//! let speed_editor = SpeedEditor::new().unwrap();
+//! let mapping = Mapping::load("mapping.toml").unwrap();
+//! let _engine = mapping
+//!     .start(&speed_editor, |action| eprintln!("{} ({:?})", action.name, action.trigger))
+//!     .unwrap();
+//!
+//! std::thread::park();
+//! ```
+
+use std::{
+    collections::{BTreeMap, VecDeque},
+    error, fmt, fs, io,
+    path::Path,
+    sync::{Arc, Condvar, Mutex, Weak, mpsc},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, de::IntoDeserializer};
+
+use crate::{
+    Button, ButtonLed, Event, EventSink, SinkClosed, SpeedEditor, WheelLed, sync::MutexExt,
+};
+
+/// The version of the format that is supported.
+const VERSION: u32 = 1;
+const DEFAULT_LONG_PRESS: Duration = Duration::from_millis(500);
+
+/// An error in a [`Mapping`] document.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum MappingError {
+    /// The document could not be read.
+    Io(io::Error),
+    /// The document is not valid TOML, or does not match the format.
+    /// The message points at the line and key that is wrong.
+    Parse(toml::de::Error),
+    /// The document has no `version`.
+    MissingVersion,
+    /// The document is written for another version of the format.
+    UnsupportedVersion {
+        /// The version of the document.
+        found: u32,
+    },
+    /// A value in the document is not valid.
+    Invalid {
+        /// The dotted path of the key, like `layers.source.activate`.
+        key: String,
+        /// What is wrong with it.
+        message: String,
+    },
+}
+
+impl fmt::Display for MappingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MappingError::Io(error) => write!(f, "cannot read the mapping: {error}"),
+            MappingError::Parse(error) => write!(f, "{error}"),
+            MappingError::MissingVersion => {
+                write!(f, "the mapping has no `version`, add `version = {VERSION}`")
+            }
+            MappingError::UnsupportedVersion { found } => {
+                write!(f, "mapping version {found} is not supported, only version {VERSION} is")
+            }
+            MappingError::Invalid { key, message } => write!(f, "invalid `{key}`: {message}"),
+        }
+    }
+}
+
+impl error::Error for MappingError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            MappingError::Io(error) => Some(error),
+            MappingError::Parse(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct VersionDocument {
+    version: Option<u32>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Document {
+    #[allow(dead_code)]
+    version: u32,
+    long_press: Option<u64>,
+    #[serde(default)]
+    buttons: BTreeMap<String, ButtonDocument>,
+    wheel: Option<WheelDocument>,
+    leds: Option<LedsDocument>,
+    #[serde(default)]
+    layers: BTreeMap<String, LayerDocument>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LayerDocument {
+    activate: Button,
+    #[serde(default)]
+    buttons: BTreeMap<String, ButtonDocument>,
+    wheel: Option<WheelDocument>,
+    leds: Option<LedsDocument>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ButtonDocument {
+    press: Option<String>,
+    release: Option<String>,
+    long_press: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct WheelDocument {
+    action: String,
+    scale: Option<f64>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LedsDocument {
+    button: Option<ButtonLed>,
+    wheel: Option<WheelLed>,
+}
+
+#[derive(Debug, Clone)]
+struct WheelBinding {
+    action: String,
+    scale: f64,
+}
+
+#[derive(Debug, Clone)]
+struct Bindings {
+    buttons: Vec<(Button, ButtonDocument)>,
+    wheel: Option<WheelBinding>,
+    leds: Option<(ButtonLed, WheelLed)>,
+}
+
+impl Bindings {
+    fn new(
+        prefix: &str,
+        buttons: BTreeMap<String, ButtonDocument>,
+        wheel: Option<WheelDocument>,
+        leds: Option<LedsDocument>,
+    ) -> Result<Self, MappingError> {
+        let buttons = buttons
+            .into_iter()
+            .map(|(name, binding)| {
+                let button = Button::deserialize(name.as_str().into_deserializer()).map_err(
+                    |error: serde::de::value::Error| MappingError::Invalid {
+                        key: format!("{prefix}buttons.{name}"),
+                        message: error.to_string(),
+                    },
+                )?;
+                Ok((button, binding))
+            })
+            .collect::<Result<_, MappingError>>()?;
+
+        let wheel = match wheel {
+            Some(WheelDocument { scale: Some(scale), .. }) if !scale.is_finite() => {
+                return Err(MappingError::Invalid {
+                    key: format!("{prefix}wheel.scale"),
+                    message: "the scale must be a finite number".to_string(),
+                });
+            }
+            Some(wheel) => {
+                Some(WheelBinding { action: wheel.action, scale: wheel.scale.unwrap_or(1.0) })
+            }
+            None => None,
+        };
+
+        let leds = leds.map(|leds| {
+            (leds.button.unwrap_or(ButtonLed::Off), leds.wheel.unwrap_or(WheelLed::Off))
+        });
+        Ok(Self { buttons, wheel, leds })
+    }
+
+    fn button(&self, button: Button) -> Option<&ButtonDocument> {
+        self.buttons.iter().find(|(bound, _)| *bound == button).map(|(_, binding)| binding)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Layer {
+    name: String,
+    activate: Button,
+    bindings: Bindings,
+}
+
+/// A description of which [`Action`]s the buttons and the jog wheel trigger,
+/// loaded from a TOML document.
+///
+/// See the [module documentation][self] for the format.
+///
+/// # Example
+///
+/// ```
+/// use bmdse::mapping::Mapping;
+///
+/// let mapping = Mapping::from_toml(
+///     r#"
+///     version = 1
+///     [buttons.Cut]
+///     press = "cut"
+///     "#,
+/// );
+/// assert!(mapping.is_ok());
+///
+/// let error = Mapping::from_toml("version = 1\n[buttons.Cat]\npress = \"cut\"").unwrap_err();
+/// assert!(error.to_string().starts_with("invalid `buttons.Cat`"));
+/// ```
+#[derive(Debug, Clone)]
+pub struct Mapping {
+    long_press: Duration,
+    base: Bindings,
+    layers: Vec<Layer>,
+}
+
+impl Mapping {
+    /// Parses a [`Mapping`] from a TOML document.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the document is not valid, pointing at what is wrong.
+    pub fn from_toml(source: &str) -> Result<Self, MappingError> {
+        // The version is checked first, so a document for another version
+        // is not reported as a bunch of unknown keys.
+        let version: VersionDocument = toml::from_str(source).map_err(MappingError::Parse)?;
+        match version.version {
+            Some(VERSION) => {}
+            Some(found) => return Err(MappingError::UnsupportedVersion { found }),
+            None => return Err(MappingError::MissingVersion),
+        }
+
+        let document: Document = toml::from_str(source).map_err(MappingError::Parse)?;
+        let base = Bindings::new("", document.buttons, document.wheel, document.leds)?;
+        let mut layers: Vec<Layer> = Vec::new();
+        for (name, layer) in document.layers {
+            let prefix = format!("layers.{name}.");
+            if let Some(other) = layers.iter().find(|other| other.activate == layer.activate) {
+                return Err(MappingError::Invalid {
+                    key: format!("{prefix}activate"),
+                    message: format!(
+                        "{:?} already activates layer `{}`",
+                        layer.activate, other.name
+                    ),
+                });
+            }
+            let bindings = Bindings::new(&prefix, layer.buttons, layer.wheel, layer.leds)?;
+            layers.push(Layer { name, activate: layer.activate, bindings });
+        }
+
+        let long_press = document.long_press.map_or(DEFAULT_LONG_PRESS, Duration::from_millis);
+        Ok(Self { long_press, base, layers })
+    }
+
+    /// Reads and parses a [`Mapping`] from a TOML file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file could not be read,
+    /// or if the document is not valid, pointing at what is wrong.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, MappingError> {
+        Self::from_toml(&fs::read_to_string(path).map_err(MappingError::Io)?)
+    }
+
+    /// Starts reporting the [`Action`]s of the [`SpeedEditor`] to `f`,
+    /// until the returned [`MappingEngine`] is dropped.
+    ///
+    /// The actions are handled by a separate thread, so `f` may use the [`SpeedEditor`].
+    /// That thread also sets the LEDs of the active layer, and keeps the device open
+    /// as long as the engine exists.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the thread could not be spawned.
+    pub fn start<F: Fn(&Action) + Send + 'static>(
+        self,
+        speed_editor: &SpeedEditor,
+        f: F,
+    ) -> Result<MappingEngine, crate::Error> {
+        MappingEngine::start(self, speed_editor.clone(), Box::new(f))
+    }
+
+    /// Starts sending the [`Action`]s of the [`SpeedEditor`] to the returned channel,
+    /// until the returned [`MappingEngine`] is dropped.
+    ///
+    /// See [`Mapping::start`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the thread could not be spawned.
+    pub fn start_channel(
+        self,
+        speed_editor: &SpeedEditor,
+    ) -> Result<(MappingEngine, mpsc::Receiver<Action>), crate::Error> {
+        let (sender, receiver) = mpsc::channel();
+        let engine = self.start(speed_editor, move |action| {
+            let _ = sender.send(action.clone());
+        })?;
+        Ok((engine, receiver))
+    }
+}
+
+/// What made an [`Action`] happen.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Trigger {
+    /// The button was pressed.
+    Press(Button),
+    /// The button was released.
+    Release(Button),
+    /// The button was held for the long press time.
+    LongPress(Button),
+    /// The jog wheel changed, with the change multiplied by the scale.
+    Wheel(f64),
+}
+
+/// A named action that happened, see [`Mapping`].
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct Action {
+    /// The name of the action, as written in the mapping.
+    pub name: String,
+    /// What made it happen.
+    pub trigger: Trigger,
+    /// The layer the binding is in, or [`None`] if it is not in a layer.
+    pub layer: Option<String>,
+}
+
+/// A button that is held, with the binding it was pressed with.
+struct Held {
+    button: Button,
+    binding: ButtonDocument,
+    layer: Option<String>,
+    since: Instant,
+    long_pressed: bool,
+}
+
+/// Turns events into actions.
+struct EngineState {
+    mapping: Mapping,
+    layer: Option<usize>,
+    held: Vec<Held>,
+}
+
+impl EngineState {
+    fn layer_name(&self) -> Option<String> {
+        self.layer.map(|index| self.mapping.layers[index].name.clone())
+    }
+
+    /// The LEDs that should be lit, if the mapping sets them.
+    fn leds(&self) -> Option<(ButtonLed, WheelLed)> {
+        self.layer
+            .and_then(|index| self.mapping.layers[index].bindings.leds)
+            .or(self.mapping.base.leds)
+    }
+
+    /// Handles the event, and returns `true` if the active layer changed.
+    fn handle(&mut self, event: &Event, now: Instant, actions: &mut Vec<Action>) -> bool {
+        let mut action = |name: &Option<String>, trigger, layer: &Option<String>| {
+            if let Some(name) = name {
+                actions.push(Action { name: name.clone(), trigger, layer: layer.clone() });
+            }
+        };
+
+        match *event {
+            Event::ButtonChange { button, pressed: true } => {
+                if let Some(index) =
+                    self.mapping.layers.iter().position(|layer| layer.activate == button)
+                {
+                    self.layer = if self.layer == Some(index) { None } else { Some(index) };
+                    return true;
+                }
+
+                let layer_binding =
+                    self.layer.and_then(|index| self.mapping.layers[index].bindings.button(button));
+                let (binding, layer) = match layer_binding {
+                    Some(binding) => (binding.clone(), self.layer_name()),
+                    None => match self.mapping.base.button(button) {
+                        Some(binding) => (binding.clone(), None),
+                        None => return false,
+                    },
+                };
+                if binding.long_press.is_none() {
+                    action(&binding.press, Trigger::Press(button), &layer);
+                }
+                self.held.push(Held { button, binding, layer, since: now, long_pressed: false });
+            }
+            Event::ButtonChange { button, pressed: false } => {
+                let Some(index) = self.held.iter().position(|held| held.button == button) else {
+                    return false;
+                };
+                let held = self.held.remove(index);
+                if held.binding.long_press.is_some() && !held.long_pressed {
+                    action(&held.binding.press, Trigger::Press(button), &held.layer);
+                }
+                action(&held.binding.release, Trigger::Release(button), &held.layer);
+            }
+            Event::WheelChange { velocity } => {
+                let layer_binding =
+                    self.layer.and_then(|index| self.mapping.layers[index].bindings.wheel.as_ref());
+                let (binding, layer) = match layer_binding {
+                    Some(binding) => (binding, self.layer_name()),
+                    None => match &self.mapping.base.wheel {
+                        Some(binding) => (binding, None),
+                        None => return false,
+                    },
+                };
+                let value = velocity as f64 * binding.scale;
+                action(&Some(binding.action.clone()), Trigger::Wheel(value), &layer);
+            }
+            Event::Disconnected => {
+                // The buttons are released with the device, so nothing stays held.
+                for held in self.held.drain(..) {
+                    action(&held.binding.release, Trigger::Release(held.button), &held.layer);
+                }
+            }
+            _ => {}
+        }
+        false
+    }
+
+    /// Triggers the long presses of the buttons that are held long enough.
+    fn expire(&mut self, now: Instant, actions: &mut Vec<Action>) {
+        for held in &mut self.held {
+            let Some(name) = &held.binding.long_press else { continue };
+            if !held.long_pressed && now >= held.since + self.mapping.long_press {
+                held.long_pressed = true;
+                actions.push(Action {
+                    name: name.clone(),
+                    trigger: Trigger::LongPress(held.button),
+                    layer: held.layer.clone(),
+                });
+            }
+        }
+    }
+
+    /// When the next long press happens, if a button with a long press is held.
+    fn next_deadline(&self) -> Option<Instant> {
+        self.held
+            .iter()
+            .filter(|held| held.binding.long_press.is_some() && !held.long_pressed)
+            .map(|held| held.since + self.mapping.long_press)
+            .min()
+    }
+}
+
+#[derive(Default)]
+struct Pending {
+    events: VecDeque<Event>,
+    stop: bool,
+}
+
+struct EngineShared {
+    pending: Mutex<Pending>,
+    changed: Condvar,
+    layer: Mutex<Option<String>>,
+}
+
+/// Reports the [`Action`]s of a [`Mapping`], see [`Mapping::start`].
+///
+/// Dropping it stops reporting actions.
+pub struct MappingEngine {
+    shared: Arc<EngineShared>,
+    handler: Option<JoinHandle<()>>,
+}
+
+impl MappingEngine {
+    fn start(
+        mapping: Mapping,
+        speed_editor: SpeedEditor,
+        f: Box<dyn Fn(&Action) + Send>,
+    ) -> Result<Self, crate::Error> {
+        let shared = Arc::new(EngineShared {
+            pending: Mutex::default(),
+            changed: Condvar::new(),
+            layer: Mutex::new(None),
+        });
+        let mut state = EngineState { mapping, layer: None, held: Vec::new() };
+        if let Some((button_led, wheel_led)) = state.leds() {
+            speed_editor.set_button_led(button_led);
+            speed_editor.set_wheel_led(wheel_led);
+        }
+
+        let handler_shared = Arc::clone(&shared);
+        let handler_speed_editor = speed_editor.clone();
+        let handler = thread::Builder::new().name("bmd_speed_editor_mapping".to_string()).spawn(
+            move || {
+                let shared = handler_shared;
+                let speed_editor = handler_speed_editor;
+                loop {
+                    let events = {
+                        let mut pending = shared.pending.lock_unpoisoned();
+                        loop {
+                            if pending.stop {
+                                return;
+                            }
+                            if !pending.events.is_empty() {
+                                break;
+                            }
+                            let now = Instant::now();
+                            pending = match state.next_deadline() {
+                                Some(deadline) if deadline <= now => break,
+                                Some(deadline) => shared
+                                    .changed
+                                    .wait_timeout(pending, deadline - now)
+                                    .map(|(pending, _)| pending)
+                                    .unwrap_or_else(|e| e.into_inner().0),
+                                None => {
+                                    shared.changed.wait(pending).unwrap_or_else(|e| e.into_inner())
+                                }
+                            };
+                        }
+                        std::mem::take(&mut pending.events)
+                    };
+
+                    let now = Instant::now();
+                    let mut actions = Vec::new();
+                    let mut layer_changed = false;
+                    for event in &events {
+                        layer_changed |= state.handle(event, now, &mut actions);
+                    }
+                    state.expire(now, &mut actions);
+
+                    if layer_changed {
+                        *shared.layer.lock_unpoisoned() = state.layer_name();
+                        if let Some((button_led, wheel_led)) = state.leds() {
+                            speed_editor.set_button_led(button_led);
+                            speed_editor.set_wheel_led(wheel_led);
+                        }
+                    }
+                    for action in &actions {
+                        f(action);
+                    }
+                }
+            },
+        )?;
+
+        speed_editor.attach_sink(Box::new(EngineSink { shared: Arc::downgrade(&shared) }));
+        Ok(Self { shared, handler: Some(handler) })
+    }
+
+    /// Returns the name of the layer that is active, or [`None`] if no layer is active.
+    pub fn active_layer(&self) -> Option<String> {
+        self.shared.layer.lock_unpoisoned().clone()
+    }
+}
+
+impl Drop for MappingEngine {
+    fn drop(&mut self) {
+        self.shared.pending.lock_unpoisoned().stop = true;
+        self.shared.changed.notify_all();
+        if let Some(handler) = self.handler.take() {
+            let _ = handler.join();
+        }
+    }
+}
+
+/// Queues the events for the engine thread, while the [`MappingEngine`] exists.
+struct EngineSink {
+    shared: Weak<EngineShared>,
+}
+
+impl EventSink for EngineSink {
+    fn send(&self, event: Event) -> Result<(), SinkClosed> {
+        let shared = self.shared.upgrade().ok_or(SinkClosed)?;
+        let mut pending = shared.pending.lock_unpoisoned();
+        if pending.stop {
+            return Err(SinkClosed);
+        }
+        if matches!(
+            event,
+            Event::ButtonChange { .. } | Event::WheelChange { .. } | Event::Disconnected
+        ) {
+            pending.events.push_back(event);
+            shared.changed.notify_all();
+        }
+        Ok(())
+    }
+}
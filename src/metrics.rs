@@ -0,0 +1,110 @@
+use std::time::{Duration, Instant};
+
+use crate::{Counters, ErrorKind};
+
+/// A snapshot of metrics about the connection to the device, meant for monitoring.
+///
+/// They describe the lifetime of the [`SpeedEditor`][crate::SpeedEditor], so they carry on
+/// across reconnects, until they are reset with
+/// [`SpeedEditor::reset_session_metrics`][crate::SpeedEditor::reset_session_metrics].
+/// See [`SpeedEditor::session_metrics`][crate::SpeedEditor::session_metrics].
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub struct SessionMetrics {
+    /// The time since the device first connected,
+    /// or [`None`] if it has not connected yet.
+    pub uptime: Option<Duration>,
+    /// The number of times the device was opened again after the first connection.
+    pub reconnects: u64,
+    /// The number of times the authentication was renewed while connected.
+    pub auth_renewals: u64,
+    /// The total time the device was disconnected since it first connected,
+    /// including the current disconnection.
+    pub disconnected_time: Duration,
+    /// Why the device disconnected the last time,
+    /// or [`None`] if it has not disconnected yet.
+    pub last_disconnect_reason: Option<DisconnectReason>,
+}
+
+/// Why the device was disconnected.
+///
+/// See [`SessionMetrics::last_disconnect_reason`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub enum DisconnectReason {
+    /// Communicating with the device failed, e.g. because it was unplugged.
+    Error {
+        /// The kind of the error.
+        kind: ErrorKind,
+        /// The error, formatted with [`Display`][std::fmt::Display].
+        message: String,
+    },
+    /// The polling thread panicked, e.g. in a callback.
+    Panic,
+    /// The [`SpeedEditor`][crate::SpeedEditor] was shut down.
+    Shutdown,
+    /// The poller was paused with the device released.
+    Paused,
+    /// The system was suspended, after which the device is reopened.
+    Suspended,
+}
+
+impl From<&crate::Error> for DisconnectReason {
+    fn from(error: &crate::Error) -> Self {
+        DisconnectReason::Error { kind: error.kind(), message: error.to_string() }
+    }
+}
+
+/// Tracks the connections behind [`SessionMetrics`].
+///
+/// The counters are shared with [`Counters`], which are never reset,
+/// so the counters at the last reset are subtracted from them.
+#[derive(Debug, Default)]
+pub(crate) struct SessionMetricsTracker {
+    first_connect: Option<Instant>,
+    disconnected_since: Option<Instant>,
+    disconnected_time: Duration,
+    last_disconnect_reason: Option<DisconnectReason>,
+    baseline: Counters,
+}
+
+impl SessionMetricsTracker {
+    pub(crate) fn record_connect(&mut self, at: Instant) {
+        self.first_connect.get_or_insert(at);
+        if let Some(since) = self.disconnected_since.take() {
+            self.disconnected_time += at.duration_since(since);
+        }
+    }
+
+    pub(crate) fn record_disconnect(&mut self, at: Instant) {
+        if self.first_connect.is_some() {
+            self.disconnected_since = Some(at);
+        }
+    }
+
+    pub(crate) fn set_disconnect_reason(&mut self, reason: DisconnectReason) {
+        self.last_disconnect_reason = Some(reason);
+    }
+
+    /// Starts over as if the device first connected now, if it is connected.
+    pub(crate) fn reset(&mut self, connected: bool, counters: Counters, now: Instant) {
+        *self = SessionMetricsTracker {
+            first_connect: connected.then_some(now),
+            baseline: counters,
+            ..SessionMetricsTracker::default()
+        };
+    }
+
+    pub(crate) fn snapshot(&self, counters: &Counters, now: Instant) -> SessionMetrics {
+        let ongoing = self.disconnected_since.map(|since| now.duration_since(since));
+        SessionMetrics {
+            uptime: self.first_connect.map(|first_connect| now.duration_since(first_connect)),
+            reconnects: counters.reconnects.saturating_sub(self.baseline.reconnects),
+            auth_renewals: counters.auth_renewals.saturating_sub(self.baseline.auth_renewals),
+            disconnected_time: self.disconnected_time + ongoing.unwrap_or_default(),
+            last_disconnect_reason: self.last_disconnect_reason.clone(),
+        }
+    }
+}
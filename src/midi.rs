@@ -0,0 +1,467 @@
+//! Using the Speed Editor as a MIDI control surface.
+//!
+//! A [`MidiBridge`] sends a note for every mapped button, and controller changes when the
+//! jog wheel turns, as described by a [`MidiMapping`]. Notes it receives for a mapped button
+//! light up its LED, so a DAW can show its state on the Speed Editor.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use bmdse::midi::{MidiBridge, MidiMapping, WheelEncoding, WheelMapping};
+//! use bmdse::{Button, SpeedEditor};
+//!
+//! let speed_editor = SpeedEditor::new().unwrap();
+//! let mapping = MidiMapping::new()
+//!     .button(Button::StopPlay, 0, 60)
+//!     .button(Button::Cam1, 0, 61)
+//!     .wheel(WheelMapping::new(0, WheelEncoding::TwosComplement { controller: 16 }));
+//! let _bridge = MidiBridge::new(&speed_editor, mapping, "Speed Editor").unwrap();
+//!
+//! std::thread::park();
+//! ```
+
+use std::{
+    io,
+    sync::{Arc, Mutex, Weak},
+};
+
+use midir::{MidiInput, MidiInputConnection, MidiOutput, MidiOutputConnection};
+
+use crate::{
+    Button, ButtonLed, Event, EventSink, SinkClosed, SpeedEditor, WheelLed, driver::Led,
+    sync::MutexExt,
+};
+
+const NOTE_OFF: u8 = 0x80;
+const NOTE_ON: u8 = 0x90;
+const CONTROL_CHANGE: u8 = 0xb0;
+/// The largest change a single relative controller message can carry.
+const MAX_STEPS: u32 = 63;
+
+/// The note a button sends while it is held.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct NoteMapping {
+    /// The button.
+    pub button: Button,
+    /// The MIDI channel (`0..=15`).
+    pub channel: u8,
+    /// The note (`0..=127`).
+    pub note: u8,
+}
+
+/// How the jog wheel sends its changes as controller changes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum WheelEncoding {
+    /// A single controller with the change as a 7 bit two's complement value,
+    /// so `1..=63` turns clockwise and `127..=65` (-1 to -63) counterclockwise.
+    TwosComplement {
+        /// The controller number (`0..=127`).
+        controller: u8,
+    },
+    /// Separate controllers for turning clockwise and counterclockwise,
+    /// with the number of steps (`1..=63`) as value.
+    IncrementDecrement {
+        /// The controller number for turning clockwise (`0..=127`).
+        increment: u8,
+        /// The controller number for turning counterclockwise (`0..=127`).
+        decrement: u8,
+    },
+}
+
+/// The controller changes the jog wheel sends.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct WheelMapping {
+    /// The MIDI channel (`0..=15`).
+    pub channel: u8,
+    /// How the changes are encoded.
+    pub encoding: WheelEncoding,
+}
+
+impl WheelMapping {
+    /// Creates a [`WheelMapping`].
+    pub fn new(channel: u8, encoding: WheelEncoding) -> Self {
+        Self { channel, encoding }
+    }
+}
+
+/// Which MIDI messages the buttons and the jog wheel send.
+///
+/// The wheel only reports changes in relative mode, which is the mode it is in by default.
+/// Changes bigger than 63 steps are split into multiple messages.
+///
+/// # Example
+///
+/// ```
+/// use bmdse::midi::{MidiMapping, WheelEncoding, WheelMapping};
+/// use bmdse::{Button, Event};
+///
+/// let mapping = MidiMapping::new()
+///     .button(Button::Cut, 1, 64)
+///     .wheel(WheelMapping::new(0, WheelEncoding::TwosComplement { controller: 16 }));
+///
+/// let pressed = Event::ButtonChange { button: Button::Cut, pressed: true };
+/// assert_eq!(mapping.messages(&pressed), [[0x91, 64, 127]]);
+/// let released = Event::ButtonChange { button: Button::Cut, pressed: false };
+/// assert_eq!(mapping.messages(&released), [[0x81, 64, 0]]);
+/// let turned = Event::WheelChange { velocity: -2 };
+/// assert_eq!(mapping.messages(&turned), [[0xb0, 16, 126]]);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MidiMapping {
+    /// The notes of the buttons, a button that is not in here does not send anything.
+    pub buttons: Vec<NoteMapping>,
+    /// The controller changes of the jog wheel, or [`None`] if it does not send anything.
+    pub wheel: Option<WheelMapping>,
+}
+
+impl MidiMapping {
+    /// Creates a [`MidiMapping`] that does not send anything.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Maps the button to a note, replacing the note it was mapped to.
+    pub fn button(mut self, button: Button, channel: u8, note: u8) -> Self {
+        self.buttons.retain(|mapping| mapping.button != button);
+        self.buttons.push(NoteMapping { button, channel, note });
+        self
+    }
+
+    /// Maps the jog wheel to controller changes.
+    pub fn wheel(mut self, wheel: WheelMapping) -> Self {
+        self.wheel = Some(wheel);
+        self
+    }
+
+    /// Returns the MIDI messages that the event sends.
+    pub fn messages(&self, event: &Event) -> Vec<[u8; 3]> {
+        match *event {
+            Event::ButtonChange { button, pressed } => {
+                let Some(mapping) = self.buttons.iter().find(|mapping| mapping.button == button)
+                else {
+                    return Vec::new();
+                };
+                let (status, velocity) = if pressed { (NOTE_ON, 127) } else { (NOTE_OFF, 0) };
+                vec![[status | (mapping.channel & 0x0f), mapping.note & 0x7f, velocity]]
+            }
+            Event::WheelChange { velocity } if velocity != 0 => {
+                let Some(wheel) = self.wheel else { return Vec::new() };
+                let status = CONTROL_CHANGE | (wheel.channel & 0x0f);
+                let mut steps = velocity.unsigned_abs();
+                let mut messages = Vec::new();
+                while steps > 0 {
+                    let chunk = steps.min(MAX_STEPS) as u8;
+                    steps -= chunk as u32;
+                    messages.push(match wheel.encoding {
+                        WheelEncoding::TwosComplement { controller } if velocity > 0 => {
+                            [status, controller & 0x7f, chunk]
+                        }
+                        WheelEncoding::TwosComplement { controller } => {
+                            [status, controller & 0x7f, 128 - chunk]
+                        }
+                        WheelEncoding::IncrementDecrement { increment, .. } if velocity > 0 => {
+                            [status, increment & 0x7f, chunk]
+                        }
+                        WheelEncoding::IncrementDecrement { decrement, .. } => {
+                            [status, decrement & 0x7f, chunk]
+                        }
+                    });
+                }
+                messages
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// Returns the LED of the button that is mapped to the note, if it has one.
+    fn led_for_note(&self, channel: u8, note: u8) -> Option<Led> {
+        self.buttons
+            .iter()
+            .find(|mapping| mapping.channel & 0x0f == channel && mapping.note & 0x7f == note)
+            .and_then(|mapping| mapping.button.to_led())
+    }
+}
+
+/// Sends the MIDI messages of the events, while the [`MidiBridge`] exists.
+struct MidiSink {
+    output: Weak<Mutex<MidiOutputConnection>>,
+    mapping: Arc<MidiMapping>,
+}
+
+impl EventSink for MidiSink {
+    fn send(&self, event: Event) -> Result<(), SinkClosed> {
+        let output = self.output.upgrade().ok_or(SinkClosed)?;
+        let mut output = output.lock_unpoisoned();
+        for message in self.mapping.messages(&event) {
+            // There is nobody to report a failed send to, and the next one might work again.
+            let _ = output.send(&message);
+        }
+        Ok(())
+    }
+}
+
+/// Connects a [`SpeedEditor`] to MIDI ports, until it is dropped.
+///
+/// See the [module documentation][self].
+pub struct MidiBridge {
+    _output: Arc<Mutex<MidiOutputConnection>>,
+    _input: MidiInputConnection<()>,
+}
+
+impl MidiBridge {
+    /// Sends the MIDI messages that `mapping` maps the events of the [`SpeedEditor`] to,
+    /// and lights up the LED of a mapped button when its note is received.
+    ///
+    /// A received note on lights up the LED of the button mapped to the note (and channel),
+    /// and a note off turns it off again if it is still lit. Only one button LED and one wheel
+    /// LED can be lit at once, so a note on replaces the LED that was lit before.
+    /// The bridge keeps the [`SpeedEditor`] open for as long as it exists.
+    ///
+    /// On platforms that support virtual MIDI ports (all but Windows), this creates an output
+    /// and an input port named `port_name` that other applications can connect to.
+    /// On Windows, it connects to existing ports whose name contains `port_name` instead,
+    /// e.g. ports created with a loopback driver.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if MIDI is not available,
+    /// or if the ports could not be created or found.
+    pub fn new(
+        speed_editor: &SpeedEditor,
+        mapping: MidiMapping,
+        port_name: &str,
+    ) -> Result<Self, crate::Error> {
+        let mapping = Arc::new(mapping);
+        let client_name = "bmdse";
+
+        let output = MidiOutput::new(client_name).map_err(midi_error)?;
+        let output = Arc::new(Mutex::new(connect_output(output, port_name)?));
+
+        let input_speed_editor = speed_editor.clone();
+        let input_mapping = Arc::clone(&mapping);
+        let on_message = move |_timestamp: u64, message: &[u8], _: &mut ()| {
+            show_note(&input_speed_editor, &input_mapping, message);
+        };
+        let input = MidiInput::new(client_name).map_err(midi_error)?;
+        let input = connect_input(input, port_name, on_message)?;
+
+        speed_editor.attach_sink(Box::new(MidiSink { output: Arc::downgrade(&output), mapping }));
+        Ok(Self { _output: output, _input: input })
+    }
+}
+
+/// Lights up or turns off the LED of the button that is mapped to a received note.
+fn show_note(speed_editor: &SpeedEditor, mapping: &MidiMapping, message: &[u8]) {
+    let &[status, note, velocity, ..] = message else { return };
+    let channel = status & 0x0f;
+    // A note on without velocity is a note off.
+    let on = match status & 0xf0 {
+        NOTE_ON => velocity > 0,
+        NOTE_OFF => false,
+        _ => return,
+    };
+    match mapping.led_for_note(channel, note) {
+        Some(Led::Button(led)) if on => speed_editor.set_button_led(led),
+        Some(Led::Button(led)) if speed_editor.button_led() == led => {
+            speed_editor.set_button_led(ButtonLed::Off);
+        }
+        Some(Led::Wheel(led)) if on => speed_editor.set_wheel_led(led),
+        Some(Led::Wheel(led)) if speed_editor.wheel_led() == led => {
+            speed_editor.set_wheel_led(WheelLed::Off);
+        }
+        _ => {}
+    }
+}
+
+#[cfg(not(windows))]
+fn connect_output(
+    output: MidiOutput,
+    port_name: &str,
+) -> Result<MidiOutputConnection, crate::Error> {
+    use midir::os::unix::VirtualOutput;
+    output.create_virtual(port_name).map_err(midi_error)
+}
+
+#[cfg(windows)]
+fn connect_output(
+    output: MidiOutput,
+    port_name: &str,
+) -> Result<MidiOutputConnection, crate::Error> {
+    let port = output
+        .ports()
+        .into_iter()
+        .find(|port| output.port_name(port).is_ok_and(|name| name.contains(port_name)))
+        .ok_or_else(port_not_found)?;
+    output.connect(&port, port_name).map_err(midi_error)
+}
+
+#[cfg(not(windows))]
+fn connect_input(
+    input: MidiInput,
+    port_name: &str,
+    on_message: impl FnMut(u64, &[u8], &mut ()) + Send + 'static,
+) -> Result<MidiInputConnection<()>, crate::Error> {
+    use midir::os::unix::VirtualInput;
+    input.create_virtual(port_name, on_message, ()).map_err(midi_error)
+}
+
+#[cfg(windows)]
+fn connect_input(
+    input: MidiInput,
+    port_name: &str,
+    on_message: impl FnMut(u64, &[u8], &mut ()) + Send + 'static,
+) -> Result<MidiInputConnection<()>, crate::Error> {
+    let port = input
+        .ports()
+        .into_iter()
+        .find(|port| input.port_name(port).is_ok_and(|name| name.contains(port_name)))
+        .ok_or_else(port_not_found)?;
+    input.connect(&port, port_name, on_message, ()).map_err(midi_error)
+}
+
+#[cfg(windows)]
+fn port_not_found() -> crate::Error {
+    io::Error::new(io::ErrorKind::NotFound, "MIDI port not found").into()
+}
+
+fn midi_error(error: impl std::fmt::Display) -> crate::Error {
+    io::Error::other(format!("MIDI: {error}")).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::FakeBackend;
+
+    fn mapping() -> MidiMapping {
+        MidiMapping::new()
+            .button(Button::Cut, 1, 64)
+            .button(Button::Jog, 15, 127)
+            .button(Button::StopPlay, 0, 60)
+            .wheel(WheelMapping::new(2, WheelEncoding::TwosComplement { controller: 16 }))
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn mapping_round_trips() {
+        let increment_decrement = mapping().wheel(WheelMapping::new(
+            0,
+            WheelEncoding::IncrementDecrement { increment: 20, decrement: 21 },
+        ));
+        for mapping in [MidiMapping::new(), mapping(), increment_decrement] {
+            let json = serde_json::to_string(&mapping).unwrap();
+            assert_eq!(serde_json::from_str::<MidiMapping>(&json).unwrap(), mapping, "{json}");
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn mapping_is_declarative() {
+        let json = r#"{
+            "buttons": [{ "button": "Cut", "channel": 1, "note": 64 }],
+            "wheel": { "channel": 0, "encoding": { "IncrementDecrement": { "increment": 20, "decrement": 21 } } }
+        }"#;
+        let expected = MidiMapping::new().button(Button::Cut, 1, 64).wheel(WheelMapping::new(
+            0,
+            WheelEncoding::IncrementDecrement { increment: 20, decrement: 21 },
+        ));
+        assert_eq!(serde_json::from_str::<MidiMapping>(json).unwrap(), expected);
+
+        let json = r#"{ "buttons": [], "wheel": null }"#;
+        assert_eq!(serde_json::from_str::<MidiMapping>(json).unwrap(), MidiMapping::new());
+    }
+
+    #[test]
+    fn button_replaces_its_note() {
+        let mapping = mapping().button(Button::Cut, 3, 65);
+        assert_eq!(mapping.buttons.len(), 3);
+        let pressed = Event::ButtonChange { button: Button::Cut, pressed: true };
+        assert_eq!(mapping.messages(&pressed), [[0x93, 65, 127]]);
+    }
+
+    #[test]
+    fn buttons_send_notes() {
+        let mapping = mapping();
+        let cases = [
+            (Button::Cut, true, vec![[0x91, 64, 127]]),
+            (Button::Cut, false, vec![[0x81, 64, 0]]),
+            (Button::Jog, true, vec![[0x9f, 127, 127]]),
+            (Button::In, true, vec![]),
+        ];
+        for (button, pressed, messages) in cases {
+            let event = Event::ButtonChange { button, pressed };
+            assert_eq!(mapping.messages(&event), messages, "{event:?}");
+        }
+
+        // Out of range values are masked.
+        let mapping = MidiMapping::new().button(Button::Cut, 0x12, 0x80);
+        let pressed = Event::ButtonChange { button: Button::Cut, pressed: true };
+        assert_eq!(mapping.messages(&pressed), [[0x92, 0x00, 127]]);
+    }
+
+    #[test]
+    fn wheel_sends_twos_complement() {
+        let mapping = mapping();
+        let cases = [
+            (0, vec![]),
+            (1, vec![[0xb2, 16, 1]]),
+            (-1, vec![[0xb2, 16, 127]]),
+            (63, vec![[0xb2, 16, 63]]),
+            (-63, vec![[0xb2, 16, 65]]),
+            (100, vec![[0xb2, 16, 63], [0xb2, 16, 37]]),
+            (-100, vec![[0xb2, 16, 65], [0xb2, 16, 91]]),
+        ];
+        for (velocity, messages) in cases {
+            assert_eq!(mapping.messages(&Event::WheelChange { velocity }), messages, "{velocity}");
+        }
+    }
+
+    #[test]
+    fn wheel_sends_increment_decrement() {
+        let mapping = MidiMapping::new().wheel(WheelMapping::new(
+            0,
+            WheelEncoding::IncrementDecrement { increment: 20, decrement: 21 },
+        ));
+        let cases = [
+            (5, vec![[0xb0, 20, 5]]),
+            (-5, vec![[0xb0, 21, 5]]),
+            (-64, vec![[0xb0, 21, 63], [0xb0, 21, 1]]),
+        ];
+        for (velocity, messages) in cases {
+            assert_eq!(mapping.messages(&Event::WheelChange { velocity }), messages, "{velocity}");
+        }
+
+        // Without a wheel mapping, and for other events, nothing is sent.
+        assert!(MidiMapping::new().messages(&Event::WheelChange { velocity: 5 }).is_empty());
+        assert!(mapping.messages(&Event::Disconnected).is_empty());
+    }
+
+    #[test]
+    fn notes_light_leds() {
+        let backend = FakeBackend::new();
+        backend.push_auth_handshake();
+        let speed_editor = SpeedEditor::builder().connect_backend(backend).unwrap();
+        let mapping = mapping();
+
+        show_note(&speed_editor, &mapping, &[0x91, 64, 100]);
+        show_note(&speed_editor, &mapping, &[0x9f, 127, 100]);
+        assert_eq!(speed_editor.button_led(), ButtonLed::Cut);
+        assert_eq!(speed_editor.wheel_led(), WheelLed::Jog);
+
+        // A note on another channel, or of a button without a LED, does nothing.
+        show_note(&speed_editor, &mapping, &[0x80, 64, 0]);
+        show_note(&speed_editor, &mapping, &[0x80, 60, 0]);
+        show_note(&speed_editor, &mapping, &[0xb1, 64, 0]);
+        assert_eq!(speed_editor.button_led(), ButtonLed::Cut);
+
+        // A note on without velocity is a note off.
+        show_note(&speed_editor, &mapping, &[0x91, 64, 0]);
+        show_note(&speed_editor, &mapping, &[0x8f, 127, 0]);
+        assert_eq!(speed_editor.button_led(), ButtonLed::Off);
+        assert_eq!(speed_editor.wheel_led(), WheelLed::Off);
+    }
+}
@@ -0,0 +1,258 @@
+use std::{ops::Deref, sync::Mutex, time::Duration};
+
+use crate::{
+    Button, ButtonLed, DeviceInfo, EventSink, PollerError, SpeedEditor, SpeedEditorBuilder,
+    SpeedEditorInterface, WheelLed, WheelMode,
+    protocol::{BATTERY_REPORT_ID, BUTTONS_REPORT_ID, BUTTONS_REPORT_LEN, WHEEL_REPORT_ID},
+    sync::MutexExt,
+    testing::FakeBackend,
+};
+
+/// A [`SpeedEditor`] without hardware, to test applications that use it.
+///
+/// Only available with the `mock` feature.
+///
+/// It derefs to a real [`SpeedEditor`], connected to a [`FakeBackend`],
+/// so application code written against [`SpeedEditor`] works with it unchanged.
+/// It also implements [`SpeedEditorInterface`], for application code written against that.
+/// While the trait is in scope, its methods take precedence over the ones of [`SpeedEditor`],
+/// so callbacks have to be boxed.
+/// The control methods like [`MockSpeedEditor::press`] feed reports through the normal
+/// parsing and callback pipeline, and block until the callbacks have been called.
+///
+/// # Example
+///
+/// ```no_run
+/// use bmdse::{Button, MockSpeedEditor};
+///
+/// let mock = MockSpeedEditor::new().unwrap();
+/// mock.set_on_button_change(|button, pressed| eprintln!("{button:?}: {pressed}"));
+///
+/// mock.press(Button::Cam1);
+/// assert!(mock.is_button_pressed(Button::Cam1));
+/// mock.release(Button::Cam1);
+/// ```
+pub struct MockSpeedEditor {
+    speed_editor: SpeedEditor,
+    backend: FakeBackend,
+    pressed: Mutex<Vec<Button>>,
+}
+
+impl MockSpeedEditor {
+    /// How long to wait for the polling thread to handle a report.
+    const TIMEOUT: Duration = Duration::from_secs(1);
+
+    /// Creates a connected [`MockSpeedEditor`], with a battery that is full and not charging.
+    ///
+    /// # Errors
+    ///
+    /// This function only errors if the polling thread could not be spawned.
+    pub fn new() -> Result<Self, crate::Error> {
+        Self::connect(SpeedEditor::builder())
+    }
+
+    /// Creates a connected [`MockSpeedEditor`] from a builder,
+    /// e.g. to register callbacks before it connects.
+    ///
+    /// # Errors
+    ///
+    /// This function only errors if the configuration is invalid,
+    /// or if the polling thread could not be spawned.
+    pub fn connect(builder: SpeedEditorBuilder) -> Result<Self, crate::Error> {
+        let backend = FakeBackend::new();
+        backend.push_auth_handshake();
+        // Answers the battery request made right after connecting.
+        backend.push_input_report(&[BATTERY_REPORT_ID, 0x00, 100]);
+
+        let speed_editor = builder.connect_backend(backend.clone())?;
+        backend.wait_until_idle(Self::TIMEOUT);
+
+        Ok(Self { speed_editor, backend, pressed: Mutex::new(Vec::new()) })
+    }
+
+    /// Presses the button, keeping the other pressed buttons pressed.
+    ///
+    /// The device reports at most 6 pressed buttons, so pressing more is ignored.
+    pub fn press(&self, button: Button) {
+        let mut pressed = self.pressed.lock_unpoisoned();
+        if pressed.contains(&button) || pressed.len() == 6 {
+            return;
+        }
+        pressed.push(button);
+        self.send_buttons(&pressed);
+    }
+
+    /// Releases the button, keeping the other pressed buttons pressed.
+    pub fn release(&self, button: Button) {
+        let mut pressed = self.pressed.lock_unpoisoned();
+        if !pressed.contains(&button) {
+            return;
+        }
+        pressed.retain(|pressed_button| *pressed_button != button);
+        self.send_buttons(&pressed);
+    }
+
+    /// Turns the wheel with the given velocity.
+    pub fn turn_wheel(&self, velocity: i32) {
+        let [a, b, c, d] = velocity.to_le_bytes();
+        self.send(&[WHEEL_REPORT_ID, 0x00, a, b, c, d, 0x00]);
+    }
+
+    /// Reports the given battery state.
+    pub fn set_battery(&self, charging: bool, level: u8) {
+        self.send(&[BATTERY_REPORT_ID, charging as u8, level]);
+    }
+
+    /// Returns the [`FakeBackend`] behind this mock, e.g. to inspect the written LED reports.
+    pub fn backend(&self) -> &FakeBackend {
+        &self.backend
+    }
+
+    fn send_buttons(&self, pressed: &[Button]) {
+        let mut report = [0x00; BUTTONS_REPORT_LEN];
+        report[0] = BUTTONS_REPORT_ID;
+        for (slot, button) in report[1..].chunks_mut(2).zip(pressed) {
+            slot.copy_from_slice(&(*button as u16).to_le_bytes());
+        }
+        self.send(&report);
+    }
+
+    fn send(&self, report: &[u8]) {
+        self.backend.push_input_report(report);
+        self.backend.wait_until_idle(Self::TIMEOUT);
+    }
+}
+
+impl Deref for MockSpeedEditor {
+    type Target = SpeedEditor;
+
+    fn deref(&self) -> &SpeedEditor {
+        &self.speed_editor
+    }
+}
+
+impl SpeedEditorInterface for MockSpeedEditor {
+    fn set_on_wheel_change(&self, f: Box<dyn Fn(i32) + Send>) {
+        self.speed_editor.set_on_wheel_change(f);
+    }
+
+    fn set_on_button_change(&self, f: Box<dyn Fn(Button, bool) + Send>) {
+        self.speed_editor.set_on_button_change(f);
+    }
+
+    fn set_on_battery_info(&self, f: Box<dyn Fn(bool, u8) + Send>) {
+        self.speed_editor.set_on_battery_info(f);
+    }
+
+    fn set_on_connect(&self, f: Box<dyn Fn(DeviceInfo) + Send>) {
+        self.speed_editor.set_on_connect(f);
+    }
+
+    fn set_on_disconnect(&self, f: Box<dyn Fn() + Send>) {
+        self.speed_editor.set_on_disconnect(f);
+    }
+
+    fn set_on_error(&self, f: Box<dyn Fn(&PollerError) + Send>) {
+        self.speed_editor.set_on_error(f);
+    }
+
+    fn attach_sink(&self, sink: Box<dyn EventSink>) {
+        self.speed_editor.attach_sink(sink);
+    }
+
+    fn is_button_pressed(&self, button: Button) -> bool {
+        self.speed_editor.is_button_pressed(button)
+    }
+
+    fn pressed_buttons(&self) -> Vec<Button> {
+        self.speed_editor.pressed_buttons()
+    }
+
+    fn set_button_led(&self, led: ButtonLed) {
+        self.speed_editor.set_button_led(led);
+    }
+
+    fn button_led(&self) -> ButtonLed {
+        self.speed_editor.button_led()
+    }
+
+    fn set_wheel_led(&self, led: WheelLed) {
+        self.speed_editor.set_wheel_led(led);
+    }
+
+    fn wheel_led(&self) -> WheelLed {
+        self.speed_editor.wheel_led()
+    }
+
+    fn set_wheel_mode(&self, mode: WheelMode) -> Result<(), crate::Error> {
+        self.speed_editor.set_wheel_mode(mode);
+        Ok(())
+    }
+
+    fn wheel_mode(&self) -> WheelMode {
+        self.speed_editor.wheel_mode()
+    }
+
+    fn is_connected(&self) -> bool {
+        self.speed_editor.is_connected()
+    }
+
+    fn reset_device(&self) -> Result<(), crate::Error> {
+        self.speed_editor.reset_device()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::mpsc,
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use super::*;
+    use crate::protocol;
+    use crate::test_util::TIMEOUT;
+
+    #[test]
+    fn mock_is_used_through_trait_object() {
+        let mock = MockSpeedEditor::new().unwrap();
+        let interface: &dyn SpeedEditorInterface = &mock;
+        assert!(interface.is_connected());
+
+        let (sender, receiver) = mpsc::channel();
+        interface.set_on_button_change(Box::new(move |button, pressed| {
+            sender.send((button, pressed)).unwrap();
+        }));
+        mock.press(Button::Cam1);
+        assert_eq!(receiver.recv_timeout(TIMEOUT), Ok((Button::Cam1, true)));
+        assert!(interface.is_button_pressed(Button::Cam1));
+        mock.release(Button::Cam1);
+        assert_eq!(receiver.recv_timeout(TIMEOUT), Ok((Button::Cam1, false)));
+        assert!(interface.pressed_buttons().is_empty());
+
+        // The LED is written to the fake backend like to a device.
+        interface.set_button_led(ButtonLed::Cam1);
+        let report = protocol::button_led_report(ButtonLed::Cam1).to_vec();
+        let start = Instant::now();
+        while !mock.backend().written().contains(&report) {
+            assert!(start.elapsed() < TIMEOUT, "the button LED was not written");
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn mock_and_speed_editor_are_interchangeable() {
+        let mock = MockSpeedEditor::new().unwrap();
+        let speed_editor = SpeedEditor::clone(&mock);
+        let interfaces: [Box<dyn SpeedEditorInterface>; 2] =
+            [Box::new(mock), Box::new(speed_editor)];
+
+        interfaces[0].set_wheel_led(WheelLed::Shuttle);
+        assert_eq!(interfaces[1].wheel_led(), WheelLed::Shuttle);
+        interfaces[1].set_button_led(ButtonLed::Cut);
+        assert_eq!(interfaces[0].button_led(), ButtonLed::Cut);
+        interfaces[0].set_wheel_mode(WheelMode::AbsoluteContinuous).unwrap();
+        assert_eq!(interfaces[1].wheel_mode(), WheelMode::AbsoluteContinuous);
+    }
+}
@@ -0,0 +1,438 @@
+//! Publishing the events of the Speed Editor to an MQTT broker, and controlling its LEDs over MQTT.
+//!
+//! An [`MqttBridge`] connects to the broker, publishes the events and subscribes to the LED
+//! topics, until it is dropped. It reconnects when the broker cannot be reached or restarts:
+//!
+//! ```no_run
+//! use bmdse::SpeedEditor;
+//! use bmdse::mqtt::{MqttBridge, MqttOptions, Qos};
+//!
+//! let speed_editor = SpeedEditor::new().unwrap();
+//! let options = MqttOptions::new().host("broker.local").qos(Qos::AtLeastOnce);
+//! let _mqtt = MqttBridge::new(&speed_editor, options).unwrap();
+//!
+//! std::thread::park();
+//! ```
+//!
+//! # Topics
+//!
+//! By default, it uses these topics, where `<serial>` is the serial number of the device:
+//!
+//! | Topic | Payload | Retained |
+//! |-------|---------|----------|
+//! | `bmdse/<serial>/button/<name>` | `1` when the button is pressed, `0` when it is released | No |
+//! | `bmdse/<serial>/wheel` | The change of the jog wheel | No |
+//! | `bmdse/<serial>/battery` | `{"level":80,"charging":false}` | Yes |
+//! | `bmdse/<serial>/state` | `online`, `disconnected` while the device is, or `offline` without the bridge | Yes |
+//! | `bmdse/<serial>/led/<name>` | Published by others: `1`, `on` or `true` lights up the LED, anything else turns it off | |
+//!
+//! The names are those of [`Button`](crate::Button), [`ButtonLed`] and [`WheelLed`].
+//! Like on the device itself, only one button LED and one wheel LED can be lit at once,
+//! so lighting up a LED replaces the LED that was lit before. Turning off a LED only
+//! turns it off if it is still lit.
+//!
+//! The topics are templates that can be changed with [`MqttOptions`], in which `{serial}` is
+//! replaced by the serial number, and `{name}` by the name of the button.
+//! The `offline` state is also the last will, so it is published when the bridge disappears
+//! without disconnecting.
+
+use std::{
+    sync::{
+        Arc, Weak,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use rumqttc::{Client, Connection, Incoming, LastWill, Outgoing, QoS};
+
+use crate::{ButtonLed, Event, EventSink, SinkClosed, SpeedEditor, WheelLed, driver::Led};
+
+/// How many requests, like publishes, can wait to be sent to the broker.
+/// Events that do not fit are dropped, so a slow broker never holds up the events.
+const REQUEST_CAPACITY: usize = 256;
+const MIN_RECONNECT_DELAY: Duration = Duration::from_secs(1);
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(30);
+/// How long dropping the bridge waits for the disconnect to be sent.
+const DISCONNECT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// The quality of service of the published messages and the LED subscription.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Qos {
+    /// The message is delivered at most once, and might be lost.
+    #[default]
+    AtMostOnce,
+    /// The message is delivered at least once, and might be duplicated.
+    AtLeastOnce,
+    /// The message is delivered exactly once.
+    ExactlyOnce,
+}
+
+impl From<Qos> for QoS {
+    fn from(qos: Qos) -> Self {
+        match qos {
+            Qos::AtMostOnce => QoS::AtMostOnce,
+            Qos::AtLeastOnce => QoS::AtLeastOnce,
+            Qos::ExactlyOnce => QoS::ExactlyOnce,
+        }
+    }
+}
+
+/// How an [`MqttBridge`] connects to the broker, and which [topics](self#topics) it uses.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct MqttOptions {
+    /// The host name or IP address of the broker.
+    pub host: String,
+    /// The port of the broker.
+    pub port: u16,
+    /// The client ID, or [`None`] to use `bmdse-<serial>`.
+    pub client_id: Option<String>,
+    /// The user name and password, if the broker requires them.
+    pub credentials: Option<(String, String)>,
+    /// How often the connection is checked when nothing is sent.
+    pub keep_alive: Duration,
+    /// The quality of service of the published messages and the LED subscription.
+    pub qos: Qos,
+    /// The topic of button changes, in which `{name}` is replaced by the name of the button.
+    pub button_topic: String,
+    /// The topic of jog wheel changes.
+    pub wheel_topic: String,
+    /// The retained topic of the battery information.
+    pub battery_topic: String,
+    /// The retained topic of the state of the device and the bridge.
+    pub state_topic: String,
+    /// The topic under which the LEDs are controlled, by their name.
+    pub led_topic: String,
+}
+
+impl Default for MqttOptions {
+    fn default() -> Self {
+        Self {
+            host: "localhost".to_string(),
+            port: MqttBridge::DEFAULT_PORT,
+            client_id: None,
+            credentials: None,
+            keep_alive: Duration::from_secs(30),
+            qos: Qos::AtMostOnce,
+            button_topic: "bmdse/{serial}/button/{name}".to_string(),
+            wheel_topic: "bmdse/{serial}/wheel".to_string(),
+            battery_topic: "bmdse/{serial}/battery".to_string(),
+            state_topic: "bmdse/{serial}/state".to_string(),
+            led_topic: "bmdse/{serial}/led".to_string(),
+        }
+    }
+}
+
+impl MqttOptions {
+    /// Creates the default [`MqttOptions`], which connect to a broker on `localhost`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the host name or IP address of the broker.
+    pub fn host(mut self, host: impl Into<String>) -> Self {
+        self.host = host.into();
+        self
+    }
+
+    /// Set the port of the broker.
+    pub fn port(mut self, port: u16) -> Self {
+        self.port = port;
+        self
+    }
+
+    /// Set the client ID.
+    pub fn client_id(mut self, client_id: impl Into<String>) -> Self {
+        self.client_id = Some(client_id.into());
+        self
+    }
+
+    /// Set the user name and password.
+    pub fn credentials(mut self, user: impl Into<String>, password: impl Into<String>) -> Self {
+        self.credentials = Some((user.into(), password.into()));
+        self
+    }
+
+    /// Set how often the connection is checked when nothing is sent.
+    pub fn keep_alive(mut self, keep_alive: Duration) -> Self {
+        self.keep_alive = keep_alive;
+        self
+    }
+
+    /// Set the quality of service of the published messages and the LED subscription.
+    pub fn qos(mut self, qos: Qos) -> Self {
+        self.qos = qos;
+        self
+    }
+
+    /// Set the topic of button changes, in which `{name}` is replaced by the name of the button.
+    pub fn button_topic(mut self, template: impl Into<String>) -> Self {
+        self.button_topic = template.into();
+        self
+    }
+
+    /// Set the topic of jog wheel changes.
+    pub fn wheel_topic(mut self, template: impl Into<String>) -> Self {
+        self.wheel_topic = template.into();
+        self
+    }
+
+    /// Set the retained topic of the battery information.
+    pub fn battery_topic(mut self, template: impl Into<String>) -> Self {
+        self.battery_topic = template.into();
+        self
+    }
+
+    /// Set the retained topic of the state of the device and the bridge.
+    pub fn state_topic(mut self, template: impl Into<String>) -> Self {
+        self.state_topic = template.into();
+        self
+    }
+
+    /// Set the topic under which the LEDs are controlled.
+    pub fn led_topic(mut self, template: impl Into<String>) -> Self {
+        self.led_topic = template.into();
+        self
+    }
+}
+
+/// The topics with the serial number filled in.
+struct Topics {
+    button: String,
+    wheel: String,
+    battery: String,
+    state: String,
+    led: String,
+}
+
+struct MqttShared {
+    client: Client,
+    topics: Topics,
+    qos: QoS,
+    connected: AtomicBool,
+    stop: AtomicBool,
+}
+
+impl MqttShared {
+    /// Queues a publish without blocking, dropping it if the queue is full.
+    fn publish(&self, topic: &str, retain: bool, payload: impl Into<Vec<u8>>) {
+        let _ = self.client.try_publish(topic, self.qos, retain, payload);
+    }
+
+    fn publish_battery(&self, level: u8, charging: bool) {
+        let payload = format!("{{\"level\":{level},\"charging\":{charging}}}");
+        self.publish(&self.topics.battery, true, payload);
+    }
+}
+
+/// Connects a [`SpeedEditor`] to an MQTT broker, until it is dropped.
+///
+/// See the [module documentation][self]. The connection is kept from a separate thread,
+/// which reconnects with an increasing delay (up to 30 seconds) when the broker cannot be
+/// reached. Events that happen while not connected are dropped, but the retained battery
+/// and state topics are published again on every connect.
+pub struct MqttBridge {
+    shared: Arc<MqttShared>,
+    connection: Option<JoinHandle<()>>,
+}
+
+impl MqttBridge {
+    /// The default port of MQTT brokers.
+    pub const DEFAULT_PORT: u16 = 1883;
+
+    /// Starts publishing the events of the [`SpeedEditor`] to the broker of `options`,
+    /// and controlling its LEDs from the LED topics.
+    ///
+    /// The serial number in the topics is that of the [device info][SpeedEditor::device_info]
+    /// when the bridge is created, or `unknown` if no device was connected yet.
+    /// The bridge keeps the [`SpeedEditor`] open for as long as it exists.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the connection thread could not be spawned.
+    /// Failing to connect is not an error, it is retried until it succeeds.
+    pub fn new(speed_editor: &SpeedEditor, options: MqttOptions) -> Result<Self, crate::Error> {
+        let serial_number = speed_editor.device_info().and_then(|info| info.serial_number);
+        let serial_number = serial_number.as_deref().unwrap_or("unknown");
+        let topic = |template: &str| template.replace("{serial}", serial_number);
+        let topics = Topics {
+            button: topic(&options.button_topic),
+            wheel: topic(&options.wheel_topic),
+            battery: topic(&options.battery_topic),
+            state: topic(&options.state_topic),
+            led: topic(options.led_topic.trim_end_matches('/')),
+        };
+
+        let client_id = options.client_id.unwrap_or_else(|| format!("bmdse-{serial_number}"));
+        let mut broker = rumqttc::MqttOptions::new(client_id, options.host, options.port);
+        broker.set_keep_alive(options.keep_alive);
+        broker.set_last_will(LastWill::new(&topics.state, "offline", options.qos.into(), true));
+        if let Some((user, password)) = options.credentials {
+            broker.set_credentials(user, password);
+        }
+        let (client, connection) = Client::new(broker, REQUEST_CAPACITY);
+
+        let shared = Arc::new(MqttShared {
+            client,
+            topics,
+            qos: options.qos.into(),
+            connected: AtomicBool::new(false),
+            stop: AtomicBool::new(false),
+        });
+
+        // The thread only holds on to the client while handling an event, so dropping the
+        // bridge closes the requests, which ends the connection.
+        let connection_shared = Arc::downgrade(&shared);
+        let connection_speed_editor = speed_editor.clone();
+        let connection =
+            thread::Builder::new().name("bmd_speed_editor_mqtt".to_string()).spawn(move || {
+                keep_connected(&connection_shared, &connection_speed_editor, connection)
+            })?;
+
+        speed_editor.attach_sink(Box::new(MqttSink { shared: Arc::downgrade(&shared) }));
+        Ok(Self { shared, connection: Some(connection) })
+    }
+
+    /// Returns `true` if the bridge is connected to the broker.
+    pub fn is_connected(&self) -> bool {
+        self.shared.connected.load(Ordering::Acquire)
+    }
+}
+
+impl Drop for MqttBridge {
+    fn drop(&mut self) {
+        self.shared.stop.store(true, Ordering::Release);
+        if self.is_connected() {
+            self.shared.publish(&self.shared.topics.state, true, "offline");
+            let _ = self.shared.client.try_disconnect();
+        }
+        let Some(connection) = self.connection.take() else { return };
+
+        // The requests are closed once the shared state is dropped. Without a broker, the
+        // thread might be connecting, so it is not waited for longer than it takes to disconnect.
+        let start = Instant::now();
+        while !connection.is_finished() {
+            if start.elapsed() >= DISCONNECT_TIMEOUT {
+                return;
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+        let _ = connection.join();
+    }
+}
+
+/// Publishes the events of the [`SpeedEditor`], while the [`MqttBridge`] exists.
+struct MqttSink {
+    shared: Weak<MqttShared>,
+}
+
+impl EventSink for MqttSink {
+    fn send(&self, event: Event) -> Result<(), SinkClosed> {
+        let shared = self.shared.upgrade().ok_or(SinkClosed)?;
+        if !shared.connected.load(Ordering::Acquire) {
+            return Ok(());
+        }
+
+        let topics = &shared.topics;
+        match event {
+            Event::ButtonChange { button, pressed } => {
+                let topic = topics.button.replace("{name}", &format!("{button:?}"));
+                shared.publish(&topic, false, if pressed { "1" } else { "0" });
+            }
+            Event::WheelChange { velocity } => {
+                shared.publish(&topics.wheel, false, velocity.to_string());
+            }
+            Event::BatteryInfo { charging, percentage } => {
+                shared.publish_battery(percentage, charging);
+            }
+            Event::Connected(_) => shared.publish(&topics.state, true, "online"),
+            Event::Disconnected => shared.publish(&topics.state, true, "disconnected"),
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+/// Drives the connection to the broker until the [`MqttBridge`] is dropped,
+/// reconnecting when the connection is lost.
+fn keep_connected(
+    weak_shared: &Weak<MqttShared>,
+    speed_editor: &SpeedEditor,
+    mut connection: Connection,
+) {
+    let mut delay = MIN_RECONNECT_DELAY;
+    // Ends once the requests are closed, which happens when the bridge is dropped.
+    for event in connection.iter() {
+        let Some(shared) = weak_shared.upgrade() else { return };
+        match event {
+            Ok(rumqttc::Event::Incoming(Incoming::ConnAck(_))) => {
+                delay = MIN_RECONNECT_DELAY;
+                let _ = shared.client.try_subscribe(format!("{}/#", shared.topics.led), shared.qos);
+                let state = if speed_editor.is_connected() { "online" } else { "disconnected" };
+                shared.publish(&shared.topics.state, true, state);
+                if let Some(battery) = speed_editor.battery_info() {
+                    shared.publish_battery(battery.level, battery.charging);
+                }
+                shared.connected.store(true, Ordering::Release);
+            }
+            Ok(rumqttc::Event::Incoming(Incoming::Publish(publish))) => {
+                show_led(speed_editor, &shared.topics.led, &publish.topic, &publish.payload);
+            }
+            Ok(rumqttc::Event::Outgoing(Outgoing::Disconnect)) => return,
+            Ok(_) => {}
+            Err(_) => {
+                shared.connected.store(false, Ordering::Release);
+                if shared.stop.load(Ordering::Acquire) {
+                    return;
+                }
+                // Iterating again reconnects, so wait before it does, without keeping the
+                // requests open.
+                drop(shared);
+                let start = Instant::now();
+                while start.elapsed() < delay {
+                    if shared_stopped(weak_shared) {
+                        return;
+                    }
+                    thread::sleep(Duration::from_millis(10));
+                }
+                delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+            }
+        }
+    }
+}
+
+fn shared_stopped(shared: &Weak<MqttShared>) -> bool {
+    shared.upgrade().is_none_or(|shared| shared.stop.load(Ordering::Acquire))
+}
+
+/// Lights up or turns off the LED that is named by the last level of a received topic.
+fn show_led(speed_editor: &SpeedEditor, led_topic: &str, topic: &str, payload: &[u8]) {
+    let Some(name) = topic.strip_prefix(led_topic).and_then(|name| name.strip_prefix('/')) else {
+        return;
+    };
+    let Some(led) = led_by_name(name) else { return };
+    let payload = String::from_utf8_lossy(payload);
+    let on = ["1", "on", "true"].iter().any(|value| payload.trim().eq_ignore_ascii_case(value));
+    match led {
+        Led::Button(led) if on => speed_editor.set_button_led(led),
+        Led::Button(led) if speed_editor.button_led() == led => {
+            speed_editor.set_button_led(ButtonLed::Off);
+        }
+        Led::Wheel(led) if on => speed_editor.set_wheel_led(led),
+        Led::Wheel(led) if speed_editor.wheel_led() == led => {
+            speed_editor.set_wheel_led(WheelLed::Off);
+        }
+        _ => {}
+    }
+}
+
+fn led_by_name(name: &str) -> Option<Led> {
+    let button_leds = (0..u32::BITS).filter_map(|bit| ButtonLed::try_from(1 << bit).ok());
+    let wheel_leds = (0..u32::BITS).filter_map(|bit| WheelLed::try_from(1 << bit).ok());
+    button_leds.map(Led::Button).chain(wheel_leds.map(Led::Wheel)).find(|led| match led {
+        Led::Button(led) => format!("{led:?}") == name,
+        Led::Wheel(led) => format!("{led:?}") == name,
+    })
+}
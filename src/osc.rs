@@ -0,0 +1,288 @@
+//! Sending the events of the Speed Editor as OSC messages over UDP.
+//!
+//! An [`OscBridge`] is an [`EventSink`], so it is attached next to the callbacks
+//! and other sinks instead of replacing them:
+//!
+//! ```no_run
+//! use bmdse::SpeedEditor;
+//! use bmdse::osc::OscBridge;
+//!
+//! let speed_editor = SpeedEditor::new().unwrap();
+//! let osc = OscBridge::new("127.0.0.1:9000".parse().unwrap(), "/bmdse").unwrap();
+//! speed_editor.attach_sink(Box::new(osc));
+//!
+//! std::thread::park();
+//! ```
+//!
+//! By default, it sends these messages, with all arguments as 32 bit integers:
+//!
+//! | Event | Address | Arguments |
+//! |-------|---------|-----------|
+//! | A button is pressed or released | `<prefix>/button/<name>` | `1` or `0` |
+//! | The jog wheel turns | `<prefix>/wheel` | The change |
+//! | Battery information | `<prefix>/battery` | The level (`0..=100`), and `1` or `0` for charging |
+
+use std::{
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket},
+    sync::{Arc, Condvar, Mutex},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use crate::{Event, EventSink, SinkClosed, sync::MutexExt};
+
+/// A message that is waiting to be sent.
+struct Message {
+    address: String,
+    arguments: Vec<i32>,
+}
+
+impl Message {
+    fn encode(&self, buffer: &mut Vec<u8>) {
+        push_string(buffer, &self.address);
+        let mut type_tags = String::from(",");
+        type_tags.extend(self.arguments.iter().map(|_| 'i'));
+        push_string(buffer, &type_tags);
+        for argument in &self.arguments {
+            buffer.extend_from_slice(&argument.to_be_bytes());
+        }
+    }
+}
+
+/// Pushes an OSC string: null terminated, and padded with nulls to a multiple of 4 bytes.
+fn push_string(buffer: &mut Vec<u8>, string: &str) {
+    buffer.extend_from_slice(string.as_bytes());
+    buffer.resize((buffer.len() / 4 + 1) * 4, 0);
+}
+
+/// Encodes the messages as a bundle that is handled immediately.
+fn encode_bundle(messages: &[Message]) -> Vec<u8> {
+    let mut buffer = Vec::new();
+    push_string(&mut buffer, "#bundle");
+    buffer.extend_from_slice(&1u64.to_be_bytes());
+    for message in messages {
+        let mut element = Vec::new();
+        message.encode(&mut element);
+        buffer.extend_from_slice(&(element.len() as u32).to_be_bytes());
+        buffer.extend_from_slice(&element);
+    }
+    buffer
+}
+
+/// The settings of an [`OscBridge`].
+struct Config {
+    button_address: String,
+    wheel_address: String,
+    battery_address: String,
+    wheel_interval: Option<Duration>,
+    bundle: bool,
+}
+
+#[derive(Default)]
+struct Pending {
+    messages: Vec<Message>,
+    /// The sum of the wheel changes that were not sent yet, when the wheel is rate limited.
+    wheel: i64,
+    stop: bool,
+}
+
+struct OscShared {
+    config: Mutex<Config>,
+    pending: Mutex<Pending>,
+    changed: Condvar,
+}
+
+/// An [`EventSink`] that sends the events as OSC messages over UDP.
+///
+/// See the [module documentation][self]. The messages are sent from a separate thread,
+/// so a slow network never holds up the events. It stops when the bridge is dropped,
+/// e.g. when the [`SpeedEditor`][crate::SpeedEditor] it is attached to is dropped.
+/// Failed sends are ignored.
+///
+/// # Example
+///
+/// ```
+/// use std::net::UdpSocket;
+///
+/// use bmdse::osc::OscBridge;
+/// use bmdse::{Button, Event, EventSink};
+///
+/// let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+/// let osc = OscBridge::new(receiver.local_addr().unwrap(), "/bmdse").unwrap();
+///
+/// osc.send(Event::ButtonChange { button: Button::Cut, pressed: true }).unwrap();
+///
+/// let mut buffer = [0; 64];
+/// let len = receiver.recv(&mut buffer).unwrap();
+/// assert_eq!(&buffer[..len], b"/bmdse/button/Cut\0\0\0,i\0\0\0\0\0\x01");
+/// ```
+pub struct OscBridge {
+    shared: Arc<OscShared>,
+    sender: Option<JoinHandle<()>>,
+}
+
+impl OscBridge {
+    /// Creates an [`OscBridge`] that sends to `target`,
+    /// with addresses that start with `address_prefix` (e.g. `/bmdse`).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the UDP socket could not be created,
+    /// or the sending thread could not be spawned.
+    pub fn new(target: SocketAddr, address_prefix: &str) -> Result<Self, crate::Error> {
+        let local: SocketAddr = match target {
+            SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+            SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+        };
+        let socket = UdpSocket::bind(local)?;
+        socket.connect(target)?;
+
+        let prefix = address_prefix.trim_end_matches('/');
+        let config = Config {
+            button_address: format!("{prefix}/button/{{name}}"),
+            wheel_address: format!("{prefix}/wheel"),
+            battery_address: format!("{prefix}/battery"),
+            wheel_interval: None,
+            bundle: false,
+        };
+        let shared = Arc::new(OscShared {
+            config: Mutex::new(config),
+            pending: Mutex::default(),
+            changed: Condvar::new(),
+        });
+
+        let sender_shared = Arc::clone(&shared);
+        let sender = thread::Builder::new()
+            .name("bmd_speed_editor_osc".to_string())
+            .spawn(move || send_pending(&sender_shared, &socket))?;
+        Ok(Self { shared, sender: Some(sender) })
+    }
+
+    /// Sets the address of button messages, in which `{name}` is replaced
+    /// by the name of the button (e.g. `StopPlay`).
+    pub fn button_address(self, template: impl Into<String>) -> Self {
+        self.shared.config.lock_unpoisoned().button_address = template.into();
+        self
+    }
+
+    /// Sets the address of jog wheel messages.
+    pub fn wheel_address(self, address: impl Into<String>) -> Self {
+        self.shared.config.lock_unpoisoned().wheel_address = address.into();
+        self
+    }
+
+    /// Sets the address of battery messages.
+    pub fn battery_address(self, address: impl Into<String>) -> Self {
+        self.shared.config.lock_unpoisoned().battery_address = address.into();
+        self
+    }
+
+    /// Sends at most one jog wheel message per `interval`, with the sum of the changes
+    /// since the last one, instead of one message for every change.
+    pub fn wheel_interval(self, interval: Duration) -> Self {
+        self.shared.config.lock_unpoisoned().wheel_interval = Some(interval);
+        self
+    }
+
+    /// Sends the messages that are waiting at the same time as a single bundle,
+    /// e.g. a button change together with the rate limited jog wheel change.
+    pub fn bundle(self, bundle: bool) -> Self {
+        self.shared.config.lock_unpoisoned().bundle = bundle;
+        self
+    }
+}
+
+impl EventSink for OscBridge {
+    fn send(&self, event: Event) -> Result<(), SinkClosed> {
+        let config = self.shared.config.lock_unpoisoned();
+        let message = match event {
+            Event::ButtonChange { button, pressed } => Message {
+                address: config.button_address.replace("{name}", &format!("{button:?}")),
+                arguments: vec![pressed as i32],
+            },
+            Event::WheelChange { velocity } if config.wheel_interval.is_some() => {
+                // The sending thread locks the config while holding the pending messages.
+                drop(config);
+                self.shared.pending.lock_unpoisoned().wheel += velocity as i64;
+                self.shared.changed.notify_all();
+                return Ok(());
+            }
+            Event::WheelChange { velocity } => {
+                Message { address: config.wheel_address.clone(), arguments: vec![velocity] }
+            }
+            Event::BatteryInfo { charging, percentage } => Message {
+                address: config.battery_address.clone(),
+                arguments: vec![percentage as i32, charging as i32],
+            },
+            _ => return Ok(()),
+        };
+        drop(config);
+
+        self.shared.pending.lock_unpoisoned().messages.push(message);
+        self.shared.changed.notify_all();
+        Ok(())
+    }
+}
+
+impl Drop for OscBridge {
+    fn drop(&mut self) {
+        self.shared.pending.lock_unpoisoned().stop = true;
+        self.shared.changed.notify_all();
+        if let Some(sender) = self.sender.take() {
+            let _ = sender.join();
+        }
+    }
+}
+
+/// Sends the pending messages until the [`OscBridge`] is dropped.
+fn send_pending(shared: &OscShared, socket: &UdpSocket) {
+    let mut next_wheel = Instant::now();
+    loop {
+        let messages = {
+            let mut pending = shared.pending.lock_unpoisoned();
+            loop {
+                if pending.stop {
+                    return;
+                }
+                let now = Instant::now();
+                if !pending.messages.is_empty() || (pending.wheel != 0 && now >= next_wheel) {
+                    break;
+                }
+                pending = if pending.wheel != 0 {
+                    shared
+                        .changed
+                        .wait_timeout(pending, next_wheel - now)
+                        .map(|(pending, _)| pending)
+                        .unwrap_or_else(|e| e.into_inner().0)
+                } else {
+                    shared.changed.wait(pending).unwrap_or_else(|e| e.into_inner())
+                };
+            }
+
+            let mut messages = std::mem::take(&mut pending.messages);
+            if pending.wheel != 0 && Instant::now() >= next_wheel {
+                let config = shared.config.lock_unpoisoned();
+                let change = pending.wheel.clamp(i32::MIN as i64, i32::MAX as i64) as i32;
+                messages.push(Message {
+                    address: config.wheel_address.clone(),
+                    arguments: vec![change],
+                });
+                pending.wheel = 0;
+                next_wheel = Instant::now() + config.wheel_interval.unwrap_or_default();
+            }
+            messages
+        };
+
+        // There is nobody to report a failed send to, and the next one might work again.
+        if shared.config.lock_unpoisoned().bundle && messages.len() > 1 {
+            let _ = socket.send(&encode_bundle(&messages));
+        } else {
+            let mut buffer = Vec::new();
+            for message in &messages {
+                buffer.clear();
+                message.encode(&mut buffer);
+                let _ = socket.send(&buffer);
+            }
+        }
+    }
+}
@@ -0,0 +1,217 @@
+use std::{sync::atomic::Ordering, time::Instant};
+
+use crate::sync::MutexExt;
+use crate::{ButtonLed, HidBackend, SpeedEditor, WheelLed, WheelMode};
+
+/// The state of a [`SpeedEditor`] that is kept while its device is used directly.
+///
+/// See [`SpeedEditor::into_parts`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub struct SpeedEditorState {
+    /// The button LED that was set.
+    pub button_led: ButtonLed,
+    /// The wheel LED that was set.
+    pub wheel_led: WheelLed,
+    /// The wheel mode that was set.
+    pub wheel_mode: WheelMode,
+    /// When the device should be authenticated again.
+    pub next_auth: Instant,
+    /// When the authentication of the device expires.
+    pub auth_expiry: Instant,
+}
+
+impl SpeedEditor {
+    /// Stops the polling thread and hands back the device, with the state needed to
+    /// continue with [`SpeedEditor::from_parts`] later.
+    ///
+    /// This is meant to talk to the device directly for a while (e.g. to try out a feature report),
+    /// without authenticating it again afterwards. The device stays authenticated until
+    /// [`SpeedEditorState::auth_expiry`], and is not touched by the [`ShutdownPolicy`][crate::ShutdownPolicy].
+    /// Like [`SpeedEditor::shutdown`], this stops the device for all clones of this [`SpeedEditor`].
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use bmdse::SpeedEditor;
+    /// use bmdse::protocol::{self, WheelMode};
+    ///
+    /// let speed_editor = SpeedEditor::new().unwrap();
+    /// let (device, state) = speed_editor.into_parts().unwrap();
+    ///
+    /// device.write(&protocol::wheel_mode_report(WheelMode::AbsoluteDeadZero)).unwrap();
+    ///
+    /// let speed_editor = SpeedEditor::from_parts(device, state).unwrap();
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::HidDeviceNotFound`][crate::Error::HidDeviceNotFound] if the device was not
+    /// connected (or [paused][SpeedEditor::pause] with the device released), the error that made
+    /// the polling thread stop if it stopped on its own, or an error if called from a callback.
+    pub fn into_parts(self) -> Result<(Box<dyn HidBackend>, SpeedEditorState), crate::Error> {
+        // The polling thread cannot wait for itself to stop.
+        if self.poller.is_polling_thread() {
+            return Err(crate::Error::driver("cannot take the device from one of its callbacks"));
+        }
+
+        let shared = &self.poller.shared;
+        shared.detach.store(true, Ordering::Release);
+        self.poller.shutdown()?;
+
+        let Some((device, auth)) = shared.detached.lock_unpoisoned().take() else {
+            return Err(crate::Error::HidDeviceNotFound);
+        };
+        let inner = self.inner.lock_unpoisoned();
+        let state = SpeedEditorState {
+            button_led: inner.button_led,
+            wheel_led: inner.wheel_led,
+            wheel_mode: inner.wheel_mode,
+            next_auth: auth.next_auth,
+            auth_expiry: auth.expiry,
+        };
+        Ok((device, state))
+    }
+
+    /// Creates a [`SpeedEditor`] for a device handed back by [`SpeedEditor::into_parts`],
+    /// restoring its LEDs and wheel mode.
+    ///
+    /// The device is only authenticated again if its authentication expired in the meantime.
+    /// Callbacks are not carried over, use
+    /// [`SpeedEditorBuilder::connect_parts`][crate::SpeedEditorBuilder::connect_parts]
+    /// to register them again.
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as
+    /// [`SpeedEditorBuilder::connect_parts`][crate::SpeedEditorBuilder::connect_parts].
+    pub fn from_parts(
+        device: Box<dyn HidBackend>,
+        state: SpeedEditorState,
+    ) -> Result<Self, crate::Error> {
+        Self::builder().connect_parts(device, state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{Arc, OnceLock, mpsc},
+        time::Duration,
+    };
+
+    use super::*;
+    use crate::test_util::{TIMEOUT, WHEEL_REPORT, connect, wait_for_write};
+    use crate::testing::{FakeBackend, Fault};
+
+    #[test]
+    fn into_parts_stops_polling() {
+        let (speed_editor, backend) = connect();
+        speed_editor.set_button_led(ButtonLed::Cut);
+        wait_for_write(&backend, &crate::protocol::button_led_report(ButtonLed::Cut));
+        let written = backend.written().len();
+
+        let (device, state) = speed_editor.into_parts().unwrap();
+        assert_eq!((state.button_led, state.wheel_led), (ButtonLed::Cut, WheelLed::Off));
+        assert!(state.next_auth < state.auth_expiry);
+        assert!(state.auth_expiry > Instant::now() + Duration::from_secs(590));
+
+        // Nothing reads the device anymore, and the LEDs were not turned off.
+        backend.push_input_report(&WHEEL_REPORT);
+        assert!(!backend.wait_until_idle(Duration::from_millis(100)));
+        assert_eq!(backend.written().len(), written);
+
+        // The device can be used directly.
+        let mut buf = [0x00; 64];
+        assert_eq!(device.read_timeout(&mut buf, 0).unwrap(), WHEEL_REPORT.len());
+        device.write(&[0x05, 0x01]).unwrap();
+        assert_eq!(backend.written().last().unwrap(), &[0x05, 0x01]);
+    }
+
+    #[test]
+    fn from_parts_resumes_without_authenticating() {
+        let (speed_editor, backend) = connect();
+        speed_editor.set_button_led(ButtonLed::Cut);
+        wait_for_write(&backend, &crate::protocol::button_led_report(ButtonLed::Cut));
+        let (device, state) = speed_editor.into_parts().unwrap();
+        let (sent, written) = (backend.sent_feature_reports().len(), backend.written().len());
+
+        let (sender, receiver) = mpsc::channel();
+        let speed_editor = SpeedEditor::builder()
+            .on_wheel_change(move |velocity| sender.send(velocity).unwrap())
+            .connect_parts(device, state)
+            .unwrap();
+        assert_eq!(speed_editor.button_led(), ButtonLed::Cut);
+
+        // The wheel mode and the LEDs are restored, in case they were changed in the meantime.
+        wait_for_write(&backend, &crate::protocol::wheel_led_report(WheelLed::Off));
+        assert_eq!(
+            backend.written()[written..],
+            [
+                crate::protocol::wheel_mode_report(WheelMode::Relative).to_vec(),
+                crate::protocol::button_led_report(ButtonLed::Cut).to_vec(),
+                crate::protocol::wheel_led_report(WheelLed::Off).to_vec(),
+            ]
+        );
+
+        backend.push_input_report(&WHEEL_REPORT);
+        assert_eq!(receiver.recv_timeout(TIMEOUT), Ok(5));
+        assert!(speed_editor.is_connected());
+        assert_eq!(backend.sent_feature_reports().len(), sent);
+    }
+
+    #[test]
+    fn from_parts_authenticates_after_expiry() {
+        let (speed_editor, backend) = connect();
+        let (device, state) = speed_editor.into_parts().unwrap();
+        let sent = backend.sent_feature_reports().len();
+
+        let now = Instant::now();
+        let state = SpeedEditorState { next_auth: now, auth_expiry: now, ..state };
+        backend.push_auth_handshake();
+        let speed_editor = SpeedEditor::from_parts(device, state).unwrap();
+        assert!(backend.wait_until_idle(TIMEOUT));
+        assert!(speed_editor.is_connected());
+        assert_eq!(backend.sent_feature_reports().len(), sent + 3);
+    }
+
+    #[test]
+    fn into_parts_returns_error_that_stopped_polling() {
+        let backend = FakeBackend::new();
+        backend.push_auth_handshake();
+        let (sender, receiver) = mpsc::channel();
+        let speed_editor = SpeedEditor::builder()
+            .on_disconnect(move || sender.send(()).unwrap())
+            .connect_backend(backend.clone())
+            .unwrap();
+
+        backend.fail_reads(1, Fault::Disconnected);
+        assert_eq!(receiver.recv_timeout(TIMEOUT), Ok(()));
+        // The polling thread stopped on its own, without a device to hand back.
+        let result = speed_editor.into_parts();
+        assert!(matches!(result, Err(crate::Error::Disconnected { .. })));
+    }
+
+    #[test]
+    fn into_parts_fails_from_callback() {
+        let backend = FakeBackend::new();
+        backend.push_auth_handshake();
+        let this = Arc::new(OnceLock::<SpeedEditor>::new());
+        let (sender, receiver) = mpsc::channel();
+        let speed_editor = SpeedEditor::builder()
+            .on_wheel_change({
+                let this = Arc::clone(&this);
+                move |_| {
+                    let result = this.get().unwrap().clone().into_parts();
+                    sender.send(result.is_err()).unwrap();
+                }
+            })
+            .connect_backend(backend.clone())
+            .unwrap();
+        assert!(this.set(speed_editor.clone()).is_ok());
+
+        backend.push_input_report(&WHEEL_REPORT);
+        assert_eq!(receiver.recv_timeout(TIMEOUT), Ok(true));
+        assert!(speed_editor.is_connected());
+    }
+}
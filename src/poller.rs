@@ -0,0 +1,935 @@
+use std::{
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        Arc, Condvar, Mutex, PoisonError,
+        atomic::{AtomicBool, AtomicU8, Ordering},
+        mpsc,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant, SystemTime},
+};
+
+use crate::capture::Capture;
+use crate::counters::AtomicCounters;
+use crate::diagnostics;
+use crate::dispatch::{Call, InnerLock};
+use crate::driver::{self, ButtonSet, HidApiSource, OpenOptions, Report, ReportMask, WheelMode};
+use crate::health::HealthCounters;
+use crate::metrics::SessionMetricsTracker;
+use crate::registry::OpenDevice;
+use crate::session::{ResumedAuth, Session};
+use crate::sync::MutexExt;
+use crate::thread::ThreadOptions;
+use crate::{
+    BatteryInfo, BatteryLevelPolicy, Button, DeviceInfo, DisconnectReason, Event, HidBackend,
+    Inner, Model, ReconnectPolicy, RestartPolicy,
+};
+
+/// State shared with the polling thread that does not need to be behind the [`Inner`] lock.
+pub(crate) struct Shared {
+    pub(crate) shutdown: AtomicBool,
+    pub(crate) connected: AtomicBool,
+    pub(crate) paused: AtomicBool,
+    /// Whether the device handle should be released while paused.
+    pub(crate) release_while_paused: AtomicBool,
+    /// Whether the battery report should be requested from the device.
+    pub(crate) battery_requested: AtomicBool,
+    /// The bits of the [`ReportMask`] of the reports that are handled.
+    pub(crate) report_mask: AtomicU8,
+    pub(crate) health: HealthCounters,
+    pub(crate) counters: AtomicCounters,
+    pub(crate) session_metrics: Mutex<SessionMetricsTracker>,
+    /// The capture of the traffic with the device, if one is running.
+    pub(crate) capture: Arc<Capture>,
+    /// Requests for raw access to the device, handled between two reads.
+    #[cfg(feature = "unstable-raw")]
+    pub(crate) raw_requests: Mutex<Vec<crate::raw::RawRequest>>,
+    /// Reports injected to be handled as if they were read from the device.
+    #[cfg(any(test, feature = "testing"))]
+    pub(crate) injected_reports: Mutex<Vec<Vec<u8>>>,
+    /// Callers waiting for the device to be [reset][crate::SpeedEditor::reset_device].
+    pub(crate) reset_requests: Mutex<Vec<mpsc::Sender<Result<(), crate::Error>>>>,
+    /// Whether the device should be handed back instead of released on shutdown.
+    pub(crate) detach: AtomicBool,
+    /// The device that was handed back, see [`SpeedEditor::into_parts`][crate::SpeedEditor::into_parts].
+    pub(crate) detached: Mutex<Option<(Box<dyn HidBackend>, ResumedAuth)>>,
+    options: PollOptions,
+    /// Whether the polling thread has stopped, signalled through `finished_changed`.
+    finished: Mutex<bool>,
+    finished_changed: Condvar,
+}
+
+impl Shared {
+    pub(crate) fn new(options: PollOptions) -> Self {
+        Self {
+            shutdown: AtomicBool::new(false),
+            connected: AtomicBool::new(false),
+            paused: AtomicBool::new(false),
+            release_while_paused: AtomicBool::new(false),
+            battery_requested: AtomicBool::new(false),
+            report_mask: AtomicU8::new(ReportMask::ALL.bits()),
+            health: HealthCounters::new(),
+            counters: AtomicCounters::default(),
+            session_metrics: Mutex::default(),
+            capture: Arc::default(),
+            #[cfg(feature = "unstable-raw")]
+            raw_requests: Mutex::new(Vec::new()),
+            #[cfg(any(test, feature = "testing"))]
+            injected_reports: Mutex::new(Vec::new()),
+            reset_requests: Mutex::new(Vec::new()),
+            detach: AtomicBool::new(false),
+            detached: Mutex::new(None),
+            options,
+            finished: Mutex::new(false),
+            finished_changed: Condvar::new(),
+        }
+    }
+}
+
+/// Marks the polling thread as finished when dropped, even if it panicked.
+struct FinishedGuard<'a>(&'a Shared);
+
+impl Drop for FinishedGuard<'_> {
+    fn drop(&mut self) {
+        *self.0.finished.lock().unwrap_or_else(|e| e.into_inner()) = true;
+        self.0.finished_changed.notify_all();
+    }
+}
+
+/// Options for the timing of the polling loop.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct PollOptions {
+    /// How long a single read waits for a report.
+    pub(crate) timeout: Duration,
+    /// How long a single read waits for a report while nothing is happening.
+    pub(crate) idle_timeout: Duration,
+    /// How long to sleep between iterations.
+    pub(crate) sleep: Option<Duration>,
+}
+
+impl PollOptions {
+    pub(crate) const MAX_TIMEOUT: Duration = Duration::from_secs(1);
+    pub(crate) const MAX_SLEEP: Duration = Duration::from_secs(1);
+
+    pub(crate) fn validate(&self) -> Result<(), crate::Error> {
+        if self.timeout.as_millis() == 0 {
+            return Err(crate::Error::InvalidConfiguration {
+                message: "poll timeout must be at least 1 millisecond",
+            });
+        }
+        if self.timeout > Self::MAX_TIMEOUT {
+            return Err(crate::Error::InvalidConfiguration {
+                message: "poll timeout must be at most 1 second",
+            });
+        }
+        if self.idle_timeout.as_millis() == 0 {
+            return Err(crate::Error::InvalidConfiguration {
+                message: "idle poll timeout must be at least 1 millisecond",
+            });
+        }
+        if self.idle_timeout > Self::MAX_TIMEOUT {
+            return Err(crate::Error::InvalidConfiguration {
+                message: "idle poll timeout must be at most 1 second",
+            });
+        }
+        if self.sleep.is_some_and(|sleep| sleep > Self::MAX_SLEEP) {
+            return Err(crate::Error::InvalidConfiguration {
+                message: "poll sleep must be at most 1 second",
+            });
+        }
+        Ok(())
+    }
+}
+
+impl Default for PollOptions {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_millis(16),
+            idle_timeout: Duration::from_millis(250),
+            sleep: None,
+        }
+    }
+}
+
+/// Owns the polling thread. Dropping it stops the thread.
+pub(crate) struct PollerHandle {
+    pub(crate) shared: Arc<Shared>,
+    thread: Mutex<Option<JoinHandle<Result<(), crate::Error>>>>,
+}
+
+impl PollerHandle {
+    /// Spawns the polling thread for the given device.
+    ///
+    /// If no device is given, the thread will open it according to the [`ReconnectPolicy`].
+    pub(crate) fn spawn(
+        hid_device: Option<Box<dyn HidBackend>>,
+        inner: Arc<InnerLock>,
+        options: ThreadOptions,
+        poll_options: PollOptions,
+    ) -> Result<Self, crate::Error> {
+        poll_options.validate()?;
+
+        let shared = Arc::new(Shared::new(poll_options));
+
+        let thread = thread::Builder::new().name(options.name.clone()).spawn({
+            let shared = Arc::clone(&shared);
+            move || {
+                let _finished = FinishedGuard(&shared);
+
+                // Setting the priority and affinity is best-effort.
+                if let Err(error) = options.apply_to_current() {
+                    inner.lock_unpoisoned().report_error(error, false);
+                }
+
+                supervise(hid_device, &inner, &shared)
+            }
+        })?;
+
+        Ok(Self { shared, thread: Mutex::new(Some(thread)) })
+    }
+
+    pub(crate) fn shutdown(&self) -> Result<(), crate::Error> {
+        self.shared.shutdown.store(true, Ordering::Release);
+
+        let Some(thread) = self.thread.lock_unpoisoned().take() else { return Ok(()) };
+
+        // If called from a callback we are on the polling thread itself,
+        // which will exit on its own after this iteration.
+        if thread.thread().id() == thread::current().id() {
+            return Ok(());
+        }
+
+        thread.join().map_err(|_| crate::Error::driver("polling thread panicked"))?
+    }
+
+    /// Returns `true` if the polling thread has stopped.
+    pub(crate) fn is_finished(&self) -> bool {
+        *self.shared.finished.lock_unpoisoned()
+    }
+
+    /// Returns `true` if called from the polling thread itself (e.g. from a callback).
+    pub(crate) fn is_polling_thread(&self) -> bool {
+        self.thread
+            .lock_unpoisoned()
+            .as_ref()
+            .is_some_and(|thread| thread.thread().id() == thread::current().id())
+    }
+
+    /// Blocks until the polling thread stops on its own or because of a shutdown.
+    pub(crate) fn join(&self) -> Result<(), crate::Error> {
+        if self.is_polling_thread() {
+            return Err(crate::Error::driver(
+                "cannot wait for the polling thread from one of its callbacks",
+            ));
+        }
+
+        // The thread handle lock is not held while waiting, so `shutdown` can still be called.
+        let mut finished = self.shared.finished.lock_unpoisoned();
+        while !*finished {
+            finished =
+                self.shared.finished_changed.wait(finished).unwrap_or_else(PoisonError::into_inner);
+        }
+        drop(finished);
+
+        // If the handle is already taken, `shutdown` has been called and returned the result.
+        let Some(thread) = self.thread.lock_unpoisoned().take() else { return Ok(()) };
+        thread.join().map_err(|_| crate::Error::driver("polling thread panicked"))?
+    }
+}
+
+impl Drop for PollerHandle {
+    fn drop(&mut self) {
+        const JOIN_TIMEOUT: Duration = Duration::from_secs(1);
+
+        self.shared.shutdown.store(true, Ordering::Release);
+
+        let Some(thread) = self.thread.get_mut().unwrap_or_else(PoisonError::into_inner).take()
+        else {
+            return;
+        };
+
+        // If a callback owned the last handle, we are dropped on the polling thread itself,
+        // which will exit on its own after this iteration.
+        if thread.thread().id() == thread::current().id() {
+            return;
+        }
+
+        let start = Instant::now();
+        while !thread.is_finished() {
+            if start.elapsed() >= JOIN_TIMEOUT {
+                // The thread will still exit after its current iteration, we just don't wait for it.
+                return;
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        let _ = thread.join();
+    }
+}
+
+/// Runs the poller until shutdown, restarting it according to the [`RestartPolicy`]
+/// when it stops because of an error or a panic.
+fn supervise(
+    mut hid_device: Option<Box<dyn HidBackend>>,
+    inner: &InnerLock,
+    shared: &Shared,
+) -> Result<(), crate::Error> {
+    let mut restarts = 0;
+    loop {
+        let result =
+            panic::catch_unwind(AssertUnwindSafe(|| run(hid_device.take(), inner, shared)));
+
+        let error = match result {
+            Ok(Ok(())) => return Ok(()),
+            Ok(Err(error)) => error,
+            Err(_) => {
+                // A panic poisons the lock if it happened while holding it.
+                inner.clear_poison();
+                // Cleaning up calls the callbacks again, which might panic again.
+                let _ = panic::catch_unwind(AssertUnwindSafe(|| {
+                    set_disconnected(inner, shared, DisconnectReason::Panic);
+                    release_all_buttons(inner);
+                }));
+                crate::Error::driver("polling thread panicked")
+            }
+        };
+
+        let restart_delay = match inner.lock_unpoisoned().restart_policy {
+            RestartPolicy::After { delay, max_restarts } if restarts < max_restarts => Some(delay),
+            _ => None,
+        };
+
+        let Some(delay) = restart_delay else {
+            inner.lock_unpoisoned().report_error(error.clone(), true);
+            return Err(error);
+        };
+
+        inner.lock_unpoisoned().report_error(error, false);
+        restarts += 1;
+        shared.health.record_restart();
+
+        if !sleep_unless_shutdown(shared, delay) {
+            return Ok(());
+        }
+    }
+}
+
+/// Runs sessions with the device until shutdown,
+/// reconnecting according to the [`ReconnectPolicy`] when a session fails.
+///
+/// Errors that stop the poller are returned without reporting them.
+fn run(
+    mut hid_device: Option<Box<dyn HidBackend>>,
+    inner: &InnerLock,
+    shared: &Shared,
+) -> Result<(), crate::Error> {
+    loop {
+        let device = match hid_device.take() {
+            Some(device) => device,
+            None => match open(inner, shared) {
+                Ok(Some(device)) => Box::new(device),
+                Ok(None) => return Ok(()),
+                Err(error) => return Err(error),
+            },
+        };
+
+        let result = session(device, inner, shared);
+
+        let reason = match &result {
+            Ok(SessionEnd::Shutdown | SessionEnd::Detached(_)) => DisconnectReason::Shutdown,
+            Ok(SessionEnd::Paused) => DisconnectReason::Paused,
+            Ok(SessionEnd::Suspended) => DisconnectReason::Suspended,
+            Err(error) => DisconnectReason::from(error),
+        };
+        set_disconnected(inner, shared, reason);
+        release_all_buttons(inner);
+
+        match result {
+            Ok(SessionEnd::Shutdown) => return Ok(()),
+            Ok(SessionEnd::Detached(session)) => {
+                *shared.detached.lock_unpoisoned() = Some(session.detach());
+                return Ok(());
+            }
+            // The device is reopened right away, which authenticates it and restores the LEDs.
+            Ok(SessionEnd::Suspended) => {}
+            Ok(SessionEnd::Paused) => {
+                if !wait_until_resumed(shared) {
+                    return Ok(());
+                }
+            }
+            Err(error) => {
+                let mut inner_guard = inner.lock_unpoisoned();
+                if inner_guard.reconnect_policy == ReconnectPolicy::Never {
+                    return Err(error);
+                }
+                inner_guard.report_error(error, false);
+            }
+        }
+    }
+}
+
+/// Opens the device according to the [`ReconnectPolicy`].
+///
+/// Returns [`None`] if a shutdown was requested while waiting.
+fn open(inner: &InnerLock, shared: &Shared) -> Result<Option<OpenDevice>, crate::Error> {
+    let (reconnect_policy, source, options) = {
+        let inner_guard = inner.lock_unpoisoned();
+        (
+            inner_guard.reconnect_policy,
+            inner_guard.hid_api.clone(),
+            inner_guard.open_options.clone(),
+        )
+    };
+
+    match reconnect_policy {
+        ReconnectPolicy::Never => driver::get_hid_device(&source, &options).map(Some),
+        ReconnectPolicy::Backoff { initial_delay, max_delay } => {
+            Ok(reconnect(inner, shared, &source, &options, initial_delay, max_delay))
+        }
+    }
+}
+
+/// Tries to open the device until it succeeds, doubling the delay between attempts.
+///
+/// Returns [`None`] if a shutdown was requested while waiting.
+fn reconnect(
+    inner: &InnerLock,
+    shared: &Shared,
+    source: &HidApiSource,
+    options: &OpenOptions,
+    initial_delay: Duration,
+    max_delay: Duration,
+) -> Option<OpenDevice> {
+    let mut delay = initial_delay;
+    loop {
+        match driver::get_hid_device(source, options) {
+            Ok(hid_device) => return Some(hid_device),
+            Err(error) => inner.lock_unpoisoned().report_error(error, false),
+        }
+
+        if !sleep_unless_shutdown(shared, delay) {
+            return None;
+        }
+
+        delay = (delay * 2).min(max_delay);
+    }
+}
+
+/// Blocks while the poller is paused.
+///
+/// Returns `false` if a shutdown was requested.
+fn wait_until_resumed(shared: &Shared) -> bool {
+    const SLICE: Duration = Duration::from_millis(10);
+
+    while shared.paused.load(Ordering::Acquire) {
+        if shared.shutdown.load(Ordering::Acquire) {
+            return false;
+        }
+        thread::sleep(SLICE);
+    }
+    !shared.shutdown.load(Ordering::Acquire)
+}
+
+/// Sleeps for the given duration, waking up early if a shutdown was requested.
+///
+/// Returns `false` if a shutdown was requested.
+pub(crate) fn sleep_unless_shutdown(shared: &Shared, duration: Duration) -> bool {
+    const SLICE: Duration = Duration::from_millis(10);
+
+    let start = Instant::now();
+    while !shared.shutdown.load(Ordering::Acquire) {
+        let remaining = duration.saturating_sub(start.elapsed());
+        if remaining.is_zero() {
+            return true;
+        }
+        thread::sleep(remaining.min(SLICE));
+    }
+    false
+}
+
+/// Why a [`session`] ended without an error.
+enum SessionEnd {
+    /// A shutdown was requested.
+    Shutdown,
+    /// A shutdown was requested, and the device should be handed back.
+    Detached(Box<Session>),
+    /// The poller was paused, and the device should be released.
+    Paused,
+    /// The system was (probably) suspended, so the device should be reopened.
+    Suspended,
+}
+
+/// Detects that the system was suspended, by looking for long gaps between loop iterations.
+///
+/// Both clocks are checked, as on some platforms the monotonic clock stops while suspended.
+struct SuspendDetector {
+    instant: Instant,
+    wall: SystemTime,
+}
+
+impl SuspendDetector {
+    /// Gaps longer than this are much longer than a single iteration can take.
+    const MAX_GAP: Duration = Duration::from_secs(10);
+
+    fn new() -> Self {
+        Self { instant: Instant::now(), wall: SystemTime::now() }
+    }
+
+    /// Returns `true` if the system was suspended since the last call.
+    fn was_suspended(&mut self) -> bool {
+        let last = std::mem::replace(self, Self::new());
+        let instant_gap = self.instant.duration_since(last.instant);
+        // A wall clock that went backwards is not a suspend.
+        let wall_gap = self.wall.duration_since(last.wall).unwrap_or_default();
+        instant_gap.max(wall_gap) > Self::MAX_GAP
+    }
+}
+
+/// Authenticates the device and polls it until a shutdown is requested,
+/// the poller is paused with the device released, the system was suspended
+/// or the device stops responding.
+fn session(
+    device: Box<dyn HidBackend>,
+    inner: &InnerLock,
+    shared: &Shared,
+) -> Result<SessionEnd, crate::Error> {
+    /// How long after the last report or LED write the poller starts to idle.
+    const IDLE_AFTER: Duration = Duration::from_secs(1);
+
+    let mut session = Session::start(device, inner, shared)?;
+
+    // After a suspend the authentication has usually lapsed and the handle might be stale.
+    let mut suspend_detector = SuspendDetector::new();
+
+    while !shared.shutdown.load(Ordering::Acquire) {
+        if suspend_detector.was_suspended() {
+            return Ok(SessionEnd::Suspended);
+        }
+
+        if let Some(sleep) = shared.options.sleep
+            && !sleep_unless_shutdown(shared, sleep)
+        {
+            break;
+        }
+
+        if shared.paused.load(Ordering::Acquire) {
+            release_all_buttons(inner);
+
+            if shared.release_while_paused.load(Ordering::Acquire) {
+                return Ok(SessionEnd::Paused);
+            }
+
+            if !wait_until_resumed(shared) {
+                break;
+            }
+
+            session.resume(inner)?;
+
+            // Being paused for a long time is not a suspend.
+            suspend_detector = SuspendDetector::new();
+        }
+
+        let next_maintenance = session.maintain(inner, shared)?;
+
+        // While the device is in use, reads time out often so that LED changes, a pause or
+        // a shutdown are noticed quickly. Otherwise the thread sleeps in longer reads, until
+        // a report arrives or something is scheduled, which saves CPU. Reports are always
+        // returned as soon as they arrive.
+        let now = Instant::now();
+        let idle = !session.is_authenticating()
+            && now.duration_since(session.last_activity()) >= IDLE_AFTER;
+        let read_timeout = if idle {
+            shared.options.idle_timeout.max(shared.options.timeout)
+        } else {
+            shared.options.timeout
+        };
+        let read_timeout = read_timeout
+            .min(next_maintenance.saturating_duration_since(now))
+            .max(Duration::from_millis(1));
+
+        session.read(read_timeout, inner, shared)?;
+    }
+
+    if shared.detach.load(Ordering::Acquire) {
+        return Ok(SessionEnd::Detached(Box::new(session)));
+    }
+
+    session.finish(inner, shared)?;
+    Ok(SessionEnd::Shutdown)
+}
+
+/// Passes a report to the raw report callback, parses it and emits its events.
+///
+/// `solicited` tells if the report was requested from the device, instead of pushed by it.
+pub(crate) fn handle_report(
+    report_bytes: &[u8],
+    model: Model,
+    solicited: bool,
+    inner: &InnerLock,
+    shared: &Shared,
+) {
+    // Masked reports are discarded before doing any work for them.
+    let report_mask = ReportMask::from_bits(shared.report_mask.load(Ordering::Acquire));
+    if let Some(&report_id) = report_bytes.first()
+        && !report_mask.contains(ReportMask::for_report_id(report_id))
+    {
+        return;
+    }
+    diagnostics::report!(
+        "received {} report {report_bytes:02x?}",
+        if solicited { "requested" } else { "pushed" }
+    );
+
+    // The lock is taken once for the whole report, the callbacks are called after releasing it.
+    let mut inner_guard = inner.lock_unpoisoned();
+    inner_guard.dispatch.queue(
+        |callbacks| callbacks.on_raw_report.is_some(),
+        || Call::RawReport(report_bytes.to_vec()),
+    );
+
+    let report = match Report::parse(report_bytes, model) {
+        Ok(report) => report,
+        Err(error) => {
+            shared.counters.record_parse_error();
+            inner_guard.report_error(error, false);
+            return;
+        }
+    };
+
+    shared.counters.record_report(&report);
+
+    // Requested reports are not the operator touching the device.
+    if !solicited
+        && matches!(
+            report,
+            Report::Wheel { .. } | Report::Buttons(_) | Report::EditorKeyboardButtons { .. }
+        )
+    {
+        inner_guard.record_input();
+    }
+
+    match report {
+        Report::Wheel { mode, value } => match mode {
+            WheelMode::Relative => {
+                inner_guard.wheel_position =
+                    inner_guard.wheel_position.saturating_add(i64::from(value));
+                inner_guard.emit(Event::WheelChange { velocity: value });
+            }
+            WheelMode::AbsoluteContinuous | WheelMode::AbsoluteDeadZero => {
+                inner_guard.wheel_position = i64::from(value);
+            }
+        },
+        Report::Buttons(buttons) => handle_buttons(&mut inner_guard, buttons),
+        Report::EditorKeyboardButtons { buttons, other } => {
+            handle_buttons(&mut inner_guard, buttons);
+
+            let prev_pressed =
+                std::mem::replace(&mut inner_guard.pressed_editor_keyboard_buttons, other.clone());
+            for button in prev_pressed.into_iter().filter(|button| !other.contains(button)) {
+                inner_guard.emit(Event::EditorKeyboardButtonChange { button, pressed: false });
+            }
+            for button in other {
+                inner_guard.emit(Event::EditorKeyboardButtonChange { button, pressed: true });
+            }
+        }
+        Report::Battery { charging, level } => {
+            let level = match inner_guard.battery_level_policy {
+                _ if level <= 100 => level,
+                BatteryLevelPolicy::Clamp => 100,
+                BatteryLevelPolicy::Drop => return,
+                BatteryLevelPolicy::ReportError => {
+                    let error = crate::Error::driver("battery level out of range");
+                    inner_guard.report_error(error, false);
+                    return;
+                }
+            };
+
+            // The cache is cleared on disconnect, so the first report after connecting counts too.
+            let charging_changed = inner_guard
+                .battery_info
+                .is_none_or(|battery_info| battery_info.charging != charging);
+            let received_at = Instant::now();
+            inner_guard.battery_info =
+                Some(BatteryInfo { charging, level, received_at, solicited });
+            inner_guard.battery_estimator.record(charging, level, received_at);
+            inner_guard.emit(Event::BatteryInfo { charging, percentage: level });
+            if charging_changed {
+                inner_guard.emit(Event::ChargingChange { charging });
+            }
+        }
+        Report::Unknown { id, data } => {
+            inner_guard.emit(Event::UnknownReport { id, data });
+        }
+    }
+}
+
+/// Updates the pressed buttons from a buttons report, emitting the changes.
+fn handle_buttons(inner_guard: &mut Inner, buttons: Vec<Button>) {
+    // Every buttons report has the state of all buttons, so it also corrects
+    // a button that is stuck because its release was missed.
+    let pressed = buttons.iter().copied().collect::<ButtonSet>();
+    let prev_pressed = std::mem::replace(&mut inner_guard.pressed_buttons, pressed);
+
+    // For all buttons that were previously pressed but are not anymore, emit a release
+    for button in prev_pressed.difference(pressed).iter() {
+        inner_guard.emit(Event::ButtonChange { button, pressed: false });
+    }
+
+    // For all buttons that are currently pressed, emit a press
+    for button in buttons {
+        inner_guard.emit(Event::ButtonChange { button, pressed: true });
+    }
+}
+
+/// Updates the connection state, emitting an event if it changed.
+///
+/// Passing the [`DeviceInfo`] marks the device as connected, [`None`] marks it as disconnected.
+pub(crate) fn set_connected(inner: &InnerLock, shared: &Shared, device_info: Option<&DeviceInfo>) {
+    let connected = device_info.is_some();
+    if shared.connected.swap(connected, Ordering::AcqRel) == connected {
+        return;
+    }
+
+    if let Some(device_info) = device_info {
+        diagnostics::connection!(
+            "connected to {} at {}",
+            device_info.product.as_deref().unwrap_or("Speed Editor"),
+            device_info.path
+        );
+        shared.health.record_connect();
+        shared.counters.record_connect();
+        shared.session_metrics.lock_unpoisoned().record_connect(Instant::now());
+    } else {
+        shared.health.record_disconnect();
+        shared.session_metrics.lock_unpoisoned().record_disconnect(Instant::now());
+        #[cfg(feature = "unstable-raw")]
+        for raw_request in shared.raw_requests.lock_unpoisoned().drain(..) {
+            raw_request.cancel();
+        }
+        #[cfg(any(test, feature = "testing"))]
+        shared.injected_reports.lock_unpoisoned().clear();
+        // Dropping the senders tells the callers the device is gone.
+        shared.reset_requests.lock_unpoisoned().clear();
+    }
+
+    let mut inner_guard = inner.lock_unpoisoned();
+    let event = match device_info {
+        Some(device_info) => {
+            inner_guard.device_info = Some(device_info.clone());
+            Event::Connected(device_info.clone())
+        }
+        None => {
+            // The next device might be a different unit, so its battery is unknown.
+            inner_guard.battery_info = None;
+            inner_guard.battery_estimator.clear();
+            Event::Disconnected
+        }
+    };
+    inner_guard.emit(event);
+    inner_guard.flush_state_diff();
+}
+
+/// Marks the device as disconnected, remembering the reason for the [`SessionMetrics`][crate::SessionMetrics]
+/// if it was connected.
+pub(crate) fn set_disconnected(inner: &InnerLock, shared: &Shared, reason: DisconnectReason) {
+    // Only the polling thread connects the device, so it cannot connect in between.
+    if shared.connected.load(Ordering::Acquire) {
+        diagnostics::connection!("disconnected: {reason:?}");
+        shared.session_metrics.lock_unpoisoned().set_disconnect_reason(reason);
+    }
+    set_connected(inner, shared, None);
+}
+
+/// Emits a release for every button that is still pressed,
+/// so nothing is left pressed when the device goes away.
+pub(crate) fn release_all_buttons(inner: &InnerLock) {
+    let mut inner_guard = inner.lock_unpoisoned();
+    for button in std::mem::take(&mut inner_guard.pressed_buttons).iter() {
+        inner_guard.emit(Event::ButtonChange { button, pressed: false });
+    }
+    for button in std::mem::take(&mut inner_guard.pressed_editor_keyboard_buttons) {
+        inner_guard.emit(Event::EditorKeyboardButtonChange { button, pressed: false });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::mpsc, time::Duration};
+
+    use hidapi::HidError;
+
+    use crate::{
+        BatteryLevelPolicy, Button, DeviceInfo, EditorKeyboardButton, Event, HidBackend, Model,
+        SpeedEditor,
+        registry::{self, Registration},
+        test_util::{TIMEOUT, WHEEL_REPORT},
+        testing::FakeBackend,
+    };
+
+    /// A [`FakeBackend`] that is registered as opened, like a device opened by its path.
+    struct RegisteredBackend {
+        backend: FakeBackend,
+        _registration: Registration,
+    }
+
+    impl RegisteredBackend {
+        fn new(backend: &FakeBackend, path: &str) -> Self {
+            Self { backend: backend.clone(), _registration: Registration::new(path).unwrap() }
+        }
+    }
+
+    impl HidBackend for RegisteredBackend {
+        fn device_info(&self) -> Result<DeviceInfo, HidError> {
+            self.backend.device_info()
+        }
+
+        fn write(&self, data: &[u8]) -> Result<usize, HidError> {
+            self.backend.write(data)
+        }
+
+        fn read_timeout(&self, buf: &mut [u8], timeout: i32) -> Result<usize, HidError> {
+            self.backend.read_timeout(buf, timeout)
+        }
+
+        fn send_feature_report(&self, data: &[u8]) -> Result<(), HidError> {
+            self.backend.send_feature_report(data)
+        }
+
+        fn get_feature_report(&self, buf: &mut [u8]) -> Result<usize, HidError> {
+            self.backend.get_feature_report(buf)
+        }
+
+        fn get_input_report(&self, buf: &mut [u8]) -> Result<usize, HidError> {
+            self.backend.get_input_report(buf)
+        }
+    }
+
+    #[test]
+    fn drop_stops_callbacks_and_releases_device() {
+        const PATH: &str = "fake-drop";
+
+        let backend = FakeBackend::new();
+        backend.push_auth_handshake();
+        let (sender, receiver) = mpsc::channel();
+        let speed_editor = SpeedEditor::builder()
+            .on_wheel_change(move |velocity| sender.send(velocity).unwrap())
+            .connect_backend(RegisteredBackend::new(&backend, PATH))
+            .unwrap();
+        let clone = speed_editor.clone();
+
+        backend.push_input_report(&WHEEL_REPORT);
+        assert_eq!(receiver.recv_timeout(TIMEOUT), Ok(5));
+
+        // Dropping a clone keeps it running.
+        drop(clone);
+        backend.push_input_report(&WHEEL_REPORT);
+        assert_eq!(receiver.recv_timeout(TIMEOUT), Ok(5));
+        assert!(registry::is_open(PATH));
+
+        drop(speed_editor);
+        assert!(!registry::is_open(PATH));
+        backend.push_input_report(&WHEEL_REPORT);
+        assert!(!backend.wait_until_idle(Duration::from_millis(100)));
+        // The callback was dropped along with its sender, without being called again.
+        assert_eq!(receiver.recv_timeout(TIMEOUT), Err(mpsc::RecvTimeoutError::Disconnected));
+
+        // The device can be opened again, and gets the report that was left.
+        backend.push_auth_handshake();
+        let (sender, receiver) = mpsc::channel();
+        let _speed_editor = SpeedEditor::builder()
+            .on_wheel_change(move |velocity| sender.send(velocity).unwrap())
+            .connect_backend(RegisteredBackend::new(&backend, PATH))
+            .unwrap();
+        assert_eq!(receiver.recv_timeout(TIMEOUT), Ok(5));
+    }
+
+    /// Connects with `policy`, and returns the battery levels and errors for `reports`.
+    fn battery_levels(policy: BatteryLevelPolicy, reports: &[[u8; 3]]) -> (Vec<u8>, Vec<String>) {
+        let backend = FakeBackend::new();
+        backend.push_auth_handshake();
+        // Answers the battery report that is requested after connecting.
+        backend.push_input_report(&[0x07, 0x00, 0x32]);
+        let (level_sender, level_receiver) = mpsc::channel();
+        let (error_sender, error_receiver) = mpsc::channel();
+        let _speed_editor = SpeedEditor::builder()
+            .battery_level_policy(policy)
+            .on_battery_info(move |_, level| level_sender.send(level).unwrap())
+            .on_error(move |error| error_sender.send(error.to_string()).unwrap())
+            .connect_backend(backend.clone())
+            .unwrap();
+        assert_eq!(level_receiver.recv_timeout(TIMEOUT), Ok(50));
+
+        for report in reports {
+            backend.push_input_report(report);
+        }
+        assert!(backend.wait_until_idle(TIMEOUT));
+        (level_receiver.try_iter().collect(), error_receiver.try_iter().collect())
+    }
+
+    #[test]
+    fn valid_battery_levels_are_passed_on() {
+        for policy in
+            [BatteryLevelPolicy::Clamp, BatteryLevelPolicy::Drop, BatteryLevelPolicy::ReportError]
+        {
+            let reports = [[0x07, 0x00, 0x00], [0x07, 0x01, 0x2a], [0x07, 0x00, 0x64]];
+            assert_eq!(battery_levels(policy, &reports), (vec![0, 42, 100], vec![]));
+        }
+    }
+
+    #[test]
+    fn battery_level_policy_handles_levels_above_100() {
+        // Reported by a device right after waking up from Bluetooth sleep.
+        let reports = [[0x07, 0x00, 0xff], [0x07, 0x00, 0x65], [0x07, 0x00, 0x2a]];
+
+        assert_eq!(
+            battery_levels(BatteryLevelPolicy::Clamp, &reports),
+            (vec![100, 100, 42], vec![])
+        );
+        assert_eq!(battery_levels(BatteryLevelPolicy::Drop, &reports), (vec![42], vec![]));
+
+        let (levels, errors) = battery_levels(BatteryLevelPolicy::ReportError, &reports);
+        assert_eq!(levels, vec![42]);
+        assert_eq!(errors.len(), 2);
+        assert!(errors.iter().all(|error| error.starts_with("poller error: ")
+            && error.contains("battery level out of range")));
+    }
+
+    #[test]
+    fn editor_keyboard_keys_are_pressed_and_released() {
+        let device_info = DeviceInfo {
+            model: Model::EditorKeyboard,
+            ..FakeBackend::new().device_info().unwrap()
+        };
+        let backend = FakeBackend::with_device_info(device_info);
+        backend.push_auth_handshake();
+        let (sender, receiver) = mpsc::channel();
+        let _speed_editor =
+            SpeedEditor::builder().sink(Box::new(sender)).connect_backend(backend.clone()).unwrap();
+
+        let key = EditorKeyboardButton::from_code(0xff);
+        backend.push_input_report(&[0x04, 0x0f, 0x00, 0xff, 0x00, 0, 0, 0, 0, 0, 0, 0, 0]);
+        backend.push_input_report(&[0x04, 0xff, 0x00, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        backend.push_input_report(&[0x04, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        assert!(backend.wait_until_idle(TIMEOUT));
+
+        let events = receiver
+            .try_iter()
+            .filter(|event| {
+                matches!(
+                    event,
+                    Event::ButtonChange { .. } | Event::EditorKeyboardButtonChange { .. }
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            events,
+            [
+                Event::ButtonChange { button: Button::Cut, pressed: true },
+                Event::EditorKeyboardButtonChange { button: key, pressed: true },
+                Event::ButtonChange { button: Button::Cut, pressed: false },
+                Event::EditorKeyboardButtonChange { button: key, pressed: true },
+                Event::EditorKeyboardButtonChange { button: key, pressed: false },
+            ]
+        );
+    }
+}
@@ -0,0 +1,208 @@
+//! The HID protocol spoken by the Speed Editor.
+//!
+//! This is meant for advanced and offline use, like analyzing captured HID traffic.
+//! To talk to a device, use [`SpeedEditor`][crate::SpeedEditor] instead,
+//! which also takes care of authenticating it.
+//!
+//! # Example
+//!
+//! ```
+//! use bmdse::protocol::{Report, WheelMode};
+//!
+//! let bytes = [0x03, 0x00, 0x05, 0x00, 0x00, 0x00, 0x00];
+//! let report = Report::try_from(&bytes[..]).unwrap();
+//! assert_eq!(report, Report::Wheel { mode: WheelMode::Relative, value: 5 });
+//! ```
+
+use crate::{ButtonLed, Model, WheelLed};
+
+pub use crate::auth::AuthSession;
+pub use crate::capture::{CaptureKind, CaptureReader, CaptureRecord};
+pub use crate::driver::{Report, WheelMode};
+
+/// The ID of the input report with the state of the wheel.
+pub const WHEEL_REPORT_ID: u8 = 0x03;
+/// The ID of the input report with the buttons that are pressed.
+pub const BUTTONS_REPORT_ID: u8 = 0x04;
+/// The ID of the feature report used for authentication.
+pub const AUTH_REPORT_ID: u8 = 0x06;
+/// The ID of the input report with the state of the battery.
+pub const BATTERY_REPORT_ID: u8 = 0x07;
+
+/// The ID of the output report that sets the [`ButtonLed`].
+pub const BUTTON_LED_REPORT_ID: u8 = 0x02;
+/// The ID of the output report that sets the [`WheelMode`].
+pub const WHEEL_MODE_REPORT_ID: u8 = 0x03;
+/// The ID of the output report that sets the [`WheelLed`].
+pub const WHEEL_LED_REPORT_ID: u8 = 0x04;
+
+/// The length of a wheel report, including the report ID.
+pub const WHEEL_REPORT_LEN: usize = 7;
+/// The length of a buttons report, including the report ID.
+pub const BUTTONS_REPORT_LEN: usize = 13;
+/// The length of a battery report, including the report ID.
+pub const BATTERY_REPORT_LEN: usize = 3;
+
+/// Returns the output report that sets the [`ButtonLed`] on a Speed Editor.
+///
+/// Every LED is a bit of the payload, in little endian.
+/// Use [`model_button_led_report`] for other models.
+///
+/// ```
+/// use bmdse::{ButtonLed, protocol};
+///
+/// let report = protocol::button_led_report(ButtonLed::Cam1);
+/// assert_eq!(report, [0x02, 0x00, 0x40, 0x00, 0x00]);
+/// ```
+pub fn button_led_report(led: ButtonLed) -> [u8; 5] {
+    model_button_led_report(Model::SpeedEditor, led)
+}
+
+/// Returns the output report that sets the [`ButtonLed`] on a device of the given model.
+///
+/// The bits of the payload are the ones of [`Model::button_led_bits`].
+pub fn model_button_led_report(model: Model, led: ButtonLed) -> [u8; 5] {
+    let mut buf = [0u8; 5];
+    buf[0] = BUTTON_LED_REPORT_ID;
+    buf[1..5].copy_from_slice(&model.button_led_bits(led).to_le_bytes());
+    buf
+}
+
+/// Returns the output report that sets the [`WheelLed`].
+///
+/// ```
+/// use bmdse::{WheelLed, protocol};
+///
+/// assert_eq!(protocol::wheel_led_report(WheelLed::Jog), [0x04, 0x01]);
+/// ```
+pub fn wheel_led_report(led: WheelLed) -> [u8; 2] {
+    [WHEEL_LED_REPORT_ID, led as u8]
+}
+
+/// Returns the output report that sets the [`WheelMode`].
+///
+/// ```
+/// use bmdse::protocol::{self, WheelMode};
+///
+/// let report = protocol::wheel_mode_report(WheelMode::AbsoluteDeadZero);
+/// assert_eq!(report, [0x03, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00]);
+/// ```
+pub fn wheel_mode_report(mode: WheelMode) -> [u8; 7] {
+    let mut buf = [0u8; 7];
+    buf[0] = WHEEL_MODE_REPORT_ID;
+    buf[1] = mode as u8;
+    buf[2..6].copy_from_slice(&0u32.to_le_bytes());
+    buf[6] = 0; // unknown
+    buf
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Button, EditorKeyboardButton};
+
+    const BUTTON_LEDS: [ButtonLed; 19] = [
+        ButtonLed::Off,
+        ButtonLed::CloseUp,
+        ButtonLed::Cut,
+        ButtonLed::Dissolve,
+        ButtonLed::SmoothCut,
+        ButtonLed::Transition,
+        ButtonLed::Snap,
+        ButtonLed::Cam7,
+        ButtonLed::Cam8,
+        ButtonLed::Cam9,
+        ButtonLed::LiveOverwrite,
+        ButtonLed::Cam4,
+        ButtonLed::Cam5,
+        ButtonLed::Cam6,
+        ButtonLed::VideoOnly,
+        ButtonLed::Cam1,
+        ButtonLed::Cam2,
+        ButtonLed::Cam3,
+        ButtonLed::AudioOnly,
+    ];
+
+    #[test]
+    fn button_led_report_layout() {
+        for led in BUTTON_LEDS {
+            let report = button_led_report(led);
+            assert_eq!(report[0], BUTTON_LED_REPORT_ID);
+            let mask = u32::from_le_bytes([report[1], report[2], report[3], report[4]]);
+            assert_eq!(ButtonLed::try_from(mask).unwrap(), led);
+            assert!(mask.count_ones() <= 1, "{led:?}");
+        }
+        assert_eq!(button_led_report(ButtonLed::AudioOnly), [0x02, 0x00, 0x00, 0x02, 0x00]);
+    }
+
+    #[test]
+    fn wheel_led_report_layout() {
+        assert_eq!(wheel_led_report(WheelLed::Off), [0x04, 0x00]);
+        assert_eq!(wheel_led_report(WheelLed::Jog), [0x04, 0x01]);
+        assert_eq!(wheel_led_report(WheelLed::Shuttle), [0x04, 0x02]);
+        assert_eq!(wheel_led_report(WheelLed::Scroll), [0x04, 0x04]);
+    }
+
+    #[test]
+    fn wheel_mode_report_layout() {
+        for mode in
+            [WheelMode::Relative, WheelMode::AbsoluteContinuous, WheelMode::AbsoluteDeadZero]
+        {
+            let report = wheel_mode_report(mode);
+            assert_eq!(report[0], WHEEL_MODE_REPORT_ID);
+            assert_eq!(WheelMode::try_from(report[1]).unwrap(), mode);
+            assert_eq!(report[2..], [0x00; 5]);
+        }
+    }
+
+    #[test]
+    fn report_lengths_match_ids() {
+        for (id, len) in [
+            (WHEEL_REPORT_ID, WHEEL_REPORT_LEN),
+            (BUTTONS_REPORT_ID, BUTTONS_REPORT_LEN),
+            (BATTERY_REPORT_ID, BATTERY_REPORT_LEN),
+        ] {
+            let mut bytes = vec![0x00; len];
+            bytes[0] = id;
+            let report = Report::try_from(&bytes[..]).unwrap();
+            assert!(!matches!(report, Report::Unknown { .. }), "{id:#04x}");
+            assert!(Report::try_from(&bytes[..len - 1]).is_err(), "{id:#04x}");
+        }
+        let report = Report::try_from(&[AUTH_REPORT_ID, 0x00][..]).unwrap();
+        assert_eq!(report, Report::Unknown { id: AUTH_REPORT_ID, data: vec![0x00] });
+    }
+
+    #[test]
+    fn wheel_value_is_signed_little_endian() {
+        let report = Report::try_from(&[0x03, 0x01, 0x00, 0x01, 0x00, 0x00, 0x00][..]).unwrap();
+        assert_eq!(report, Report::Wheel { mode: WheelMode::AbsoluteContinuous, value: 256 });
+        let report = Report::try_from(&[0x03, 0x03, 0x00, 0xff, 0xff, 0xff, 0x00][..]).unwrap();
+        assert_eq!(report, Report::Wheel { mode: WheelMode::AbsoluteDeadZero, value: -256 });
+        assert!(Report::try_from(&[0x03, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00][..]).is_err());
+    }
+
+    #[test]
+    fn battery_is_only_charging_when_flag_is_one() {
+        for (flag, charging) in [(0x00, false), (0x01, true), (0x02, false)] {
+            let report = Report::try_from(&[0x07, flag, 0x64][..]).unwrap();
+            assert_eq!(report, Report::Battery { charging, level: 100 });
+        }
+    }
+
+    #[test]
+    fn unknown_buttons_are_only_accepted_for_editor_keyboard() {
+        let bytes = [0x04, 0x0f, 0x00, 0xff, 0x00, 0, 0, 0, 0, 0, 0, 0, 0];
+        assert!(Report::try_from(&bytes[..]).is_err());
+        let report = Report::parse(&bytes, Model::EditorKeyboard).unwrap();
+        let other = vec![EditorKeyboardButton::from_code(0xff)];
+        assert_eq!(report, Report::EditorKeyboardButtons { buttons: vec![Button::Cut], other });
+    }
+
+    #[test]
+    fn button_led_reports_are_the_same_for_both_models() {
+        for led in BUTTON_LEDS {
+            let report = model_button_led_report(Model::EditorKeyboard, led);
+            assert_eq!(report, button_led_report(led));
+        }
+    }
+}
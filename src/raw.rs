@@ -0,0 +1,240 @@
+use std::sync::mpsc;
+
+use crate::{HidBackend, SpeedEditor, protocol::AUTH_REPORT_ID, sync::MutexExt};
+
+/// A request to access the device directly, handled by the polling thread
+/// so it does not race its reads.
+pub(crate) enum RawRequest {
+    SendFeature { data: Vec<u8>, reply: mpsc::Sender<Result<(), crate::Error>> },
+    GetFeature { report_id: u8, len: usize, reply: mpsc::Sender<Result<Vec<u8>, crate::Error>> },
+    WriteOutput { data: Vec<u8>, reply: mpsc::Sender<Result<(), crate::Error>> },
+}
+
+impl RawRequest {
+    /// Handles the request, sending the result back to the caller.
+    pub(crate) fn handle(self, device: &(impl HidBackend + ?Sized)) {
+        // The caller might have gone away, in which case nobody cares about the result.
+        match self {
+            RawRequest::SendFeature { data, reply } => {
+                let result = device
+                    .send_feature_report(&data)
+                    .map_err(|error| crate::Error::hid("failed to send feature report", error));
+                let _ = reply.send(result);
+            }
+            RawRequest::GetFeature { report_id, len, reply } => {
+                let mut buf = vec![0x00; len.max(1)];
+                buf[0] = report_id;
+                let result = device
+                    .get_feature_report(&mut buf)
+                    .map(|len| {
+                        buf.truncate(len);
+                        buf
+                    })
+                    .map_err(|error| crate::Error::hid("failed to get feature report", error));
+                let _ = reply.send(result);
+            }
+            RawRequest::WriteOutput { data, reply } => {
+                let result = device
+                    .write(&data)
+                    .map(|_| ())
+                    .map_err(|error| crate::Error::hid("failed to write output report", error));
+                let _ = reply.send(result);
+            }
+        }
+    }
+
+    /// Answers the request without handling it, because the device disconnected.
+    pub(crate) fn cancel(self) {
+        match self {
+            RawRequest::SendFeature { reply, .. } => {
+                let _ = reply.send(Err(crate::Error::HidDeviceNotFound));
+            }
+            RawRequest::GetFeature { reply, .. } => {
+                let _ = reply.send(Err(crate::Error::HidDeviceNotFound));
+            }
+            RawRequest::WriteOutput { reply, .. } => {
+                let _ = reply.send(Err(crate::Error::HidDeviceNotFound));
+            }
+        }
+    }
+}
+
+/// Raw access to feature and output reports, for reverse engineering the device.
+///
+/// These methods are **unstable** and only available with the `unstable-raw` feature.
+/// Use them at your own risk: sending the wrong report might put the device
+/// in a state this crate does not expect.
+impl SpeedEditor {
+    /// Sends a feature report to the device. The first byte is the report ID.
+    ///
+    /// The report is sent by the polling thread between two reads,
+    /// and this blocks until it was sent.
+    /// While the device is [paused][SpeedEditor::pause], this blocks until it is resumed.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::HidDeviceNotFound`][crate::Error::HidDeviceNotFound]
+    /// if the device is not connected, and
+    /// [`Error::InvalidConfiguration`][crate::Error::InvalidConfiguration] for report ID 6,
+    /// which is used to authenticate the device.
+    /// Also returns an error if called from a callback, or if sending the report failed.
+    pub fn send_feature_report(&self, data: &[u8]) -> Result<(), crate::Error> {
+        let Some(&report_id) = data.first() else {
+            return Err(crate::Error::InvalidConfiguration {
+                message: "a feature report needs at least a report ID",
+            });
+        };
+        check_report_id(report_id)?;
+        self.raw_request(|reply| RawRequest::SendFeature { data: data.to_vec(), reply })
+    }
+
+    /// Gets the feature report with the given ID from the device.
+    ///
+    /// `len` is the maximum length of the report, including the report ID,
+    /// which is also the first byte of the returned report.
+    ///
+    /// See [`SpeedEditor::send_feature_report`] for how the request is made.
+    ///
+    /// # Errors
+    ///
+    /// See [`SpeedEditor::send_feature_report`].
+    pub fn get_feature_report(&self, report_id: u8, len: usize) -> Result<Vec<u8>, crate::Error> {
+        check_report_id(report_id)?;
+        self.raw_request(|reply| RawRequest::GetFeature { report_id, len, reply })
+    }
+
+    /// Writes an output report with the given ID and payload to the device.
+    ///
+    /// The report is written by the polling thread between two reads, in order with the LED
+    /// writes it makes itself. The reports this crate knows about are built by the functions
+    /// in [`protocol`][crate::protocol], like
+    /// [`button_led_report`][crate::protocol::button_led_report].
+    ///
+    /// Note that the polling thread does not know about the changes made this way.
+    /// For example, it does not write the LEDs again after setting them with a raw report,
+    /// until they are changed with [`SpeedEditor::set_button_led`].
+    ///
+    /// See [`SpeedEditor::send_feature_report`] for how the request is made.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::HidDeviceNotFound`][crate::Error::HidDeviceNotFound]
+    /// if the device is not connected.
+    /// Also returns an error if called from a callback, or if writing the report failed.
+    pub fn write_output_report(&self, report_id: u8, payload: &[u8]) -> Result<(), crate::Error> {
+        let mut data = Vec::with_capacity(payload.len() + 1);
+        data.push(report_id);
+        data.extend_from_slice(payload);
+        self.raw_request(|reply| RawRequest::WriteOutput { data, reply })
+    }
+
+    fn raw_request<T>(
+        &self,
+        request: impl FnOnce(mpsc::Sender<Result<T, crate::Error>>) -> RawRequest,
+    ) -> Result<T, crate::Error> {
+        if self.poller.is_polling_thread() {
+            return Err(crate::Error::driver(
+                "cannot access raw reports from one of the callbacks",
+            ));
+        }
+
+        let (reply, result) = mpsc::channel();
+        {
+            // The connection is checked while holding the lock, so a disconnect either
+            // happens before this check or cancels the request.
+            let mut raw_requests = self.poller.shared.raw_requests.lock_unpoisoned();
+            if !self.is_connected() {
+                return Err(crate::Error::HidDeviceNotFound);
+            }
+            raw_requests.push(request(reply));
+        }
+        result.recv().unwrap_or(Err(crate::Error::HidDeviceNotFound))
+    }
+}
+
+/// Refuses the report used for authentication, as accessing it breaks the handshake.
+fn check_report_id(report_id: u8) -> Result<(), crate::Error> {
+    if report_id == AUTH_REPORT_ID {
+        return Err(crate::Error::InvalidConfiguration {
+            message: "feature report 6 is reserved for authenticating the device",
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use crate::test_util::{TIMEOUT, connect};
+    use crate::testing::{FakeBackend, Fault};
+    use crate::{ButtonLed, Error, SpeedEditor, protocol};
+
+    #[test]
+    fn output_report_is_written_after_led_changes() {
+        let (speed_editor, backend) = connect();
+
+        speed_editor.set_button_led(ButtonLed::Cut);
+        speed_editor.write_output_report(0x05, &[0x01, 0x02, 0x03]).unwrap();
+        assert_eq!(
+            backend.written()[3..],
+            [protocol::button_led_report(ButtonLed::Cut).to_vec(), vec![0x05, 0x01, 0x02, 0x03]]
+        );
+    }
+
+    #[test]
+    fn failed_write_is_returned() {
+        let (speed_editor, backend) = connect();
+
+        backend.fail_writes(1, Fault::Error);
+        let error = speed_editor.write_output_report(0x05, &[0x01]).unwrap_err();
+        assert!(
+            matches!(error, Error::Driver { message: "failed to write output report", .. }),
+            "{error}"
+        );
+        assert_eq!(backend.written().len(), 3);
+        assert!(speed_editor.is_connected());
+    }
+
+    #[test]
+    fn feature_reports_go_through_poller() {
+        let (speed_editor, backend) = connect();
+
+        speed_editor.send_feature_report(&[0x09, 0x01]).unwrap();
+        assert_eq!(backend.sent_feature_reports().last().unwrap(), &[0x09, 0x01]);
+
+        backend.push_feature_report(&[0x09, 0x02, 0x03]);
+        assert_eq!(speed_editor.get_feature_report(0x09, 8).unwrap(), [0x09, 0x02, 0x03]);
+    }
+
+    #[test]
+    fn auth_report_is_refused() {
+        let (speed_editor, backend) = connect();
+        let sent = backend.sent_feature_reports().len();
+
+        for result in [
+            speed_editor.send_feature_report(&[]),
+            speed_editor.send_feature_report(&[0x06, 0x00]),
+            speed_editor.get_feature_report(0x06, 10).map(|_| ()),
+        ] {
+            assert!(matches!(result, Err(Error::InvalidConfiguration { .. })));
+        }
+        assert_eq!(backend.sent_feature_reports().len(), sent);
+    }
+
+    #[test]
+    fn requests_fail_when_disconnected() {
+        let backend = FakeBackend::new();
+        backend.push_auth_handshake();
+        let (sender, receiver) = mpsc::channel();
+        let speed_editor = SpeedEditor::builder()
+            .on_disconnect(move || sender.send(()).unwrap())
+            .connect_backend(backend.clone())
+            .unwrap();
+
+        backend.fail_reads(1, Fault::Disconnected);
+        assert_eq!(receiver.recv_timeout(TIMEOUT), Ok(()));
+        let error = speed_editor.write_output_report(0x05, &[0x01]).unwrap_err();
+        assert!(matches!(error, Error::HidDeviceNotFound));
+    }
+}
@@ -0,0 +1,85 @@
+use std::{collections::HashMap, sync::Mutex, thread};
+
+use hidapi::{HidDevice, HidError};
+
+use crate::{DeviceInfo, HidBackend, sync::MutexExt};
+
+/// The paths of the devices opened by this process, with the thread that opened them.
+static OPEN_DEVICES: Mutex<Option<HashMap<String, String>>> = Mutex::new(None);
+
+/// Marks a device as opened by this process until it is dropped.
+pub(crate) struct Registration {
+    path: String,
+}
+
+impl Registration {
+    /// Marks the device at `path` as opened by the current thread.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::AlreadyOpen`][crate::Error::AlreadyOpen]
+    /// if it is already opened by this process.
+    pub(crate) fn new(path: &str) -> Result<Self, crate::Error> {
+        let mut open_devices = OPEN_DEVICES.lock_unpoisoned();
+        let open_devices = open_devices.get_or_insert_with(HashMap::new);
+        if let Some(owner) = open_devices.get(path) {
+            return Err(crate::Error::AlreadyOpen { path: path.to_string(), owner: owner.clone() });
+        }
+
+        let current = thread::current();
+        let owner = match current.name() {
+            Some(name) => name.to_string(),
+            None => format!("{:?}", current.id()),
+        };
+        open_devices.insert(path.to_string(), owner);
+        Ok(Self { path: path.to_string() })
+    }
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        if let Some(open_devices) = OPEN_DEVICES.lock_unpoisoned().as_mut() {
+            open_devices.remove(&self.path);
+        }
+    }
+}
+
+/// Returns `true` if the device at `path` is opened by this process.
+pub(crate) fn is_open(path: &str) -> bool {
+    OPEN_DEVICES
+        .lock_unpoisoned()
+        .as_ref()
+        .is_some_and(|open_devices| open_devices.contains_key(path))
+}
+
+/// A [`HidDevice`] that is registered as opened until it is dropped.
+pub(crate) struct OpenDevice {
+    pub(crate) device: HidDevice,
+    pub(crate) _registration: Registration,
+}
+
+impl HidBackend for OpenDevice {
+    fn device_info(&self) -> Result<DeviceInfo, HidError> {
+        self.device.device_info()
+    }
+
+    fn write(&self, data: &[u8]) -> Result<usize, HidError> {
+        HidBackend::write(&self.device, data)
+    }
+
+    fn read_timeout(&self, buf: &mut [u8], timeout: i32) -> Result<usize, HidError> {
+        HidBackend::read_timeout(&self.device, buf, timeout)
+    }
+
+    fn send_feature_report(&self, data: &[u8]) -> Result<(), HidError> {
+        HidBackend::send_feature_report(&self.device, data)
+    }
+
+    fn get_feature_report(&self, buf: &mut [u8]) -> Result<usize, HidError> {
+        HidBackend::get_feature_report(&self.device, buf)
+    }
+
+    fn get_input_report(&self, buf: &mut [u8]) -> Result<usize, HidError> {
+        HidBackend::get_input_report(&self.device, buf)
+    }
+}
@@ -0,0 +1,1114 @@
+use std::{
+    sync::atomic::Ordering,
+    time::{Duration, Instant},
+};
+
+use crate::capture::CapturingBackend;
+use crate::diagnostics;
+use crate::dispatch::InnerLock;
+use crate::driver;
+use crate::poller::{
+    Shared, handle_report, release_all_buttons, set_connected, sleep_unless_shutdown,
+};
+use crate::protocol::{AuthSession, BUTTONS_REPORT_ID, WheelMode};
+use crate::sync::MutexExt;
+use crate::{
+    AuthRetryPolicy, ButtonLed, DeviceInfo, HidBackend, ShutdownPolicy, Transport, WheelLed,
+};
+
+/// A connection with an authenticated device.
+///
+/// This does all the work of talking to the device, without deciding when to do it,
+/// so that the polling thread and [`SpeedEditorSync`][crate::SpeedEditorSync] share it.
+pub(crate) struct Session {
+    /// All traffic goes through the capture, which does nothing unless one is running.
+    device: CapturingBackend,
+    device_info: DeviceInfo,
+
+    verify_device: bool,
+    auth_schedule: AuthSchedule,
+    next_auth: Instant,
+    auth_expiry: Instant,
+    failed_auth_attempts: u32,
+    /// The authentication in progress, which is done one step per call to [`Session::maintain`].
+    reauth: Option<AuthSession>,
+
+    last_wheel_mode: Option<WheelMode>,
+    last_button_led: Option<ButtonLed>,
+    last_wheel_led: Option<WheelLed>,
+    last_led_write: Instant,
+    last_battery_poll: Instant,
+    last_buttons_report: Instant,
+    last_activity: Instant,
+    consecutive_failures: u32,
+}
+
+impl Session {
+    /// The number of failed reads in a row after which the device is considered disconnected.
+    /// With the backoff between them, this takes about half a second.
+    const MAX_CONSECUTIVE_FAILURES: u32 = 10;
+    /// The longest wait after a failed read.
+    const MAX_READ_BACKOFF: Duration = Duration::from_millis(250);
+    /// The most reports handled in a row, so LEDs and authentication are never starved.
+    const MAX_REPORTS_PER_PASS: usize = 32;
+
+    /// Authenticates the device, unless it was [resumed][crate::Inner::resumed_auth] while still
+    /// authenticated, writes the wheel mode, and marks it as connected.
+    ///
+    /// The device might have been used by another application since it was last connected,
+    /// so the wheel mode is written right away. The LEDs are written by [`Session::maintain`].
+    pub(crate) fn start(
+        device: Box<dyn HidBackend>,
+        inner: &InnerLock,
+        shared: &Shared,
+    ) -> Result<Self, crate::Error> {
+        let device = CapturingBackend { device, capture: shared.capture.clone() };
+
+        let device_info = device
+            .device_info()
+            .map_err(|error| crate::Error::hid("failed to get device info", error))?;
+
+        let (verify_device, auth_schedule, resumed_auth) = {
+            let mut inner = inner.lock_unpoisoned();
+            (
+                inner.verify_device,
+                AuthSchedule { margin: inner.auth_margin, timeout: inner.auth_timeout },
+                inner.resumed_auth.take(),
+            )
+        };
+
+        let (next_auth, auth_expiry) = match resumed_auth {
+            Some(auth) if auth.expiry > Instant::now() => {
+                shared.health.set_next_auth(auth.next_auth);
+                shared.health.set_auth_expiry(auth.expiry);
+                (auth.next_auth, auth.expiry)
+            }
+            _ => {
+                let host_challenge = verify_device.then(AuthSession::random_host_challenge);
+                let auth_time = driver::authenticate(&device, host_challenge)?;
+                auth_schedule.next_auth(auth_time, shared)
+            }
+        };
+        let last_wheel_mode = Self::restore_wheel_mode(&device, inner)?;
+        set_connected(inner, shared, Some(&device_info));
+
+        // The device only pushes its battery state every now and then, so it is known right away.
+        shared.battery_requested.store(driver::CAN_REQUEST_INPUT_REPORTS, Ordering::Release);
+
+        let now = Instant::now();
+        Ok(Self {
+            device,
+            device_info,
+            verify_device,
+            auth_schedule,
+            next_auth,
+            auth_expiry,
+            failed_auth_attempts: 0,
+            reauth: None,
+            last_wheel_mode,
+            // Starting without any known LED state makes sure the LEDs are restored
+            // after reconnecting.
+            last_button_led: None,
+            last_wheel_led: None,
+            last_led_write: now,
+            last_battery_poll: now,
+            last_buttons_report: now,
+            last_activity: now,
+            consecutive_failures: 0,
+        })
+    }
+
+    pub(crate) fn device_info(&self) -> &DeviceInfo {
+        &self.device_info
+    }
+
+    /// Returns `true` if the device is being authenticated again.
+    pub(crate) fn is_authenticating(&self) -> bool {
+        self.reauth.is_some()
+    }
+
+    /// Returns when the last report was read or LED was written.
+    pub(crate) fn last_activity(&self) -> Instant {
+        self.last_activity
+    }
+
+    /// Discards everything that happened while paused, writes the wheel mode like
+    /// [`Session::start`] does, and makes sure the LEDs are restored.
+    pub(crate) fn resume(&mut self, inner: &InnerLock) -> Result<(), crate::Error> {
+        let mut buf = [0x00; 64];
+        while let Ok(Some(_)) = driver::read(&self.device, &mut buf, 0) {}
+        self.last_button_led = None;
+        self.last_wheel_led = None;
+        // The device might have forgotten about a handshake in progress.
+        self.reauth = None;
+        self.last_wheel_mode = Self::restore_wheel_mode(&self.device, inner)?;
+        Ok(())
+    }
+
+    /// Writes the wheel mode, as another application might have changed it.
+    ///
+    /// Returns the mode that was written, which is nothing in observer mode,
+    /// where the other application owns the wheel.
+    fn restore_wheel_mode(
+        device: &CapturingBackend,
+        inner: &InnerLock,
+    ) -> Result<Option<WheelMode>, crate::Error> {
+        let (observer, wheel_mode) = {
+            let inner_guard = inner.lock_unpoisoned();
+            (inner_guard.observer, inner_guard.wheel_mode)
+        };
+        if observer {
+            return Ok(None);
+        }
+        driver::set_wheel_mode(device, wheel_mode)?;
+        Ok(Some(wheel_mode))
+    }
+
+    /// Does everything besides reading reports: authenticating again, writing the LEDs
+    /// and requesting reports from the device.
+    ///
+    /// Returns when this should be called again at the latest.
+    pub(crate) fn maintain(
+        &mut self,
+        inner: &InnerLock,
+        shared: &Shared,
+    ) -> Result<Instant, crate::Error> {
+        let reset_requests = std::mem::take(&mut *shared.reset_requests.lock_unpoisoned());
+        if !reset_requests.is_empty() {
+            let result = self.reset(inner, shared);
+            for reply in reset_requests {
+                // The caller might have gone away, in which case nobody cares about the result.
+                let _ = reply.send(result.clone());
+            }
+        }
+
+        self.maintain_auth(inner, shared)?;
+
+        // Everything that needs the configuration is done while taking the lock only once.
+        let (battery_poll_interval, stuck_button_timeout, keepalive, next_idle_checks) = {
+            let mut inner_guard = inner.lock_unpoisoned();
+
+            // Writing the wheel LED again keeps a wireless link from dozing off.
+            // In observer mode another application owns the LEDs and the wheel.
+            let write_leds = !inner_guard.observer;
+            let next_standby_check = inner_guard.check_standby();
+            let wheel_mode = inner_guard.wheel_mode;
+            let (button_led, wheel_led) = inner_guard.leds();
+            let keepalive = inner_guard
+                .keepalive
+                .filter(|_| write_leds && self.device_info.transport != Transport::Usb);
+            if keepalive.is_some_and(|interval| self.last_led_write.elapsed() >= interval) {
+                self.last_wheel_led = None;
+            }
+
+            if write_leds && self.last_wheel_mode.is_none_or(|last_mode| last_mode != wheel_mode) {
+                driver::set_wheel_mode(&self.device, wheel_mode)?;
+                self.last_wheel_mode = Some(wheel_mode);
+                self.last_activity = Instant::now();
+            }
+            if write_leds && self.last_button_led.is_none_or(|last_led| last_led != button_led) {
+                driver::set_button_led(&self.device, self.device_info.model, button_led)
+                    .inspect_err(|_| shared.counters.record_led_write_error())?;
+                self.last_button_led = Some(button_led);
+                self.last_led_write = Instant::now();
+                self.last_activity = self.last_led_write;
+            }
+            if write_leds && self.last_wheel_led.is_none_or(|last_led| last_led != wheel_led) {
+                driver::set_wheel_led(&self.device, wheel_led)
+                    .inspect_err(|_| shared.counters.record_led_write_error())?;
+                self.last_wheel_led = Some(wheel_led);
+                self.last_led_write = Instant::now();
+                self.last_activity = self.last_led_write;
+            }
+
+            (
+                inner_guard.battery_poll_interval.filter(|_| driver::CAN_REQUEST_INPUT_REPORTS),
+                // A button that is held for a long time might be stuck because its release was
+                // missed, which asking for the state of all buttons corrects.
+                inner_guard
+                    .stuck_button_timeout
+                    .filter(|_| driver::CAN_REQUEST_INPUT_REPORTS)
+                    .filter(|_| {
+                        !inner_guard.pressed_buttons.is_empty()
+                            || !inner_guard.pressed_editor_keyboard_buttons.is_empty()
+                    }),
+                keepalive,
+                [inner_guard.check_idle(), next_standby_check],
+            )
+        };
+
+        let mut buf = [0x00; 64];
+        let model = self.device_info.model;
+
+        // A requested battery report is handled just like a pushed one.
+        if battery_poll_interval
+            .is_some_and(|interval| self.last_battery_poll.elapsed() >= interval)
+        {
+            shared.battery_requested.store(true, Ordering::Release);
+        }
+        if shared.battery_requested.swap(false, Ordering::AcqRel) {
+            self.last_battery_poll = Instant::now();
+            match driver::request_battery(&self.device, &mut buf) {
+                Ok(report_bytes) => handle_report(report_bytes, model, true, inner, shared),
+                Err(error) => inner.lock_unpoisoned().report_error(error, false),
+            }
+        }
+
+        if stuck_button_timeout.is_some_and(|timeout| self.last_buttons_report.elapsed() >= timeout)
+        {
+            self.last_buttons_report = Instant::now();
+            match driver::request_buttons(&self.device, &mut buf) {
+                Ok(report_bytes) => handle_report(report_bytes, model, true, inner, shared),
+                Err(error) => inner.lock_unpoisoned().report_error(error, false),
+            }
+        }
+
+        #[cfg(feature = "unstable-raw")]
+        for raw_request in std::mem::take(&mut *shared.raw_requests.lock_unpoisoned()) {
+            raw_request.handle(&self.device);
+        }
+
+        #[cfg(any(test, feature = "testing"))]
+        for report_bytes in std::mem::take(&mut *shared.injected_reports.lock_unpoisoned()) {
+            shared.health.record_report();
+            handle_report(&report_bytes, model, false, inner, shared);
+        }
+
+        // The LEDs could have changed, and buttons could have been released.
+        inner.lock_unpoisoned().flush_state_diff();
+
+        // A handshake in progress continues right away.
+        if self.reauth.is_some() {
+            return Ok(Instant::now());
+        }
+        let deadlines = [
+            battery_poll_interval.map(|interval| self.last_battery_poll + interval),
+            stuck_button_timeout.map(|timeout| self.last_buttons_report + timeout),
+            keepalive.map(|interval| self.last_led_write + interval),
+        ];
+        Ok(deadlines
+            .into_iter()
+            .chain(next_idle_checks)
+            .flatten()
+            .fold(self.next_auth, Instant::min))
+    }
+
+    /// Brings the device back into the state this crate expects, after a glitch or another
+    /// application changed it, see [`SpeedEditor::reset_device`][crate::SpeedEditor::reset_device].
+    ///
+    /// Stops at the first step that fails. A failed authentication is retried like a failed
+    /// re-authentication, and the LEDs are written by the next call to [`Session::maintain`].
+    pub(crate) fn reset(&mut self, inner: &InnerLock, shared: &Shared) -> Result<(), crate::Error> {
+        release_all_buttons(inner);
+        inner.lock_unpoisoned().wheel_position = 0;
+
+        // A handshake in progress is replaced by a complete one.
+        self.reauth = None;
+        let host_challenge = self.verify_device.then(AuthSession::random_host_challenge);
+        match driver::authenticate(&self.device, host_challenge) {
+            Ok(auth_time) => {
+                (self.next_auth, self.auth_expiry) =
+                    self.auth_schedule.next_auth(auth_time, shared);
+                self.failed_auth_attempts = 0;
+            }
+            Err(error) => {
+                self.next_auth = Instant::now();
+                shared.health.set_next_auth(self.next_auth);
+                return Err(error);
+            }
+        }
+
+        // In observer mode another application owns the LEDs and the wheel.
+        let (observer, wheel_mode, button_led, wheel_led) = {
+            let inner_guard = inner.lock_unpoisoned();
+            let (button_led, wheel_led) = inner_guard.leds();
+            (inner_guard.observer, inner_guard.wheel_mode, button_led, wheel_led)
+        };
+        if observer {
+            return Ok(());
+        }
+
+        self.last_wheel_mode = None;
+        driver::set_wheel_mode(&self.device, wheel_mode)?;
+        self.last_wheel_mode = Some(wheel_mode);
+        self.last_button_led = None;
+        self.last_wheel_led = None;
+        driver::set_button_led(&self.device, self.device_info.model, button_led)
+            .inspect_err(|_| shared.counters.record_led_write_error())?;
+        self.last_button_led = Some(button_led);
+        driver::set_wheel_led(&self.device, wheel_led)
+            .inspect_err(|_| shared.counters.record_led_write_error())?;
+        self.last_wheel_led = Some(wheel_led);
+        self.last_led_write = Instant::now();
+        self.last_activity = self.last_led_write;
+        Ok(())
+    }
+
+    /// Authenticates the device again when it is due.
+    ///
+    /// This takes a few round trips. Only one is made per call,
+    /// so reports keep being read in between.
+    fn maintain_auth(&mut self, inner: &InnerLock, shared: &Shared) -> Result<(), crate::Error> {
+        if self.reauth.is_none() && Instant::now() >= self.next_auth {
+            let host_challenge = self.verify_device.then(AuthSession::random_host_challenge);
+            self.reauth = Some(driver::auth_session(host_challenge));
+        }
+        let Some(session) = &mut self.reauth else { return Ok(()) };
+
+        match driver::authenticate_step(&self.device, session) {
+            Ok(None) => {}
+            Ok(Some(auth_time)) => {
+                self.reauth = None;
+                (self.next_auth, self.auth_expiry) =
+                    self.auth_schedule.next_auth(auth_time, shared);
+                shared.counters.record_auth_renewal();
+                self.failed_auth_attempts = 0;
+            }
+            Err(error) => {
+                self.reauth = None;
+                // Retry later, while still reading reports in the meantime.
+                let AuthRetryPolicy { max_attempts, delay } =
+                    inner.lock_unpoisoned().auth_retry_policy;
+                self.failed_auth_attempts += 1;
+                // There is no point in trying again if the device went away.
+                if self.failed_auth_attempts >= max_attempts
+                    || matches!(error, crate::Error::Disconnected { .. })
+                {
+                    return Err(error);
+                }
+                inner.lock_unpoisoned().report_error(error, false);
+                self.next_auth = Instant::now() + delay;
+                shared.health.set_next_auth(self.next_auth);
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads and handles the reports from the device, waiting up to `timeout` for the first.
+    ///
+    /// The device queues reports while nothing reads them (e.g. during a fast spin of the
+    /// wheel), which are all handled before returning.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the device is considered disconnected. Other errors are reported,
+    /// after waiting a bit to keep a failing device from spinning the thread.
+    pub(crate) fn read(
+        &mut self,
+        timeout: Duration,
+        inner: &InnerLock,
+        shared: &Shared,
+    ) -> Result<(), crate::Error> {
+        let mut buf = [0x00; 64];
+        let mut read_timeout = timeout.as_millis().min(i32::MAX as u128) as i32;
+        let mut handled_reports = false;
+        for _ in 0..Self::MAX_REPORTS_PER_PASS {
+            let report_bytes = match driver::read(&self.device, &mut buf, read_timeout) {
+                Ok(Some(report_bytes)) => {
+                    shared.health.record_report();
+                    self.consecutive_failures = 0;
+                    self.last_activity = Instant::now();
+                    if report_bytes.first() == Some(&BUTTONS_REPORT_ID) {
+                        self.last_buttons_report = self.last_activity;
+                    }
+                    report_bytes
+                }
+                // Timing out without a report is not a failure.
+                Ok(None) => break,
+                Err(error) => {
+                    shared.health.record_read_error();
+                    shared.counters.record_read_error();
+                    self.consecutive_failures += 1;
+                    // There is no point in trying again if the device went away.
+                    if self.consecutive_failures >= Self::MAX_CONSECUTIVE_FAILURES
+                        || matches!(error, crate::Error::Disconnected { .. })
+                    {
+                        return Err(error);
+                    }
+                    inner.lock_unpoisoned().report_error(error, false);
+                    // Reads fail right away, so waiting keeps a failing device from spinning the
+                    // thread. The wait doubles with every failure, starting at 1 millisecond.
+                    let backoff =
+                        Duration::from_millis(1 << (self.consecutive_failures - 1).min(8));
+                    sleep_unless_shutdown(shared, backoff.min(Self::MAX_READ_BACKOFF));
+                    break;
+                }
+            };
+
+            handle_report(report_bytes, self.device_info.model, false, inner, shared);
+            handled_reports = true;
+            read_timeout = 0;
+        }
+        if handled_reports {
+            inner.lock_unpoisoned().flush_state_diff();
+        }
+        Ok(())
+    }
+
+    /// Ends the session without touching the device, and returns it with its authentication.
+    pub(crate) fn detach(self) -> (Box<dyn HidBackend>, ResumedAuth) {
+        (self.device.device, ResumedAuth { next_auth: self.next_auth, expiry: self.auth_expiry })
+    }
+
+    /// Ends the session, turning off the LEDs if the [`ShutdownPolicy`] asks for it.
+    pub(crate) fn finish(self, inner: &InnerLock, shared: &Shared) -> Result<(), crate::Error> {
+        let (shutdown_policy, observer) = {
+            let inner_guard = inner.lock_unpoisoned();
+            (inner_guard.shutdown_policy, inner_guard.observer)
+        };
+        if shutdown_policy == ShutdownPolicy::ClearLeds && !observer {
+            driver::set_button_led(&self.device, self.device_info.model, ButtonLed::Off)
+                .inspect_err(|_| shared.counters.record_led_write_error())?;
+            driver::set_wheel_led(&self.device, WheelLed::Off)
+                .inspect_err(|_| shared.counters.record_led_write_error())?;
+        }
+        Ok(())
+    }
+}
+
+/// The authentication of a device that is handed over to a new session,
+/// see [`SpeedEditor::into_parts`][crate::SpeedEditor::into_parts].
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct ResumedAuth {
+    /// When the device should be authenticated again.
+    pub(crate) next_auth: Instant,
+    /// When the authentication of the device expires.
+    pub(crate) expiry: Instant,
+}
+
+/// When to authenticate the device again.
+struct AuthSchedule {
+    /// How long before the authentication expires to authenticate again.
+    margin: Option<Duration>,
+    /// The timeout to use if it is shorter than the one returned by the device.
+    timeout: Option<Duration>,
+}
+
+impl AuthSchedule {
+    const DEFAULT_MARGIN: Duration = Duration::from_secs(5);
+    /// The longest timeout that is trusted. The device usually returns 600 seconds.
+    const MAX_TIMEOUT: Duration = Duration::from_secs(3600);
+    /// How soon to authenticate again if the device returned a timeout of 0.
+    const ZERO_TIMEOUT_RETRY: Duration = Duration::from_secs(1);
+
+    /// Returns when the device should be authenticated again and when its authentication
+    /// expires, given the timeout (in seconds) it returned from the last authentication,
+    /// and updates the health accordingly.
+    fn next_auth(&self, auth_time: u16, shared: &Shared) -> (Instant, Instant) {
+        let now = Instant::now();
+
+        // The device was seen returning 0 after a brownout,
+        // in which case it is not clear whether it is authenticated at all.
+        if auth_time == 0 {
+            diagnostics::recoverable!("authenticated with a timeout of 0 seconds, retrying soon");
+            shared.counters.record_invalid_auth_timeout();
+            let next_auth = now + Self::ZERO_TIMEOUT_RETRY;
+            shared.health.set_next_auth(next_auth);
+            shared.health.set_auth_expiry(now);
+            return (next_auth, now);
+        }
+
+        diagnostics::connection!("authenticated for {auth_time} seconds");
+        let reported = Duration::from_secs(auth_time as u64);
+        let mut timeout = reported.min(Self::MAX_TIMEOUT);
+        if timeout != reported {
+            diagnostics::recoverable!(
+                "authenticated with a timeout of {auth_time} seconds, using {} seconds instead",
+                timeout.as_secs()
+            );
+            shared.counters.record_invalid_auth_timeout();
+        }
+        if let Some(override_timeout) = self.timeout {
+            timeout = timeout.min(override_timeout);
+        }
+        // A margin that is too large for the timeout would authenticate all the time.
+        let margin = self.margin.unwrap_or(Self::DEFAULT_MARGIN).min(timeout / 2);
+
+        let next_auth = now + timeout.saturating_sub(margin);
+        shared.health.set_next_auth(next_auth);
+        shared.health.set_auth_expiry(now + timeout);
+        (next_auth, now + timeout)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{Arc, Mutex, mpsc},
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use hidapi::HidError;
+
+    use super::{AuthSchedule, Session};
+    use crate::dispatch::InnerLock;
+    use crate::poller::{PollOptions, Shared};
+    use crate::protocol::{self, AUTH_REPORT_ID};
+    use crate::sync::MutexExt;
+    use crate::test_util::{TIMEOUT, connect_with, wait_for};
+    use crate::testing::{FakeBackend, Fault};
+    use crate::{
+        AuthFailure, AuthRetryPolicy, AuthStep, Button, ButtonLed, DeviceInfo, Error, HidBackend,
+        Inner, SpeedEditor, SpeedEditorBuilder, Transport, WheelLed, WheelMode,
+    };
+
+    const SCHEDULE: AuthSchedule = AuthSchedule { margin: None, timeout: None };
+
+    /// Returns how long after now the device is authenticated again and its authentication
+    /// expires, and whether the timeout was counted as invalid.
+    fn schedule(schedule: &AuthSchedule, auth_time: u16) -> (Duration, Duration, bool) {
+        let shared = Shared::new(PollOptions::default());
+        let before = Instant::now();
+        let (next_auth, expiry) = schedule.next_auth(auth_time, &shared);
+        let invalid = shared.counters.snapshot().invalid_auth_timeouts > 0;
+        (next_auth - before, expiry - before, invalid)
+    }
+
+    /// Asserts that `duration` is `expected`, give or take the time the test took.
+    fn assert_about(duration: Duration, expected: Duration) {
+        assert!(
+            duration >= expected && duration < expected + Duration::from_secs(1),
+            "{duration:?} is not about {expected:?}"
+        );
+    }
+
+    #[test]
+    fn zero_timeout_retries_soon() {
+        let (next_auth, expiry, invalid) = schedule(&SCHEDULE, 0);
+        assert_about(next_auth, AuthSchedule::ZERO_TIMEOUT_RETRY);
+        assert_about(expiry, Duration::ZERO);
+        assert!(invalid);
+    }
+
+    #[test]
+    fn short_timeouts_are_trusted() {
+        let (next_auth, expiry, invalid) = schedule(&SCHEDULE, 1);
+        // The margin is at most half of the timeout.
+        assert_about(next_auth, Duration::from_millis(500));
+        assert_about(expiry, Duration::from_secs(1));
+        assert!(!invalid);
+
+        let (next_auth, expiry, invalid) = schedule(&SCHEDULE, 9);
+        assert_about(next_auth, Duration::from_millis(4500));
+        assert_about(expiry, Duration::from_secs(9));
+        assert!(!invalid);
+    }
+
+    #[test]
+    fn usual_timeout_keeps_margin() {
+        let (next_auth, expiry, invalid) = schedule(&SCHEDULE, 600);
+        assert_about(next_auth, Duration::from_secs(595));
+        assert_about(expiry, Duration::from_secs(600));
+        assert!(!invalid);
+    }
+
+    #[test]
+    fn long_timeout_is_clamped() {
+        let (next_auth, expiry, invalid) = schedule(&SCHEDULE, u16::MAX);
+        assert_about(next_auth, AuthSchedule::MAX_TIMEOUT - AuthSchedule::DEFAULT_MARGIN);
+        assert_about(expiry, AuthSchedule::MAX_TIMEOUT);
+        assert!(invalid);
+    }
+
+    #[test]
+    fn shorter_override_is_used() {
+        let overridden = AuthSchedule {
+            margin: Some(Duration::from_secs(2)),
+            timeout: Some(Duration::from_secs(30)),
+        };
+        let (next_auth, expiry, invalid) = schedule(&overridden, 600);
+        assert_about(next_auth, Duration::from_secs(28));
+        assert_about(expiry, Duration::from_secs(30));
+        assert!(!invalid);
+    }
+
+    /// Queues the feature reports of an authentication that asks to authenticate again
+    /// right away, as its timeout is within the margin of 5 seconds.
+    fn push_short_auth_handshake(backend: &FakeBackend) {
+        backend.push_feature_report(&[0x06, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]);
+        backend.push_feature_report(&[0x06, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]);
+        backend.push_feature_report(&[0x06, 0x04, 0x05, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]);
+    }
+
+    fn wheel_report(velocity: u8) -> [u8; 7] {
+        [0x03, 0x00, velocity, 0x00, 0x00, 0x00, 0x00]
+    }
+
+    fn buttons_report(buttons: &[Button]) -> [u8; 13] {
+        let mut report = [0x00; 13];
+        report[0] = 0x04;
+        for (slot, button) in buttons.iter().enumerate() {
+            report[1 + slot * 2..3 + slot * 2].copy_from_slice(&(*button as u16).to_le_bytes());
+        }
+        report
+    }
+
+    /// Sends whether each authentication error was fatal.
+    fn auth_errors(builder: SpeedEditorBuilder) -> (SpeedEditorBuilder, mpsc::Receiver<bool>) {
+        let (sender, receiver) = mpsc::channel();
+        let builder = builder.on_error(move |error| {
+            if matches!(error.error, Error::Authentication { .. }) {
+                let _ = sender.send(error.fatal);
+            }
+        });
+        (builder, receiver)
+    }
+
+    #[test]
+    fn failed_reauth_is_retried_while_reading() {
+        let backend = FakeBackend::new();
+        push_short_auth_handshake(&backend);
+        // The first renewal fails reading the challenge, the second succeeds.
+        backend.push_feature_report_error("Broken");
+        backend.push_auth_handshake();
+        let (wheel_sender, wheel_receiver) = mpsc::channel();
+        let (builder, errors) = auth_errors(
+            SpeedEditor::builder()
+                .auth_retry_policy(AuthRetryPolicy {
+                    max_attempts: 2,
+                    delay: Duration::from_millis(300),
+                })
+                .on_wheel_change(move |velocity| wheel_sender.send(velocity).unwrap()),
+        );
+        let speed_editor = connect_with(builder, backend.clone());
+        assert_eq!(errors.recv_timeout(TIMEOUT), Ok(false));
+
+        // Reports are read while waiting to retry.
+        backend.push_input_report(&wheel_report(5));
+        assert_eq!(wheel_receiver.recv_timeout(TIMEOUT), Ok(5));
+        assert_eq!(speed_editor.counters().auth_renewals, 0);
+
+        assert!(wait_for(|| speed_editor.counters().auth_renewals == 1));
+        assert!(speed_editor.is_connected());
+        assert_eq!(errors.try_recv(), Err(mpsc::TryRecvError::Empty));
+    }
+
+    #[test]
+    fn reauth_gives_up_after_max_attempts() {
+        let backend = FakeBackend::new();
+        push_short_auth_handshake(&backend);
+        let (disconnect_sender, disconnect_receiver) = mpsc::channel();
+        let (builder, errors) = auth_errors(
+            SpeedEditor::builder()
+                .auth_retry_policy(AuthRetryPolicy {
+                    max_attempts: 2,
+                    delay: Duration::from_millis(10),
+                })
+                .on_disconnect(move || disconnect_sender.send(()).unwrap()),
+        );
+        // Without another handshake, every authentication after the first fails.
+        let speed_editor = connect_with(builder, backend.clone());
+
+        assert_eq!(errors.recv_timeout(TIMEOUT), Ok(false));
+        assert_eq!(errors.recv_timeout(TIMEOUT), Ok(true));
+        assert_eq!(disconnect_receiver.recv_timeout(TIMEOUT), Ok(()));
+        assert!(!speed_editor.is_connected());
+        assert_eq!(speed_editor.counters().auth_renewals, 0);
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    enum Traffic {
+        Auth,
+        Wheel(u8),
+    }
+
+    /// A [`FakeBackend`] that logs the authentication round trips and the wheel reports it
+    /// returns, and queues wheel reports once the device is authenticated again.
+    struct ReauthBackend {
+        backend: FakeBackend,
+        log: Arc<Mutex<Vec<Traffic>>>,
+    }
+
+    impl ReauthBackend {
+        /// The number of round trips of a handshake.
+        const AUTH_ROUND_TRIPS: usize = 6;
+
+        fn log_auth(&self) {
+            let mut log = self.log.lock_unpoisoned();
+            log.push(Traffic::Auth);
+            // The first round trip of the second handshake.
+            if log.len() == Self::AUTH_ROUND_TRIPS + 1 {
+                for velocity in 1..=3 {
+                    self.backend.push_input_report(&wheel_report(velocity));
+                }
+            }
+        }
+    }
+
+    impl HidBackend for ReauthBackend {
+        fn device_info(&self) -> Result<DeviceInfo, HidError> {
+            self.backend.device_info()
+        }
+
+        fn write(&self, data: &[u8]) -> Result<usize, HidError> {
+            self.backend.write(data)
+        }
+
+        fn read_timeout(&self, buf: &mut [u8], timeout: i32) -> Result<usize, HidError> {
+            let len = self.backend.read_timeout(buf, timeout)?;
+            if len > 0 && buf[0] == protocol::WHEEL_REPORT_ID {
+                self.log.lock_unpoisoned().push(Traffic::Wheel(buf[2]));
+            }
+            Ok(len)
+        }
+
+        fn send_feature_report(&self, data: &[u8]) -> Result<(), HidError> {
+            self.backend.send_feature_report(data)?;
+            if data[0] == AUTH_REPORT_ID {
+                self.log_auth();
+            }
+            Ok(())
+        }
+
+        fn get_feature_report(&self, buf: &mut [u8]) -> Result<usize, HidError> {
+            let len = self.backend.get_feature_report(buf)?;
+            if buf[0] == AUTH_REPORT_ID {
+                self.log_auth();
+            }
+            Ok(len)
+        }
+
+        fn get_input_report(&self, buf: &mut [u8]) -> Result<usize, HidError> {
+            self.backend.get_input_report(buf)
+        }
+    }
+
+    #[test]
+    fn reports_are_read_during_reauth() {
+        let backend = FakeBackend::new();
+        backend.push_auth_handshake();
+        backend.push_auth_handshake();
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let (sender, receiver) = mpsc::channel();
+        let speed_editor = connect_with(
+            SpeedEditor::builder()
+                .auth_timeout(Some(Duration::from_millis(200)))
+                .on_wheel_change(move |velocity| sender.send(velocity).unwrap()),
+            ReauthBackend { backend: backend.clone(), log: log.clone() },
+        );
+
+        assert!(wait_for(|| speed_editor.counters().auth_renewals == 1));
+        let velocities: Vec<_> = (0..3).map(|_| receiver.recv_timeout(TIMEOUT).unwrap()).collect();
+        assert_eq!(velocities, [1, 2, 3]);
+
+        // The reports queued by the first round trip are handled before the second.
+        let log = log.lock_unpoisoned();
+        let reauth = &log[ReauthBackend::AUTH_ROUND_TRIPS..][..ReauthBackend::AUTH_ROUND_TRIPS + 3];
+        assert_eq!(
+            reauth,
+            [
+                Traffic::Auth,
+                Traffic::Wheel(1),
+                Traffic::Wheel(2),
+                Traffic::Wheel(3),
+                Traffic::Auth,
+                Traffic::Auth,
+                Traffic::Auth,
+                Traffic::Auth,
+                Traffic::Auth,
+            ]
+        );
+    }
+
+    fn bluetooth_backend() -> FakeBackend {
+        let device_info = DeviceInfo {
+            transport: Transport::Bluetooth,
+            ..FakeBackend::new().device_info().unwrap()
+        };
+        let backend = FakeBackend::with_device_info(device_info);
+        backend.push_auth_handshake();
+        backend
+    }
+
+    /// Returns how often the wheel LED was written to `backend`.
+    fn wheel_led_writes(backend: &FakeBackend) -> usize {
+        let report = protocol::wheel_led_report(WheelLed::default());
+        backend.written().iter().filter(|written| **written == report).count()
+    }
+
+    #[test]
+    fn keepalive_writes_wheel_led_at_interval() {
+        let backend = bluetooth_backend();
+        let _speed_editor = connect_with(
+            SpeedEditor::builder().keepalive(Some(Duration::from_millis(50))),
+            backend.clone(),
+        );
+
+        thread::sleep(Duration::from_millis(500));
+        // Once after connecting, and about every 50 milliseconds after that.
+        let writes = wheel_led_writes(&backend);
+        assert!((3..=12).contains(&writes), "{writes} writes");
+    }
+
+    #[test]
+    fn keepalive_is_off_by_default_and_over_usb() {
+        let bluetooth = bluetooth_backend();
+        let _bluetooth = connect_with(SpeedEditor::builder(), bluetooth.clone());
+
+        let usb = FakeBackend::new();
+        usb.push_auth_handshake();
+        let _usb = connect_with(
+            SpeedEditor::builder().keepalive(Some(Duration::from_millis(50))),
+            usb.clone(),
+        );
+
+        thread::sleep(Duration::from_millis(300));
+        assert_eq!(wheel_led_writes(&bluetooth), 1);
+        assert_eq!(wheel_led_writes(&usb), 1);
+    }
+
+    /// Connects while sending the button changes.
+    fn connect_buttons(
+        builder: SpeedEditorBuilder,
+    ) -> (SpeedEditor, FakeBackend, mpsc::Receiver<(Button, bool)>) {
+        let backend = FakeBackend::new();
+        backend.push_auth_handshake();
+        let (sender, receiver) = mpsc::channel();
+        let speed_editor = connect_with(
+            builder
+                .on_button_change(move |button, pressed| sender.send((button, pressed)).unwrap()),
+            backend.clone(),
+        );
+        (speed_editor, backend, receiver)
+    }
+
+    #[test]
+    fn buttons_report_releases_missing_buttons() {
+        let (speed_editor, backend, receiver) = connect_buttons(SpeedEditor::builder());
+
+        backend.push_input_report(&buttons_report(&[Button::Cut]));
+        assert_eq!(receiver.recv_timeout(TIMEOUT), Ok((Button::Cut, true)));
+
+        // The release of Cut was missed, the next report has only In.
+        backend.push_input_report(&buttons_report(&[Button::In]));
+        let changes: Vec<_> = (0..2).map(|_| receiver.recv_timeout(TIMEOUT).unwrap()).collect();
+        assert!(changes.contains(&(Button::Cut, false)), "{changes:?}");
+        assert!(changes.contains(&(Button::In, true)), "{changes:?}");
+        assert_eq!(speed_editor.pressed_buttons(), [Button::In]);
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    #[test]
+    fn stuck_button_is_released_by_requested_report() {
+        let (speed_editor, backend, receiver) = connect_buttons(
+            SpeedEditor::builder().stuck_button_timeout(Some(Duration::from_millis(50))),
+        );
+
+        backend.push_input_report(&buttons_report(&[Button::Cut]));
+        assert_eq!(receiver.recv_timeout(TIMEOUT), Ok((Button::Cut, true)));
+
+        // The release is never pushed, only returned when the buttons report is requested.
+        backend.set_unresponsive(true);
+        backend.push_input_report(&buttons_report(&[]));
+        assert_eq!(receiver.recv_timeout(TIMEOUT), Ok((Button::Cut, false)));
+        assert!(speed_editor.pressed_buttons().is_empty());
+    }
+
+    /// Connects while sending disconnects.
+    fn connect_disconnects() -> (SpeedEditor, FakeBackend, mpsc::Receiver<()>) {
+        let backend = FakeBackend::new();
+        backend.push_auth_handshake();
+        let (sender, receiver) = mpsc::channel();
+        let speed_editor = connect_with(
+            SpeedEditor::builder().on_disconnect(move || sender.send(()).unwrap()),
+            backend.clone(),
+        );
+        (speed_editor, backend, receiver)
+    }
+
+    #[test]
+    fn failing_reads_back_off_until_disconnected() {
+        let (speed_editor, backend, receiver) = connect_disconnects();
+
+        let start = Instant::now();
+        backend.fail_reads(u32::MAX, Fault::Error);
+        assert_eq!(receiver.recv_timeout(TIMEOUT), Ok(()));
+        // The waits after the first 9 failures add up to about half a second.
+        assert!(start.elapsed() >= Duration::from_millis(450), "{:?}", start.elapsed());
+        assert_eq!(speed_editor.counters().read_errors, 10);
+    }
+
+    #[test]
+    fn successful_read_resets_failures() {
+        let (speed_editor, backend, receiver) = connect_disconnects();
+
+        for _ in 0..2 {
+            backend.fail_reads(9, Fault::Error);
+            backend.push_input_report(&wheel_report(5));
+            assert!(backend.wait_until_idle(TIMEOUT));
+        }
+        assert!(speed_editor.is_connected());
+        assert_eq!(speed_editor.counters().read_errors, 18);
+        assert_eq!(receiver.try_recv(), Err(mpsc::TryRecvError::Empty));
+    }
+
+    #[test]
+    fn disconnected_read_ends_session_right_away() {
+        let (speed_editor, backend, receiver) = connect_disconnects();
+
+        backend.fail_reads(1, Fault::Disconnected);
+        backend.push_input_report(&wheel_report(5));
+        assert_eq!(receiver.recv_timeout(TIMEOUT), Ok(()));
+        assert_eq!(speed_editor.counters().read_errors, 1);
+    }
+
+    #[test]
+    fn queued_reports_are_handled_in_one_pass() {
+        let backend = FakeBackend::new();
+        backend.push_auth_handshake();
+        let (diff_sender, diff_receiver) = mpsc::channel();
+        let (wheel_sender, wheel_receiver) = mpsc::channel();
+        let _speed_editor = connect_with(
+            SpeedEditor::builder()
+                .on_state_change(move |diff| diff_sender.send(diff.clone()).unwrap())
+                .on_wheel_change(move |velocity| wheel_sender.send(velocity).unwrap()),
+            backend.clone(),
+        );
+        assert_eq!(diff_receiver.recv_timeout(TIMEOUT).unwrap().connected, Some(true));
+
+        // The reports queue up like they do while the poller is busy.
+        backend.set_unresponsive(true);
+        for velocity in 1..=10 {
+            backend.push_input_report(&wheel_report(velocity));
+        }
+        backend.set_unresponsive(false);
+
+        // A state change is notified once per pass.
+        assert_eq!(diff_receiver.recv_timeout(TIMEOUT).unwrap().wheel_delta, 55);
+        let velocities: Vec<_> =
+            (0..10).map(|_| wheel_receiver.recv_timeout(TIMEOUT).unwrap()).collect();
+        assert_eq!(velocities, (1..=10).collect::<Vec<_>>());
+        assert!(backend.wait_until_idle(TIMEOUT));
+        assert_eq!(diff_receiver.try_recv(), Err(mpsc::TryRecvError::Empty));
+    }
+
+    #[test]
+    fn wheel_mode_is_written_when_reconnecting() {
+        let backend = FakeBackend::new();
+        let inner =
+            InnerLock::new(Inner { wheel_mode: WheelMode::AbsoluteDeadZero, ..Inner::default() });
+        let shared = Shared::new(PollOptions::default());
+        let wheel_mode = protocol::wheel_mode_report(WheelMode::AbsoluteDeadZero).to_vec();
+
+        // Every connection starts a session, also after another application used the device.
+        for connections in 1..=2 {
+            backend.push_auth_handshake();
+            let session = Session::start(Box::new(backend.clone()), &inner, &shared).unwrap();
+            assert_eq!(backend.written().last(), Some(&wheel_mode));
+            assert_eq!(backend.written().len(), connections);
+            drop(session);
+        }
+    }
+
+    #[test]
+    fn resume_restores_wheel_mode() {
+        let backend = FakeBackend::new();
+        backend.push_auth_handshake();
+        let (sender, receiver) = mpsc::channel();
+        let speed_editor = connect_with(
+            SpeedEditor::builder()
+                .wheel_mode(WheelMode::AbsoluteContinuous)
+                .on_button_change(move |button, pressed| sender.send((button, pressed)).unwrap()),
+            backend.clone(),
+        );
+        let restored = [
+            protocol::wheel_mode_report(WheelMode::AbsoluteContinuous).to_vec(),
+            protocol::button_led_report(ButtonLed::Off).to_vec(),
+            protocol::wheel_led_report(WheelLed::Off).to_vec(),
+        ];
+        assert!(wait_for(|| backend.written() == restored));
+
+        // Releasing the pressed button tells that the poller is paused.
+        backend.push_input_report(&[0x04, 0x0f, 0x00, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(receiver.recv_timeout(TIMEOUT), Ok((Button::Cut, true)));
+        speed_editor.pause(false);
+        assert_eq!(receiver.recv_timeout(TIMEOUT), Ok((Button::Cut, false)));
+
+        // Another application might have changed the wheel mode in the meantime.
+        speed_editor.resume();
+        assert!(wait_for(|| backend.written().len() == 6));
+        assert_eq!(backend.written()[3..], restored);
+    }
+
+    #[test]
+    fn leds_are_only_written_when_changed() {
+        let backend = FakeBackend::new();
+        backend.push_auth_handshake();
+        let speed_editor = connect_with(SpeedEditor::builder(), backend.clone());
+
+        // The wheel mode and both LEDs are written after connecting.
+        assert!(wait_for(|| backend.written().len() == 3));
+        assert_eq!(
+            backend.written(),
+            [
+                protocol::wheel_mode_report(WheelMode::Relative).to_vec(),
+                protocol::button_led_report(ButtonLed::Off).to_vec(),
+                protocol::wheel_led_report(WheelLed::Off).to_vec(),
+            ]
+        );
+
+        speed_editor.set_button_led(ButtonLed::Cut);
+        assert!(wait_for(|| backend.written().len() == 4));
+        speed_editor.set_button_led(ButtonLed::Cut);
+        speed_editor.set_wheel_led(WheelLed::Jog);
+        assert!(wait_for(|| backend.written().len() == 5));
+        speed_editor.set_wheel_mode(WheelMode::AbsoluteContinuous);
+        assert!(wait_for(|| backend.written().len() == 6));
+
+        // Passes without changes write nothing.
+        backend.push_input_report(&wheel_report(5));
+        assert!(backend.wait_until_idle(TIMEOUT));
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(
+            backend.written()[3..],
+            [
+                protocol::button_led_report(ButtonLed::Cut).to_vec(),
+                protocol::wheel_led_report(WheelLed::Jog).to_vec(),
+                protocol::wheel_mode_report(WheelMode::AbsoluteContinuous).to_vec(),
+            ]
+        );
+    }
+
+    #[test]
+    fn observer_does_not_write_leds() {
+        let backend = FakeBackend::new();
+        backend.push_auth_handshake();
+        let speed_editor = connect_with(SpeedEditor::builder().observer(true), backend.clone());
+
+        speed_editor.set_button_led(ButtonLed::Cut);
+        backend.push_input_report(&wheel_report(5));
+        assert!(backend.wait_until_idle(TIMEOUT));
+        thread::sleep(Duration::from_millis(50));
+        assert!(backend.written().is_empty());
+    }
+
+    #[test]
+    fn verify_device_rejects_wrong_response() {
+        // The scripted device answers every challenge with zeros.
+        let backend = FakeBackend::new();
+        backend.push_auth_handshake();
+        let (sender, receiver) = mpsc::channel();
+        let _speed_editor = SpeedEditor::builder()
+            .verify_device(true)
+            .on_error(move |error| {
+                let _ = sender.send(error.error.clone());
+            })
+            .connect_backend(backend.clone())
+            .unwrap();
+
+        let error = receiver.recv_timeout(TIMEOUT).unwrap();
+        assert!(
+            matches!(
+                error,
+                Error::Authentication {
+                    step: AuthStep::ReadResponse,
+                    detail: AuthFailure::ResponseMismatch { received: 0, .. },
+                }
+            ),
+            "{error}"
+        );
+        // The challenge that was sent is random instead of zero.
+        assert_ne!(backend.sent_feature_reports()[1][2..], [0x00; 8]);
+    }
+}
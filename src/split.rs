@@ -0,0 +1,282 @@
+use std::{sync::mpsc, time::Duration};
+
+use crate::{
+    BatteryInfo, Button, ButtonLed, DeviceInfo, Event, EventSink, PollerError, SpeedEditor,
+    StateDiff, WheelLed,
+};
+
+/// The half of a [`SpeedEditor`] that receives its events, see [`SpeedEditor::split`].
+///
+/// Dropping it unregisters all callbacks and sinks, so events are not delivered anymore.
+/// The device stays open as long as a [`ControlHalf`] is left.
+pub struct EventHalf {
+    speed_editor: SpeedEditor,
+}
+
+/// The half of a [`SpeedEditor`] that controls the device, see [`SpeedEditor::split`].
+///
+/// It can be cloned to control the device from multiple places.
+/// Dropping all clones leaves the LEDs as they are, and keeps the device open
+/// as long as the [`EventHalf`] is left.
+#[derive(Clone)]
+pub struct ControlHalf {
+    speed_editor: SpeedEditor,
+}
+
+impl SpeedEditor {
+    /// Splits the [`SpeedEditor`] into the half that receives its events
+    /// and the half that controls the device, to hand them to different tasks.
+    ///
+    /// Both halves keep the device open. Dropping both stops it,
+    /// just like dropping the last clone of a [`SpeedEditor`].
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use bmdse::{ButtonLed, Event, SpeedEditor};
+    ///
+    /// let (events, control) = SpeedEditor::new().unwrap().split();
+    ///
+    /// std::thread::spawn(move || {
+    ///     control.set_button_led(ButtonLed::Cam1);
+    /// });
+    ///
+    /// for event in events.events() {
+    ///     if let Event::ButtonChange { button, pressed } = event {
+    ///         eprintln!("button {button:?} {}", if pressed { "pressed" } else { "released" });
+    ///     }
+    /// }
+    /// ```
+    pub fn split(self) -> (EventHalf, ControlHalf) {
+        (EventHalf { speed_editor: self.clone() }, ControlHalf { speed_editor: self })
+    }
+}
+
+impl EventHalf {
+    /// See [`SpeedEditor::set_on_wheel_change`].
+    pub fn set_on_wheel_change<F: Fn(i32) + Send + 'static>(&self, f: F) {
+        self.speed_editor.set_on_wheel_change(f);
+    }
+
+    /// See [`SpeedEditor::set_on_button_change`].
+    pub fn set_on_button_change<F: Fn(Button, bool) + Send + 'static>(&self, f: F) {
+        self.speed_editor.set_on_button_change(f);
+    }
+
+    /// See [`SpeedEditor::set_on_battery_info`].
+    pub fn set_on_battery_info<F: Fn(bool, u8) + Send + 'static>(&self, f: F) {
+        self.speed_editor.set_on_battery_info(f);
+    }
+
+    /// See [`SpeedEditor::set_on_charging_change`].
+    pub fn set_on_charging_change<F: Fn(bool) + Send + 'static>(&self, f: F) {
+        self.speed_editor.set_on_charging_change(f);
+    }
+
+    /// See [`SpeedEditor::set_on_raw_report`].
+    pub fn set_on_raw_report<F: Fn(&[u8]) + Send + 'static>(&self, f: F) {
+        self.speed_editor.set_on_raw_report(f);
+    }
+
+    /// See [`SpeedEditor::set_on_connect`].
+    pub fn set_on_connect<F: Fn(DeviceInfo) + Send + 'static>(&self, f: F) {
+        self.speed_editor.set_on_connect(f);
+    }
+
+    /// See [`SpeedEditor::set_on_disconnect`].
+    pub fn set_on_disconnect<F: Fn() + Send + 'static>(&self, f: F) {
+        self.speed_editor.set_on_disconnect(f);
+    }
+
+    /// See [`SpeedEditor::set_on_error`].
+    pub fn set_on_error<F: Fn(&PollerError) + Send + 'static>(&self, f: F) {
+        self.speed_editor.set_on_error(f);
+    }
+
+    /// See [`SpeedEditor::set_on_state_change`].
+    pub fn set_on_state_change<F: Fn(&StateDiff) + Send + 'static>(&self, f: F) {
+        self.speed_editor.set_on_state_change(f);
+    }
+
+    /// See [`SpeedEditor::set_on_idle`].
+    pub fn set_on_idle<F: Fn() + Send + 'static>(&self, threshold: Duration, f: F) {
+        self.speed_editor.set_on_idle(threshold, f);
+    }
+
+    /// See [`SpeedEditor::set_on_active`].
+    pub fn set_on_active<F: Fn() + Send + 'static>(&self, f: F) {
+        self.speed_editor.set_on_active(f);
+    }
+
+    /// See [`SpeedEditor::idle_for`].
+    pub fn idle_for(&self) -> Duration {
+        self.speed_editor.idle_for()
+    }
+
+    /// See [`SpeedEditor::attach_sink`].
+    pub fn attach_sink(&self, sink: Box<dyn EventSink>) {
+        self.speed_editor.attach_sink(sink);
+    }
+
+    /// Returns a channel that receives all [`Event`]s from now on.
+    ///
+    /// The channel is closed when the polling thread stops.
+    pub fn events(&self) -> mpsc::Receiver<Event> {
+        let (sender, receiver) = mpsc::channel();
+        self.attach_sink(Box::new(sender));
+        receiver
+    }
+
+    /// See [`SpeedEditor::last_error`].
+    pub fn last_error(&self) -> Option<PollerError> {
+        self.speed_editor.last_error()
+    }
+
+    /// See [`SpeedEditor::is_button_pressed`].
+    pub fn is_button_pressed(&self, button: Button) -> bool {
+        self.speed_editor.is_button_pressed(button)
+    }
+
+    /// See [`SpeedEditor::pressed_buttons`].
+    pub fn pressed_buttons(&self) -> Vec<Button> {
+        self.speed_editor.pressed_buttons()
+    }
+}
+
+impl Drop for EventHalf {
+    fn drop(&mut self) {
+        self.speed_editor.inner.lock_unpoisoned().update_callbacks(|callbacks| {
+            callbacks.on_wheel_change = None;
+            callbacks.on_button_change = None;
+            callbacks.on_battery_info = None;
+            callbacks.on_charging_change = None;
+            callbacks.on_raw_report = None;
+            callbacks.on_connect = None;
+            callbacks.on_disconnect = None;
+            callbacks.on_error = None;
+            callbacks.on_state_change = None;
+            callbacks.on_idle = None;
+            callbacks.on_active = None;
+            callbacks.sinks.clear();
+        });
+    }
+}
+
+impl ControlHalf {
+    /// See [`SpeedEditor::set_button_led`].
+    pub fn set_button_led(&self, led: ButtonLed) {
+        self.speed_editor.set_button_led(led);
+    }
+
+    /// See [`SpeedEditor::button_led`].
+    pub fn button_led(&self) -> ButtonLed {
+        self.speed_editor.button_led()
+    }
+
+    /// See [`SpeedEditor::set_wheel_led`].
+    pub fn set_wheel_led(&self, led: WheelLed) {
+        self.speed_editor.set_wheel_led(led);
+    }
+
+    /// See [`SpeedEditor::wheel_led`].
+    pub fn wheel_led(&self) -> WheelLed {
+        self.speed_editor.wheel_led()
+    }
+
+    /// See [`SpeedEditor::request_battery_update`].
+    ///
+    /// # Errors
+    ///
+    /// See [`SpeedEditor::request_battery_update`].
+    pub fn request_battery_update(&self) -> Result<(), crate::Error> {
+        self.speed_editor.request_battery_update()
+    }
+
+    /// See [`SpeedEditor::reset_device`], which also writes the wheel mode again.
+    ///
+    /// # Errors
+    ///
+    /// See [`SpeedEditor::reset_device`].
+    pub fn reset_device(&self) -> Result<(), crate::Error> {
+        self.speed_editor.reset_device()
+    }
+
+    /// See [`SpeedEditor::battery_info`].
+    pub fn battery_info(&self) -> Option<BatteryInfo> {
+        self.speed_editor.battery_info()
+    }
+
+    /// See [`SpeedEditor::is_connected`].
+    pub fn is_connected(&self) -> bool {
+        self.speed_editor.is_connected()
+    }
+
+    /// See [`SpeedEditor::device_info`].
+    pub fn device_info(&self) -> Option<DeviceInfo> {
+        self.speed_editor.device_info()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::mpsc,
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use crate::test_util::{CUT_PRESSED, RELEASED, TIMEOUT};
+    use crate::{Button, ButtonLed, SpeedEditor, protocol, testing::FakeBackend};
+
+    fn split() -> (super::EventHalf, super::ControlHalf, FakeBackend) {
+        let backend = FakeBackend::new();
+        backend.push_auth_handshake();
+        let (events, control) =
+            SpeedEditor::builder().connect_backend(backend.clone()).unwrap().split();
+        (events, control, backend)
+    }
+
+    #[test]
+    fn control_half_sets_led_from_event_callback() {
+        let (events, control, backend) = split();
+
+        let (sender, receiver) = mpsc::channel();
+        events.set_on_button_change({
+            let control = control.clone();
+            move |button, pressed| {
+                if pressed {
+                    control.set_button_led(ButtonLed::Cut);
+                    sender.send(button).unwrap();
+                }
+            }
+        });
+        backend.push_input_report(&CUT_PRESSED);
+
+        assert_eq!(receiver.recv_timeout(TIMEOUT), Ok(Button::Cut));
+        assert_eq!(control.button_led(), ButtonLed::Cut);
+
+        let report = protocol::button_led_report(ButtonLed::Cut).to_vec();
+        let start = Instant::now();
+        while !backend.written().contains(&report) {
+            assert!(start.elapsed() < TIMEOUT, "LED report was not written");
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn dropping_event_half_unregisters_callbacks() {
+        let (events, control, backend) = split();
+
+        let (sender, receiver) = mpsc::channel();
+        events.set_on_button_change(move |button, pressed| sender.send((button, pressed)).unwrap());
+        backend.push_input_report(&CUT_PRESSED);
+        assert_eq!(receiver.recv_timeout(TIMEOUT), Ok((Button::Cut, true)));
+
+        drop(events);
+        backend.push_input_report(&RELEASED);
+        assert!(backend.wait_until_idle(TIMEOUT));
+        assert_eq!(receiver.try_recv(), Err(mpsc::TryRecvError::Disconnected));
+        control.set_button_led(ButtonLed::Cam1);
+        assert_eq!(control.button_led(), ButtonLed::Cam1);
+    }
+}
@@ -0,0 +1,98 @@
+use std::sync::atomic::Ordering;
+
+use crate::{Button, ButtonLed, SpeedEditor, WheelLed, WheelMode};
+
+/// A snapshot of the state of a [`SpeedEditor`], e.g. to save it with a session of an application.
+///
+/// See [`SpeedEditor::state`] and [`SpeedEditor::apply_state`].
+/// With the `serde` feature, it can be serialized and deserialized.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub struct DeviceState {
+    /// The button LED that is set.
+    pub button_led: ButtonLed,
+    /// The wheel LED that is set.
+    pub wheel_led: WheelLed,
+    /// The wheel mode that is set.
+    pub wheel_mode: WheelMode,
+    /// The sum of the wheel velocities in relative mode, or the last position in the absolute
+    /// modes. It starts at 0, and starts over when [resetting][SpeedEditor::reset_device].
+    pub wheel_position: i64,
+    /// The buttons that are pressed.
+    pub pressed_buttons: Vec<Button>,
+    /// The last reported battery percentage (`0..=100`),
+    /// or [`None`] if the battery has not been reported yet.
+    pub battery_level: Option<u8>,
+    /// Whether the battery was charging when it was last reported,
+    /// or [`None`] if the battery has not been reported yet.
+    pub charging: Option<bool>,
+    /// Whether the device is connected and responding.
+    pub connected: bool,
+}
+
+impl SpeedEditor {
+    /// Returns a snapshot of the state of the device.
+    ///
+    /// Unlike calling the separate getters, it is taken at once,
+    /// so it never mixes the state from before and after a report.
+    pub fn state(&self) -> DeviceState {
+        let inner = self.inner.lock_unpoisoned();
+        DeviceState {
+            button_led: inner.button_led,
+            wheel_led: inner.wheel_led,
+            wheel_mode: inner.wheel_mode,
+            wheel_position: inner.wheel_position,
+            pressed_buttons: inner.pressed_buttons.iter().collect(),
+            battery_level: inner.battery_info.map(|battery_info| battery_info.level),
+            charging: inner.battery_info.map(|battery_info| battery_info.charging),
+            connected: self.poller.shared.connected.load(Ordering::Acquire),
+        }
+    }
+
+    /// Restores the parts of the `state` that can be controlled, which are the LEDs,
+    /// the wheel mode and the wheel position.
+    ///
+    /// The LEDs and the wheel mode are written by the polling thread,
+    /// and are also restored after reconnecting.
+    pub fn apply_state(&self, state: &DeviceState) {
+        let mut inner = self.inner.lock_unpoisoned();
+        inner.set_button_led(state.button_led);
+        inner.set_wheel_led(state.wheel_led);
+        inner.wheel_mode = state.wheel_mode;
+        inner.wheel_position = state.wheel_position;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol;
+    use crate::test_util::{TIMEOUT, WHEEL_REPORT, connect, wait_for};
+
+    #[test]
+    fn state_tracks_wheel_mode_and_position() {
+        let (speed_editor, backend) = connect();
+
+        // Relative reports add up, absolute ones replace the position.
+        backend.push_input_report(&WHEEL_REPORT);
+        backend.push_input_report(&[0x03, 0x00, 0xfd, 0xff, 0xff, 0xff, 0x00]);
+        assert!(backend.wait_until_idle(TIMEOUT));
+        let state = speed_editor.state();
+        assert_eq!((state.wheel_mode, state.wheel_position), (WheelMode::Relative, 2));
+        backend.push_input_report(&[0x03, 0x01, 0x00, 0x01, 0x00, 0x00, 0x00]);
+        assert!(backend.wait_until_idle(TIMEOUT));
+        assert_eq!(speed_editor.state().wheel_position, 256);
+
+        let mut saved = state.clone();
+        saved.wheel_mode = WheelMode::AbsoluteDeadZero;
+        saved.wheel_position = 42;
+        speed_editor.apply_state(&saved);
+        let state = speed_editor.state();
+        assert_eq!((state.wheel_mode, state.wheel_position), (WheelMode::AbsoluteDeadZero, 42));
+
+        // The restored wheel mode is written by the polling thread.
+        let report = protocol::wheel_mode_report(WheelMode::AbsoluteDeadZero).to_vec();
+        assert!(wait_for(|| backend.written().contains(&report)), "the wheel mode was not written");
+    }
+}
@@ -0,0 +1,95 @@
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+/// Locking that ignores poisoning.
+///
+/// A panicking callback poisons the lock it was called under, but it cannot leave the state
+/// behind the locks of this crate half-updated. Recovering the guard keeps one bad callback
+/// from taking down every other thread that uses the [`SpeedEditor`][crate::SpeedEditor].
+pub(crate) trait MutexExt<T> {
+    /// Locks the mutex, recovering the guard if it was poisoned.
+    fn lock_unpoisoned(&self) -> MutexGuard<'_, T>;
+}
+
+impl<T> MutexExt<T> for Mutex<T> {
+    fn lock_unpoisoned(&self) -> MutexGuard<'_, T> {
+        self.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        panic::{self, AssertUnwindSafe},
+        sync::{
+            Mutex,
+            atomic::{AtomicBool, Ordering},
+            mpsc,
+        },
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use super::MutexExt;
+    use crate::test_util::{TIMEOUT, WHEEL_REPORT, connect};
+    use crate::{Button, ButtonLed};
+
+    #[test]
+    fn poisoned_mutex_is_recovered() {
+        let mutex = Mutex::new(1);
+        let _ = panic::catch_unwind(AssertUnwindSafe(|| {
+            let _guard = mutex.lock_unpoisoned();
+            panic!("poisoning the mutex");
+        }));
+
+        assert!(mutex.is_poisoned());
+        *mutex.lock_unpoisoned() += 1;
+        assert_eq!(*mutex.lock_unpoisoned(), 2);
+    }
+
+    #[test]
+    fn api_works_after_callback_panicked_on_polling_thread() {
+        let (speed_editor, backend) = connect();
+        speed_editor.set_on_wheel_change(|_| panic!("panicking in a callback"));
+        backend.push_input_report(&WHEEL_REPORT);
+
+        let start = Instant::now();
+        while !speed_editor.is_stopped() {
+            assert!(start.elapsed() < TIMEOUT, "polling thread did not stop");
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        assert!(!speed_editor.is_connected());
+        assert!(speed_editor.last_error().is_some_and(|error| error.fatal));
+        speed_editor.set_button_led(ButtonLed::Cam1);
+        assert_eq!(speed_editor.button_led(), ButtonLed::Cam1);
+        assert!(!speed_editor.is_button_pressed(Button::Cut));
+        speed_editor.set_on_wheel_change(|_| {});
+    }
+
+    #[test]
+    fn callbacks_are_kept_after_callback_panicked_on_calling_thread() {
+        let (speed_editor, backend) = connect();
+        // Nothing receives the event yet, so it is buffered.
+        backend.push_input_report(&WHEEL_REPORT);
+        assert!(backend.wait_until_idle(TIMEOUT));
+
+        // Passing the buffered event to the new callback makes it panic on this thread.
+        let (sender, receiver) = mpsc::channel();
+        let panicked = AtomicBool::new(false);
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            speed_editor.set_on_wheel_change(move |velocity| {
+                if !panicked.swap(true, Ordering::Relaxed) {
+                    panic!("panicking in a callback");
+                }
+                sender.send(velocity).unwrap();
+            });
+        }));
+        assert!(result.is_err());
+
+        backend.push_input_report(&WHEEL_REPORT);
+        assert_eq!(receiver.recv_timeout(TIMEOUT), Ok(5));
+        speed_editor.set_button_led(ButtonLed::Cam2);
+        assert_eq!(speed_editor.button_led(), ButtonLed::Cam2);
+        assert!(!speed_editor.is_stopped());
+    }
+}
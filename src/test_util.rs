@@ -0,0 +1,64 @@
+//! Fixtures shared by the tests of the modules.
+
+use std::{
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{HidBackend, SpeedEditor, SpeedEditorBuilder, testing::FakeBackend};
+
+/// How long a test waits for something to happen on the polling thread.
+pub(crate) const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A wheel report in relative mode with a velocity of 5.
+pub(crate) const WHEEL_REPORT: [u8; 7] = [0x03, 0x00, 0x05, 0x00, 0x00, 0x00, 0x00];
+/// A buttons report with only [`Button::Cut`][crate::Button::Cut] pressed.
+pub(crate) const CUT_PRESSED: [u8; 13] = [0x04, 0x0f, 0x00, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+/// A buttons report without any button pressed.
+pub(crate) const RELEASED: [u8; 13] = [0x04, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+
+/// Connects to a [`FakeBackend`], and waits until it wrote the wheel mode and the LEDs after
+/// connecting.
+pub(crate) fn connect() -> (SpeedEditor, FakeBackend) {
+    let backend = FakeBackend::new();
+    backend.push_auth_handshake();
+    let speed_editor = SpeedEditor::builder().connect_backend(backend.clone()).unwrap();
+    assert!(backend.wait_until_idle(TIMEOUT));
+    assert_eq!(backend.written().len(), 3);
+    (speed_editor, backend)
+}
+
+/// Connects to `backend` with `builder`, and waits until it is authenticated.
+pub(crate) fn connect_with(
+    builder: SpeedEditorBuilder,
+    backend: impl HidBackend + 'static,
+) -> SpeedEditor {
+    let (sender, receiver) = mpsc::channel();
+    let speed_editor = builder
+        .on_connect(move |_| {
+            let _ = sender.send(());
+        })
+        .connect_backend(backend)
+        .unwrap();
+    receiver.recv_timeout(TIMEOUT).unwrap();
+    speed_editor
+}
+
+/// Waits until `condition` holds, returning `false` if it did not in time.
+pub(crate) fn wait_for(condition: impl Fn() -> bool) -> bool {
+    let start = Instant::now();
+    while !condition() {
+        if start.elapsed() >= TIMEOUT {
+            return false;
+        }
+        thread::sleep(Duration::from_millis(1));
+    }
+    true
+}
+
+/// Waits until `report` is the last report written to `backend`.
+pub(crate) fn wait_for_write(backend: &FakeBackend, report: &[u8]) {
+    let written = wait_for(|| backend.written().last().map(Vec::as_slice) == Some(report));
+    assert!(written, "{report:02x?} was not written");
+}
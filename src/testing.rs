@@ -0,0 +1,601 @@
+//! Helpers to run the driver without hardware.
+//!
+//! Only available with the `testing` feature.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use bmdse::{SpeedEditor, testing::FakeBackend};
+//!
+//! let backend = FakeBackend::new();
+//! backend.push_auth_handshake();
+//!
+//! let speed_editor = SpeedEditor::builder()
+//!     .on_wheel_change(|velocity| eprintln!("{velocity}"))
+//!     .connect_backend(backend.clone())
+//!     .unwrap();
+//!
+//! backend.push_input_report(&[0x03, 0x00, 0x05, 0x00, 0x00, 0x00, 0x00]);
+//! ```
+
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Condvar, Mutex, PoisonError},
+    thread,
+    time::{Duration, Instant},
+};
+
+use hidapi::HidError;
+
+use crate::{
+    AuthStep, DeviceInfo, HidBackend, Model, SpeedEditor, Transport, protocol::AUTH_REPORT_ID,
+    sync::MutexExt,
+};
+
+// For `benches/button_diff.rs`.
+#[doc(hidden)]
+pub use crate::driver::ButtonSet;
+
+/// How a [`FakeBackend`] fails when a fault is injected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Fault {
+    /// Fail like hidapi does when the device was unplugged,
+    /// which turns into [`Error::Disconnected`][crate::Error::Disconnected].
+    Disconnected,
+    /// Fail with a generic error.
+    Error,
+}
+
+impl Fault {
+    fn to_hid_error(self) -> HidError {
+        let message = match self {
+            Fault::Disconnected => "fake: (device disconnected)",
+            Fault::Error => "fake: injected fault",
+        };
+        HidError::HidApiError { message: message.to_string() }
+    }
+}
+
+/// An in-memory [`HidBackend`] that answers with scripted reports,
+/// and records everything that is written to it.
+///
+/// Clones share the same state, so a clone can be kept to script and inspect
+/// the backend after handing it to
+/// [`SpeedEditorBuilder::connect_backend`][crate::SpeedEditorBuilder::connect_backend].
+///
+/// Faults can be injected to exercise error handling, like
+/// [`FakeBackend::fail_reads`] or [`FakeBackend::fail_auth_at`].
+#[derive(Clone)]
+pub struct FakeBackend {
+    state: Arc<Mutex<FakeState>>,
+    /// Wakes up a read that is waiting for an input report.
+    input_pushed: Arc<Condvar>,
+}
+
+struct FakeState {
+    device_info: DeviceInfo,
+    input_reports: VecDeque<Result<Vec<u8>, String>>,
+    feature_reports: VecDeque<Result<Vec<u8>, String>>,
+    written: Vec<Vec<u8>>,
+    sent_feature_reports: Vec<Vec<u8>>,
+    /// The number of reads that found nothing to return.
+    idle_reads: u64,
+
+    /// The number of reads still to fail, and how.
+    failing_reads: (u32, Fault),
+    /// The number of writes still to fail, and how.
+    failing_writes: (u32, Fault),
+    /// The authentication step to fail, and how.
+    failing_auth_step: Option<(AuthStep, Fault)>,
+    /// The step of the authentication handshake of the next feature report.
+    next_auth_step: AuthStep,
+    /// Whether reads never return anything, as if the device stopped responding.
+    unresponsive: bool,
+    /// How long every operation takes.
+    delay: Duration,
+}
+
+impl FakeState {
+    /// Fails the current authentication step if asked to, and moves on to the next step.
+    fn auth_step(&mut self, step: AuthStep) -> Result<(), HidError> {
+        self.next_auth_step = match step {
+            AuthStep::Reset => AuthStep::ReadChallenge,
+            AuthStep::ReadChallenge => AuthStep::SendChallenge,
+            AuthStep::SendChallenge => AuthStep::ReadResponse,
+            AuthStep::ReadResponse => AuthStep::SendResponse,
+            AuthStep::SendResponse => AuthStep::ReadStatus,
+            AuthStep::ReadStatus => AuthStep::Reset,
+        };
+
+        let Some((failing_step, fault)) = self.failing_auth_step else { return Ok(()) };
+        if failing_step != step {
+            return Ok(());
+        }
+        self.failing_auth_step = None;
+        self.next_auth_step = AuthStep::Reset;
+
+        // The handshake is aborted, so the replies scripted for the rest of it are dropped.
+        let remaining_replies = match step {
+            AuthStep::Reset | AuthStep::ReadChallenge => 3,
+            AuthStep::SendChallenge | AuthStep::ReadResponse => 2,
+            AuthStep::SendResponse | AuthStep::ReadStatus => 1,
+        };
+        for _ in 0..remaining_replies {
+            self.feature_reports.pop_front();
+        }
+        Err(fault.to_hid_error())
+    }
+}
+
+/// Takes one from the count of a fault, returning the error if it is still counting.
+fn take_fault((count, fault): &mut (u32, Fault)) -> Result<(), HidError> {
+    if *count == 0 {
+        return Ok(());
+    }
+    *count -= 1;
+    Err(fault.to_hid_error())
+}
+
+impl FakeBackend {
+    /// Creates a [`FakeBackend`] that looks like a Speed Editor connected over USB.
+    pub fn new() -> Self {
+        Self::with_device_info(DeviceInfo {
+            path: "fake".to_string(),
+            serial_number: None,
+            manufacturer: Some("Blackmagic Design".to_string()),
+            product: Some("DaVinci Resolve Speed Editor".to_string()),
+            release_number: 0,
+            interface_number: 0,
+            usage_page: 0xff00,
+            usage: 0x0001,
+            transport: Transport::Usb,
+            product_id: Model::SpeedEditor.product_id(),
+            model: Model::SpeedEditor,
+        })
+    }
+
+    /// Creates a [`FakeBackend`] that reports the given [`DeviceInfo`].
+    pub fn with_device_info(device_info: DeviceInfo) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(FakeState {
+                device_info,
+                input_reports: VecDeque::new(),
+                feature_reports: VecDeque::new(),
+                written: Vec::new(),
+                sent_feature_reports: Vec::new(),
+                idle_reads: 0,
+                failing_reads: (0, Fault::Error),
+                failing_writes: (0, Fault::Error),
+                failing_auth_step: None,
+                next_auth_step: AuthStep::Reset,
+                unresponsive: false,
+                delay: Duration::ZERO,
+            })),
+            input_pushed: Arc::new(Condvar::new()),
+        }
+    }
+
+    /// Queues an input report, which is returned by the next read.
+    pub fn push_input_report(&self, bytes: &[u8]) {
+        self.state.lock_unpoisoned().input_reports.push_back(Ok(bytes.to_vec()));
+        self.input_pushed.notify_all();
+    }
+
+    /// Queues a failed read.
+    pub fn push_read_error(&self, message: &str) {
+        self.state.lock_unpoisoned().input_reports.push_back(Err(message.to_string()));
+        self.input_pushed.notify_all();
+    }
+
+    /// Queues a feature report, which is returned by the next request for a feature report.
+    pub fn push_feature_report(&self, bytes: &[u8]) {
+        self.state.lock_unpoisoned().feature_reports.push_back(Ok(bytes.to_vec()));
+    }
+
+    /// Queues a failed request for a feature report.
+    pub fn push_feature_report_error(&self, message: &str) {
+        self.state.lock_unpoisoned().feature_reports.push_back(Err(message.to_string()));
+    }
+
+    /// Queues the feature reports of a successful authentication,
+    /// which asks to authenticate again after 600 seconds.
+    pub fn push_auth_handshake(&self) {
+        // The challenge, the response to our challenge, and the status.
+        self.push_feature_report(&[0x06, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]);
+        self.push_feature_report(&[0x06, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]);
+        self.push_feature_report(&[0x06, 0x04, 0x58, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]);
+    }
+
+    /// Makes the next `count` reads fail, before any queued input report is returned.
+    pub fn fail_reads(&self, count: u32, fault: Fault) {
+        self.state.lock_unpoisoned().failing_reads = (count, fault);
+    }
+
+    /// Makes the next `count` writes fail (e.g. of the LEDs).
+    pub fn fail_writes(&self, count: u32, fault: Fault) {
+        self.state.lock_unpoisoned().failing_writes = (count, fault);
+    }
+
+    /// Makes the next authentication fail at the given step.
+    ///
+    /// The feature reports queued for the rest of that handshake are dropped,
+    /// so a handshake queued with [`FakeBackend::push_auth_handshake`] after it still succeeds.
+    pub fn fail_auth_at(&self, step: AuthStep, fault: Fault) {
+        self.state.lock_unpoisoned().failing_auth_step = Some((step, fault));
+    }
+
+    /// Returns `bytes` from the next read, before any queued input report.
+    ///
+    /// This is meant for reports that are too short or otherwise invalid.
+    pub fn corrupt_next_report(&self, bytes: &[u8]) {
+        self.state.lock_unpoisoned().input_reports.push_front(Ok(bytes.to_vec()));
+        self.input_pushed.notify_all();
+    }
+
+    /// Makes reads time out without returning anything, as if the device stopped responding,
+    /// until this is called again with `false`. Queued input reports are kept until then.
+    pub fn set_unresponsive(&self, unresponsive: bool) {
+        self.state.lock_unpoisoned().unresponsive = unresponsive;
+        self.input_pushed.notify_all();
+    }
+
+    /// Makes every read, write and feature report take the given time
+    /// before it is answered. Defaults to no delay.
+    pub fn set_delay(&self, delay: Duration) {
+        self.state.lock_unpoisoned().delay = delay;
+    }
+
+    /// Blocks until all queued input reports have been read and handled,
+    /// or until the timeout passes.
+    ///
+    /// A read that finds nothing to return means the reports before it were handled,
+    /// as the polling thread handles a report before reading the next one.
+    ///
+    /// Returns `false` if the timeout passed.
+    pub fn wait_until_idle(&self, timeout: Duration) -> bool {
+        const SLICE: Duration = Duration::from_millis(1);
+
+        let idle_reads = self.state.lock_unpoisoned().idle_reads;
+        let start = Instant::now();
+        loop {
+            {
+                let state = self.state.lock_unpoisoned();
+                if state.input_reports.is_empty() && state.idle_reads > idle_reads {
+                    return true;
+                }
+            }
+            if start.elapsed() >= timeout {
+                return false;
+            }
+            thread::sleep(SLICE);
+        }
+    }
+
+    /// Returns all output reports that were written, in order.
+    pub fn written(&self) -> Vec<Vec<u8>> {
+        self.state.lock_unpoisoned().written.clone()
+    }
+
+    /// Returns all feature reports that were sent, in order.
+    pub fn sent_feature_reports(&self) -> Vec<Vec<u8>> {
+        self.state.lock_unpoisoned().sent_feature_reports.clone()
+    }
+}
+
+impl Default for FakeBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Copies a scripted report into `buf`, or turns a scripted failure into a [`HidError`].
+fn answer(report: Result<Vec<u8>, String>, buf: &mut [u8]) -> Result<usize, HidError> {
+    let bytes = report.map_err(|message| HidError::HidApiError { message })?;
+    let len = bytes.len().min(buf.len());
+    buf[..len].copy_from_slice(&bytes[..len]);
+    Ok(len)
+}
+
+impl FakeBackend {
+    /// Waits for the configured delay, without holding the lock.
+    fn delay(&self) {
+        let delay = self.state.lock_unpoisoned().delay;
+        if !delay.is_zero() {
+            thread::sleep(delay);
+        }
+    }
+}
+
+impl HidBackend for FakeBackend {
+    fn device_info(&self) -> Result<DeviceInfo, HidError> {
+        Ok(self.state.lock_unpoisoned().device_info.clone())
+    }
+
+    fn write(&self, data: &[u8]) -> Result<usize, HidError> {
+        self.delay();
+        let mut state = self.state.lock_unpoisoned();
+        take_fault(&mut state.failing_writes)?;
+        state.written.push(data.to_vec());
+        Ok(data.len())
+    }
+
+    fn read_timeout(&self, buf: &mut [u8], timeout: i32) -> Result<usize, HidError> {
+        // Like hidapi, a negative timeout blocks until a report arrives.
+        let deadline = u64::try_from(timeout)
+            .ok()
+            .map(|timeout| Instant::now() + Duration::from_millis(timeout));
+        let report = {
+            let mut state = self.state.lock_unpoisoned();
+            take_fault(&mut state.failing_reads)?;
+            let mut counted_idle = false;
+            loop {
+                if !state.unresponsive
+                    && let Some(report) = state.input_reports.pop_front()
+                {
+                    break Some(report);
+                }
+                if !counted_idle {
+                    state.idle_reads += 1;
+                    counted_idle = true;
+                }
+                // Wait like a real device that has nothing to say, until a report is pushed.
+                let remaining = match deadline {
+                    Some(deadline) => deadline.saturating_duration_since(Instant::now()),
+                    None => Duration::MAX,
+                };
+                if remaining.is_zero() {
+                    break None;
+                }
+                state = self
+                    .input_pushed
+                    .wait_timeout(state, remaining)
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .0;
+            }
+        };
+        match report {
+            Some(report) => {
+                self.delay();
+                answer(report, buf)
+            }
+            None => Ok(0),
+        }
+    }
+
+    fn send_feature_report(&self, data: &[u8]) -> Result<(), HidError> {
+        self.delay();
+        let mut state = self.state.lock_unpoisoned();
+        if data.first() == Some(&AUTH_REPORT_ID) {
+            let step = state.next_auth_step;
+            state.auth_step(step)?;
+        }
+        state.sent_feature_reports.push(data.to_vec());
+        Ok(())
+    }
+
+    fn get_feature_report(&self, buf: &mut [u8]) -> Result<usize, HidError> {
+        self.delay();
+        let report = {
+            let mut state = self.state.lock_unpoisoned();
+            if buf.first() == Some(&AUTH_REPORT_ID) {
+                let step = state.next_auth_step;
+                state.auth_step(step)?;
+            }
+            state.feature_reports.pop_front()
+        };
+        answer(report.unwrap_or_else(|| Err("no feature report was scripted".to_string())), buf)
+    }
+
+    fn get_input_report(&self, buf: &mut [u8]) -> Result<usize, HidError> {
+        self.delay();
+        // The queued input report with the requested ID is taken out of order.
+        let report_id = buf.first().copied();
+        let report = {
+            let mut state = self.state.lock_unpoisoned();
+            let position = state.input_reports.iter().position(|report| {
+                report.as_ref().is_ok_and(|bytes| bytes.first().copied() == report_id)
+            });
+            position.and_then(|position| state.input_reports.remove(position))
+        };
+        answer(report.unwrap_or_else(|| Err("no input report was scripted".to_string())), buf)
+    }
+}
+
+impl SpeedEditor {
+    /// Injects a report, which the polling thread handles as if it was read from the device.
+    ///
+    /// This goes through the same parsing and dispatching as a real report,
+    /// including [`on_raw_report`][SpeedEditor::on_raw_report] and reporting parse errors
+    /// through [`on_error`][SpeedEditor::on_error]. This is meant to replay captured reports
+    /// to reproduce bugs. Only available with the `testing` feature.
+    ///
+    /// The report is handled asynchronously, between two reads.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::HidDeviceNotFound`][crate::Error::HidDeviceNotFound]
+    /// if the device is not connected.
+    pub fn inject_report(&self, bytes: &[u8]) -> Result<(), crate::Error> {
+        // The connection is checked while holding the lock, so a disconnect either
+        // happens before this check or discards the report.
+        let mut injected_reports = self.poller.shared.injected_reports.lock_unpoisoned();
+        if !self.is_connected() {
+            return Err(crate::Error::HidDeviceNotFound);
+        }
+        injected_reports.push(bytes.to_vec());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use super::*;
+    use crate::test_util::{TIMEOUT, WHEEL_REPORT};
+    use crate::{Button, Error, driver};
+
+    fn read(backend: &FakeBackend) -> Result<Vec<u8>, Error> {
+        let mut buf = [0x00; 64];
+        let len = backend
+            .read_timeout(&mut buf, 10)
+            .map_err(|error| Error::hid("failed to read", error))?;
+        Ok(buf[..len].to_vec())
+    }
+
+    #[test]
+    fn failing_reads_come_before_queued_reports() {
+        let backend = FakeBackend::new();
+        backend.push_input_report(&WHEEL_REPORT);
+        backend.fail_reads(2, Fault::Disconnected);
+
+        assert!(matches!(read(&backend), Err(Error::Disconnected { .. })));
+        assert!(matches!(read(&backend), Err(Error::Disconnected { .. })));
+        assert_eq!(read(&backend).unwrap(), WHEEL_REPORT);
+
+        backend.fail_reads(1, Fault::Error);
+        assert!(matches!(read(&backend), Err(Error::Driver { .. })));
+        assert!(read(&backend).unwrap().is_empty());
+    }
+
+    #[test]
+    fn failing_writes_are_not_recorded() {
+        let backend = FakeBackend::new();
+        backend.fail_writes(1, Fault::Error);
+
+        assert!(backend.write(&[0x04, 0x01]).is_err());
+        assert_eq!(backend.write(&[0x04, 0x02]).unwrap(), 2);
+        assert_eq!(backend.written(), [vec![0x04, 0x02]]);
+    }
+
+    #[test]
+    fn failed_auth_drops_rest_of_handshake() {
+        let backend = FakeBackend::new();
+        backend.push_auth_handshake();
+        backend.push_auth_handshake();
+        backend.fail_auth_at(AuthStep::ReadResponse, Fault::Error);
+
+        let error = driver::authenticate(&backend, None).unwrap_err();
+        assert!(matches!(error, Error::Authentication { step: AuthStep::ReadResponse, .. }));
+        assert_eq!(driver::authenticate(&backend, None).unwrap(), 600);
+    }
+
+    #[test]
+    fn corrupt_report_comes_first() {
+        let backend = FakeBackend::new();
+        backend.push_input_report(&WHEEL_REPORT);
+        backend.corrupt_next_report(&[0x03, 0x00]);
+
+        assert_eq!(read(&backend).unwrap(), [0x03, 0x00]);
+        assert_eq!(read(&backend).unwrap(), WHEEL_REPORT);
+    }
+
+    #[test]
+    fn unresponsive_backend_keeps_reports() {
+        let backend = FakeBackend::new();
+        backend.push_input_report(&WHEEL_REPORT);
+        backend.set_unresponsive(true);
+
+        assert!(read(&backend).unwrap().is_empty());
+        backend.set_unresponsive(false);
+        assert_eq!(read(&backend).unwrap(), WHEEL_REPORT);
+    }
+
+    #[test]
+    fn delay_slows_down_every_operation() {
+        const DELAY: Duration = Duration::from_millis(50);
+
+        let backend = FakeBackend::new();
+        backend.push_input_report(&WHEEL_REPORT);
+        backend.set_delay(DELAY);
+
+        let start = Instant::now();
+        backend.write(&[0x04, 0x01]).unwrap();
+        assert!(start.elapsed() >= DELAY);
+
+        let start = Instant::now();
+        read(&backend).unwrap();
+        assert!(start.elapsed() >= DELAY);
+    }
+
+    #[test]
+    fn corrupt_report_is_reported_by_poller() {
+        let backend = FakeBackend::new();
+        backend.push_auth_handshake();
+        let (sender, receiver) = mpsc::channel();
+        let speed_editor = SpeedEditor::builder()
+            .on_raw_report(move |report| sender.send(report.to_vec()).unwrap())
+            .connect_backend(backend.clone())
+            .unwrap();
+
+        // Both are queued before the polling thread gets to read them.
+        backend.set_unresponsive(true);
+        backend.push_input_report(&WHEEL_REPORT);
+        backend.corrupt_next_report(&[0x03, 0x00]);
+        backend.set_unresponsive(false);
+        assert_eq!(receiver.recv_timeout(TIMEOUT), Ok(vec![0x03, 0x00]));
+        assert_eq!(receiver.recv_timeout(TIMEOUT), Ok(WHEEL_REPORT.to_vec()));
+        assert_eq!(speed_editor.counters().parse_errors, 1);
+        assert!(speed_editor.is_connected());
+    }
+
+    #[test]
+    fn injected_reports_are_handled_like_read_reports() {
+        let backend = FakeBackend::new();
+        backend.push_auth_handshake();
+        // Answers the battery report that is requested after connecting.
+        backend.push_input_report(&[0x07, 0x00, 0x32]);
+        let (raw_sender, raw_receiver) = mpsc::channel();
+        let (button_sender, button_receiver) = mpsc::channel();
+        let (battery_sender, battery_receiver) = mpsc::channel();
+        let (error_sender, error_receiver) = mpsc::channel();
+        let speed_editor = SpeedEditor::builder()
+            .on_raw_report(move |report| raw_sender.send(report.to_vec()).unwrap())
+            .on_battery_info(move |charging, level| battery_sender.send((charging, level)).unwrap())
+            .on_button_change(move |button, pressed| button_sender.send((button, pressed)).unwrap())
+            .on_error(move |error| error_sender.send(error.error.clone()).unwrap())
+            .connect_backend(backend.clone())
+            .unwrap();
+        assert_eq!(raw_receiver.recv_timeout(TIMEOUT), Ok(vec![0x07, 0x00, 0x32]));
+        assert_eq!(battery_receiver.recv_timeout(TIMEOUT), Ok((false, 50)));
+
+        let buttons = [0x04, 0x0f, 0x00, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        speed_editor.inject_report(&buttons).unwrap();
+        assert_eq!(raw_receiver.recv_timeout(TIMEOUT), Ok(buttons.to_vec()));
+        assert_eq!(button_receiver.recv_timeout(TIMEOUT), Ok((Button::Cut, true)));
+        assert_eq!(speed_editor.pressed_buttons(), [Button::Cut]);
+
+        speed_editor.inject_report(&[0x07, 0x01, 0x2a]).unwrap();
+        assert_eq!(raw_receiver.recv_timeout(TIMEOUT), Ok(vec![0x07, 0x01, 0x2a]));
+        assert_eq!(battery_receiver.recv_timeout(TIMEOUT), Ok((true, 42)));
+        let battery_info = speed_editor.battery_info().unwrap();
+        assert_eq!((battery_info.charging, battery_info.level), (true, 42));
+
+        // A report that cannot be parsed is reported, and leaves the state alone.
+        let invalid = [0x04, 0xff, 0x00, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        speed_editor.inject_report(&invalid).unwrap();
+        assert_eq!(raw_receiver.recv_timeout(TIMEOUT), Ok(invalid.to_vec()));
+        let error = error_receiver.recv_timeout(TIMEOUT).unwrap();
+        assert!(matches!(error, Error::InvalidReport { report_id: Some(0x04), .. }), "{error}");
+        assert_eq!(speed_editor.counters().parse_errors, 1);
+        assert_eq!(speed_editor.pressed_buttons(), [Button::Cut]);
+        assert!(speed_editor.is_connected());
+    }
+
+    #[test]
+    fn injecting_fails_when_disconnected() {
+        let backend = FakeBackend::new();
+        backend.push_auth_handshake();
+        let (sender, receiver) = mpsc::channel();
+        let speed_editor = SpeedEditor::builder()
+            .on_disconnect(move || sender.send(()).unwrap())
+            .connect_backend(backend.clone())
+            .unwrap();
+
+        backend.fail_reads(1, Fault::Disconnected);
+        assert_eq!(receiver.recv_timeout(TIMEOUT), Ok(()));
+        let error = speed_editor.inject_report(&WHEEL_REPORT).unwrap_err();
+        assert!(matches!(error, Error::HidDeviceNotFound));
+    }
+}
@@ -0,0 +1,102 @@
+use std::io;
+
+/// The scheduling priority of the polling thread.
+///
+/// Setting the priority is best-effort: raising it usually requires extra privileges,
+/// and not every platform supports every priority.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum ThreadPriority {
+    /// Keep the priority the thread was spawned with.
+    #[default]
+    Default,
+    /// A raised priority, while still using the normal scheduler.
+    ///
+    /// On Unix, this lowers the niceness of the thread to `-10`.
+    Elevated,
+    /// Real-time scheduling with the given priority (`1..=99`).
+    ///
+    /// On Unix, this uses the `SCHED_FIFO` scheduling policy.
+    Realtime(u8),
+}
+
+/// Options for the polling thread.
+#[derive(Debug, Clone)]
+pub(crate) struct ThreadOptions {
+    pub(crate) name: String,
+    pub(crate) priority: ThreadPriority,
+    pub(crate) affinity: Option<Vec<usize>>,
+}
+
+impl Default for ThreadOptions {
+    fn default() -> Self {
+        Self {
+            name: "bmd_speed_editor_poller".to_string(),
+            priority: ThreadPriority::default(),
+            affinity: None,
+        }
+    }
+}
+
+impl ThreadOptions {
+    /// Applies the priority and affinity to the current thread.
+    pub(crate) fn apply_to_current(&self) -> Result<(), crate::Error> {
+        if self.priority != ThreadPriority::Default {
+            set_current_priority(self.priority)?;
+        }
+        if let Some(cores) = &self.affinity {
+            set_current_affinity(cores)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(unix)]
+fn set_current_priority(priority: ThreadPriority) -> Result<(), crate::Error> {
+    match priority {
+        ThreadPriority::Default => Ok(()),
+        ThreadPriority::Elevated => {
+            // On Linux, the niceness is per thread when using the thread ID.
+            #[cfg(target_os = "linux")]
+            let who = unsafe { libc::gettid() } as libc::id_t;
+            #[cfg(not(target_os = "linux"))]
+            let who = 0;
+
+            if unsafe { libc::setpriority(libc::PRIO_PROCESS, who, -10) } != 0 {
+                return Err(io::Error::last_os_error().into());
+            }
+            Ok(())
+        }
+        ThreadPriority::Realtime(priority) => {
+            let param = libc::sched_param { sched_priority: priority.clamp(1, 99) as libc::c_int };
+            let result = unsafe {
+                libc::pthread_setschedparam(libc::pthread_self(), libc::SCHED_FIFO, &param)
+            };
+            if result != 0 {
+                return Err(io::Error::from_raw_os_error(result).into());
+            }
+            Ok(())
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn set_current_priority(_priority: ThreadPriority) -> Result<(), crate::Error> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "thread priority is not supported").into())
+}
+
+#[cfg(target_os = "linux")]
+fn set_current_affinity(cores: &[usize]) -> Result<(), crate::Error> {
+    let mut set = unsafe { std::mem::zeroed::<libc::cpu_set_t>() };
+    for &core in cores {
+        unsafe { libc::CPU_SET(core, &mut set) };
+    }
+    if unsafe { libc::sched_setaffinity(0, size_of::<libc::cpu_set_t>(), &set) } != 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_current_affinity(_cores: &[usize]) -> Result<(), crate::Error> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "thread affinity is not supported").into())
+}
@@ -0,0 +1,681 @@
+//! Using the Speed Editor as a keyboard and scroll wheel, through virtual devices on Linux.
+//!
+//! [`bridge`] creates a virtual keyboard with uinput, and presses the [`Shortcut`] that a
+//! [`KeyMap`] maps a button to while the button is held.
+//! Any application then receives the shortcuts as if they were typed on a keyboard,
+//! so the Speed Editor can control an editor without writing an application for it.
+//! Likewise, [`scroll_bridge`] turns the jog wheel into a high resolution scroll wheel.
+//!
+//! Creating a virtual device needs write access to `/dev/uinput`,
+//! which usually means being root or in the `input` group.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use bmdse::SpeedEditor;
+//! use bmdse::uinput::{self, Key, KeyMap, Shortcut};
+//!
+//! let speed_editor = SpeedEditor::new().unwrap();
+//!
+//! let mut keymap = KeyMap::davinci_resolve();
+//! keymap.set(bmdse::Button::Cam1, Shortcut::new(Key::NUM_1).with(Key::LEFT_ALT));
+//! let _bridge = uinput::bridge(&speed_editor, keymap).unwrap();
+//!
+//! // The shortcuts are typed until the bridge is dropped.
+//! std::thread::park();
+//! ```
+
+use std::{
+    collections::HashMap,
+    ffi::c_char,
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    os::{fd::AsRawFd, unix::fs::OpenOptionsExt},
+    sync::{Arc, Condvar, Mutex, Weak},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use crate::{Button, Event, EventSink, SinkClosed, SpeedEditor, sync::MutexExt};
+
+/// `_IOW('U', 100, int)`
+const UI_SET_EVBIT: u64 = 0x4004_5564;
+/// `_IOW('U', 101, int)`
+const UI_SET_KEYBIT: u64 = 0x4004_5565;
+/// `_IOW('U', 102, int)`
+const UI_SET_RELBIT: u64 = 0x4004_5566;
+/// `_IO('U', 1)`
+const UI_DEV_CREATE: u64 = 0x5501;
+/// `_IO('U', 2)`
+const UI_DEV_DESTROY: u64 = 0x5502;
+
+const EV_SYN: u16 = 0x00;
+const EV_KEY: u16 = 0x01;
+const EV_REL: u16 = 0x02;
+const SYN_REPORT: u16 = 0x00;
+const BTN_LEFT: u16 = 0x110;
+const REL_X: u16 = 0x00;
+const REL_Y: u16 = 0x01;
+const REL_HWHEEL: u16 = 0x06;
+const REL_WHEEL: u16 = 0x08;
+const REL_WHEEL_HI_RES: u16 = 0x0b;
+const REL_HWHEEL_HI_RES: u16 = 0x0c;
+const BUS_VIRTUAL: u16 = 0x06;
+/// The high resolution scroll units of one notch of a regular scroll wheel.
+const HI_RES_PER_NOTCH: i32 = 120;
+
+/// The highest key code the virtual keyboard can press, which covers all keyboard keys.
+const MAX_KEY: u16 = 0xff;
+
+/// A Linux key code, as defined in `linux/input-event-codes.h`.
+///
+/// The most common keys are available as constants,
+/// any other key can be created from its code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Key(pub u16);
+
+#[allow(missing_docs)]
+impl Key {
+    pub const ESC: Key = Key(1);
+    pub const NUM_1: Key = Key(2);
+    pub const NUM_2: Key = Key(3);
+    pub const NUM_3: Key = Key(4);
+    pub const NUM_4: Key = Key(5);
+    pub const NUM_5: Key = Key(6);
+    pub const NUM_6: Key = Key(7);
+    pub const NUM_7: Key = Key(8);
+    pub const NUM_8: Key = Key(9);
+    pub const NUM_9: Key = Key(10);
+    pub const NUM_0: Key = Key(11);
+    pub const MINUS: Key = Key(12);
+    pub const EQUAL: Key = Key(13);
+    pub const BACKSPACE: Key = Key(14);
+    pub const TAB: Key = Key(15);
+    pub const Q: Key = Key(16);
+    pub const W: Key = Key(17);
+    pub const E: Key = Key(18);
+    pub const R: Key = Key(19);
+    pub const T: Key = Key(20);
+    pub const Y: Key = Key(21);
+    pub const U: Key = Key(22);
+    pub const I: Key = Key(23);
+    pub const O: Key = Key(24);
+    pub const P: Key = Key(25);
+    pub const LEFT_BRACE: Key = Key(26);
+    pub const RIGHT_BRACE: Key = Key(27);
+    pub const ENTER: Key = Key(28);
+    pub const LEFT_CTRL: Key = Key(29);
+    pub const A: Key = Key(30);
+    pub const S: Key = Key(31);
+    pub const D: Key = Key(32);
+    pub const F: Key = Key(33);
+    pub const G: Key = Key(34);
+    pub const H: Key = Key(35);
+    pub const J: Key = Key(36);
+    pub const K: Key = Key(37);
+    pub const L: Key = Key(38);
+    pub const SEMICOLON: Key = Key(39);
+    pub const APOSTROPHE: Key = Key(40);
+    pub const GRAVE: Key = Key(41);
+    pub const LEFT_SHIFT: Key = Key(42);
+    pub const BACKSLASH: Key = Key(43);
+    pub const Z: Key = Key(44);
+    pub const X: Key = Key(45);
+    pub const C: Key = Key(46);
+    pub const V: Key = Key(47);
+    pub const B: Key = Key(48);
+    pub const N: Key = Key(49);
+    pub const M: Key = Key(50);
+    pub const COMMA: Key = Key(51);
+    pub const DOT: Key = Key(52);
+    pub const SLASH: Key = Key(53);
+    pub const RIGHT_SHIFT: Key = Key(54);
+    pub const LEFT_ALT: Key = Key(56);
+    pub const SPACE: Key = Key(57);
+    pub const F1: Key = Key(59);
+    pub const F2: Key = Key(60);
+    pub const F3: Key = Key(61);
+    pub const F4: Key = Key(62);
+    pub const F5: Key = Key(63);
+    pub const F6: Key = Key(64);
+    pub const F7: Key = Key(65);
+    pub const F8: Key = Key(66);
+    pub const F9: Key = Key(67);
+    pub const F10: Key = Key(68);
+    pub const F11: Key = Key(87);
+    pub const F12: Key = Key(88);
+    pub const RIGHT_CTRL: Key = Key(97);
+    pub const RIGHT_ALT: Key = Key(100);
+    pub const HOME: Key = Key(102);
+    pub const UP: Key = Key(103);
+    pub const PAGE_UP: Key = Key(104);
+    pub const LEFT: Key = Key(105);
+    pub const RIGHT: Key = Key(106);
+    pub const END: Key = Key(107);
+    pub const DOWN: Key = Key(108);
+    pub const PAGE_DOWN: Key = Key(109);
+    pub const INSERT: Key = Key(110);
+    pub const DELETE: Key = Key(111);
+    pub const LEFT_META: Key = Key(125);
+    pub const RIGHT_META: Key = Key(126);
+}
+
+/// A key with the modifiers that are held with it, like `Ctrl+Shift+Z`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Shortcut {
+    /// The modifiers, pressed in order before the key and released in reverse order after it.
+    pub modifiers: Vec<Key>,
+    /// The key.
+    pub key: Key,
+}
+
+impl Shortcut {
+    /// Creates a [`Shortcut`] of a single key without modifiers.
+    pub fn new(key: Key) -> Self {
+        Self { modifiers: Vec::new(), key }
+    }
+
+    /// Adds a modifier that is held with the key.
+    pub fn with(mut self, modifier: Key) -> Self {
+        self.modifiers.push(modifier);
+        self
+    }
+
+    /// Creates a [`Shortcut`] of the key with `Ctrl`.
+    pub fn ctrl(key: Key) -> Self {
+        Self::new(key).with(Key::LEFT_CTRL)
+    }
+
+    /// Creates a [`Shortcut`] of the key with `Shift`.
+    pub fn shift(key: Key) -> Self {
+        Self::new(key).with(Key::LEFT_SHIFT)
+    }
+}
+
+impl From<Key> for Shortcut {
+    fn from(key: Key) -> Self {
+        Self::new(key)
+    }
+}
+
+/// Which [`Shortcut`] each button types.
+///
+/// Start from [`KeyMap::davinci_resolve`] and override the buttons you want,
+/// or start from an empty [`KeyMap::new`].
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct KeyMap {
+    shortcuts: Vec<(Button, Shortcut)>,
+}
+
+impl KeyMap {
+    /// Creates a [`KeyMap`] without any shortcuts.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a [`KeyMap`] with the default shortcuts of the edit page of DaVinci Resolve.
+    ///
+    /// Only the buttons with an equivalent keyboard shortcut are mapped.
+    pub fn davinci_resolve() -> Self {
+        let mut keymap = Self::new();
+        keymap.set(Button::SmartInsert, Key::F9);
+        keymap.set(Button::Append, Shortcut::shift(Key::F12));
+        keymap.set(Button::RippleOverwrite, Shortcut::shift(Key::F10));
+        keymap.set(Button::PlaceOnTop, Key::F12);
+        keymap.set(Button::SourceOverwrite, Key::F10);
+        keymap.set(Button::In, Key::I);
+        keymap.set(Button::Out, Key::O);
+        keymap.set(Button::TrimIn, Shortcut::shift(Key::LEFT_BRACE));
+        keymap.set(Button::TrimOut, Shortcut::shift(Key::RIGHT_BRACE));
+        keymap.set(Button::Transition, Shortcut::ctrl(Key::T));
+        keymap.set(Button::Split, Shortcut::ctrl(Key::B));
+        keymap.set(Button::Snap, Key::N);
+        keymap.set(Button::RippleDelete, Shortcut::shift(Key::BACKSPACE));
+        keymap.set(Button::FullView, Shortcut::ctrl(Key::F));
+        keymap.set(Button::Escape, Key::ESC);
+        keymap.set(Button::StopPlay, Key::SPACE);
+        keymap
+    }
+
+    /// Maps the button to the shortcut, replacing the shortcut it was mapped to.
+    pub fn set(&mut self, button: Button, shortcut: impl Into<Shortcut>) {
+        let shortcut = shortcut.into();
+        match self.shortcuts.iter_mut().find(|(mapped, _)| *mapped == button) {
+            Some((_, mapped)) => *mapped = shortcut,
+            None => self.shortcuts.push((button, shortcut)),
+        }
+    }
+
+    /// Removes the shortcut of the button, returning it if there was one.
+    pub fn remove(&mut self, button: Button) -> Option<Shortcut> {
+        let index = self.shortcuts.iter().position(|(mapped, _)| *mapped == button)?;
+        Some(self.shortcuts.remove(index).1)
+    }
+
+    /// Returns the shortcut the button is mapped to.
+    pub fn get(&self, button: Button) -> Option<&Shortcut> {
+        self.shortcuts.iter().find(|(mapped, _)| *mapped == button).map(|(_, shortcut)| shortcut)
+    }
+}
+
+/// A virtual input device created with uinput, which is removed again when dropped.
+struct VirtualDevice {
+    file: File,
+}
+
+impl VirtualDevice {
+    /// Creates the device, after `setup` enabled the events it sends.
+    fn new(name: &str, setup: impl FnOnce(libc::c_int) -> io::Result<()>) -> io::Result<Self> {
+        let file =
+            OpenOptions::new().write(true).custom_flags(libc::O_NONBLOCK).open("/dev/uinput")?;
+        let fd = file.as_raw_fd();
+        setup(fd)?;
+
+        let mut device = unsafe { std::mem::zeroed::<libc::uinput_user_dev>() };
+        // The name is truncated to leave room for its terminating zero.
+        for (dst, &src) in device.name.iter_mut().zip(name.as_bytes().iter().take(79)) {
+            *dst = src as c_char;
+        }
+        device.id.bustype = BUS_VIRTUAL;
+        device.id.vendor = crate::VENDOR_ID;
+        let bytes = unsafe {
+            std::slice::from_raw_parts(
+                (&raw const device).cast::<u8>(),
+                size_of::<libc::uinput_user_dev>(),
+            )
+        };
+        (&file).write_all(bytes)?;
+        ioctl(fd, UI_DEV_CREATE, 0)?;
+
+        Ok(Self { file })
+    }
+
+    fn write_event(&self, type_: u16, code: u16, value: i32) -> io::Result<()> {
+        // The kernel fills in the time.
+        let mut event = unsafe { std::mem::zeroed::<libc::input_event>() };
+        event.type_ = type_;
+        event.code = code;
+        event.value = value;
+        let bytes = unsafe {
+            std::slice::from_raw_parts(
+                (&raw const event).cast::<u8>(),
+                size_of::<libc::input_event>(),
+            )
+        };
+        (&self.file).write_all(bytes)
+    }
+
+    fn sync(&self) -> io::Result<()> {
+        self.write_event(EV_SYN, SYN_REPORT, 0)
+    }
+}
+
+impl Drop for VirtualDevice {
+    fn drop(&mut self) {
+        let _ = ioctl(self.file.as_raw_fd(), UI_DEV_DESTROY, 0);
+    }
+}
+
+/// A virtual keyboard that keeps track of the keys it holds.
+struct VirtualKeyboard {
+    device: VirtualDevice,
+    /// How many shortcuts hold each key, so a modifier that is shared by two held shortcuts
+    /// is only released with the last one.
+    held: HashMap<Key, u32>,
+}
+
+impl VirtualKeyboard {
+    fn new(name: &str) -> io::Result<Self> {
+        let device = VirtualDevice::new(name, |fd| {
+            ioctl(fd, UI_SET_EVBIT, EV_KEY as libc::c_int)?;
+            for code in 1..=MAX_KEY {
+                ioctl(fd, UI_SET_KEYBIT, code as libc::c_int)?;
+            }
+            Ok(())
+        })?;
+        Ok(Self { device, held: HashMap::new() })
+    }
+
+    fn press(&mut self, shortcut: &Shortcut) -> io::Result<()> {
+        for &key in shortcut.modifiers.iter().chain([&shortcut.key]) {
+            let count = self.held.entry(key).or_default();
+            *count += 1;
+            if *count == 1 {
+                self.device.write_event(EV_KEY, key.0, 1)?;
+            }
+        }
+        self.device.sync()
+    }
+
+    fn release(&mut self, shortcut: &Shortcut) -> io::Result<()> {
+        for &key in [&shortcut.key].into_iter().chain(shortcut.modifiers.iter().rev()) {
+            let Some(count) = self.held.get_mut(&key) else { continue };
+            *count -= 1;
+            if *count == 0 {
+                self.held.remove(&key);
+                self.device.write_event(EV_KEY, key.0, 0)?;
+            }
+        }
+        self.device.sync()
+    }
+
+    fn release_all(&mut self) -> io::Result<()> {
+        for (key, _) in std::mem::take(&mut self.held) {
+            self.device.write_event(EV_KEY, key.0, 0)?;
+        }
+        self.device.sync()
+    }
+}
+
+impl Drop for VirtualKeyboard {
+    fn drop(&mut self) {
+        // Keys that stay pressed would repeat forever in other applications.
+        let _ = self.release_all();
+    }
+}
+
+fn ioctl(fd: libc::c_int, request: u64, arg: libc::c_int) -> io::Result<()> {
+    if unsafe { libc::ioctl(fd, request as _, arg) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+struct BridgeState {
+    keyboard: VirtualKeyboard,
+    keymap: KeyMap,
+}
+
+/// Types the shortcuts of a [`SpeedEditor`] until it is dropped, see [`bridge`].
+///
+/// Dropping it releases all held keys and removes the virtual keyboard.
+pub struct KeyboardBridge {
+    state: Arc<Mutex<BridgeState>>,
+}
+
+impl KeyboardBridge {
+    /// Replaces the [`KeyMap`], releasing the keys that are held.
+    pub fn set_keymap(&self, keymap: KeyMap) {
+        let mut state = self.state.lock_unpoisoned();
+        let _ = state.keyboard.release_all();
+        state.keymap = keymap;
+    }
+}
+
+/// Forwards the button events to the virtual keyboard, while the [`KeyboardBridge`] exists.
+struct BridgeSink {
+    state: Weak<Mutex<BridgeState>>,
+}
+
+impl EventSink for BridgeSink {
+    fn send(&self, event: Event) -> Result<(), SinkClosed> {
+        let state = self.state.upgrade().ok_or(SinkClosed)?;
+        let mut state = state.lock_unpoisoned();
+        let state = &mut *state;
+        // There is nobody to report a failed write to, and the next key might work again.
+        let _ = match event {
+            Event::ButtonChange { button, pressed } => match state.keymap.get(button) {
+                Some(shortcut) if pressed => state.keyboard.press(shortcut),
+                Some(shortcut) => state.keyboard.release(shortcut),
+                None => Ok(()),
+            },
+            Event::Disconnected => state.keyboard.release_all(),
+            _ => Ok(()),
+        };
+        Ok(())
+    }
+}
+
+/// Creates a virtual keyboard that types the [`Shortcut`] that `keymap` maps a button to,
+/// pressing it when the button is pressed and releasing it when the button is released.
+///
+/// Holding multiple buttons holds all their keys, and a modifier they share stays held until
+/// the last of them is released. The keyboard types until the returned [`KeyboardBridge`]
+/// is dropped. Writing to the keyboard is done by the polling thread, and failed writes are
+/// ignored.
+///
+/// # Errors
+///
+/// Returns an error if `/dev/uinput` could not be opened (e.g. because of missing permissions)
+/// or the virtual keyboard could not be created.
+pub fn bridge(speed_editor: &SpeedEditor, keymap: KeyMap) -> Result<KeyboardBridge, crate::Error> {
+    let keyboard = VirtualKeyboard::new("Speed Editor Keyboard")?;
+    let state = Arc::new(Mutex::new(BridgeState { keyboard, keymap }));
+    speed_editor.attach_sink(Box::new(BridgeSink { state: Arc::downgrade(&state) }));
+    Ok(KeyboardBridge { state })
+}
+
+/// How [`scroll_bridge`] turns the jog wheel into scrolling.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct ScrollOptions {
+    /// The high resolution scroll units per unit of wheel velocity,
+    /// where 120 units are one notch of a regular scroll wheel.
+    pub scale: f32,
+    /// The button that makes the wheel scroll horizontally while it is held.
+    pub horizontal_modifier: Option<Button>,
+    /// How often the scrolling is written at most.
+    /// The wheel changes in between are added up, so fast spins do not flood the system.
+    pub interval: Duration,
+}
+
+impl Default for ScrollOptions {
+    fn default() -> Self {
+        Self { scale: 10.0, horizontal_modifier: None, interval: Duration::from_millis(8) }
+    }
+}
+
+impl ScrollOptions {
+    /// Creates the default [`ScrollOptions`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the high resolution scroll units per unit of wheel velocity.
+    ///
+    /// A negative scale inverts the direction, which by default scrolls down and to the right
+    /// when turning the wheel clockwise.
+    pub fn scale(mut self, scale: f32) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    /// Set the button that makes the wheel scroll horizontally while it is held.
+    pub fn horizontal_modifier(mut self, button: Button) -> Self {
+        self.horizontal_modifier = Some(button);
+        self
+    }
+
+    /// Set how often the scrolling is written at most.
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+}
+
+/// A virtual mouse that only scrolls, and keeps the fractions of notches it did not scroll yet.
+struct VirtualScrollWheel {
+    device: VirtualDevice,
+    vertical_remainder: i32,
+    horizontal_remainder: i32,
+}
+
+impl VirtualScrollWheel {
+    fn new(name: &str) -> io::Result<Self> {
+        let device = VirtualDevice::new(name, |fd| {
+            ioctl(fd, UI_SET_EVBIT, EV_REL as libc::c_int)?;
+            for code in [REL_WHEEL, REL_HWHEEL, REL_WHEEL_HI_RES, REL_HWHEEL_HI_RES] {
+                ioctl(fd, UI_SET_RELBIT, code as libc::c_int)?;
+            }
+            // Without pointer motion and a button, the device is not recognized as a mouse,
+            // even though it never sends them.
+            for code in [REL_X, REL_Y] {
+                ioctl(fd, UI_SET_RELBIT, code as libc::c_int)?;
+            }
+            ioctl(fd, UI_SET_EVBIT, EV_KEY as libc::c_int)?;
+            ioctl(fd, UI_SET_KEYBIT, BTN_LEFT as libc::c_int)
+        })?;
+        Ok(Self { device, vertical_remainder: 0, horizontal_remainder: 0 })
+    }
+
+    /// Scrolls by high resolution units, also sending a regular scroll event for every whole
+    /// notch for applications that do not support high resolution scrolling.
+    fn scroll(&mut self, vertical: i32, horizontal: i32) -> io::Result<()> {
+        let axes = [
+            (vertical, REL_WHEEL_HI_RES, REL_WHEEL, &mut self.vertical_remainder),
+            (horizontal, REL_HWHEEL_HI_RES, REL_HWHEEL, &mut self.horizontal_remainder),
+        ];
+        for (units, hi_res_axis, axis, remainder) in axes {
+            if units == 0 {
+                continue;
+            }
+            self.device.write_event(EV_REL, hi_res_axis, units)?;
+            *remainder += units;
+            let notches = *remainder / HI_RES_PER_NOTCH;
+            if notches != 0 {
+                self.device.write_event(EV_REL, axis, notches)?;
+                *remainder -= notches * HI_RES_PER_NOTCH;
+            }
+        }
+        self.device.sync()
+    }
+}
+
+/// The scrolling that was not written yet, shared between the sink and the writing thread.
+#[derive(Default)]
+struct PendingScroll {
+    vertical: f32,
+    horizontal: f32,
+    horizontal_modifier_held: bool,
+    stop: bool,
+}
+
+impl PendingScroll {
+    /// Takes the whole units to scroll, keeping the fractions for the next time.
+    fn take(&mut self) -> (i32, i32) {
+        let (vertical, horizontal) = (self.vertical.trunc(), self.horizontal.trunc());
+        self.vertical -= vertical;
+        self.horizontal -= horizontal;
+        (vertical as i32, horizontal as i32)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.vertical.abs() < 1.0 && self.horizontal.abs() < 1.0
+    }
+}
+
+struct ScrollShared {
+    options: ScrollOptions,
+    pending: Mutex<PendingScroll>,
+    changed: Condvar,
+}
+
+/// Scrolls with the jog wheel of a [`SpeedEditor`] until it is dropped, see [`scroll_bridge`].
+///
+/// Dropping it stops scrolling and removes the virtual device.
+pub struct ScrollBridge {
+    shared: Arc<ScrollShared>,
+    writer: Option<JoinHandle<()>>,
+}
+
+impl Drop for ScrollBridge {
+    fn drop(&mut self) {
+        self.shared.pending.lock_unpoisoned().stop = true;
+        self.shared.changed.notify_all();
+        if let Some(writer) = self.writer.take() {
+            let _ = writer.join();
+        }
+    }
+}
+
+/// Adds the wheel changes to the pending scrolling, while the [`ScrollBridge`] exists.
+struct ScrollSink {
+    shared: Weak<ScrollShared>,
+}
+
+impl EventSink for ScrollSink {
+    fn send(&self, event: Event) -> Result<(), SinkClosed> {
+        let shared = self.shared.upgrade().ok_or(SinkClosed)?;
+        let mut pending = shared.pending.lock_unpoisoned();
+        if pending.stop {
+            return Err(SinkClosed);
+        }
+        match event {
+            Event::WheelChange { velocity } => {
+                let units = velocity as f32 * shared.options.scale;
+                // Scrolling down is a negative vertical scroll.
+                if pending.horizontal_modifier_held {
+                    pending.horizontal += units;
+                } else {
+                    pending.vertical -= units;
+                }
+                shared.changed.notify_all();
+            }
+            Event::ButtonChange { button, pressed }
+                if shared.options.horizontal_modifier == Some(button) =>
+            {
+                pending.horizontal_modifier_held = pressed;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+/// Creates a virtual scroll wheel that scrolls when the jog wheel is turned,
+/// with high resolution scroll events and regular ones for every whole notch.
+///
+/// The wheel only reports changes in relative mode, which is the mode it is in by default.
+/// The changes are added up and written at most once per [`ScrollOptions::interval`]
+/// by a separate thread, until the returned [`ScrollBridge`] is dropped.
+/// Failed writes are ignored.
+///
+/// # Example
+///
+/// ```no_run
+/// use bmdse::uinput::{self, ScrollOptions};
+/// use bmdse::{Button, SpeedEditor};
+///
+/// let speed_editor = SpeedEditor::new().unwrap();
+/// let options = ScrollOptions::new().scale(20.0).horizontal_modifier(Button::Shuttle);
+/// let _scroll = uinput::scroll_bridge(&speed_editor, options).unwrap();
+///
+/// std::thread::park();
+/// ```
+///
+/// # Errors
+///
+/// Returns an error if `/dev/uinput` could not be opened (e.g. because of missing permissions),
+/// the virtual device could not be created, or the writing thread could not be spawned.
+pub fn scroll_bridge(
+    speed_editor: &SpeedEditor,
+    options: ScrollOptions,
+) -> Result<ScrollBridge, crate::Error> {
+    let mut wheel = VirtualScrollWheel::new("Speed Editor Scroll Wheel")?;
+    let shared =
+        Arc::new(ScrollShared { options, pending: Mutex::default(), changed: Condvar::new() });
+
+    let writer_shared = Arc::clone(&shared);
+    let writer =
+        thread::Builder::new().name("bmd_speed_editor_scroll".to_string()).spawn(move || {
+            let shared = writer_shared;
+            loop {
+                let (vertical, horizontal) = {
+                    let mut pending = shared.pending.lock_unpoisoned();
+                    while !pending.stop && pending.is_empty() {
+                        pending = shared.changed.wait(pending).unwrap_or_else(|e| e.into_inner());
+                    }
+                    if pending.stop {
+                        return;
+                    }
+                    pending.take()
+                };
+                // There is nobody to report a failed write to, and the next one might work again.
+                let _ = wheel.scroll(vertical, horizontal);
+                thread::sleep(shared.options.interval);
+            }
+        })?;
+
+    speed_editor.attach_sink(Box::new(ScrollSink { shared: Arc::downgrade(&shared) }));
+    Ok(ScrollBridge { shared, writer: Some(writer) })
+}
@@ -0,0 +1,126 @@
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::driver::{self, HidApiSource, OpenOptions};
+use crate::registry::OpenDevice;
+
+/// A token that can be used to cancel [waiting for a device][crate::wait_for_device]
+/// from another thread.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    /// Creates a new [`CancellationToken`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancel everything that is waiting on this token (or any of its clones).
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Release);
+    }
+
+    /// Returns `true` if [`cancel`][CancellationToken::cancel] has been called.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Acquire)
+    }
+}
+
+/// Blocks until a Speed Editor is plugged in, then opens it.
+pub(crate) fn wait_for_hid_device(
+    source: &HidApiSource,
+    options: &OpenOptions,
+    timeout: Option<Duration>,
+    cancel: Option<&CancellationToken>,
+) -> Result<OpenDevice, crate::Error> {
+    const POLL_INTERVAL: Duration = Duration::from_millis(500);
+    const SLICE: Duration = Duration::from_millis(20);
+
+    let start = Instant::now();
+    loop {
+        // The HID API is only locked while polling, so others can use it while we wait.
+        let hid_device = source.with(|api| {
+            // The device might be listed before it can be opened, so we keep trying until it can.
+            if driver::is_device_present(api, options)? {
+                Ok(driver::open_hid_device(api, options).ok())
+            } else {
+                Ok(None)
+            }
+        })?;
+
+        if let Some(hid_device) = hid_device {
+            return Ok(hid_device);
+        }
+
+        let next_poll = Instant::now() + POLL_INTERVAL;
+        while Instant::now() < next_poll {
+            if cancel.is_some_and(CancellationToken::is_cancelled) {
+                return Err(crate::Error::Cancelled);
+            }
+            if timeout.is_some_and(|timeout| start.elapsed() >= timeout) {
+                return Err(crate::Error::Timeout);
+            }
+            thread::sleep(SLICE);
+        }
+    }
+}
+
+/// How opening the device is retried when it fails,
+/// see [`SpeedEditorBuilder::open_retries`][crate::SpeedEditorBuilder::open_retries].
+#[derive(Default)]
+pub(crate) struct OpenRetry {
+    /// The number of attempts after the first one.
+    pub(crate) retries: u32,
+    /// The delay before the first retry, which doubles for every following retry.
+    pub(crate) delay: Duration,
+    /// How long all attempts together may take.
+    pub(crate) timeout: Option<Duration>,
+    pub(crate) on_error: Option<OpenErrorCallback>,
+}
+
+impl OpenRetry {
+    /// The longest delay between two attempts, unless the first delay is longer.
+    const MAX_DELAY: Duration = Duration::from_secs(5);
+
+    /// Calls `open` until it succeeds, or until it failed too often or with an error
+    /// that retrying does not fix, in which case the last error is returned.
+    pub(crate) fn run<T>(
+        &self,
+        mut open: impl FnMut() -> Result<T, crate::Error>,
+    ) -> Result<T, crate::Error> {
+        let start = Instant::now();
+        let mut delay = self.delay;
+        let mut attempt = 1;
+        loop {
+            let error = match open() {
+                Ok(value) => return Ok(value),
+                Err(error) => error,
+            };
+            if let Some(on_error) = &self.on_error {
+                on_error(attempt, &error);
+            }
+
+            // Right after plugging in, udev might not have given access to the device yet.
+            let retryable =
+                error.is_recoverable() || matches!(error, crate::Error::PermissionDenied { .. });
+            let timed_out = self.timeout.is_some_and(|timeout| start.elapsed() + delay > timeout);
+            if !retryable || attempt > self.retries || timed_out {
+                return Err(error);
+            }
+
+            thread::sleep(delay);
+            delay = delay.saturating_mul(2).min(Self::MAX_DELAY.max(self.delay));
+            attempt += 1;
+        }
+    }
+}
+
+type OpenErrorCallback = Box<dyn Fn(u32, &crate::Error) + Send>;
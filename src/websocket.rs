@@ -0,0 +1,425 @@
+//! Streaming the events of the Speed Editor to browsers over WebSocket.
+//!
+//! A [`WsBridge`] is an [`EventSink`], so it is attached next to the callbacks
+//! and other sinks instead of replacing them:
+//!
+//! ```no_run
+//! use bmdse::SpeedEditor;
+//! use bmdse::websocket::{WsBridge, WsCommand};
+//!
+//! let speed_editor = SpeedEditor::new().unwrap();
+//! let ws = WsBridge::serve("0.0.0.0:9001")
+//!     .unwrap()
+//!     .allow(&speed_editor, [WsCommand::SetButtonLed, WsCommand::SetWheelLed]);
+//! speed_editor.attach_sink(Box::new(ws));
+//!
+//! std::thread::park();
+//! ```
+//!
+//! Every connected client receives each [`Event`] as a JSON text message,
+//! in the format of its `serde` implementation:
+//!
+//! ```json
+//! {"ButtonChange":{"button":"Cut","pressed":true}}
+//! ```
+//!
+//! Clients can send these commands as JSON text messages,
+//! but only the ones that are [allowed][WsBridge::allow]:
+//!
+//! | Command | Message |
+//! |---------|---------|
+//! | [`WsCommand::SetButtonLed`] | `{"command":"set_button_led","led":"Cam1"}` |
+//! | [`WsCommand::SetWheelLed`] | `{"command":"set_wheel_led","led":"Jog"}` |
+//!
+//! A command that is not allowed or not understood is answered with `{"error":"<message>"}`.
+//! Anyone who can reach the address can connect, so only allow commands on a trusted network.
+
+use std::{
+    collections::VecDeque,
+    io,
+    net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    sync::{Arc, Condvar, Mutex, Weak},
+    thread,
+    time::{Duration, Instant},
+};
+
+use tungstenite::{
+    HandshakeError, Message, ServerHandshake, Utf8Bytes, WebSocket,
+    handshake::{MidHandshake, server::NoCallback},
+    protocol::{CloseFrame, WebSocketConfig, frame::coding::CloseCode},
+};
+
+use crate::{
+    ButtonLed, Event, EventSink, SinkClosed, SpeedEditor, WheelLed, dispatch::InnerLock,
+    sync::MutexExt,
+};
+
+/// How often the server thread checks for connections and messages from clients.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+/// How long a client gets to finish the opening or the closing handshake.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+/// The largest message that is accepted from a client, which is plenty for any command.
+const MAX_MESSAGE_SIZE: usize = 4096;
+
+/// A command that clients of a [`WsBridge`] can send, see the [module documentation][self].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum WsCommand {
+    /// Sets the button LED, like [`SpeedEditor::set_button_led`].
+    SetButtonLed,
+    /// Sets the wheel LED, like [`SpeedEditor::set_wheel_led`].
+    SetWheelLed,
+}
+
+/// A command as it is sent by a client.
+#[derive(serde::Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+enum Request {
+    SetButtonLed { led: ButtonLed },
+    SetWheelLed { led: WheelLed },
+}
+
+impl Request {
+    fn command(&self) -> WsCommand {
+        match self {
+            Request::SetButtonLed { .. } => WsCommand::SetButtonLed,
+            Request::SetWheelLed { .. } => WsCommand::SetWheelLed,
+        }
+    }
+}
+
+/// The settings of a [`WsBridge`].
+struct Config {
+    allowed: Vec<WsCommand>,
+    /// The [`SpeedEditor`] the commands are for. It is not kept open by the bridge,
+    /// as the bridge is usually attached to it.
+    speed_editor: Weak<InnerLock>,
+    ping_interval: Duration,
+    timeout: Duration,
+    queue_capacity: usize,
+}
+
+#[derive(Default)]
+struct Pending {
+    /// The events that were not passed to the clients yet, already encoded.
+    messages: Vec<Utf8Bytes>,
+    stop: bool,
+}
+
+struct WsShared {
+    config: Mutex<Config>,
+    pending: Mutex<Pending>,
+    changed: Condvar,
+}
+
+/// An [`EventSink`] that streams the events as JSON to WebSocket clients,
+/// and applies the commands they send.
+///
+/// See the [module documentation][self]. The clients are served from a separate thread,
+/// so a slow client never holds up the events. When the bridge is dropped, e.g. because the
+/// [`SpeedEditor`] it is attached to is dropped or shut down, it stops accepting connections
+/// and sends the clients a close message. The server thread stops once they acknowledged it,
+/// or after 5 seconds, without holding up the drop.
+///
+/// Each client has a queue of events that were not sent yet. A client that falls behind
+/// by more than the [queue capacity][WsBridge::queue_capacity] is disconnected,
+/// instead of silently skipping events (like the release of a button).
+pub struct WsBridge {
+    shared: Arc<WsShared>,
+    local_addr: SocketAddr,
+}
+
+impl WsBridge {
+    /// Creates a [`WsBridge`] that accepts WebSocket connections on `addr`, on any path.
+    ///
+    /// Clients cannot send any commands until they are [allowed][WsBridge::allow].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the address could not be bound,
+    /// or the server thread could not be spawned.
+    pub fn serve(addr: impl ToSocketAddrs) -> Result<Self, crate::Error> {
+        let listener = TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+        let local_addr = listener.local_addr()?;
+
+        let config = Config {
+            allowed: Vec::new(),
+            speed_editor: Weak::new(),
+            ping_interval: Duration::from_secs(10),
+            timeout: Duration::from_secs(30),
+            queue_capacity: 256,
+        };
+        let shared = Arc::new(WsShared {
+            config: Mutex::new(config),
+            pending: Mutex::default(),
+            changed: Condvar::new(),
+        });
+
+        let server_shared = Arc::clone(&shared);
+        thread::Builder::new()
+            .name("bmd_speed_editor_websocket".to_string())
+            .spawn(move || serve_clients(&server_shared, listener))?;
+        Ok(Self { shared, local_addr })
+    }
+
+    /// Allows clients to send the commands, which are applied to the [`SpeedEditor`].
+    ///
+    /// This replaces the commands that were allowed before.
+    /// The bridge does not keep the [`SpeedEditor`] open, so it can be attached to it.
+    pub fn allow(
+        self,
+        speed_editor: &SpeedEditor,
+        commands: impl IntoIterator<Item = WsCommand>,
+    ) -> Self {
+        let mut config = self.shared.config.lock_unpoisoned();
+        config.allowed = commands.into_iter().collect();
+        config.speed_editor = Arc::downgrade(&speed_editor.inner);
+        drop(config);
+        self
+    }
+
+    /// Sets how often clients are pinged. The default is 10 seconds.
+    pub fn ping_interval(self, interval: Duration) -> Self {
+        self.shared.config.lock_unpoisoned().ping_interval = interval;
+        self
+    }
+
+    /// Sets after how long without any message from a client (including the replies to pings)
+    /// it is disconnected. The default is 30 seconds.
+    pub fn timeout(self, timeout: Duration) -> Self {
+        self.shared.config.lock_unpoisoned().timeout = timeout;
+        self
+    }
+
+    /// Sets how many events can wait to be sent to a single client
+    /// before it is disconnected. The default is 256.
+    pub fn queue_capacity(self, capacity: usize) -> Self {
+        self.shared.config.lock_unpoisoned().queue_capacity = capacity;
+        self
+    }
+
+    /// Returns the address the bridge accepts connections on,
+    /// e.g. to find the port that was picked when binding to port 0.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+}
+
+impl EventSink for WsBridge {
+    fn send(&self, event: Event) -> Result<(), SinkClosed> {
+        // Encoded once here, instead of for every client.
+        let Ok(json) = serde_json::to_string(&event) else { return Ok(()) };
+        self.shared.pending.lock_unpoisoned().messages.push(json.into());
+        self.shared.changed.notify_all();
+        Ok(())
+    }
+}
+
+impl Drop for WsBridge {
+    fn drop(&mut self) {
+        // The server thread is not joined, as this usually runs on the polling thread,
+        // which should not wait for the clients to acknowledge the close message.
+        self.shared.pending.lock_unpoisoned().stop = true;
+        self.shared.changed.notify_all();
+    }
+}
+
+/// A client that connected and finished the opening handshake.
+struct Client {
+    socket: WebSocket<TcpStream>,
+    /// The messages that were not written to the socket yet.
+    queue: VecDeque<Utf8Bytes>,
+    last_seen: Instant,
+    last_ping: Instant,
+    /// When the closing handshake was started.
+    closing: Option<Instant>,
+}
+
+impl Client {
+    fn new(socket: WebSocket<TcpStream>) -> Self {
+        let now = Instant::now();
+        Self { socket, queue: VecDeque::new(), last_seen: now, last_ping: now, closing: None }
+    }
+
+    /// Starts the closing handshake, dropping the messages that were not sent.
+    fn close(&mut self, code: CloseCode, reason: &str) {
+        if self.closing.is_some() {
+            return;
+        }
+        self.queue.clear();
+        let _ = self.socket.close(Some(CloseFrame { code, reason: reason.into() }));
+        self.closing = Some(Instant::now());
+    }
+
+    /// Reads the messages of the client and writes the queued messages to it.
+    ///
+    /// Returns `false` once the client is gone.
+    fn poll(&mut self, shared: &WsShared, ping_interval: Duration, timeout: Duration) -> bool {
+        let now = Instant::now();
+        loop {
+            match self.socket.read() {
+                Ok(Message::Text(text)) => {
+                    self.last_seen = now;
+                    if self.closing.is_none()
+                        && let Some(reply) = apply_command(shared, &text)
+                    {
+                        self.queue.push_back(reply);
+                    }
+                }
+                Ok(_) => self.last_seen = now,
+                Err(error) if is_would_block(&error) => break,
+                // The connection was closed, by either side, or failed.
+                Err(_) => return false,
+            }
+        }
+
+        if let Some(closing) = self.closing {
+            let _ = self.socket.flush();
+            return now.duration_since(closing) < HANDSHAKE_TIMEOUT;
+        }
+        if now.duration_since(self.last_seen) >= timeout {
+            // It does not answer anymore, so it would not answer a close message either.
+            return false;
+        }
+        if now.duration_since(self.last_ping) >= ping_interval {
+            self.last_ping = now;
+            if self.socket.write(Message::Ping(Default::default())).is_err() {
+                return false;
+            }
+        }
+
+        // Only more messages are written once the previous ones were sent,
+        // so the messages of a slow client pile up in its queue.
+        match self.socket.flush() {
+            Ok(()) => {}
+            Err(error) if is_would_block(&error) => return true,
+            Err(_) => return false,
+        }
+        while let Some(text) = self.queue.pop_front() {
+            if self.socket.write(Message::Text(text)).is_err() {
+                return false;
+            }
+        }
+        match self.socket.flush() {
+            Ok(()) => true,
+            Err(error) => is_would_block(&error),
+        }
+    }
+}
+
+fn is_would_block(error: &tungstenite::Error) -> bool {
+    matches!(error, tungstenite::Error::Io(error) if error.kind() == io::ErrorKind::WouldBlock)
+}
+
+/// Applies a command from a client, returning the reply if it failed.
+fn apply_command(shared: &WsShared, text: &str) -> Option<Utf8Bytes> {
+    let error = |message: String| Some(serde_json::json!({ "error": message }).to_string().into());
+
+    let request = match serde_json::from_str::<Request>(text) {
+        Ok(request) => request,
+        Err(parse_error) => return error(format!("invalid command: {parse_error}")),
+    };
+    let speed_editor = {
+        let config = shared.config.lock_unpoisoned();
+        if !config.allowed.contains(&request.command()) {
+            return error(format!("command not allowed: {:?}", request.command()));
+        }
+        config.speed_editor.upgrade()
+    };
+    let Some(speed_editor) = speed_editor else {
+        return error("the Speed Editor is closed".to_string());
+    };
+
+    let mut inner = speed_editor.lock_unpoisoned();
+    match request {
+        Request::SetButtonLed { led } => inner.set_button_led(led),
+        Request::SetWheelLed { led } => inner.set_wheel_led(led),
+    }
+    None
+}
+
+type Handshake = MidHandshake<ServerHandshake<TcpStream, NoCallback>>;
+
+/// Serves the clients until the [`WsBridge`] is dropped, then closes their connections.
+fn serve_clients(shared: &WsShared, listener: TcpListener) {
+    let socket_config = WebSocketConfig::default()
+        .max_message_size(Some(MAX_MESSAGE_SIZE))
+        .max_frame_size(Some(MAX_MESSAGE_SIZE));
+    let mut handshakes: Vec<(Instant, Handshake)> = Vec::new();
+    let mut clients: Vec<Client> = Vec::new();
+
+    loop {
+        let (messages, stop) = {
+            let mut pending = shared.pending.lock_unpoisoned();
+            if pending.messages.is_empty() && !pending.stop {
+                pending = shared
+                    .changed
+                    .wait_timeout(pending, POLL_INTERVAL)
+                    .map(|(pending, _)| pending)
+                    .unwrap_or_else(|e| e.into_inner().0);
+            }
+            (std::mem::take(&mut pending.messages), pending.stop)
+        };
+        if stop {
+            drop(listener);
+            close_clients(shared, clients);
+            return;
+        }
+
+        let (ping_interval, timeout, queue_capacity) = {
+            let config = shared.config.lock_unpoisoned();
+            (config.ping_interval, config.timeout, config.queue_capacity)
+        };
+
+        while let Ok((stream, _)) = listener.accept() {
+            // Events are small and should arrive right away.
+            if stream.set_nonblocking(true).is_err() || stream.set_nodelay(true).is_err() {
+                continue;
+            }
+            match tungstenite::accept_with_config(stream, Some(socket_config)) {
+                Ok(socket) => clients.push(Client::new(socket)),
+                Err(HandshakeError::Interrupted(handshake)) => {
+                    handshakes.push((Instant::now(), handshake));
+                }
+                Err(HandshakeError::Failure(_)) => {}
+            }
+        }
+
+        for (started, handshake) in std::mem::take(&mut handshakes) {
+            match handshake.handshake() {
+                Ok(socket) => clients.push(Client::new(socket)),
+                Err(HandshakeError::Interrupted(handshake))
+                    if started.elapsed() < HANDSHAKE_TIMEOUT =>
+                {
+                    handshakes.push((started, handshake));
+                }
+                Err(_) => {}
+            }
+        }
+
+        clients.retain_mut(|client| {
+            if client.closing.is_none() {
+                client.queue.extend(messages.iter().cloned());
+            }
+            if !client.poll(shared, ping_interval, timeout) {
+                return false;
+            }
+            // Whatever is still queued could not be sent yet.
+            if client.queue.len() > queue_capacity {
+                client.close(CloseCode::Again, "too slow to receive the events");
+            }
+            true
+        });
+    }
+}
+
+/// Closes the connections of the clients, waiting until they acknowledged it.
+fn close_clients(shared: &WsShared, mut clients: Vec<Client>) {
+    for client in &mut clients {
+        client.close(CloseCode::Away, "the Speed Editor is closed");
+    }
+    while !clients.is_empty() {
+        clients.retain_mut(|client| client.poll(shared, Duration::MAX, Duration::MAX));
+        thread::sleep(POLL_INTERVAL);
+    }
+}